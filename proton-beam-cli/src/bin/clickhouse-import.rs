@@ -1,6 +1,6 @@
 //! Bulk importer for ClickHouse
 //!
-//! This tool reads `.pb.gz` files (protobuf + gzip compressed Nostr events)
+//! This tool reads `.pb.gz` / `.pb.zst` files (compressed protobuf Nostr events)
 //! and imports them into ClickHouse for efficient querying.
 //!
 //! # Usage
@@ -23,15 +23,21 @@
 //!
 //! # Batch size control (for memory management)
 //! proton-beam-clickhouse-import --input events.pb.gz --batch-size 10000
+//!
+//! # Take relay_source from a specific index (default: index.db next to each input)
+//! proton-beam-clickhouse-import --input pb_data/*.pb.gz --index pb_data/index.db
+//!
+//! # zstd files written with a trained dictionary
+//! proton-beam-clickhouse-import --input pb_data/*.pb.zst --zstd-dictionary nostr.dict
 //! ```
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
-use proton_beam_core::{create_gzip_decoder, read_events_delimited};
+use proton_beam_core::{EventIndex, create_decoder, read_events_delimited};
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use tracing::info;
 
@@ -44,7 +50,7 @@ use proton_beam_cli::{
 #[command(name = "proton-beam-clickhouse-import")]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Input .pb.gz file(s) to import
+    /// Input .pb.gz or .pb.zst file(s) to import
     #[arg(short, long, required = true)]
     input: Vec<PathBuf>,
 
@@ -72,6 +78,15 @@ struct Args {
     #[arg(long, default_value = "events_local")]
     table: String,
 
+    /// Event index to take each event's relay_source from
+    /// (default: index.db next to each input file, if present)
+    #[arg(long)]
+    index: Option<PathBuf>,
+
+    /// Trained zstd dictionary the .pb.zst files were written with
+    #[arg(long, value_name = "PATH")]
+    zstd_dictionary: Option<PathBuf>,
+
     /// Batch size for inserts (events per batch)
     #[arg(long, default_value = "5000")]
     batch_size: usize,
@@ -163,6 +178,17 @@ async fn run_import(args: Args) -> Result<()> {
         None
     };
 
+    let zstd_dictionary = args
+        .zstd_dictionary
+        .as_ref()
+        .map(|path| {
+            std::fs::read(path).context(format!(
+                "Failed to read zstd dictionary: {}",
+                path.display()
+            ))
+        })
+        .transpose()?;
+
     // Process each input file
     let mut total_events = 0u64;
     let start_time = Instant::now();
@@ -170,9 +196,13 @@ async fn run_import(args: Args) -> Result<()> {
     for input_path in &args.input {
         info!("Processing file: {}", input_path.display());
 
+        let index = open_index(input_path, args.index.as_deref())?;
+
         let file_events = process_file(
             input_path,
             client.as_ref(),
+            index.as_ref(),
+            zstd_dictionary.as_deref(),
             args.batch_size,
             args.dry_run,
         )
@@ -206,17 +236,37 @@ async fn run_import(args: Args) -> Result<()> {
     Ok(())
 }
 
+/// Open the index holding relay provenance for `input_path`, if there is one
+#[cfg(feature = "clickhouse")]
+fn open_index(input_path: &Path, index_path: Option<&Path>) -> Result<Option<EventIndex>> {
+    let index_path = match index_path {
+        Some(path) => path.to_path_buf(),
+        None => match input_path.parent() {
+            Some(dir) if dir.join("index.db").exists() => dir.join("index.db"),
+            _ => return Ok(None),
+        },
+    };
+
+    let index = EventIndex::new(&index_path)
+        .context(format!("Failed to open index {}", index_path.display()))?;
+    info!("Reading relay sources from {}", index_path.display());
+    Ok(Some(index))
+}
+
 #[cfg(feature = "clickhouse")]
 async fn process_file(
     path: &PathBuf,
     client: Option<&ClickHouseClient>,
+    index: Option<&EventIndex>,
+    zstd_dictionary: Option<&[u8]>,
     batch_size: usize,
     dry_run: bool,
 ) -> Result<u64> {
     // Open and decompress file
     let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
     let buf_reader = BufReader::new(file);
-    let decoder = create_gzip_decoder(buf_reader);
+    let decoder = create_decoder(buf_reader, zstd_dictionary)
+        .context(format!("Failed to decode {}", path.display()))?;
 
     // Create progress bar
     let pb = ProgressBar::new_spinner();
@@ -238,8 +288,21 @@ async fn process_file(
             // In dry run, just count
             total_count += 1;
         } else {
-            // Convert to EventRow
-            event_batch.push(EventRow::from(event));
+            // Convert to EventRow, tagged with the first relay that delivered it
+            let relay_source = match index {
+                Some(index) => index
+                    .query_relays(&event.id)
+                    .context("Failed to look up relay source")?
+                    .into_iter()
+                    .next()
+                    .map(|sighting| sighting.relay_url),
+                None => None,
+            };
+            let row = EventRow::from(event);
+            event_batch.push(match relay_source {
+                Some(relay_url) => row.with_relay_source(relay_url),
+                None => row,
+            });
 
             // Insert batch when full
             if event_batch.len() >= batch_size {
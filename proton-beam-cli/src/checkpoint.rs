@@ -0,0 +1,270 @@
+//! Checkpoints of parallel conversions
+//!
+//! A parallel conversion splits its input into chunks at line boundaries,
+//! and each chunk is converted into temp files of its own
+//! (`thread_{chunk}_{date}.pb.{gz,zst}.tmp`, plus
+//! `thread_{chunk}_rejects.jsonl.tmp` with `--rejects`). Every so often a chunk
+//! finishes its temp files and records how far into the input it got and how
+//! long each of its temp files was at that point. After a crash, the temp
+//! files of unfinished chunks are cut back to those lengths with
+//! [`ChunkProgress::restore_files`], conversion carries on from the recorded
+//! offsets, and finished chunks are skipped.
+
+use anyhow::{Context, Result};
+use proton_beam_core::Codec;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the checkpoint file inside the temp directory
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Progress of a parallel conversion
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Input file being converted
+    pub input: PathBuf,
+    /// Size of the input file when the conversion started
+    pub input_size: u64,
+    /// Codec of the temp files
+    pub codec: String,
+    /// Progress of each chunk, in input order
+    pub chunks: Vec<ChunkProgress>,
+}
+
+/// How far one chunk of the input has been converted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkProgress {
+    /// Offset of the chunk's first line
+    pub start: u64,
+    /// Offset just past the chunk's last line
+    pub end: u64,
+    /// Offset up to which every event is in the temp files
+    pub position: u64,
+    /// Lines read up to `position`
+    pub lines: u64,
+    /// Events stored up to `position`
+    pub valid_events: u64,
+    /// Lines that failed to parse, validate or store up to `position`
+    pub invalid_events: u64,
+    /// Empty lines up to `position`
+    pub skipped_lines: u64,
+    /// Events left out by the event filter up to `position`
+    pub filtered_events: u64,
+    /// Length of each of the chunk's temp files at `position`, by file name
+    pub files: BTreeMap<String, u64>,
+    /// Whether the whole chunk has been converted
+    pub done: bool,
+}
+
+impl Checkpoint {
+    /// Start a conversion of `input` split into `chunks` of (start, end) offsets
+    pub fn new(input: &Path, input_size: u64, codec: Codec, chunks: &[(u64, u64)]) -> Self {
+        Self {
+            input: input.to_path_buf(),
+            input_size,
+            codec: codec.to_string(),
+            chunks: chunks
+                .iter()
+                .map(|&(start, end)| ChunkProgress {
+                    start,
+                    end,
+                    position: start,
+                    ..ChunkProgress::default()
+                })
+                .collect(),
+        }
+    }
+
+    /// Path of the checkpoint file in `temp_dir`
+    pub fn path(temp_dir: &Path) -> PathBuf {
+        temp_dir.join(CHECKPOINT_FILE)
+    }
+
+    /// Read the checkpoint in `temp_dir`, if there is one
+    pub fn load(temp_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(temp_dir);
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+        };
+        let checkpoint = serde_json::from_str(&json)
+            .context(format!("Invalid checkpoint: {}", path.display()))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Write the checkpoint to `temp_dir`, replacing the previous one atomically
+    pub fn save(&self, temp_dir: &Path) -> Result<()> {
+        let path = Self::path(temp_dir);
+        let new_path = path.with_extension("json.new");
+        let mut file =
+            File::create(&new_path).context(format!("Failed to create {}", new_path.display()))?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.write_all(b"\n")?;
+        file.sync_all()
+            .context(format!("Failed to sync {}", new_path.display()))?;
+        std::fs::rename(&new_path, &path).context(format!("Failed to write {}", path.display()))
+    }
+
+    /// Check that the checkpoint belongs to a conversion of `input` with `codec`
+    pub fn check_input(&self, input: &Path, input_size: u64, codec: Codec) -> Result<()> {
+        if self.input != input {
+            anyhow::bail!(
+                "The checkpoint is for {}, not {}",
+                self.input.display(),
+                input.display()
+            );
+        }
+        if self.input_size != input_size {
+            anyhow::bail!(
+                "{} has changed size since the checkpoint ({} bytes, now {})",
+                input.display(),
+                self.input_size,
+                input_size
+            );
+        }
+        if self.codec != codec.to_string() {
+            anyhow::bail!(
+                "The checkpointed conversion writes {} files, not {}",
+                self.codec,
+                codec
+            );
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProgress {
+    /// Record the current length of every temp file of chunk `chunk`
+    ///
+    /// The files must be complete: every compressed stream finished.
+    pub fn record_files(&mut self, temp_dir: &Path, chunk: usize) -> Result<()> {
+        self.files.clear();
+        for (name, path) in chunk_files(temp_dir, chunk)? {
+            let len = std::fs::metadata(&path)
+                .context(format!("Failed to read metadata of {}", path.display()))?
+                .len();
+            self.files.insert(name, len);
+        }
+        Ok(())
+    }
+
+    /// Cut the temp files of chunk `chunk` back to their recorded lengths
+    ///
+    /// Files created after the checkpoint are removed.
+    pub fn restore_files(&self, temp_dir: &Path, chunk: usize) -> Result<()> {
+        let mut restored = 0;
+        for (name, path) in chunk_files(temp_dir, chunk)? {
+            let Some(&len) = self.files.get(&name) else {
+                std::fs::remove_file(&path)
+                    .context(format!("Failed to remove {}", path.display()))?;
+                continue;
+            };
+            let file = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .context(format!("Failed to open {}", path.display()))?;
+            if file.metadata()?.len() < len {
+                anyhow::bail!(
+                    "{} is shorter than at the checkpoint ({} bytes)",
+                    path.display(),
+                    len
+                );
+            }
+            file.set_len(len)
+                .context(format!("Failed to truncate {}", path.display()))?;
+            restored += 1;
+        }
+
+        if restored < self.files.len() {
+            anyhow::bail!(
+                "Temp files of chunk {} are missing from {}",
+                chunk,
+                temp_dir.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Temp files written for chunk `chunk`, by file name
+fn chunk_files(temp_dir: &Path, chunk: usize) -> Result<Vec<(String, PathBuf)>> {
+    let prefix = format!("thread_{}_", chunk);
+    let mut files = Vec::new();
+    for entry in
+        std::fs::read_dir(temp_dir).context(format!("Failed to read {}", temp_dir.display()))?
+    {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.starts_with(&prefix) && name.ends_with(".tmp") {
+            files.push((name.to_string(), path));
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(Checkpoint::load(temp_dir.path()).unwrap().is_none());
+
+        let input = Path::new("events.jsonl");
+        let mut checkpoint = Checkpoint::new(input, 300, Codec::Zstd, &[(0, 100), (100, 300)]);
+        checkpoint.chunks[1].position = 250;
+        checkpoint.chunks[1].files.insert("file".to_string(), 42);
+        checkpoint.save(temp_dir.path()).unwrap();
+
+        let loaded = Checkpoint::load(temp_dir.path()).unwrap().unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(loaded.chunks[0].position, 0);
+        assert!(loaded.check_input(input, 300, Codec::Zstd).is_ok());
+        assert!(loaded.check_input(input, 301, Codec::Zstd).is_err());
+        assert!(loaded.check_input(input, 300, Codec::Gzip).is_err());
+        assert!(
+            loaded
+                .check_input(Path::new("other.jsonl"), 300, Codec::Zstd)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_restore_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, len: usize| {
+            std::fs::write(temp_dir.path().join(name), vec![0u8; len]).unwrap();
+        };
+        let len = |name: &str| std::fs::metadata(temp_dir.path().join(name)).map(|m| m.len());
+
+        write("thread_1_2025_01_01.pb.gz.tmp", 10);
+        write("thread_11_2025_01_01.pb.gz.tmp", 10);
+        let mut progress = ChunkProgress::default();
+        progress.record_files(temp_dir.path(), 1).unwrap();
+        assert_eq!(progress.files.len(), 1);
+
+        // Writes after the checkpoint are undone, for this chunk only
+        write("thread_1_2025_01_01.pb.gz.tmp", 25);
+        write("thread_1_2025_01_02.pb.gz.tmp", 5);
+        write("thread_11_2025_01_02.pb.gz.tmp", 5);
+        progress.restore_files(temp_dir.path(), 1).unwrap();
+        assert_eq!(len("thread_1_2025_01_01.pb.gz.tmp").unwrap(), 10);
+        assert!(len("thread_1_2025_01_02.pb.gz.tmp").is_err());
+        assert_eq!(len("thread_11_2025_01_01.pb.gz.tmp").unwrap(), 10);
+        assert_eq!(len("thread_11_2025_01_02.pb.gz.tmp").unwrap(), 5);
+
+        // Flushed data that has gone missing cannot be restored
+        write("thread_1_2025_01_01.pb.gz.tmp", 3);
+        assert!(progress.restore_files(temp_dir.path(), 1).is_err());
+        std::fs::remove_file(temp_dir.path().join("thread_1_2025_01_01.pb.gz.tmp")).unwrap();
+        assert!(progress.restore_files(temp_dir.path(), 1).is_err());
+    }
+}
@@ -79,11 +79,20 @@ impl From<ProtoEvent> for EventRow {
             content: event.content,
             sig: event.sig,
             tags,
-            relay_source: String::new(), // Can be populated if we track relay sources
+            relay_source: String::new(), // Filled in from the index, see `with_relay_source`
         }
     }
 }
 
+#[cfg(feature = "clickhouse")]
+impl EventRow {
+    /// Set the relay the event was first received from
+    pub fn with_relay_source(mut self, relay_url: impl Into<String>) -> Self {
+        self.relay_source = relay_url.into();
+        self
+    }
+}
+
 /// ClickHouse client wrapper for event insertion
 #[cfg(feature = "clickhouse")]
 pub struct ClickHouseClient {
@@ -257,6 +266,24 @@ mod tests {
         assert_eq!(event_row.tags[1], vec!["p", "pubkey"]);
     }
 
+    #[test]
+    fn test_event_row_relay_source() {
+        let proto_event = ProtoEvent {
+            id: "test123".to_string(),
+            pubkey: "pubkey456".to_string(),
+            created_at: 1234567890,
+            kind: 1,
+            tags: vec![],
+            content: String::new(),
+            sig: "signature789".to_string(),
+        };
+
+        let event_row = EventRow::from(proto_event);
+        assert_eq!(event_row.relay_source, "");
+        let event_row = event_row.with_relay_source("wss://relay.damus.io");
+        assert_eq!(event_row.relay_source, "wss://relay.damus.io");
+    }
+
     #[test]
     fn test_default_config() {
         let config = ClickHouseConfig::default();
@@ -1,13 +1,41 @@
+//! Reading JSONL events for `convert`
+//!
+//! Input can be a file, a directory or glob pattern of files, or stdin
+//! (`-`). Each file or stream may be plain JSONL or compressed with gzip,
+//! zstd or bzip2, which is detected from its first bytes. Progress is
+//! measured in input bytes as read from disk, before decompression.
+//!
+//! Besides plain event JSON, lines may be relay wire messages
+//! (`["EVENT", subid, {...}]`), as captured from a relay connection, and
+//! input files may be SQLite relay databases such as nostr-rs-relay's,
+//! whose `event` table keeps each event's JSON in its `content` column.
+
 use anyhow::{Context, Result};
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
 use regex::Regex;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Lines};
-use std::path::Path;
-use std::sync::OnceLock;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 
 // Buffer size for input file reading (1MB for optimal performance)
 const INPUT_BUFFER_SIZE: usize = 1024 * 1024;
 
+/// File name endings of the inputs picked up from directories
+const JSONL_EXTENSIONS: [&str; 4] = [".jsonl", ".jsonl.gz", ".jsonl.zst", ".jsonl.bz2"];
+
+/// Header every SQLite database file starts with
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// Rows fetched per query when reading a relay database
+const SQLITE_BATCH_SIZE: usize = 10_000;
+
 /// Regex to extract kind value from JSON
 /// Matches: "kind": 123, "kind":456, etc.
 static KIND_REGEX: OnceLock<Regex> = OnceLock::new();
@@ -17,11 +45,327 @@ fn get_kind_regex() -> &'static Regex {
         .get_or_init(|| Regex::new(r#""kind"\s*:\s*(\d+)"#).expect("Failed to compile kind regex"))
 }
 
+/// Compression of an input stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputCompression {
+    /// Plain JSONL
+    None,
+    /// gzip, possibly several concatenated members
+    Gzip,
+    /// zstd, possibly several concatenated frames
+    Zstd,
+    /// bzip2, possibly several concatenated streams
+    Bzip2,
+}
+
+impl InputCompression {
+    /// Compression of a stream starting with `bytes`
+    pub fn from_magic(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if bytes.starts_with(b"BZh") {
+            Self::Bzip2
+        } else {
+            Self::None
+        }
+    }
+
+    /// Compression of the file at `path`
+    pub fn of_file(path: &Path) -> Result<Self> {
+        Ok(Self::from_magic(&read_head(path)?))
+    }
+}
+
+/// Whether the file at `path` is a SQLite database
+pub fn is_sqlite_file(path: &Path) -> Result<bool> {
+    Ok(read_head(path)?.starts_with(SQLITE_MAGIC))
+}
+
+/// First bytes of the file at `path`, enough to tell its format
+fn read_head(path: &Path) -> Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SQLITE_MAGIC.len());
+    File::open(path)
+        .context(format!("Failed to open input file: {}", path.display()))?
+        .take(SQLITE_MAGIC.len() as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Where `convert` reads events from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    /// Standard input
+    Stdin,
+    /// Files, read one after the other
+    Files(Vec<PathBuf>),
+}
+
+impl InputSource {
+    /// Resolve an input argument: `-`, a file, a directory or a glob pattern
+    ///
+    /// Directories contribute the `.jsonl`, `.jsonl.gz`, `.jsonl.zst` and
+    /// `.jsonl.bz2` files directly inside them. Directory entries and glob
+    /// matches are read in name order.
+    pub fn resolve(input: &str) -> Result<Self> {
+        if input == "-" {
+            return Ok(Self::Stdin);
+        }
+
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(path)
+                .context(format!("Failed to read input directory: {}", input))?
+            {
+                let path = entry?.path();
+                if path.is_file() && is_jsonl_file(&path) {
+                    files.push(path);
+                }
+            }
+            if files.is_empty() {
+                anyhow::bail!("No JSONL files in input directory: {}", input);
+            }
+            files.sort();
+            return Ok(Self::Files(files));
+        }
+        if path.exists() {
+            return Ok(Self::Files(vec![path.to_path_buf()]));
+        }
+
+        if input.contains(['*', '?', '[']) {
+            let mut files = glob::glob(input)
+                .context(format!("Invalid input pattern: {}", input))?
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .context(format!("Failed to expand input pattern: {}", input))?;
+            files.retain(|path| path.is_file());
+            if files.is_empty() {
+                anyhow::bail!("No input files match: {}", input);
+            }
+            files.sort();
+            return Ok(Self::Files(files));
+        }
+
+        anyhow::bail!("Input file does not exist: {}", input)
+    }
+
+    /// Total size of the input files, or `None` for stdin
+    ///
+    /// Also `None` when a relay database is among the files: only the event
+    /// JSON is read from those, so their size says little about progress.
+    pub fn total_bytes(&self) -> Option<u64> {
+        match self {
+            Self::Stdin => None,
+            Self::Files(files) => {
+                if files
+                    .iter()
+                    .any(|path| is_sqlite_file(path).unwrap_or(false))
+                {
+                    return None;
+                }
+                Some(
+                    files
+                        .iter()
+                        .filter_map(|path| std::fs::metadata(path).ok())
+                        .map(|metadata| metadata.len())
+                        .sum(),
+                )
+            }
+        }
+    }
+
+    /// The single uncompressed JSONL file of this source, if that is what it is
+    ///
+    /// Only such a file can be split into chunks at byte offsets.
+    pub fn plain_file(&self) -> Result<Option<&Path>> {
+        match self {
+            Self::Files(files) if files.len() == 1 => {
+                let path = files[0].as_path();
+                let plain = InputCompression::of_file(path)? == InputCompression::None
+                    && !is_sqlite_file(path)?;
+                Ok(plain.then_some(path))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Whether `path` is named like a JSONL input, compressed or not
+fn is_jsonl_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| JSONL_EXTENSIONS.iter().any(|ext| name.ends_with(ext)))
+}
+
+/// Line reader over a stream or relay database
+type LineReader = Box<dyn Iterator<Item = std::io::Result<String>> + Send>;
+
+/// Open a stream, counting the bytes read from it into `bytes_read`
+fn open_stream<R: Read + Send + 'static>(
+    reader: R,
+    bytes_read: &Arc<AtomicU64>,
+) -> Result<LineReader> {
+    let mut reader = BufReader::with_capacity(
+        INPUT_BUFFER_SIZE,
+        CountingReader {
+            inner: reader,
+            count: Arc::clone(bytes_read),
+        },
+    );
+    let compression = InputCompression::from_magic(reader.fill_buf()?);
+
+    let reader: Box<dyn BufRead + Send> = match compression {
+        InputCompression::None => Box::new(reader),
+        InputCompression::Gzip => Box::new(BufReader::with_capacity(
+            INPUT_BUFFER_SIZE,
+            MultiGzDecoder::new(reader),
+        )),
+        InputCompression::Zstd => {
+            let mut decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+            // Accept dumps compressed with `zstd --long`
+            decoder.window_log_max(31)?;
+            Box::new(BufReader::with_capacity(INPUT_BUFFER_SIZE, decoder))
+        }
+        InputCompression::Bzip2 => Box::new(BufReader::with_capacity(
+            INPUT_BUFFER_SIZE,
+            MultiBzDecoder::new(reader),
+        )),
+    };
+    Ok(Box::new(reader.lines()))
+}
+
+/// Open a SQLite relay database, counting the event JSON read into `bytes_read`
+fn open_database(path: &Path, bytes_read: &Arc<AtomicU64>) -> Result<LineReader> {
+    Ok(Box::new(RelayDatabaseReader::open(
+        path,
+        Arc::clone(bytes_read),
+    )?))
+}
+
+/// Reads event JSON from the `event` table of a SQLite relay database
+///
+/// Rows are read in rowid order, a batch at a time. Events the relay has
+/// hidden (nostr-rs-relay marks deleted events that way) are left out.
+struct RelayDatabaseReader {
+    conn: Connection,
+    query: String,
+    last_rowid: i64,
+    batch: VecDeque<String>,
+    done: bool,
+    count: Arc<AtomicU64>,
+}
+
+impl RelayDatabaseReader {
+    fn open(path: &Path, count: Arc<AtomicU64>) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .context(format!("Failed to open relay database: {}", path.display()))?;
+
+        let columns = conn
+            .prepare("SELECT name FROM pragma_table_info('event')")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if !columns.iter().any(|column| column == "content") {
+            anyhow::bail!(
+                "Not a relay database (no event table with a content column): {}",
+                path.display()
+            );
+        }
+        let hidden = if columns.iter().any(|column| column == "hidden") {
+            " AND IFNULL(hidden, 0) = 0"
+        } else {
+            ""
+        };
+
+        Ok(Self {
+            conn,
+            query: format!(
+                "SELECT rowid, content FROM event WHERE rowid > ?1{} ORDER BY rowid LIMIT {}",
+                hidden, SQLITE_BATCH_SIZE
+            ),
+            last_rowid: i64::MIN,
+            batch: VecDeque::new(),
+            done: false,
+            count,
+        })
+    }
+
+    /// Fetch the rows after the last one read
+    fn fetch_batch(&mut self) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare_cached(&self.query)?;
+        let mut rows = stmt.query([self.last_rowid])?;
+        let mut fetched = 0;
+        while let Some(row) = rows.next()? {
+            fetched += 1;
+            self.last_rowid = row.get(0)?;
+            // Some relays store the JSON as a blob rather than text
+            let json = match row.get_ref(1)? {
+                ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+                    String::from_utf8_lossy(bytes).into_owned()
+                }
+                _ => String::new(),
+            };
+            self.count.fetch_add(json.len() as u64, Ordering::Relaxed);
+            self.batch.push_back(json);
+        }
+        self.done = fetched < SQLITE_BATCH_SIZE;
+        Ok(())
+    }
+}
+
+impl Iterator for RelayDatabaseReader {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty()
+            && !self.done
+            && let Err(e) = self.fetch_batch()
+        {
+            self.done = true;
+            return Some(Err(std::io::Error::other(e)));
+        }
+        self.batch.pop_front().map(Ok)
+    }
+}
+
+/// Reader that adds the bytes read through it to a shared counter
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// A line of input and where it was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLine {
+    /// The line, or the event JSON of a relay message
+    pub text: String,
+    /// File the line was read from, or `None` for stdin and other streams
+    pub file: Option<Arc<str>>,
+    /// Number of the line in its file or stream, counting from 1
+    ///
+    /// Relay messages without an event and lines dropped by kind filtering
+    /// are counted too. For relay databases, this is the number of the row.
+    pub line: u64,
+}
+
 /// Input reader for JSONL files with optional preprocessing
 pub struct InputReader {
-    reader: Lines<BufReader<File>>,
+    reader: Option<LineReader>,
+    files: std::vec::IntoIter<PathBuf>,
+    file: Option<Arc<str>>,
+    line: u64,
+    bytes_read: Arc<AtomicU64>,
     filter_invalid_kinds: bool,
     filtered_count: usize,
+    skipped_messages: usize,
 }
 
 impl InputReader {
@@ -34,26 +378,73 @@ impl InputReader {
     /// Create a new input reader with preprocessing options
     ///
     /// # Arguments
-    /// * `input` - Path to the input file
+    /// * `input` - Input file, directory, glob pattern or `-` for stdin
     /// * `filter_invalid_kinds` - If true, filters out events with kind values > 65535
     pub fn with_options(input: &str, filter_invalid_kinds: bool) -> Result<Self> {
-        let path = Path::new(input);
-        if !path.exists() {
-            anyhow::bail!("Input file does not exist: {}", input);
-        }
-
-        let file = File::open(path).context(format!("Failed to open input file: {}", input))?;
-        let reader = BufReader::with_capacity(INPUT_BUFFER_SIZE, file);
+        Self::from_source(InputSource::resolve(input)?, filter_invalid_kinds)
+    }
 
-        Ok(Self::from_reader(reader, filter_invalid_kinds))
+    /// Create an input reader over a resolved input source
+    pub fn from_source(source: InputSource, filter_invalid_kinds: bool) -> Result<Self> {
+        let mut reader = Self {
+            reader: None,
+            files: Vec::new().into_iter(),
+            file: None,
+            line: 0,
+            bytes_read: Arc::new(AtomicU64::new(0)),
+            filter_invalid_kinds,
+            filtered_count: 0,
+            skipped_messages: 0,
+        };
+        match source {
+            InputSource::Stdin => {
+                reader.reader = Some(open_stream(std::io::stdin(), &reader.bytes_read)?);
+            }
+            InputSource::Files(files) => {
+                reader.files = files.into_iter();
+                reader.open_next_file()?;
+            }
+        }
+        Ok(reader)
     }
 
-    pub fn from_reader(reader: BufReader<File>, filter_invalid_kinds: bool) -> Self {
-        Self {
-            reader: reader.lines(),
+    /// Create an input reader over any stream, decompressing it as needed
+    pub fn from_reader<R: Read + Send + 'static>(
+        reader: R,
+        filter_invalid_kinds: bool,
+    ) -> Result<Self> {
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        Ok(Self {
+            reader: Some(open_stream(reader, &bytes_read)?),
+            files: Vec::new().into_iter(),
+            file: None,
+            line: 0,
+            bytes_read,
             filter_invalid_kinds,
             filtered_count: 0,
+            skipped_messages: 0,
+        })
+    }
+
+    /// Start reading the next input file; returns false once all are read
+    fn open_next_file(&mut self) -> Result<bool> {
+        let Some(path) = self.files.next() else {
+            self.reader = None;
+            return Ok(false);
+        };
+        self.file = Some(path.display().to_string().into());
+        self.line = 0;
+        if is_sqlite_file(&path)? {
+            self.reader = Some(open_database(&path, &self.bytes_read)?);
+            return Ok(true);
         }
+        let file =
+            File::open(&path).context(format!("Failed to open input file: {}", path.display()))?;
+        self.reader = Some(
+            open_stream(file, &self.bytes_read)
+                .context(format!("Failed to read input file: {}", path.display()))?,
+        );
+        Ok(true)
     }
 
     /// Get the number of lines filtered out due to invalid kinds
@@ -61,6 +452,22 @@ impl InputReader {
         self.filtered_count
     }
 
+    /// Get the number of relay messages skipped because they carry no event
+    pub fn skipped_messages(&self) -> usize {
+        self.skipped_messages
+    }
+
+    /// Bytes read from the input so far, before decompression
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Shared counter behind [`Self::bytes_read`], for reporting progress
+    /// while the reader is being iterated
+    pub fn byte_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.bytes_read)
+    }
+
     /// Check if a JSON line has a valid kind value (0-65535)
     pub fn has_valid_kind(line: &str) -> bool {
         let regex = get_kind_regex();
@@ -75,18 +482,66 @@ impl InputReader {
         // If no kind field found or parsing failed, assume valid
         // (will be caught later in validation)
     }
+
+    /// The event JSON carried by a line
+    ///
+    /// Relay wire messages (`["EVENT", subid, {...}]` from a relay or
+    /// `["EVENT", {...}]` from a client) are unwrapped to their event;
+    /// other relay messages such as `EOSE` or `NOTICE` yield `None`. Any
+    /// other line is returned as is and left for the parser to judge.
+    pub fn event_json(line: &str) -> Option<Cow<'_, str>> {
+        if !line.trim_start().starts_with('[') {
+            return Some(Cow::Borrowed(line));
+        }
+        let Ok(serde_json::Value::Array(mut message)) = serde_json::from_str(line) else {
+            return Some(Cow::Borrowed(line));
+        };
+        match message.first().and_then(|label| label.as_str()) {
+            Some("EVENT") => match message.pop() {
+                Some(event @ serde_json::Value::Object(_)) if message.len() <= 2 => {
+                    Some(Cow::Owned(event.to_string()))
+                }
+                _ => Some(Cow::Borrowed(line)),
+            },
+            Some(_) => None,
+            None => Some(Cow::Borrowed(line)),
+        }
+    }
 }
 
 impl Iterator for InputReader {
-    type Item = Result<String>;
+    type Item = Result<InputLine>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let line_result = self.reader.next()?;
+            let line_result = match self.reader.as_mut()?.next() {
+                Some(line_result) => line_result,
+                None => match self.open_next_file() {
+                    Ok(_) => continue,
+                    Err(e) => return Some(Err(e)),
+                },
+            };
 
+            self.line += 1;
             let line = match line_result {
                 Ok(l) => l,
-                Err(e) => return Some(Err(e).context("Failed to read line from file")),
+                Err(e) => {
+                    let location = match &self.file {
+                        Some(file) => format!("line {} of {}", self.line, file),
+                        None => format!("line {}", self.line),
+                    };
+                    return Some(Err(e).context(format!("Failed to read input {}", location)));
+                }
+            };
+
+            // Unwrap relay wire messages, skipping those without an event
+            let line = match Self::event_json(&line) {
+                Some(Cow::Borrowed(_)) => line,
+                Some(Cow::Owned(event)) => event,
+                None => {
+                    self.skipped_messages += 1;
+                    continue;
+                }
             };
 
             // Apply kind filtering if enabled
@@ -95,7 +550,11 @@ impl Iterator for InputReader {
                 continue; // Skip this line and read the next one
             }
 
-            return Some(Ok(line));
+            return Some(Ok(InputLine {
+                text: line,
+                file: self.file.clone(),
+                line: self.line,
+            }));
         }
     }
 }
@@ -104,7 +563,19 @@ impl Iterator for InputReader {
 mod tests {
     use super::*;
     use std::io::Write;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
 
     #[test]
     fn test_file_reader() {
@@ -116,7 +587,7 @@ mod tests {
         file.flush().unwrap();
 
         let reader = InputReader::new(file.path().to_str().unwrap()).unwrap();
-        let lines: Vec<String> = reader.map(|r| r.unwrap()).collect();
+        let lines: Vec<String> = reader.map(|r| r.unwrap().text).collect();
 
         assert_eq!(lines.len(), 3);
         assert_eq!(lines[0], "line 1");
@@ -166,7 +637,7 @@ mod tests {
         )
         .unwrap();
 
-        let lines: Vec<String> = reader.by_ref().map(|r| r.unwrap()).collect();
+        let lines: Vec<String> = reader.by_ref().map(|r| r.unwrap().text).collect();
 
         // Should only get 3 valid lines
         assert_eq!(lines.len(), 3);
@@ -187,10 +658,166 @@ mod tests {
 
         // InputReader::new() uses with_options(input, false) so filtering is disabled
         let mut reader = InputReader::new(file.path().to_str().unwrap()).unwrap();
-        let lines: Vec<String> = reader.by_ref().map(|r| r.unwrap()).collect();
+        let lines: Vec<String> = reader.by_ref().map(|r| r.unwrap().text).collect();
 
         // The `new()` method explicitly disables filtering for backward compatibility
         assert_eq!(lines.len(), 1);
         assert_eq!(reader.filtered_count(), 0);
     }
+
+    #[test]
+    fn test_compressed_readers() {
+        let data = b"line 1\nline 2\n";
+        let zstd = zstd::encode_all(&data[..], 3).unwrap();
+        // Concatenated gzip members are read as one stream
+        let mut gzip_members = gzip(b"line 1\n");
+        gzip_members.extend(gzip(b"line 2\n"));
+
+        for compressed in [data.to_vec(), gzip(data), gzip_members, zstd, bzip2(data)] {
+            let len = compressed.len() as u64;
+            let mut reader =
+                InputReader::from_reader(std::io::Cursor::new(compressed), false).unwrap();
+            let lines: Vec<String> = reader.by_ref().map(|r| r.unwrap().text).collect();
+            assert_eq!(lines, ["line 1", "line 2"]);
+            assert_eq!(reader.bytes_read(), len);
+        }
+    }
+
+    #[test]
+    fn test_directory_and_glob_inputs() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("b.jsonl.gz"), gzip(b"b 1\nb 2\n")).unwrap();
+        std::fs::write(dir.join("a.jsonl"), b"a 1\n").unwrap();
+        std::fs::write(dir.join("c.jsonl.bz2"), bzip2(b"c 1\n")).unwrap();
+        std::fs::write(dir.join("notes.txt"), b"not events\n").unwrap();
+        std::fs::create_dir(dir.join("nested.jsonl")).unwrap();
+
+        let source = InputSource::resolve(dir.to_str().unwrap()).unwrap();
+        let total = source.total_bytes().unwrap();
+        assert_eq!(source.plain_file().unwrap(), None);
+        let mut reader = InputReader::from_source(source, false).unwrap();
+        let lines: Vec<String> = reader.by_ref().map(|r| r.unwrap().text).collect();
+        assert_eq!(lines, ["a 1", "b 1", "b 2", "c 1"]);
+        assert_eq!(reader.bytes_read(), total);
+
+        let pattern = dir.join("*.jsonl*");
+        let source = InputSource::resolve(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(
+            source,
+            InputSource::Files(vec![
+                dir.join("a.jsonl"),
+                dir.join("b.jsonl.gz"),
+                dir.join("c.jsonl.bz2"),
+            ])
+        );
+
+        let single = InputSource::resolve(dir.join("a.jsonl").to_str().unwrap()).unwrap();
+        assert_eq!(
+            single.plain_file().unwrap(),
+            Some(dir.join("a.jsonl").as_path())
+        );
+        assert_eq!(InputSource::resolve("-").unwrap(), InputSource::Stdin);
+        assert_eq!(InputSource::Stdin.total_bytes(), None);
+        assert!(InputSource::resolve(dir.join("*.bz").to_str().unwrap()).is_err());
+        assert!(InputSource::resolve(dir.join("nested.jsonl").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_line_locations() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let lines = [
+            r#"["EOSE","sub1"]"#,
+            r#"{"kind": 100000}"#,
+            r#"["EVENT","sub1",{"kind":1}]"#,
+        ];
+        std::fs::write(dir.join("a.jsonl"), lines.join("\n")).unwrap();
+        std::fs::write(dir.join("b.jsonl.gz"), gzip(b"\n{\"kind\":2}\n")).unwrap();
+
+        let source = InputSource::resolve(dir.to_str().unwrap()).unwrap();
+        let read: Vec<(String, Option<Arc<str>>, u64)> = InputReader::from_source(source, true)
+            .unwrap()
+            .map(|r| r.unwrap())
+            .map(|input| (input.text, input.file, input.line))
+            .collect();
+        let file = |name: &str| Some(Arc::from(dir.join(name).display().to_string()));
+        assert_eq!(
+            read,
+            [
+                (r#"{"kind":1}"#.to_string(), file("a.jsonl"), 3),
+                (String::new(), file("b.jsonl.gz"), 1),
+                (r#"{"kind":2}"#.to_string(), file("b.jsonl.gz"), 2),
+            ]
+        );
+
+        let mut stdin_like = InputReader::from_reader(&b"x\ny\n"[..], false).unwrap();
+        let second = stdin_like.nth(1).unwrap().unwrap();
+        assert_eq!((second.file, second.line), (None, 2));
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = r#"{"id":"abc","kind":1}"#;
+        assert_eq!(InputReader::event_json(event).unwrap(), event);
+        let wrapped = format!(r#"["EVENT","sub1",{}]"#, event);
+        assert_eq!(
+            InputReader::event_json(&wrapped).unwrap(),
+            r#"{"id":"abc","kind":1}"#
+        );
+        let from_client = format!(r#"["EVENT",{}]"#, event);
+        assert!(matches!(
+            InputReader::event_json(&from_client),
+            Some(Cow::Owned(_))
+        ));
+
+        assert_eq!(InputReader::event_json(r#"["EOSE","sub1"]"#), None);
+        assert_eq!(InputReader::event_json(r#"["OK","abc",true,""]"#), None);
+        // Malformed messages are passed on to fail parsing
+        for line in [r#"["EVENT","sub1"]"#, "[1,2]", "[not json"] {
+            assert_eq!(InputReader::event_json(line).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn test_relay_database_reader() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("relay.db");
+        let mut conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE event (id INTEGER PRIMARY KEY, content BLOB)")
+            .unwrap();
+        let count = SQLITE_BATCH_SIZE + 5;
+        let tx = conn.transaction().unwrap();
+        for i in 0..count {
+            tx.execute(
+                "INSERT INTO event (content) VALUES (?1)",
+                [format!(r#"["EVENT","s",{{"n":{}}}]"#, i)],
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+        conn.execute("UPDATE event SET content = X'7B7D' WHERE id = 1", [])
+            .unwrap();
+        drop(conn);
+
+        assert!(is_sqlite_file(&path).unwrap());
+        let source = InputSource::resolve(path.to_str().unwrap()).unwrap();
+        assert_eq!(source.plain_file().unwrap(), None);
+        assert_eq!(source.total_bytes(), None);
+
+        let lines: Vec<String> = InputReader::from_source(source, false)
+            .unwrap()
+            .map(|r| r.unwrap().text)
+            .collect();
+        assert_eq!(lines.len(), count);
+        assert_eq!(lines[0], "{}");
+        assert_eq!(lines[count - 1], format!(r#"{{"n":{}}}"#, count - 1));
+
+        let other = temp_dir.path().join("other.db");
+        Connection::open(&other)
+            .unwrap()
+            .execute_batch("CREATE TABLE events (json TEXT)")
+            .unwrap();
+        assert!(InputReader::with_options(other.to_str().unwrap(), false).is_err());
+    }
 }
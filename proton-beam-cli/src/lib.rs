@@ -2,9 +2,13 @@
 //!
 //! This library provides reusable components for the proton-beam CLI tool.
 
+pub mod checkpoint;
 pub mod input;
 pub mod progress;
+pub mod rejects;
+pub mod serve;
 pub mod storage;
+pub mod verify;
 
 #[cfg(feature = "s3")]
 pub mod s3;
@@ -2,54 +2,39 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
 use proton_beam_core::{
-    ProtoEvent, compute_event_hash, validate_basic_fields, validate_event_id_from_hash,
-    validate_signature_from_hash,
+    Codec, EventIndex, Filter, FormatVersion, ProtoEvent, compute_event_hash,
+    validate_basic_fields, validate_event_id_from_hash, validate_signature_from_hash,
 };
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
 use std::time::Duration;
 use tracing::{debug, error, info, warn};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 
 // Performance tuning constants
-const LINE_COUNT_BUFFER_SIZE: usize = 1024 * 1024; // 1MB for line counting
-const LINE_COUNT_READ_BUFFER: usize = 512 * 1024; // 512KB for read buffer
 // const FILE_READER_BUFFER_SIZE: usize = 1024 * 1024; // 1MB for file reading
 // const STORAGE_WRITER_BUFFER_SIZE: usize = 512 * 1024; // 512KB for writing
 const PROGRESS_UPDATE_INTERVAL: u64 = 1000; // Update progress every N lines
 const INDEX_BATCH_SIZE: usize = 5000; // Batch size for index operations
-
-fn count_lines(path: &Path) -> Result<u64> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::with_capacity(LINE_COUNT_BUFFER_SIZE, file);
-    let mut count = 0u64;
-    let mut buffer = [0u8; LINE_COUNT_READ_BUFFER];
-
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        count += bytecount::count(&buffer[..bytes_read], b'\n') as u64;
-    }
-
-    Ok(count)
-}
-
-mod input;
-mod progress;
-mod storage;
+const CHECKPOINT_INTERVAL: u64 = 256 * 1024 * 1024; // Checkpoint each chunk every 256MB of input
+const PIPELINE_BATCH_LINES: usize = 1000; // Lines handed to a conversion worker at a time
 
 #[cfg(feature = "s3")]
-mod s3;
+use proton_beam_cli::s3;
 
-use input::InputReader;
-use storage::{ErrorStats, LogErrorContext, StorageManager};
+use proton_beam_cli::checkpoint::{Checkpoint, ChunkProgress};
+use proton_beam_cli::input::{InputLine, InputReader, InputSource};
+use proton_beam_cli::rejects::{self, RejectWriter};
+use proton_beam_cli::serve::{self, ServeConfig};
+use proton_beam_cli::storage::{ErrorCategory, ErrorStats, LogErrorContext, StorageManager};
+use proton_beam_cli::verify::verify_archive;
 
 #[derive(Parser, Debug)]
 #[command(name = "proton-beam")]
@@ -64,7 +49,7 @@ struct Cli {
 enum Commands {
     /// Convert Nostr events from JSON to protobuf format
     Convert {
-        /// Input file path (.jsonl)
+        /// Input: a .jsonl file (optionally .gz, .zst or .bz2), a SQLite relay database, a directory, a glob, or - for stdin
         #[arg(value_name = "INPUT")]
         input: PathBuf,
 
@@ -111,6 +96,38 @@ enum Commands {
         /// Upload output files to S3 (format: s3://bucket/prefix)
         #[arg(long)]
         s3_output: Option<String>,
+
+        /// Only keep events matching a NIP-01 filter (JSON, e.g. '{"kinds":[0,1]}')
+        #[arg(long, value_name = "JSON", value_parser = parse_filter)]
+        filter: Option<Filter>,
+
+        /// Event format: v1 (hex strings) or v2 (binary id/pubkey/sig, ~160 bytes smaller)
+        #[arg(long, default_value_t = FormatVersion::V1)]
+        format: FormatVersion,
+
+        /// Compression codec: gzip (.pb.gz) or zstd (.pb.zst, much faster to decompress)
+        #[arg(long, default_value_t = Codec::Gzip)]
+        codec: Codec,
+
+        /// Trained zstd dictionary (see `train-dictionary`); needs --codec zstd
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Write each batch as an independently decodable block so single events can be fetched
+        #[arg(long)]
+        seekable: bool,
+
+        /// Honour NIP-09 deletion requests, tombstoning deleted events in OUTPUT_DIR/index.db
+        #[arg(long)]
+        process_deletions: bool,
+
+        /// Continue an interrupted parallel conversion from its checkpoint in OUTPUT_DIR/tmp
+        #[arg(long)]
+        resume: bool,
+
+        /// Write each rejected line with its error to this JSONL file (gzipped if it ends in .gz)
+        #[arg(long, value_name = "PATH")]
+        rejects: Option<PathBuf>,
     },
 
     /// Merge temporary protobuf files from a parallel conversion
@@ -130,6 +147,127 @@ enum Commands {
         /// Delete temp directory after successful merge
         #[arg(long)]
         cleanup: bool,
+
+        /// Event format of the merged files: v1 or v2
+        #[arg(long, default_value_t = FormatVersion::V1)]
+        format: FormatVersion,
+
+        /// Compression codec of the merged files: gzip or zstd
+        #[arg(long, default_value_t = Codec::Gzip)]
+        codec: Codec,
+
+        /// Trained zstd dictionary used by the conversion; needs --codec zstd
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Write independently decodable blocks so single events can be fetched
+        #[arg(long)]
+        seekable: bool,
+
+        /// Events per block with --seekable
+        #[arg(long, default_value_t = 1000, requires = "seekable")]
+        block_size: usize,
+
+        /// Honour NIP-09 deletion requests, tombstoning deleted events in OUTPUT_DIR/index.db
+        #[arg(long)]
+        process_deletions: bool,
+    },
+
+    /// Rewrite daily files without deleted or superseded events, sorted by created_at
+    Compact {
+        /// Directory containing protobuf files
+        #[arg(value_name = "PB_DIR", default_value = "./pb_data")]
+        pb_dir: PathBuf,
+
+        /// Path to SQLite index database (defaults to PB_DIR/index.db)
+        #[arg(long)]
+        index_path: Option<PathBuf>,
+
+        /// First day to compact (YYYY-MM-DD or Unix timestamp)
+        #[arg(long, value_parser = parse_since)]
+        since: Option<i64>,
+
+        /// Last day to compact (YYYY-MM-DD or Unix timestamp)
+        #[arg(long, value_parser = parse_until)]
+        until: Option<i64>,
+
+        /// Compression level of the rewritten files (0-9, default: 9)
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..=9), default_value_t = 9)]
+        compression_level: u32,
+
+        /// Event format of the rewritten files: v1 or v2
+        #[arg(long, default_value_t = FormatVersion::V1)]
+        format: FormatVersion,
+
+        /// Compression codec of the rewritten files (defaults to each file's current codec)
+        #[arg(long)]
+        codec: Option<Codec>,
+
+        /// Trained zstd dictionary the .pb.zst files were written with
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Write independently decodable blocks so single events can be fetched
+        #[arg(long)]
+        seekable: bool,
+
+        /// Events per block with --seekable
+        #[arg(long, default_value_t = 1000, requires = "seekable")]
+        block_size: usize,
+
+        /// Show detailed progress information
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Check every stored event and the index against the files, reporting problems as JSON
+    Verify {
+        /// Directory containing protobuf files
+        #[arg(value_name = "PB_DIR", default_value = "./pb_data")]
+        pb_dir: PathBuf,
+
+        /// Path to SQLite index database (defaults to PB_DIR/index.db, skipped if absent)
+        #[arg(long)]
+        index_path: Option<PathBuf>,
+
+        /// Validate Schnorr signatures (default: true)
+        #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+        validate_signatures: bool,
+
+        /// Trained zstd dictionary the .pb.zst files were written with
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Where to write the JSON report, or - for stdout
+        #[arg(short, long, default_value = "-")]
+        output: PathBuf,
+
+        /// Show detailed progress information
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Train a zstd dictionary on sample events for use with --codec zstd
+    TrainDictionary {
+        /// Sample files: JSONL events or protobuf files (.pb.gz, .pb.zst)
+        #[arg(value_name = "INPUT", required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Where to write the dictionary
+        #[arg(short, long, default_value = "./nostr.dict")]
+        output: PathBuf,
+
+        /// Maximum number of events to sample
+        #[arg(long, default_value_t = 100_000)]
+        samples: usize,
+
+        /// Maximum dictionary size in bytes
+        #[arg(long, default_value_t = 112_640)]
+        max_size: usize,
+
+        /// Event format the dictionary is trained for: v1 or v2
+        #[arg(long, default_value_t = FormatVersion::V1)]
+        format: FormatVersion,
     },
 
     /// Build or rebuild the event index from protobuf files
@@ -137,6 +275,85 @@ enum Commands {
         #[command(subcommand)]
         action: IndexAction,
     },
+
+    /// Export protobuf events back to NIP-01 JSONL
+    Export {
+        /// Protobuf files (.pb.gz, .pb.zst) or directories containing them
+        #[arg(value_name = "INPUT", default_value = "./pb_data")]
+        inputs: Vec<PathBuf>,
+
+        /// Output file, or - for stdout
+        #[arg(short, long, default_value = "-")]
+        output: PathBuf,
+
+        /// Gzip the output (implied by an output path ending in .gz)
+        #[arg(long)]
+        gzip: bool,
+
+        /// Only export events of this kind (repeatable)
+        #[arg(long = "kind", value_name = "KIND")]
+        kinds: Vec<u16>,
+
+        /// Only export events by this author (hex pubkey, repeatable)
+        #[arg(long = "author", value_name = "PUBKEY")]
+        authors: Vec<String>,
+
+        /// Only export events created on or after this date (YYYY-MM-DD or Unix timestamp)
+        #[arg(long, value_parser = parse_since)]
+        since: Option<i64>,
+
+        /// Only export events created on or before this date (YYYY-MM-DD or Unix timestamp)
+        #[arg(long, value_parser = parse_until)]
+        until: Option<i64>,
+
+        /// Only export events matching a NIP-01 filter (JSON); its limit caps the export
+        #[arg(long, value_name = "JSON", value_parser = parse_filter,
+              conflicts_with_all = ["kinds", "authors", "since", "until"])]
+        filter: Option<Filter>,
+
+        /// Look events up in the index instead of scanning every file (exports newest first)
+        #[arg(long)]
+        use_index: bool,
+
+        /// Path to SQLite index database (defaults to INPUT/index.db)
+        #[arg(long, requires = "use_index")]
+        index_path: Option<PathBuf>,
+
+        /// Trained zstd dictionary the .pb.zst files were written with
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Show detailed progress information
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Serve the archive to Nostr clients as a read-only relay (REQ, CLOSE, COUNT)
+    Serve {
+        /// Directory containing protobuf files
+        #[arg(value_name = "PB_DIR", default_value = "./pb_data")]
+        pb_dir: PathBuf,
+
+        /// Path to SQLite index database (defaults to PB_DIR/index.db)
+        #[arg(long)]
+        index_path: Option<PathBuf>,
+
+        /// Address to accept WebSocket connections on
+        #[arg(short, long, default_value = "127.0.0.1:7777")]
+        listen: SocketAddr,
+
+        /// Most events returned per filter, whatever limit the client asks for
+        #[arg(long, default_value_t = serve::DEFAULT_MAX_LIMIT)]
+        max_limit: usize,
+
+        /// Trained zstd dictionary the .pb.zst files were written with
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Show detailed progress information
+        #[arg(short, long)]
+        verbose: bool,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -158,6 +375,18 @@ enum IndexAction {
         /// Upload index to S3 (format: s3://bucket/prefix)
         #[arg(long)]
         s3_output: Option<String>,
+
+        /// Trained zstd dictionary the .pb.zst files were written with
+        #[arg(long, value_name = "PATH")]
+        zstd_dictionary: Option<PathBuf>,
+
+        /// Also index single-letter tags (e, p, t, ...) for tag queries; grows the index
+        #[arg(long)]
+        tags: bool,
+
+        /// Honour NIP-09 deletion requests, tombstoning the events they delete
+        #[arg(long)]
+        deletions: bool,
     },
 }
 
@@ -167,6 +396,7 @@ struct ConversionStats {
     valid_events: u64,
     invalid_events: u64,
     skipped_lines: u64,
+    filtered_events: u64,
 }
 
 impl ConversionStats {
@@ -176,6 +406,7 @@ impl ConversionStats {
             valid_events: 0,
             invalid_events: 0,
             skipped_lines: 0,
+            filtered_events: 0,
         }
     }
 
@@ -187,6 +418,9 @@ impl ConversionStats {
         if self.skipped_lines > 0 {
             println!("  ⏭️  Skipped lines:      {}", self.skipped_lines);
         }
+        if self.filtered_events > 0 {
+            println!("  🔎 Filtered out:       {}", self.filtered_events);
+        }
 
         let success_rate = if self.total_lines > 0 {
             (self.valid_events as f64 / self.total_lines as f64) * 100.0
@@ -212,15 +446,22 @@ async fn main() -> Result<()> {
             compression_level,
             verbose,
             cleanup,
+            format,
+            codec,
+            zstd_dictionary,
+            seekable,
+            block_size,
+            process_deletions,
         } => {
             // Initialize logging
             init_logging(verbose, &output_dir);
 
+            let zstd_dictionary = load_zstd_dictionary(zstd_dictionary.as_deref(), codec)?;
             let temp_dir = output_dir.join("tmp");
 
             if !temp_dir.exists() {
                 anyhow::bail!(
-                    "Temp directory does not exist: {}\nExpected to find thread_*.pb.gz.tmp files there.",
+                    "Temp directory does not exist: {}\nExpected to find thread_*.pb.{{gz,zst}}.tmp files there.",
                     temp_dir.display()
                 );
             }
@@ -228,15 +469,30 @@ async fn main() -> Result<()> {
             println!("🔄 Proton Beam - Merge Temporary Files");
             println!("   Output: {}", output_dir.display());
             println!("   Temp dir: {}", temp_dir.display());
-            println!("   Compression: {}", compression_level);
+            println!("   Compression: {} (level {})", codec, compression_level);
             println!();
 
             info!("Starting merge process...");
             info!("Output directory: {}", output_dir.display());
             info!("Temp directory: {}", temp_dir.display());
 
+            let mut index = if process_deletions {
+                Some(open_deletion_index(&output_dir)?)
+            } else {
+                None
+            };
+
             // Merge temporary files
-            merge_temp_files(&output_dir, &temp_dir, compression_level)?;
+            merge_temp_files(
+                &output_dir,
+                &temp_dir,
+                compression_level,
+                format,
+                codec,
+                zstd_dictionary.as_deref(),
+                seekable.then_some(block_size),
+                index.as_mut(),
+            )?;
 
             info!("Merge complete!");
             println!("\n✅ Merge complete!");
@@ -267,22 +523,34 @@ async fn main() -> Result<()> {
             no_filter_kinds,
             compression_level,
             s3_output,
+            filter,
+            format,
+            codec,
+            zstd_dictionary,
+            seekable,
+            process_deletions,
+            resume,
+            rejects,
         } => {
             // Apply no_filter_kinds flag
             let filter_invalid_kinds = filter_invalid_kinds && !no_filter_kinds;
+            let zstd_dictionary = load_zstd_dictionary(zstd_dictionary.as_deref(), codec)?;
             // Create output directory first (needed for log file)
             std::fs::create_dir_all(&output_dir).context("Failed to create output directory")?;
 
             // Initialize logging (creates log file in output_dir)
             init_logging(verbose, &output_dir);
 
+            let source =
+                InputSource::resolve(input.to_str().context("Input path is not valid UTF-8")?)?;
+
             // Check available disk space (warn if low)
-            let file_size = std::fs::metadata(&input).map(|m| m.len()).unwrap_or(0);
+            let file_size = source.total_bytes().unwrap_or(0);
             if file_size > 0 {
                 // Estimate output size (conservative: 30-50% of input depending on compression)
                 let estimated_output = file_size / 2;
                 info!(
-                    "Input file size: {:.2} GB, estimated output: {:.2} GB",
+                    "Input size: {:.2} GB, estimated output: {:.2} GB",
                     file_size as f64 / 1_000_000_000.0,
                     estimated_output as f64 / 1_000_000_000.0
                 );
@@ -298,6 +566,12 @@ async fn main() -> Result<()> {
                     .unwrap_or(1)
             });
 
+            // Only a single uncompressed file can be split into chunks
+            let plain_file = source.plain_file()?.map(Path::to_path_buf);
+            if resume && plain_file.is_none() {
+                anyhow::bail!("--resume needs a single uncompressed input file");
+            }
+
             // Log to file
             info!("Starting Proton Beam - Conversion");
             info!("Input: {}", input.display());
@@ -328,7 +602,23 @@ async fn main() -> Result<()> {
                     "disabled"
                 }
             );
-            info!("Compression level: {}", compression_level);
+            info!("Compression: {} (level {})", codec, compression_level);
+            if zstd_dictionary.is_some() {
+                info!("Using trained zstd dictionary");
+            }
+            info!("Event format: {}", format);
+            if seekable {
+                info!("Seekable blocks of up to {} events", batch_size);
+            }
+            if let Some(filter) = &filter {
+                info!("Event filter: {}", filter.to_json()?);
+            }
+            let index = if process_deletions {
+                info!("Processing NIP-09 deletions into the index");
+                Some(open_deletion_index(&output_dir)?)
+            } else {
+                None
+            };
 
             // Print clean startup message to stdout
             if !no_progress {
@@ -339,8 +629,17 @@ async fn main() -> Result<()> {
                 println!();
             }
 
-            // Run conversion (always parallel if num_threads > 1)
-            if num_threads > 1 {
+            // A resumed conversion rewrites it from the checkpointed rejects of each chunk
+            let rejects = match &rejects {
+                Some(path) => {
+                    info!("Writing rejected lines to {}", path.display());
+                    Some(Arc::new(Mutex::new(RejectWriter::create(path, false)?)))
+                }
+                None => None,
+            };
+
+            // Run conversion (always parallel if num_threads > 1; only chunked runs checkpoint)
+            let result = if let Some(input) = plain_file.filter(|_| num_threads > 1 || resume) {
                 convert_events_parallel(
                     &input,
                     &output_dir,
@@ -351,10 +650,37 @@ async fn main() -> Result<()> {
                     num_threads,
                     filter_invalid_kinds,
                     compression_level,
-                )?;
+                    format,
+                    codec,
+                    zstd_dictionary.as_deref(),
+                    seekable,
+                    filter.as_ref(),
+                    resume,
+                    index,
+                    rejects.as_ref(),
+                )
+            } else if num_threads > 1 {
+                convert_events_pipelined(
+                    source,
+                    &output_dir,
+                    validate_signatures,
+                    validate_event_ids,
+                    batch_size,
+                    !no_progress,
+                    num_threads,
+                    filter_invalid_kinds,
+                    compression_level,
+                    format,
+                    codec,
+                    zstd_dictionary.as_deref(),
+                    seekable,
+                    filter.as_ref(),
+                    index,
+                    rejects.as_ref(),
+                )
             } else {
                 convert_events(
-                    &input,
+                    source,
                     &output_dir,
                     validate_signatures,
                     validate_event_ids,
@@ -362,8 +688,27 @@ async fn main() -> Result<()> {
                     !no_progress,
                     filter_invalid_kinds,
                     compression_level,
-                )?;
+                    format,
+                    codec,
+                    zstd_dictionary.as_deref(),
+                    seekable,
+                    filter.as_ref(),
+                    index,
+                    rejects.as_ref(),
+                )
+            };
+
+            // Finish the rejects file even if the conversion failed
+            if let Some(rejects) = rejects {
+                let rejects = Arc::try_unwrap(rejects)
+                    .map_err(|_| anyhow::anyhow!("Rejects file is still in use"))?
+                    .into_inner()
+                    .unwrap();
+                let count = rejects.count();
+                rejects.finish()?;
+                info!("Wrote {} rejected lines", count);
             }
+            result?;
 
             // Upload to S3 if requested
             #[cfg(feature = "s3")]
@@ -385,29 +730,170 @@ async fn main() -> Result<()> {
             }
         }
 
+        Commands::Compact {
+            pb_dir,
+            index_path,
+            since,
+            until,
+            compression_level,
+            format,
+            codec,
+            zstd_dictionary,
+            seekable,
+            block_size,
+            verbose,
+        } => {
+            init_logging(verbose, &pb_dir);
+
+            let zstd_dictionary = load_zstd_dictionary(zstd_dictionary.as_deref(), Codec::Zstd)?;
+            let index_path = index_path.unwrap_or_else(|| pb_dir.join("index.db"));
+            if !index_path.exists() {
+                anyhow::bail!(
+                    "Index not found: {}\nBuild it first with: proton-beam index rebuild {} --deletions",
+                    index_path.display(),
+                    pb_dir.display()
+                );
+            }
+
+            info!("Starting Proton Beam - Compaction");
+            info!("Protobuf directory: {}", pb_dir.display());
+            info!("Index database: {}", index_path.display());
+
+            println!("🗜️  Proton Beam - Compacting Archive");
+            println!("   Source: {}", pb_dir.display());
+            println!("   Index: {}", index_path.display());
+            println!("   Compression level: {}", compression_level);
+            println!();
+
+            compact_archive(
+                &pb_dir,
+                &index_path,
+                since,
+                until,
+                compression_level,
+                format,
+                codec,
+                zstd_dictionary.as_deref(),
+                seekable.then_some(block_size),
+            )?;
+        }
+
+        Commands::Verify {
+            pb_dir,
+            index_path,
+            validate_signatures,
+            zstd_dictionary,
+            output,
+            verbose,
+        } => {
+            init_logging(verbose, &pb_dir);
+
+            let zstd_dictionary = load_zstd_dictionary(zstd_dictionary.as_deref(), Codec::Zstd)?;
+            let index_path = match index_path {
+                Some(index_path) if !index_path.exists() => {
+                    anyhow::bail!("Index not found: {}", index_path.display())
+                }
+                Some(index_path) => Some(index_path),
+                None => Some(pb_dir.join("index.db")).filter(|path| path.exists()),
+            };
+            let index = index_path
+                .map(|index_path| {
+                    EventIndex::new(&index_path)
+                        .context(format!("Failed to open index: {}", index_path.display()))
+                })
+                .transpose()?;
+
+            info!("Starting Proton Beam - Verification");
+            info!("Protobuf directory: {}", pb_dir.display());
+            if index.is_none() {
+                warn!("No index found, checking the event files only");
+            }
+
+            let report = verify_archive(
+                &pb_dir,
+                index.as_ref(),
+                zstd_dictionary.as_deref(),
+                validate_signatures,
+            )?;
+
+            let json = serde_json::to_string_pretty(&report)?;
+            if output == Path::new("-") {
+                println!("{}", json);
+            } else {
+                fs::write(&output, json + "\n")
+                    .context(format!("Failed to write report: {}", output.display()))?;
+            }
+
+            // stdout may be the report itself, so summarize on stderr
+            eprintln!(
+                "Verified {} events in {} files: {} corrupted frames, {} invalid events, {} orphaned index rows, {} missing index rows",
+                report.events,
+                report.files,
+                report.corrupted_frames.len(),
+                report.invalid_events.len(),
+                report.orphaned_index_rows.len(),
+                report.missing_index_rows.len()
+            );
+            info!("Verification complete: {} problems", report.problems());
+            if report.problems() > 0 {
+                anyhow::bail!("Archive verification found {} problems", report.problems());
+            }
+        }
+
+        Commands::TrainDictionary {
+            inputs,
+            output,
+            samples,
+            max_size,
+            format,
+        } => {
+            println!("📚 Proton Beam - Training zstd Dictionary");
+            println!("   Output: {}", output.display());
+            println!();
+
+            train_dictionary(&inputs, &output, samples, max_size, format)?;
+        }
+
         Commands::Index { action } => match action {
             IndexAction::Rebuild {
                 pb_dir,
                 index_path,
                 verbose,
                 s3_output,
+                zstd_dictionary,
+                tags,
+                deletions,
             } => {
                 // Initialize logging
                 init_logging(verbose, &pb_dir);
 
+                let zstd_dictionary =
+                    load_zstd_dictionary(zstd_dictionary.as_deref(), Codec::Zstd)?;
+
                 // Determine index path
                 let index_path = index_path.unwrap_or_else(|| pb_dir.join("index.db"));
 
                 info!("Starting Proton Beam - Index Rebuild");
                 info!("Protobuf directory: {}", pb_dir.display());
                 info!("Index database: {}", index_path.display());
+                info!("Tag index: {}", if tags { "enabled" } else { "disabled" });
+                info!(
+                    "Deletion processing: {}",
+                    if deletions { "enabled" } else { "disabled" }
+                );
 
                 println!("🔍 Proton Beam - Rebuilding Event Index");
                 println!("   Source: {}", pb_dir.display());
                 println!("   Index: {}", index_path.display());
                 println!();
 
-                rebuild_index(&pb_dir, &index_path)?;
+                rebuild_index(
+                    &pb_dir,
+                    &index_path,
+                    zstd_dictionary.as_deref(),
+                    tags,
+                    deletions,
+                )?;
 
                 // Upload to S3 if requested
                 #[cfg(feature = "s3")]
@@ -431,11 +917,171 @@ async fn main() -> Result<()> {
                 }
             }
         },
+
+        Commands::Export {
+            inputs,
+            output,
+            gzip,
+            kinds,
+            authors,
+            since,
+            until,
+            filter,
+            use_index,
+            index_path,
+            zstd_dictionary,
+            verbose,
+        } => {
+            let log_dir = match inputs.first() {
+                Some(input) if input.is_dir() => input.clone(),
+                Some(input) => input.parent().unwrap_or(Path::new(".")).to_path_buf(),
+                None => PathBuf::from("."),
+            };
+            init_logging(verbose, &log_dir);
+
+            let zstd_dictionary = load_zstd_dictionary(zstd_dictionary.as_deref(), Codec::Zstd)?;
+            let filter = filter.unwrap_or(Filter {
+                kinds,
+                authors,
+                since,
+                until,
+                ..Filter::default()
+            });
+            let gzip = gzip || output.extension().is_some_and(|ext| ext == "gz");
+            let source = if use_index {
+                let [pb_dir] = inputs.as_slice() else {
+                    anyhow::bail!("--use-index takes a single protobuf directory");
+                };
+                let index_path = index_path.unwrap_or_else(|| pb_dir.join("index.db"));
+                ExportSource::Index {
+                    pb_dir: pb_dir.clone(),
+                    index_path,
+                }
+            } else {
+                ExportSource::Files(inputs)
+            };
+
+            info!("Starting Proton Beam - Export");
+            info!("Output: {}", output.display());
+            info!("Event filter: {}", filter.to_json()?);
+
+            let exported =
+                export_events(&source, &output, gzip, &filter, zstd_dictionary.as_deref())?;
+
+            // stdout may be the export itself, so report on stderr
+            eprintln!("✅ Exported {} events", exported);
+            info!("Export complete: {} events", exported);
+        }
+
+        Commands::Serve {
+            pb_dir,
+            index_path,
+            listen,
+            max_limit,
+            zstd_dictionary,
+            verbose,
+        } => {
+            init_logging(verbose, &pb_dir);
+
+            let zstd_dictionary = load_zstd_dictionary(zstd_dictionary.as_deref(), Codec::Zstd)?;
+            let index_path = index_path.unwrap_or_else(|| pb_dir.join("index.db"));
+            if !index_path.exists() {
+                anyhow::bail!(
+                    "Index not found: {}\nBuild it first with: proton-beam index rebuild {}",
+                    index_path.display(),
+                    pb_dir.display()
+                );
+            }
+
+            let listener = tokio::net::TcpListener::bind(listen)
+                .await
+                .context(format!("Failed to listen on {}", listen))?;
+
+            info!("Starting Proton Beam - Relay");
+            info!("Protobuf directory: {}", pb_dir.display());
+            info!("Index database: {}", index_path.display());
+            info!("Listening on {}", listen);
+
+            println!("📡 Proton Beam - Serving Archive as a Read-Only Relay");
+            println!("   Source: {}", pb_dir.display());
+            println!("   Index: {}", index_path.display());
+            println!("   Listening: ws://{}", listen);
+            println!();
+
+            let config = ServeConfig {
+                index_path,
+                data_dir: pb_dir,
+                zstd_dictionary,
+                max_limit,
+            };
+            tokio::select! {
+                result = serve::serve(listener, Arc::new(config)) => result?,
+                _ = tokio::signal::ctrl_c() => {
+                    info!("Shutting down relay");
+                    println!("\n👋 Relay stopped");
+                }
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Open the index of an output directory with NIP-09 deletion processing on
+fn open_deletion_index(output_dir: &Path) -> Result<EventIndex> {
+    std::fs::create_dir_all(output_dir).context("Failed to create output directory")?;
+    let index_path = output_dir.join("index.db");
+    let mut index = EventIndex::new(&index_path).context(format!(
+        "Failed to open event index: {}",
+        index_path.display()
+    ))?;
+    if !index.deletion_processing_enabled() {
+        index
+            .set_deletion_processing(true)
+            .context("Failed to enable deletion processing")?;
+    }
+    Ok(index)
+}
+
+/// Read a trained zstd dictionary, which only makes sense with `codec` zstd
+fn load_zstd_dictionary(path: Option<&Path>, codec: Codec) -> Result<Option<Vec<u8>>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    if codec != Codec::Zstd {
+        anyhow::bail!("--zstd-dictionary requires --codec zstd");
+    }
+    let dictionary = std::fs::read(path).context(format!(
+        "Failed to read zstd dictionary: {}",
+        path.display()
+    ))?;
+    Ok(Some(dictionary))
+}
+
+fn parse_filter(json: &str) -> std::result::Result<Filter, String> {
+    Filter::try_from(json).map_err(|e| format!("invalid filter: {}", e))
+}
+
+/// Parse a Unix timestamp, or a YYYY-MM-DD date as the start of that day (UTC)
+fn parse_since(value: &str) -> std::result::Result<i64, String> {
+    parse_date(value, chrono::NaiveTime::MIN)
+}
+
+/// Parse a Unix timestamp, or a YYYY-MM-DD date as the end of that day (UTC)
+fn parse_until(value: &str) -> std::result::Result<i64, String> {
+    let end_of_day = chrono::NaiveTime::from_hms_opt(23, 59, 59).expect("valid time");
+    parse_date(value, end_of_day)
+}
+
+fn parse_date(value: &str, time: chrono::NaiveTime) -> std::result::Result<i64, String> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(time).and_utc().timestamp())
+        .map_err(|_| format!("expected YYYY-MM-DD or a Unix timestamp, got '{}'", value))
+}
+
 static LOG_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
 
 fn init_logging(verbose: bool, output_dir: &Path) {
@@ -519,7 +1165,7 @@ fn init_logging(verbose: bool, output_dir: &Path) {
 
 #[allow(clippy::too_many_arguments)]
 fn convert_events(
-    input: &Path,
+    source: InputSource,
     output_dir: &Path,
     validate_signatures: bool,
     validate_event_ids: bool,
@@ -527,64 +1173,55 @@ fn convert_events(
     show_progress: bool,
     filter_invalid_kinds: bool,
     compression_level: u32,
+    format: FormatVersion,
+    codec: Codec,
+    zstd_dictionary: Option<&[u8]>,
+    seekable: bool,
+    filter: Option<&Filter>,
+    index: Option<EventIndex>,
+    rejects: Option<&Arc<Mutex<RejectWriter>>>,
 ) -> Result<()> {
     // Create output directory if it doesn't exist
     std::fs::create_dir_all(output_dir).context("Failed to create output directory")?;
 
     // Initialize storage manager
-    let mut storage = StorageManager::new(output_dir, batch_size, compression_level)?;
+    let mut storage = StorageManager::new(output_dir, batch_size, compression_level)?
+        .with_format(format)
+        .with_codec(codec)
+        .with_seekable(seekable);
+    if let Some(dictionary) = zstd_dictionary {
+        storage = storage.with_zstd_dictionary(dictionary.to_vec());
+    }
+    if let Some(index) = index {
+        storage = storage.with_index(index);
+    }
+    if let Some(rejects) = rejects {
+        storage = storage.with_rejects(Arc::clone(rejects));
+    }
 
     // Initialize input reader with preprocessing options
-    let mut reader = InputReader::with_options(input.to_str().unwrap(), filter_invalid_kinds)?;
+    let total_bytes = source.total_bytes().unwrap_or(0);
+    let mut reader = InputReader::from_source(source, filter_invalid_kinds)?;
+    let bytes_read = reader.byte_counter();
 
-    // Count total lines for progress bar
-    let total_lines = if show_progress {
-        count_lines(input).unwrap_or(0)
-    } else {
-        0
-    };
-
-    // Set up progress bar
-    let progress = if show_progress && total_lines > 0 {
-        let pb = ProgressBar::new(total_lines);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
-                .unwrap()
-                .progress_chars("█▓▒░ ")
-                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
-        );
-        pb.enable_steady_tick(Duration::from_millis(100));
-        Some(pb)
-    } else if show_progress {
-        // Fallback to spinner if we can't count lines
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} [{elapsed_precise}] {msg}")
-                .unwrap()
-                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
-        );
-        pb.enable_steady_tick(Duration::from_millis(100));
-        Some(pb)
-    } else {
-        None
-    };
+    // Set up progress bar (track by input bytes read, before decompression)
+    let progress = show_progress.then(|| input_progress_bar(total_bytes));
 
     let mut stats = ConversionStats::new();
 
     // Process each line
-    for (line_num, line_result) in reader.by_ref().enumerate() {
+    for line_result in reader.by_ref() {
         stats.total_lines += 1;
 
-        let line = match line_result {
-            Ok(line) => line,
+        let input = match line_result {
+            Ok(input) => input,
             Err(e) => {
-                error!("Failed to read line {}: {}", line_num + 1, e);
+                error!("{:#}", e);
                 stats.skipped_lines += 1;
                 continue;
             }
         };
+        let line = input.text;
 
         // Skip empty lines
         if line.trim().is_empty() {
@@ -594,97 +1231,36 @@ fn convert_events(
 
         // Update progress
         if let Some(ref pb) = progress {
-            pb.set_position(stats.total_lines);
+            pb.set_position(bytes_read.load(Ordering::Relaxed));
             pb.set_message(format!(
                 "Valid: {} | Errors: {}",
                 stats.valid_events, stats.invalid_events
             ));
         }
 
-        // Parse JSON to ProtoEvent
-        let event = match ProtoEvent::try_from(line.as_str()) {
-            Ok(event) => event,
-            Err(e) => {
-                storage.log_error(
-                    LogErrorContext::from_line((line_num + 1) as u64),
-                    &format!("parse_error: {}", e),
-                    None,
-                );
-                stats.invalid_events += 1;
-                continue;
-            }
-        };
-
-        // Validate basic fields first (fast check)
-        if let Err(e) = validate_basic_fields(&event) {
-            storage.log_error(
-                LogErrorContext::from_line((line_num + 1) as u64),
-                &format!("validation_error: {}", e),
-                Some(&event.id),
-            );
-            stats.invalid_events += 1;
-            continue;
-        }
-
-        // Compute hash once and reuse for both validations if needed
-        if validate_signatures || validate_event_ids {
-            let hash = match compute_event_hash(&event) {
-                Ok(h) => h,
-                Err(e) => {
-                    storage.log_error(
-                        LogErrorContext::from_line((line_num + 1) as u64),
-                        &format!("hash_error: {}", e),
-                        Some(&event.id),
-                    );
-                    stats.invalid_events += 1;
-                    continue;
-                }
-            };
-
-            if validate_event_ids && let Err(e) = validate_event_id_from_hash(&event, &hash) {
-                storage.log_error(
-                    LogErrorContext::from_line((line_num + 1) as u64),
-                    &format!("validation_error: {}", e),
-                    Some(&event.id),
-                );
-                stats.invalid_events += 1;
-                continue;
-            }
-
-            if validate_signatures && let Err(e) = validate_signature_from_hash(&event, &hash) {
-                storage.log_error(
-                    LogErrorContext::from_line((line_num + 1) as u64),
-                    &format!("validation_error: {}", e),
-                    Some(&event.id),
-                );
-                stats.invalid_events += 1;
-                continue;
-            }
-        }
-
-        // Store the event
-        match storage.store_event(event) {
-            Ok(_) => {
-                stats.valid_events += 1;
-                debug!("Successfully stored event from line {}", line_num + 1);
-            }
-            Err(e) => {
-                error!("Failed to store event from line {}: {}", line_num + 1, e);
-                storage.log_error(
-                    LogErrorContext::from_line((line_num + 1) as u64),
-                    &format!("storage_error: {}", e),
-                    None,
-                );
-                stats.invalid_events += 1;
-            }
+        let outcome = convert_line(
+            &line,
+            &mut storage,
+            LogErrorContext::from_line(input.line).with_file(input.file.as_deref()),
+            validate_signatures,
+            validate_event_ids,
+            filter,
+        );
+        match outcome {
+            LineOutcome::Stored => stats.valid_events += 1,
+            LineOutcome::Invalid => stats.invalid_events += 1,
+            LineOutcome::Filtered => stats.filtered_events += 1,
         }
     }
 
     // Flush any remaining events
     storage.flush()?;
 
-    // Get filtered count from reader
+    // Get filtered count from reader; relay messages without an event count as skipped
     let filtered_count = reader.filtered_count();
+    let skipped_messages = reader.skipped_messages() as u64;
+    stats.total_lines += skipped_messages;
+    stats.skipped_lines += skipped_messages;
 
     // Clean up progress bar
     if let Some(pb) = progress {
@@ -714,14 +1290,380 @@ fn convert_events(
     stats.print_summary(Some(error_stats));
 
     // Exit code: 0 if any events succeeded, 1 if all failed
-    if stats.valid_events == 0 && stats.total_lines > 0 {
-        std::process::exit(1);
+    if stats.valid_events == 0 && stats.total_lines > stats.filtered_events {
+        return Err(anyhow::anyhow!(
+            "Conversion failed: no valid events processed"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Progress bar tracking input bytes read, before decompression
+///
+/// Falls back to a spinner when the input size is unknown (`total_bytes` is 0).
+fn input_progress_bar(total_bytes: u64) -> ProgressBar {
+    let pb = if total_bytes > 0 {
+        let pb = ProgressBar::new(total_bytes);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent}%) {msg}")
+                .unwrap()
+                .progress_chars("█▓▒░ ")
+                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+        );
+        pb
+    } else {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} [{elapsed_precise}] {bytes} {msg}")
+                .unwrap()
+                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+        );
+        pb
+    };
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb
+}
+
+/// What became of the event on one input line
+enum LineOutcome {
+    Stored,
+    Invalid,
+    Filtered,
+}
+
+/// Parse, filter, validate and store the event on one input line
+///
+/// Failures are logged to `storage` with `context`.
+fn convert_line(
+    line: &str,
+    storage: &mut StorageManager,
+    context: LogErrorContext,
+    validate_signatures: bool,
+    validate_event_ids: bool,
+    filter: Option<&Filter>,
+) -> LineOutcome {
+    let event = match ProtoEvent::try_from(line) {
+        Ok(event) => event,
+        Err(e) => {
+            storage.reject(
+                context,
+                ErrorCategory::from_error(&e),
+                &format!("parse_error: {}", e),
+                None,
+                line,
+            );
+            return LineOutcome::Invalid;
+        }
+    };
+
+    if let Some(filter) = filter
+        && !filter.matches(&event)
+    {
+        return LineOutcome::Filtered;
+    }
+
+    if let Err(e) = validate_basic_fields(&event) {
+        storage.reject(
+            context,
+            ErrorCategory::from_error(&e),
+            &format!("validation_error: {}", e),
+            Some(&event.id),
+            line,
+        );
+        return LineOutcome::Invalid;
+    }
+
+    if validate_signatures || validate_event_ids {
+        let hash = match compute_event_hash(&event) {
+            Ok(h) => h,
+            Err(e) => {
+                storage.reject(
+                    context,
+                    ErrorCategory::HashError,
+                    &format!("hash_error: {}", e),
+                    Some(&event.id),
+                    line,
+                );
+                return LineOutcome::Invalid;
+            }
+        };
+
+        let validation = if validate_event_ids {
+            validate_event_id_from_hash(&event, &hash)
+        } else {
+            Ok(())
+        }
+        .and_then(|()| {
+            if validate_signatures {
+                validate_signature_from_hash(&event, &hash)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = validation {
+            storage.reject(
+                context,
+                ErrorCategory::from_error(&e),
+                &format!("validation_error: {}", e),
+                Some(&event.id),
+                line,
+            );
+            return LineOutcome::Invalid;
+        }
+    }
+
+    if let Err(e) = storage.store_event(event) {
+        error!("Failed to store event from line {}: {}", context.line, e);
+        storage.reject(
+            context,
+            ErrorCategory::StorageError,
+            &format!("storage_error: {}", e),
+            None,
+            line,
+        );
+        return LineOutcome::Invalid;
+    }
+    LineOutcome::Stored
+}
+
+/// Parallel version of convert_events for inputs that cannot be split into chunks
+///
+/// Compressed files, stdin, relay databases and multi-file inputs are read
+/// on the calling thread, which hands their lines out in batches of
+/// [`PIPELINE_BATCH_LINES`] to `num_threads` workers. The workers parse,
+/// validate and store events into their own temp files, which are merged as
+/// in [`convert_events_parallel`]. Nothing is checkpointed.
+#[allow(clippy::too_many_arguments)]
+fn convert_events_pipelined(
+    source: InputSource,
+    output_dir: &Path,
+    validate_signatures: bool,
+    validate_event_ids: bool,
+    batch_size: usize,
+    show_progress: bool,
+    num_threads: usize,
+    filter_invalid_kinds: bool,
+    compression_level: u32,
+    format: FormatVersion,
+    codec: Codec,
+    zstd_dictionary: Option<&[u8]>,
+    seekable: bool,
+    filter: Option<&Filter>,
+    mut index: Option<EventIndex>,
+    rejects: Option<&Arc<Mutex<RejectWriter>>>,
+) -> Result<()> {
+    // Create output directory if it doesn't exist
+    std::fs::create_dir_all(output_dir).context("Failed to create output directory")?;
+
+    // Create temp directory for the workers' writes
+    let temp_dir = output_dir.join("tmp");
+    if Checkpoint::path(&temp_dir).exists() {
+        anyhow::bail!(
+            "{} holds the checkpoint of an interrupted conversion\nContinue it with --resume, or remove the directory to start over",
+            temp_dir.display()
+        );
+    }
+    std::fs::create_dir_all(&temp_dir).context("Failed to create temp directory")?;
+
+    // Set up every worker's storage up front, so workers cannot fail to start
+    let storages = (0..num_threads)
+        .map(|worker_id| {
+            let mut storage = StorageManager::new_with_prefix(
+                &temp_dir,
+                batch_size,
+                worker_id,
+                compression_level,
+            )?
+            .with_format(format)
+            .with_codec(codec);
+            if let Some(dictionary) = zstd_dictionary {
+                storage = storage.with_zstd_dictionary(dictionary.to_vec());
+            }
+            if let Some(rejects) = rejects {
+                storage = storage.with_rejects(Arc::clone(rejects));
+            }
+            Ok(storage)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let total_bytes = source.total_bytes().unwrap_or(0);
+    let mut reader = InputReader::from_source(source, filter_invalid_kinds)?;
+    let bytes_read = reader.byte_counter();
+    let progress = show_progress.then(|| input_progress_bar(total_bytes));
+    info!("Converting with {} worker threads", num_threads);
+
+    // The reader counts lines; workers count what became of their events
+    let mut stats = ConversionStats::new();
+    let valid_events = AtomicU64::new(0);
+    let invalid_events = AtomicU64::new(0);
+    let filtered_events = AtomicU64::new(0);
+
+    // Bounded, so reading stays at most a few batches ahead of the workers.
+    // Workers own the receiver, so if they all exit, sending fails instead of blocking.
+    let (sender, receiver) = mpsc::sync_channel::<Vec<InputLine>>(num_threads * 2);
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let results: Vec<Result<ErrorStats>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = storages
+            .into_iter()
+            .enumerate()
+            .map(|(worker_id, mut storage)| {
+                let receiver = Arc::clone(&receiver);
+                let valid_events = &valid_events;
+                let invalid_events = &invalid_events;
+                let filtered_events = &filtered_events;
+                scope.spawn(move || {
+                    loop {
+                        // Release the lock before converting, so others can take a batch
+                        let batch = receiver.lock().unwrap().recv();
+                        let Ok(batch) = batch else {
+                            break;
+                        };
+                        for input in batch {
+                            let counter = match convert_line(
+                                &input.text,
+                                &mut storage,
+                                LogErrorContext::new(input.line, worker_id)
+                                    .with_file(input.file.as_deref()),
+                                validate_signatures,
+                                validate_event_ids,
+                                filter,
+                            ) {
+                                LineOutcome::Stored => valid_events,
+                                LineOutcome::Invalid => invalid_events,
+                                LineOutcome::Filtered => filtered_events,
+                            };
+                            counter.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    storage.close()?;
+                    Ok(storage.clone_error_stats())
+                })
+            })
+            .collect();
+        drop(receiver);
+
+        let mut batch = Vec::with_capacity(PIPELINE_BATCH_LINES);
+        for line_result in reader.by_ref() {
+            stats.total_lines += 1;
+
+            let input = match line_result {
+                Ok(input) => input,
+                Err(e) => {
+                    error!("{:#}", e);
+                    stats.skipped_lines += 1;
+                    continue;
+                }
+            };
+
+            // Skip empty lines
+            if input.text.trim().is_empty() {
+                stats.skipped_lines += 1;
+                continue;
+            }
+
+            batch.push(input);
+            if batch.len() == PIPELINE_BATCH_LINES {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(PIPELINE_BATCH_LINES));
+                if sender.send(full).is_err() {
+                    break;
+                }
+
+                if let Some(ref pb) = progress {
+                    pb.set_position(bytes_read.load(Ordering::Relaxed));
+                    pb.set_message(format!(
+                        "Valid: {} | Errors: {}",
+                        valid_events.load(Ordering::Relaxed),
+                        invalid_events.load(Ordering::Relaxed)
+                    ));
+                }
+            }
+        }
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+        // Closing the channel lets the workers finish
+        drop(sender);
+
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Conversion worker panicked")))
+            })
+            .collect()
+    });
+
+    // Relay messages without an event count as skipped
+    let filtered_count = reader.filtered_count();
+    let skipped_messages = reader.skipped_messages() as u64;
+    stats.total_lines += skipped_messages;
+    stats.skipped_lines += skipped_messages;
+    stats.valid_events = valid_events.load(Ordering::Relaxed);
+    stats.invalid_events = invalid_events.load(Ordering::Relaxed);
+    stats.filtered_events = filtered_events.load(Ordering::Relaxed);
+
+    let mut merged_error_stats = ErrorStats::new();
+    for result in results {
+        let error_stats = result.context(format!(
+            "Conversion worker failed; salvage its output with 'proton-beam merge {}'",
+            output_dir.display()
+        ))?;
+        merged_error_stats.merge(&error_stats);
+    }
+
+    if let Some(pb) = progress {
+        pb.finish_with_message("Merging temporary files...");
+    }
+    if filtered_count > 0 {
+        info!(
+            "Pre-filtered {} events with invalid kind values",
+            filtered_count
+        );
+    }
+
+    info!("All lines converted, merging temporary files...");
+
+    // Merge temporary files; blocks are only cut while merging
+    if let Err(e) = merge_temp_files(
+        output_dir,
+        &temp_dir,
+        compression_level,
+        format,
+        codec,
+        zstd_dictionary,
+        seekable.then_some(batch_size),
+        index.as_mut(),
+    ) {
+        error!("Failed to merge temp files: {:?}", e);
+        return Err(e).context("Failed to merge temporary files");
+    }
+
+    // Clean up temp directory
+    std::fs::remove_dir_all(&temp_dir).context("Failed to remove temp directory")?;
+
+    info!("Merge complete");
+    stats.print_summary(Some(&merged_error_stats));
+
+    // Exit code: 0 if any events succeeded, 1 if all failed
+    if stats.valid_events == 0 && stats.total_lines > stats.filtered_events {
+        return Err(anyhow::anyhow!(
+            "Conversion failed: no valid events processed"
+        ));
     }
 
     Ok(())
 }
 
 /// Parallel version of convert_events using file chunking
+///
+/// Progress is checkpointed to `OUTPUT_DIR/tmp` as chunks go. With `resume`,
+/// the chunks of the checkpointed conversion are picked up where they left
+/// off instead of starting over.
 #[allow(clippy::too_many_arguments)]
 fn convert_events_parallel(
     input: &Path,
@@ -733,6 +1675,14 @@ fn convert_events_parallel(
     num_threads: usize,
     filter_invalid_kinds: bool,
     compression_level: u32,
+    format: FormatVersion,
+    codec: Codec,
+    zstd_dictionary: Option<&[u8]>,
+    seekable: bool,
+    filter: Option<&Filter>,
+    resume: bool,
+    mut index: Option<EventIndex>,
+    rejects: Option<&Arc<Mutex<RejectWriter>>>,
 ) -> Result<()> {
     // Create output directory if it doesn't exist
     std::fs::create_dir_all(output_dir).context("Failed to create output directory")?;
@@ -746,18 +1696,70 @@ fn convert_events_parallel(
     let valid_events = Arc::new(AtomicU64::new(0));
     let invalid_events = Arc::new(AtomicU64::new(0));
     let skipped_lines = Arc::new(AtomicU64::new(0));
+    let filtered_events = Arc::new(AtomicU64::new(0));
     let bytes_processed = Arc::new(AtomicU64::new(0));
 
     // Get file size for progress bar
     let file_size = std::fs::metadata(input)?.len();
+    let input_path = std::fs::canonicalize(input).context("Failed to resolve input path")?;
+
+    // When resuming, chunks come from the checkpoint so they match the temp files
+    let checkpoint = if resume {
+        let checkpoint = Checkpoint::load(&temp_dir)?.context(format!(
+            "No checkpoint to resume from in {}",
+            temp_dir.display()
+        ))?;
+        checkpoint.check_input(&input_path, file_size, codec)?;
+        for (chunk, progress) in checkpoint.chunks.iter().enumerate() {
+            if !progress.done {
+                progress.restore_files(&temp_dir, chunk)?;
+            }
+        }
+        info!(
+            "Resuming from checkpoint: {}/{} chunks already converted",
+            checkpoint.chunks.iter().filter(|c| c.done).count(),
+            checkpoint.chunks.len()
+        );
+        checkpoint
+    } else {
+        if Checkpoint::path(&temp_dir).exists() {
+            anyhow::bail!(
+                "{} holds the checkpoint of an interrupted conversion\nContinue it with --resume, or remove the directory to start over",
+                temp_dir.display()
+            );
+        }
 
-    // Find chunk boundaries
-    info!(
-        "Calculating chunk boundaries for {} threads...",
-        num_threads
-    );
-    let chunks = find_chunk_boundaries(input, num_threads)?;
-    info!("Processing {} chunks in parallel", chunks.len());
+        // Find chunk boundaries
+        info!(
+            "Calculating chunk boundaries for {} threads...",
+            num_threads
+        );
+        let chunks = find_chunk_boundaries(input, num_threads)?;
+        let checkpoint = Checkpoint::new(&input_path, file_size, codec, &chunks);
+        checkpoint.save(&temp_dir)?;
+        checkpoint
+    };
+
+    // Count what was converted before the checkpoint
+    for progress in &checkpoint.chunks {
+        total_lines.fetch_add(progress.lines, Ordering::Relaxed);
+        valid_events.fetch_add(progress.valid_events, Ordering::Relaxed);
+        invalid_events.fetch_add(progress.invalid_events, Ordering::Relaxed);
+        skipped_lines.fetch_add(progress.skipped_lines, Ordering::Relaxed);
+        filtered_events.fetch_add(progress.filtered_events, Ordering::Relaxed);
+        bytes_processed.fetch_add(progress.position - progress.start, Ordering::Relaxed);
+    }
+
+    let chunk_count = checkpoint.chunks.len();
+    let pending: Vec<(usize, ChunkProgress)> = checkpoint
+        .chunks
+        .iter()
+        .cloned()
+        .enumerate()
+        .filter(|(_, progress)| !progress.done)
+        .collect();
+    let checkpoint = Mutex::new(checkpoint);
+    info!("Processing {} chunks in parallel", pending.len());
 
     // Progress bar (track by bytes processed for parallel mode)
     let progress = if show_progress {
@@ -769,6 +1771,7 @@ fn convert_events_parallel(
                 .progress_chars("█▓▒░ ")
                 .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
         );
+        pb.set_position(bytes_processed.load(Ordering::Relaxed));
         pb.enable_steady_tick(Duration::from_millis(100));
         Some(Arc::new(pb))
     } else {
@@ -780,13 +1783,16 @@ fn convert_events_parallel(
     let error_stats_list: Arc<Mutex<Vec<ErrorStats>>> = Arc::new(Mutex::new(Vec::new()));
 
     rayon::scope(|scope| {
-        for (thread_id, (start, end)) in chunks.into_iter().enumerate() {
+        for (thread_id, chunk) in pending {
+            let (start, end) = (chunk.start, chunk.end);
+            let checkpoint = &checkpoint;
             let input = input.to_path_buf();
             let temp_dir = temp_dir.clone();
             let total_lines = Arc::clone(&total_lines);
             let valid_events = Arc::clone(&valid_events);
             let invalid_events = Arc::clone(&invalid_events);
             let skipped_lines = Arc::clone(&skipped_lines);
+            let filtered_events = Arc::clone(&filtered_events);
             let bytes_processed = Arc::clone(&bytes_processed);
             let progress = progress.as_ref().map(Arc::clone);
             let errors = Arc::clone(&parallel_errors);
@@ -796,13 +1802,14 @@ fn convert_events_parallel(
                 match process_chunk(
                     thread_id,
                     &input,
-                    start,
-                    end,
+                    chunk,
+                    checkpoint,
                     temp_dir.as_path(),
                     total_lines,
                     valid_events,
                     invalid_events,
                     skipped_lines,
+                    filtered_events,
                     bytes_processed,
                     progress,
                     validate_signatures,
@@ -810,6 +1817,11 @@ fn convert_events_parallel(
                     batch_size,
                     filter_invalid_kinds,
                     compression_level,
+                    format,
+                    codec,
+                    zstd_dictionary,
+                    filter,
+                    rejects.is_some(),
                 ) {
                     Ok(stats) => {
                         // Collect error stats from this thread
@@ -837,8 +1849,8 @@ fn convert_events_parallel(
             "\n⚠️  WARNING: {} thread(s) failed during parallel processing:",
             errors.len()
         );
-        eprintln!("   Partial data from these threads has been saved to temp files.");
-        eprintln!("   However, events after the error point in each failed chunk are LOST.\n");
+        eprintln!("   Partial data from these threads has been saved to temp files,");
+        eprintln!("   up to the last checkpoint of each failed chunk.\n");
 
         for (thread_id, e) in &errors {
             error!("Thread {} failed: {:?}", thread_id, e);
@@ -846,21 +1858,31 @@ fn convert_events_parallel(
         }
 
         eprintln!("\n📝 Recovery options:");
+        eprintln!("   1. Fix the underlying issue and re-run the conversion with --resume");
+        eprintln!("      (Failed chunks continue from their last checkpoint)");
         eprintln!(
-            "   1. Use 'proton-beam merge {}' to salvage successfully processed data",
+            "   2. Use 'proton-beam merge {}' to salvage successfully processed data",
             output_dir.display()
         );
-        eprintln!("      (Note: You will be missing data from the failed chunks)");
-        eprintln!("   2. Fix the underlying issue and re-run the full conversion");
-        eprintln!("      (Recommended for complete data integrity)\n");
+        eprintln!("      (Note: You will be missing data from the failed chunks)\n");
 
         return Err(anyhow::anyhow!(
             "Parallel processing failed: {}/{} chunks encountered errors. See above for details.",
             errors.len(),
-            num_threads
+            chunk_count
         ));
     }
 
+    // Rejects of the chunks go to the rejects file in input order
+    if let Some(rejects) = rejects {
+        let mut rejects = rejects.lock().unwrap();
+        let mut first_line = 0;
+        for (chunk, progress) in checkpoint.lock().unwrap().chunks.iter().enumerate() {
+            rejects.append_chunk_file(&rejects::chunk_path(&temp_dir, chunk), first_line)?;
+            first_line += progress.lines;
+        }
+    }
+
     // Clean up progress bar
     if let Some(pb) = progress {
         pb.finish_with_message("Merging temporary files...");
@@ -868,8 +1890,17 @@ fn convert_events_parallel(
 
     info!("All chunks processed, merging temporary files...");
 
-    // Merge temporary files
-    if let Err(e) = merge_temp_files(output_dir, &temp_dir, compression_level) {
+    // Merge temporary files; blocks are only cut while merging
+    if let Err(e) = merge_temp_files(
+        output_dir,
+        &temp_dir,
+        compression_level,
+        format,
+        codec,
+        zstd_dictionary,
+        seekable.then_some(batch_size),
+        index.as_mut(),
+    ) {
         error!("Failed to merge temp files: {:?}", e);
         return Err(e).context("Failed to merge temporary files");
     }
@@ -895,11 +1926,12 @@ fn convert_events_parallel(
         valid_events: valid_events.load(Ordering::Relaxed),
         invalid_events: invalid_events.load(Ordering::Relaxed),
         skipped_lines: skipped_lines.load(Ordering::Relaxed),
+        filtered_events: filtered_events.load(Ordering::Relaxed),
     };
     final_stats.print_summary(Some(&merged_error_stats));
 
     // Exit code: 0 if any events succeeded, 1 if all failed
-    if final_stats.valid_events == 0 && final_stats.total_lines > 0 {
+    if final_stats.valid_events == 0 && final_stats.total_lines > final_stats.filtered_events {
         return Err(anyhow::anyhow!(
             "Conversion failed: no valid events processed"
         ));
@@ -965,6 +1997,13 @@ fn find_chunk_boundaries(path: &Path, num_chunks: usize) -> Result<Vec<(u64, u64
 /// - Events after the error point in this chunk are LOST
 /// - Other threads continue processing their chunks
 /// - The overall parallel conversion will fail, but temp files are preserved for recovery
+/// - `convert --resume` truncates the temp files back to the chunk's last checkpoint
+///   and converts the rest of the chunk again
+///
+/// The chunk starts at its checkpointed position, and every
+/// [`CHECKPOINT_INTERVAL`] bytes its temp files are finished and its
+/// progress saved to `checkpoint`. With `write_rejects`, rejected lines go
+/// to the chunk's rejects temp file, which is checkpointed with them.
 ///
 /// Common failure scenarios:
 /// - I/O errors reading from input file (disk issues, NFS timeouts)
@@ -976,13 +2015,14 @@ fn find_chunk_boundaries(path: &Path, num_chunks: usize) -> Result<Vec<(u64, u64
 fn process_chunk(
     thread_id: usize,
     input_path: &Path,
-    start: u64,
-    end: u64,
+    mut chunk: ChunkProgress,
+    checkpoint: &Mutex<Checkpoint>,
     temp_dir: &Path,
     total_lines: Arc<AtomicU64>,
     valid_events: Arc<AtomicU64>,
     invalid_events: Arc<AtomicU64>,
     skipped_lines: Arc<AtomicU64>,
+    filtered_events: Arc<AtomicU64>,
     bytes_processed: Arc<AtomicU64>,
     progress: Option<Arc<ProgressBar>>,
     validate_signatures: bool,
@@ -990,26 +2030,49 @@ fn process_chunk(
     batch_size: usize,
     filter_invalid_kinds: bool,
     compression_level: u32,
+    format: FormatVersion,
+    codec: Codec,
+    zstd_dictionary: Option<&[u8]>,
+    filter: Option<&Filter>,
+    write_rejects: bool,
 ) -> Result<ErrorStats> {
-    // Open the file and seek to start position
+    let (start, end) = (chunk.start, chunk.end);
+
+    // Open the file and seek to where the chunk was last checkpointed
+    let input_name = input_path.display().to_string();
     let file = File::open(input_path)?;
     let mut reader = BufReader::new(file);
-    reader.seek(SeekFrom::Start(start))?;
+    reader.seek(SeekFrom::Start(chunk.position))?;
 
     // Thread-local state
     let mut storage =
-        StorageManager::new_with_prefix(temp_dir, batch_size, thread_id, compression_level)?;
+        StorageManager::new_with_prefix(temp_dir, batch_size, thread_id, compression_level)?
+            .with_format(format)
+            .with_codec(codec);
+    if let Some(dictionary) = zstd_dictionary {
+        storage = storage.with_zstd_dictionary(dictionary.to_vec());
+    }
+    if write_rejects {
+        // Picks up after what was rejected up to the last checkpoint
+        let rejects = RejectWriter::create(&rejects::chunk_path(temp_dir, thread_id), true)?;
+        storage = storage.with_rejects(Arc::new(Mutex::new(rejects)));
+    }
 
-    // Local stats for this chunk (for logging only)
-    let mut local_total = 0u64;
-    let mut local_valid = 0u64;
-    let mut local_invalid = 0u64;
+    // Kind prefilter count for this chunk (for logging only)
     let mut filtered_count = 0usize;
 
-    let mut position = start;
-    let mut line_num = 0u64;
+    let mut position = chunk.position;
+    let mut line_num = chunk.lines;
+    let mut last_checkpoint = position;
 
     while position < end {
+        if position - last_checkpoint >= CHECKPOINT_INTERVAL {
+            chunk.position = position;
+            chunk.lines = line_num;
+            checkpoint_chunk(&mut storage, checkpoint, temp_dir, thread_id, &mut chunk)?;
+            last_checkpoint = position;
+        }
+
         let mut line = String::new();
         let bytes_read = reader.read_line(&mut line)?;
 
@@ -1019,7 +2082,6 @@ fn process_chunk(
 
         position += bytes_read as u64;
         line_num += 1;
-        local_total += 1;
 
         // Update atomic counters
         total_lines.fetch_add(1, Ordering::Relaxed);
@@ -1027,10 +2089,22 @@ fn process_chunk(
 
         // Skip empty lines
         if line.trim().is_empty() {
+            chunk.skipped_lines += 1;
             skipped_lines.fetch_add(1, Ordering::Relaxed);
             continue;
         }
 
+        // Unwrap relay wire messages, skipping those without an event
+        let line = match InputReader::event_json(&line) {
+            Some(Cow::Borrowed(_)) => line,
+            Some(Cow::Owned(event)) => event,
+            None => {
+                chunk.skipped_lines += 1;
+                skipped_lines.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+
         // Pre-filter invalid kinds if enabled
         if filter_invalid_kinds && !InputReader::has_valid_kind(&line) {
             filtered_count += 1;
@@ -1053,115 +2127,39 @@ fn process_chunk(
             ));
         }
 
-        // Parse JSON to ProtoEvent
-        let event = match ProtoEvent::try_from(line.as_str()) {
-            Ok(event) => event,
-            Err(e) => {
-                storage.log_error(
-                    LogErrorContext::new(line_num, thread_id)
-                        .with_chunk_offset(start)
-                        .with_bytes_read(position - start),
-                    &format!("parse_error: {}", e),
-                    None,
-                );
-                local_invalid += 1;
-                invalid_events.fetch_add(1, Ordering::Relaxed);
-                continue;
-            }
+        let context = LogErrorContext::new(line_num, thread_id)
+            .with_file(Some(&input_name))
+            .with_chunk_offset(start)
+            .with_bytes_read(position - start);
+        let outcome = convert_line(
+            &line,
+            &mut storage,
+            context,
+            validate_signatures,
+            validate_event_ids,
+            filter,
+        );
+        let (chunk_count, counter) = match outcome {
+            LineOutcome::Stored => (&mut chunk.valid_events, &valid_events),
+            LineOutcome::Invalid => (&mut chunk.invalid_events, &invalid_events),
+            LineOutcome::Filtered => (&mut chunk.filtered_events, &filtered_events),
         };
-
-        // Validate basic fields first (fast check)
-        if let Err(e) = validate_basic_fields(&event) {
-            storage.log_error(
-                LogErrorContext::new(line_num, thread_id)
-                    .with_chunk_offset(start)
-                    .with_bytes_read(position - start),
-                &format!("validation_error: {}", e),
-                Some(&event.id),
-            );
-            local_invalid += 1;
-            invalid_events.fetch_add(1, Ordering::Relaxed);
-            continue;
-        }
-
-        // Compute hash once and reuse for both validations if needed
-        if validate_signatures || validate_event_ids {
-            let hash = match compute_event_hash(&event) {
-                Ok(h) => h,
-                Err(e) => {
-                    storage.log_error(
-                        LogErrorContext::new(line_num, thread_id)
-                            .with_chunk_offset(start)
-                            .with_bytes_read(position - start),
-                        &format!("hash_error: {}", e),
-                        Some(&event.id),
-                    );
-                    local_invalid += 1;
-                    invalid_events.fetch_add(1, Ordering::Relaxed);
-                    continue;
-                }
-            };
-
-            if validate_event_ids && let Err(e) = validate_event_id_from_hash(&event, &hash) {
-                storage.log_error(
-                    LogErrorContext::new(line_num, thread_id)
-                        .with_chunk_offset(start)
-                        .with_bytes_read(position - start),
-                    &format!("validation_error: {}", e),
-                    Some(&event.id),
-                );
-                local_invalid += 1;
-                invalid_events.fetch_add(1, Ordering::Relaxed);
-                continue;
-            }
-
-            if validate_signatures && let Err(e) = validate_signature_from_hash(&event, &hash) {
-                storage.log_error(
-                    LogErrorContext::new(line_num, thread_id)
-                        .with_chunk_offset(start)
-                        .with_bytes_read(position - start),
-                    &format!("validation_error: {}", e),
-                    Some(&event.id),
-                );
-                local_invalid += 1;
-                invalid_events.fetch_add(1, Ordering::Relaxed);
-                continue;
-            }
-        }
-
-        // Store the event
-        match storage.store_event(event) {
-            Ok(_) => {
-                local_valid += 1;
-                valid_events.fetch_add(1, Ordering::Relaxed);
-            }
-            Err(e) => {
-                error!(
-                    "Thread {}: Failed to store event from line {}: {}",
-                    thread_id, line_num, e
-                );
-                storage.log_error(
-                    LogErrorContext::new(line_num, thread_id)
-                        .with_chunk_offset(start)
-                        .with_bytes_read(position - start),
-                    &format!("storage_error: {}", e),
-                    None,
-                );
-                local_invalid += 1;
-                invalid_events.fetch_add(1, Ordering::Relaxed);
-            }
-        }
+        *chunk_count += 1;
+        counter.fetch_add(1, Ordering::Relaxed);
     }
 
-    // Flush any remaining events
-    storage.flush()?;
+    // Finish the temp files and mark the chunk done
+    chunk.position = position;
+    chunk.lines = line_num;
+    chunk.done = true;
+    checkpoint_chunk(&mut storage, checkpoint, temp_dir, thread_id, &mut chunk)?;
 
     info!(
         "Thread {} completed: {} lines, {} valid, {} errors{}",
         thread_id,
-        local_total,
-        local_valid,
-        local_invalid,
+        chunk.lines,
+        chunk.valid_events,
+        chunk.invalid_events,
         if filtered_count > 0 {
             format!(", {} filtered", filtered_count)
         } else {
@@ -1173,8 +2171,58 @@ fn process_chunk(
     Ok(storage.clone_error_stats())
 }
 
+/// Finish the temp files of a chunk and save its progress to the checkpoint
+fn checkpoint_chunk(
+    storage: &mut StorageManager,
+    checkpoint: &Mutex<Checkpoint>,
+    temp_dir: &Path,
+    thread_id: usize,
+    progress: &mut ChunkProgress,
+) -> Result<()> {
+    storage.close()?;
+    progress.record_files(temp_dir, thread_id)?;
+
+    let mut checkpoint = checkpoint.lock().unwrap();
+    checkpoint.chunks[thread_id] = progress.clone();
+    checkpoint.save(temp_dir)?;
+    debug!(
+        "Thread {}: checkpoint at byte {} (line {})",
+        thread_id, progress.position, progress.lines
+    );
+    Ok(())
+}
+
 /// Merge temporary files into final date-organized files
-fn merge_temp_files(output_dir: &Path, temp_dir: &Path, compression_level: u32) -> Result<()> {
+///
+/// With a `block_size`, the merged files are seekable: a new block starts
+/// every `block_size` events. With an `index`, or an existing
+/// `output_dir/index.db` otherwise, every merged file is (re)indexed, so
+/// the block offsets recorded for it stay valid.
+#[allow(clippy::too_many_arguments)]
+fn merge_temp_files(
+    output_dir: &Path,
+    temp_dir: &Path,
+    compression_level: u32,
+    format: FormatVersion,
+    codec: Codec,
+    zstd_dictionary: Option<&[u8]>,
+    block_size: Option<usize>,
+    index: Option<&mut EventIndex>,
+) -> Result<()> {
+    let index_path = output_dir.join("index.db");
+    let mut existing_index;
+    let mut index = match index {
+        Some(index) => Some(index),
+        None if index_path.exists() => {
+            existing_index = EventIndex::new(&index_path).context(format!(
+                "Failed to open event index: {}",
+                index_path.display()
+            ))?;
+            Some(&mut existing_index)
+        }
+        None => None,
+    };
+
     // Group temp files by date
     let mut files_by_date: HashMap<String, Vec<PathBuf>> = HashMap::new();
 
@@ -1187,7 +2235,9 @@ fn merge_temp_files(output_dir: &Path, temp_dir: &Path, compression_level: u32)
     info!("Found {} files in temp directory", temp_files.len());
 
     for path in temp_files {
-        if path.extension().and_then(|s| s.to_str()) == Some("tmp") {
+        if rejects::is_chunk_file(&path) {
+            debug!("Skipping rejects file: {}", path.display());
+        } else if path.extension().and_then(|s| s.to_str()) == Some("tmp") {
             match extract_date_from_temp_filename(&path) {
                 Some(date) => {
                     debug!("Grouping temp file: {} -> date: {}", path.display(), date);
@@ -1222,7 +2272,18 @@ fn merge_temp_files(output_dir: &Path, temp_dir: &Path, compression_level: u32)
             date
         );
 
-        match merge_protobuf_files_with_dedup(&temp_files, output_dir, &date, compression_level) {
+        match merge_protobuf_files_with_dedup(
+            &temp_files,
+            output_dir,
+            &date,
+            compression_level,
+            format,
+            codec,
+            zstd_dictionary,
+            block_size,
+            None,
+            index.as_deref_mut(),
+        ) {
             Ok(stats) => {
                 info!(
                     "Merge summary for {}: {} events, {} duplicates, {} corrupted skipped",
@@ -1249,18 +2310,19 @@ fn merge_temp_files(output_dir: &Path, temp_dir: &Path, compression_level: u32)
 }
 
 /// Extract date string from temp filename
-/// Format: thread_{id}_{date}.pb.gz.tmp
+/// Format: thread_{id}_{date}.pb.{gz,zst}.tmp
 fn extract_date_from_temp_filename(path: &Path) -> Option<String> {
     let filename = path.file_name()?.to_str()?;
 
     // Remove .tmp extension
     let without_tmp = filename.strip_suffix(".tmp")?;
 
-    // Remove .pb.gz extension
-    let without_pb_gz = without_tmp.strip_suffix(".pb.gz")?;
+    // Remove .pb.gz / .pb.zst extension
+    let codec = Codec::from_path(path)?;
+    let without_pb = without_tmp.strip_suffix(&format!(".pb.{}", codec.extension()))?;
 
     // Split by underscore: thread_{id}_{date}
-    let parts: Vec<&str> = without_pb_gz.split('_').collect();
+    let parts: Vec<&str> = without_pb.split('_').collect();
 
     // We need at least ["thread", "{id}", "{year}", "{month}", "{day}"]
     if parts.len() >= 5 && parts[0] == "thread" {
@@ -1277,22 +2339,37 @@ struct MergeStats {
     written_events: u64,
     duplicates: u64,
     corrupted: u64,
+    /// Dead events left out while compacting
+    dropped: u64,
 }
 
+/// Merge `sources` and any existing file for `date_str` into that day's file
+///
+/// With `compact`, the listed dead events are left out and the rest are
+/// written in `created_at` order, which holds the whole day in memory. With
+/// an `index`, the rewritten file is indexed; when compacting, the index
+/// update, including removing the dead events, is a single transaction.
+#[allow(clippy::too_many_arguments)]
 fn merge_protobuf_files_with_dedup(
     sources: &[PathBuf],
     output_dir: &Path,
     date_str: &str,
     compression_level: u32,
+    format: FormatVersion,
+    codec: Codec,
+    zstd_dictionary: Option<&[u8]>,
+    block_size: Option<usize>,
+    compact: Option<&HashSet<String>>,
+    mut index: Option<&mut EventIndex>,
 ) -> Result<MergeStats> {
     use proton_beam_core::{
-        create_gzip_decoder, create_gzip_encoder_with_level, read_events_delimited,
-        write_event_delimited,
+        Encoder, SeekTable, create_decoder, create_encoder, read_events_delimited,
+        write_event_delimited_as, write_format_header,
     };
     use std::io::BufWriter;
 
-    let final_file = output_dir.join(format!("{}.pb.gz", date_str));
-    let temp_output = output_dir.join(format!("{}.pb.gz.tmp", date_str));
+    let final_file = output_dir.join(format!("{}.pb.{}", date_str, codec.extension()));
+    let temp_output = output_dir.join(format!("{}.pb.{}.tmp", date_str, codec.extension()));
 
     debug!(
         "Merging {} source files into {}",
@@ -1310,18 +2387,67 @@ fn merge_protobuf_files_with_dedup(
         all_sources.push(final_file.clone());
     }
 
+    // A final file written with the other codec is folded in and replaced
+    let stale_files: Vec<PathBuf> = [Codec::Gzip, Codec::Zstd]
+        .into_iter()
+        .filter(|other| *other != codec)
+        .map(|other| output_dir.join(format!("{}.pb.{}", date_str, other.extension())))
+        .filter(|path| path.exists())
+        .collect();
+    all_sources.extend(stale_files.iter().cloned());
+
     let output_file = File::create(&temp_output).context(format!(
         "Failed to create temp output file: {}",
         temp_output.display()
     ))?;
-    let gz = create_gzip_encoder_with_level(output_file, compression_level);
-    let mut writer = BufWriter::new(gz);
 
-    // Deduplicate during merge (streaming)
-    let mut seen_ids = HashSet::new();
+    // Each block is a complete gzip member or zstd frame starting with its own header
+    let mut seek_table = SeekTable::default();
+    let mut start_block = |mut file: File| -> Result<(BufWriter<Encoder<File>>, u64)> {
+        let block_offset = file.stream_position()?;
+        seek_table.block_offsets.push(block_offset);
+        let encoder = create_encoder(file, codec, compression_level, zstd_dictionary)?;
+        let mut writer = BufWriter::new(encoder);
+        write_format_header(&mut writer, format).context("Failed to write format header")?;
+        Ok((writer, block_offset))
+    };
+    let finish_block = |writer: BufWriter<Encoder<File>>| -> Result<File> {
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush writer")?
+            .finish()
+            .context("Failed to finish compressed stream")
+    };
+    let (mut writer, mut block_offset) = start_block(output_file)?;
+    let mut block_events = 0usize;
     let mut event_count = 0u64;
+
+    // Write an event, returning the writer and the offset of the block it went into
+    let mut write_event = |mut writer: BufWriter<Encoder<File>>,
+                           event: &ProtoEvent|
+     -> Result<(BufWriter<Encoder<File>>, u64)> {
+        if block_size.is_some_and(|size| block_events >= size) {
+            (writer, block_offset) = start_block(finish_block(writer)?)?;
+            block_events = 0;
+        }
+        block_events += 1;
+
+        write_event_delimited_as(&mut writer, event, format).context(format!(
+            "Failed to write event {} to output file: {}",
+            event_count + 1,
+            temp_output.display()
+        ))?;
+        event_count += 1;
+        Ok((writer, block_offset))
+    };
+
+    // Deduplicate during merge (streaming, unless compacting)
+    let mut seen_ids = HashSet::new();
+    let mut compacted: Vec<ProtoEvent> = Vec::new();
     let mut duplicate_count = 0u64;
     let mut corrupted_events = 0u64;
+    let mut dropped_events = 0u64;
     let mut source_errors = 0u64;
 
     for (idx, source) in all_sources.iter().enumerate() {
@@ -1344,10 +2470,21 @@ fn merge_protobuf_files_with_dedup(
                 continue;
             }
         };
-        let gz = create_gzip_decoder(file);
+        let decoder = match create_decoder(file, zstd_dictionary) {
+            Ok(d) => d,
+            Err(e) => {
+                source_errors += 1;
+                error!(
+                    "Failed to decode source {} (skipping): {}",
+                    source.display(),
+                    e
+                );
+                continue;
+            }
+        };
 
         let mut source_events = 0;
-        for (event_idx, event_result) in read_events_delimited(gz).enumerate() {
+        for (event_idx, event_result) in read_events_delimited(decoder).enumerate() {
             // IMPROVED: Handle corrupted events gracefully - continue merge instead of failing
             let event = match event_result {
                 Ok(e) => e,
@@ -1367,14 +2504,13 @@ fn merge_protobuf_files_with_dedup(
                 duplicate_count += 1;
                 continue;
             }
-
-            write_event_delimited(&mut writer, &event).context(format!(
-                "Failed to write event {} to output file: {}",
-                event_count + 1,
-                temp_output.display()
-            ))?;
-            event_count += 1;
             source_events += 1;
+
+            match compact {
+                Some(dead) if dead.contains(&event.id) => dropped_events += 1,
+                Some(_) => compacted.push(event),
+                None => (writer, _) = write_event(writer, &event)?,
+            }
         }
         debug!(
             "Processed {} events from {}",
@@ -1383,8 +2519,34 @@ fn merge_protobuf_files_with_dedup(
         );
     }
 
-    writer.flush().context("Failed to flush writer")?;
-    drop(writer);
+    // Compacting replaces the day's file, so anything unreadable would be lost for good
+    if compact.is_some() && (source_errors > 0 || corrupted_events > 0) {
+        drop(writer);
+        let _ = std::fs::remove_file(&temp_output);
+        anyhow::bail!(
+            "{} source files and {} events could not be read; leaving {} untouched \
+             (was it written with a --zstd-dictionary?)",
+            source_errors,
+            corrupted_events,
+            final_file.display()
+        );
+    }
+
+    // Compacted events are written oldest first, remembering where each one went
+    compacted.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+    let mut block_offsets = Vec::with_capacity(compacted.len());
+    for event in &compacted {
+        let offset;
+        (writer, offset) = write_event(writer, event)?;
+        block_offsets.push(offset);
+    }
+
+    let mut output_file = finish_block(writer)?;
+    if block_size.is_some() && codec == Codec::Zstd {
+        seek_table
+            .write_to(&mut output_file)
+            .context("Failed to write seek table")?;
+    }
 
     debug!(
         "Renaming {} to {}",
@@ -1396,6 +2558,44 @@ fn merge_protobuf_files_with_dedup(
         temp_output.display(),
         final_file.display()
     ))?;
+    let file_name = final_file
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown");
+    for stale in &stale_files {
+        std::fs::remove_file(stale).context(format!("Failed to remove {}", stale.display()))?;
+
+        // Rows for the removed file follow its events, even ones not read back below
+        if let Some(index) = index.as_deref_mut() {
+            let stale_name = stale
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown");
+            index
+                .move_file(stale_name, file_name)
+                .context(format!("Failed to update index for {}", stale_name))?;
+        }
+    }
+
+    // Events already in the index moved to new blocks, or out of a stale file
+    match (index, compact) {
+        (Some(index), Some(dead)) => {
+            let rows: Vec<_> = compacted
+                .iter()
+                .zip(&block_offsets)
+                .map(|(event, &offset)| (event, file_name, offset))
+                .collect();
+            let removed: Vec<String> = dead.iter().cloned().collect();
+            index
+                .rewrite_file(&rows, &removed)
+                .context(format!("Failed to update index for {}", file_name))?;
+        }
+        (Some(index), None) => {
+            index_event_file(index, &final_file, zstd_dictionary, true)
+                .context(format!("Failed to index {}", final_file.display()))?;
+        }
+        (None, _) => {}
+    }
 
     // Log merge summary with all relevant stats
     if corrupted_events > 0 {
@@ -1419,12 +2619,115 @@ fn merge_protobuf_files_with_dedup(
         written_events: event_count,
         duplicates: duplicate_count,
         corrupted: corrupted_events,
+        dropped: dropped_events,
+    })
+}
+
+/// Index every event of a protobuf file along with the block holding it
+///
+/// With `relocate`, events that are already indexed are pointed at this
+/// file instead of being skipped, as needed after the file is rewritten.
+/// Returns (inserted, duplicates), where duplicates were relocated if asked.
+fn index_event_file(
+    index: &mut EventIndex,
+    path: &Path,
+    zstd_dictionary: Option<&[u8]>,
+    relocate: bool,
+) -> Result<(u64, u64)> {
+    use proton_beam_core::scan_blocks;
+
+    /// Index a batch of (event, block offset) pairs from `file_name` and clear it
+    fn insert_batch(
+        index: &mut EventIndex,
+        batch: &mut Vec<(ProtoEvent, u64)>,
+        file_name: &str,
+        relocate: bool,
+    ) -> proton_beam_core::Result<(usize, usize)> {
+        let batch_refs: Vec<_> = batch
+            .iter()
+            .map(|(event, block_offset)| (event, file_name, *block_offset))
+            .collect();
+        let counts = if relocate {
+            index.upsert_batch_at(&batch_refs)?
+        } else {
+            index.insert_batch_at(&batch_refs)?
+        };
+        batch.clear();
+        Ok(counts)
+    }
+
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown");
+    let mut total_events = 0u64;
+    let mut total_duplicates = 0u64;
+
+    // Walk the file block by block, streaming events instead of loading them into memory
+    let file = File::open(path).context(format!("Failed to open {}", file_name))?;
+    let mut batch: Vec<(ProtoEvent, u64)> = Vec::with_capacity(INDEX_BATCH_SIZE);
+
+    let scan = scan_blocks(file, zstd_dictionary, |block_offset, events| {
+        for (event_idx, event_result) in events.enumerate() {
+            let event = match event_result {
+                Ok(ev) => ev,
+                Err(e) => {
+                    warn!(
+                        "Corrupted event {} in block at {} of {}, skipping rest of block: {}",
+                        event_idx + 1,
+                        block_offset,
+                        file_name,
+                        e
+                    );
+                    break;
+                }
+            };
+            batch.push((event, block_offset));
+
+            // Insert in batches for performance; duplicates are ignored
+            if batch.len() >= INDEX_BATCH_SIZE {
+                let (inserted, duplicates_in_batch) =
+                    insert_batch(index, &mut batch, file_name, relocate)?;
+                total_events += inserted as u64;
+                total_duplicates += duplicates_in_batch as u64;
+            }
+        }
+        Ok(())
     })
+    .context(format!("Failed to index {}", file_name))?;
+    if !scan.complete {
+        warn!(
+            "{} ends with a truncated or unreadable block after byte {}",
+            file_name, scan.valid_len
+        );
+    }
+
+    // Insert remaining events
+    if !batch.is_empty() {
+        let (inserted, duplicates_in_batch) = insert_batch(index, &mut batch, file_name, relocate)
+            .context("Failed to insert final batch into index")?;
+
+        total_events += inserted as u64;
+        total_duplicates += duplicates_in_batch as u64;
+    }
+
+    debug!(
+        "Indexed {} events from {}",
+        total_events + total_duplicates,
+        file_name
+    );
+    Ok((total_events, total_duplicates))
 }
 
 /// Rebuild the event index from existing protobuf files
-fn rebuild_index(pb_dir: &Path, index_path: &Path) -> Result<()> {
-    use proton_beam_core::{EventIndex, create_gzip_decoder, read_events_delimited};
+fn rebuild_index(
+    pb_dir: &Path,
+    index_path: &Path,
+    zstd_dictionary: Option<&[u8]>,
+    index_tags: bool,
+    process_deletions: bool,
+) -> Result<()> {
+    use proton_beam_core::is_event_file;
     use std::time::Instant;
 
     // Verify pb_dir exists
@@ -1445,18 +2748,24 @@ fn rebuild_index(pb_dir: &Path, index_path: &Path) -> Result<()> {
     let mut index =
         EventIndex::new_bulk_mode(index_path).context("Failed to create event index")?;
     info!("Using bulk insert mode with optimized SQLite settings");
+    if index_tags {
+        index
+            .set_tag_index(true)
+            .context("Failed to enable tag index")?;
+    }
+    if process_deletions {
+        index
+            .set_deletion_processing(true)
+            .context("Failed to enable deletion processing")?;
+    }
 
-    // Find all .pb.gz files in the directory
+    // Find all .pb.gz and .pb.zst files in the directory
     let mut pb_files: Vec<PathBuf> = Vec::new();
     for entry in std::fs::read_dir(pb_dir)? {
         let entry = entry?;
         let path = entry.path();
 
-        if path.is_file()
-            && let Some(extension) = path.extension()
-            && extension == "gz"
-            && path.to_str().unwrap_or("").ends_with(".pb.gz")
-        {
+        if path.is_file() && is_event_file(&path) {
             pb_files.push(path);
         }
     }
@@ -1489,69 +2798,15 @@ fn rebuild_index(pb_dir: &Path, index_path: &Path) -> Result<()> {
 
     // Process each file
     for (file_idx, pb_file) in pb_files.iter().enumerate() {
-        let file_name = pb_file
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
-
         progress.set_position(file_idx as u64);
         progress.set_message(format!(
             "Events: {} | Dupes: {}",
             total_events, total_duplicates
         ));
 
-        // Open and decompress the file
-        let file = File::open(pb_file).context(format!("Failed to open {}", file_name))?;
-        let gz = create_gzip_decoder(file);
-
-        // Stream events instead of loading all into memory
-        let mut file_events = 0;
-        let mut batch: Vec<(ProtoEvent, &str)> = Vec::with_capacity(INDEX_BATCH_SIZE);
-
-        for (event_idx, event_result) in read_events_delimited(gz).enumerate() {
-            let event = match event_result {
-                Ok(ev) => ev,
-                Err(e) => {
-                    warn!(
-                        "Corrupted event {} in {} during indexing: {}",
-                        event_idx + 1,
-                        file_name,
-                        e
-                    );
-                    continue;
-                }
-            };
-            batch.push((event, file_name));
-            file_events += 1;
-
-            // Insert in batches for performance
-            if batch.len() >= INDEX_BATCH_SIZE {
-                let batch_refs: Vec<_> = batch.iter().map(|(e, f)| (e, *f)).collect();
-
-                // Count how many were actually inserted (duplicates are ignored)
-                let (inserted, duplicates_in_batch) = index
-                    .insert_batch(&batch_refs)
-                    .context("Failed to insert batch into index")?;
-
-                total_events += inserted as u64;
-                total_duplicates += duplicates_in_batch as u64;
-                batch.clear();
-            }
-        }
-
-        // Insert remaining events
-        if !batch.is_empty() {
-            let batch_refs: Vec<_> = batch.iter().map(|(e, f)| (e, *f)).collect();
-
-            let (inserted, duplicates_in_batch) = index
-                .insert_batch(&batch_refs)
-                .context("Failed to insert final batch into index")?;
-
-            total_events += inserted as u64;
-            total_duplicates += duplicates_in_batch as u64;
-        }
-
-        debug!("Indexed {} events from {}", file_events, file_name);
+        let (inserted, duplicates) = index_event_file(&mut index, pb_file, zstd_dictionary, false)?;
+        total_events += inserted;
+        total_duplicates += duplicates;
     }
 
     progress.finish_with_message(format!(
@@ -1584,3 +2839,343 @@ fn rebuild_index(pb_dir: &Path, index_path: &Path) -> Result<()> {
 
     Ok(())
 }
+
+/// Rewrite the daily files between `since` and `until` without dead events
+///
+/// Dead events are those the index knows to be deleted by NIP-09 requests
+/// or superseded by newer replaceable versions. Each day is rewritten
+/// through [`merge_protobuf_files_with_dedup`], sorted by `created_at`, and
+/// the index is updated to match in one transaction per day.
+#[allow(clippy::too_many_arguments)]
+fn compact_archive(
+    pb_dir: &Path,
+    index_path: &Path,
+    since: Option<i64>,
+    until: Option<i64>,
+    compression_level: u32,
+    format: FormatVersion,
+    codec: Option<Codec>,
+    zstd_dictionary: Option<&[u8]>,
+    block_size: Option<usize>,
+) -> Result<()> {
+    use proton_beam_core::is_event_file;
+
+    const DAY_SECONDS: i64 = 86_400;
+
+    let mut index = EventIndex::new(index_path).context("Failed to open event index")?;
+    if !index.deletion_processing_enabled() {
+        warn!("Deletion processing is off in this index; only superseded events are dropped");
+        println!(
+            "⚠️  Deletion processing is off in this index, so deleted events are kept.\n   Rebuild it with: proton-beam index rebuild {} --deletions\n",
+            pb_dir.display()
+        );
+    }
+
+    // Daily files in range, grouped by day (a day may have a .pb.gz and a .pb.zst file)
+    let mut files_by_date: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for entry in
+        std::fs::read_dir(pb_dir).context(format!("Failed to read {}", pb_dir.display()))?
+    {
+        let path = entry?.path();
+        if !path.is_file() || !is_event_file(&path) {
+            continue;
+        }
+        let Some(date) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|name| name.split(".pb.").next())
+        else {
+            continue;
+        };
+        let Ok(day) = chrono::NaiveDate::parse_from_str(date, "%Y_%m_%d") else {
+            debug!("Skipping {}: not a daily file", path.display());
+            continue;
+        };
+        let day_start = day.and_time(chrono::NaiveTime::MIN).and_utc().timestamp();
+        if since.is_some_and(|since| day_start + DAY_SECONDS <= since)
+            || until.is_some_and(|until| day_start > until)
+        {
+            continue;
+        }
+        files_by_date
+            .entry(date.to_string())
+            .or_default()
+            .push(path);
+    }
+
+    if files_by_date.is_empty() {
+        println!(
+            "⚠️  No daily protobuf files in range in {}",
+            pb_dir.display()
+        );
+        return Ok(());
+    }
+
+    let mut dates: Vec<_> = files_by_date.into_iter().collect();
+    dates.sort();
+
+    let mut total_kept = 0u64;
+    let mut total_dropped = 0u64;
+    let mut bytes_before = 0u64;
+    let mut bytes_after = 0u64;
+    for (date, mut files) in dates {
+        files.sort();
+
+        let mut dead = HashSet::new();
+        for file in &files {
+            bytes_before += file.metadata()?.len();
+            let file_name = file
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown");
+            dead.extend(
+                index
+                    .dead_events(file_name)
+                    .context(format!("Failed to look up dead events in {}", file_name))?,
+            );
+        }
+        let codec = codec
+            .or_else(|| Codec::from_path(&files[0]))
+            .unwrap_or(Codec::Gzip);
+
+        let stats = merge_protobuf_files_with_dedup(
+            &[],
+            pb_dir,
+            &date,
+            compression_level,
+            format,
+            codec,
+            zstd_dictionary,
+            block_size,
+            Some(&dead),
+            Some(&mut index),
+        )
+        .context(format!("Failed to compact {}", date))?;
+
+        let compacted = pb_dir.join(format!("{}.pb.{}", date, codec.extension()));
+        bytes_after += compacted.metadata()?.len();
+        total_kept += stats.written_events;
+        total_dropped += stats.dropped;
+
+        info!(
+            "Compacted {}: {} events kept, {} dropped, {} duplicates, {} corrupted skipped",
+            date, stats.written_events, stats.dropped, stats.duplicates, stats.corrupted
+        );
+        println!(
+            "   ✅ {} (kept: {}, dropped: {}, dupes: {}, corrupt: {})",
+            date, stats.written_events, stats.dropped, stats.duplicates, stats.corrupted
+        );
+    }
+
+    println!("\n✅ Compaction Complete");
+    println!("  Events kept:         {}", total_kept);
+    println!("  Events dropped:      {}", total_dropped);
+    println!(
+        "  Size:                {:.2} MB -> {:.2} MB",
+        bytes_before as f64 / 1_000_000.0,
+        bytes_after as f64 / 1_000_000.0
+    );
+
+    info!(
+        "Compaction complete: {} events kept, {} dropped",
+        total_kept, total_dropped
+    );
+
+    Ok(())
+}
+
+/// Train a zstd dictionary on events sampled from JSONL or protobuf files
+fn train_dictionary(
+    inputs: &[PathBuf],
+    output: &Path,
+    max_samples: usize,
+    max_size: usize,
+    format: FormatVersion,
+) -> Result<()> {
+    use proton_beam_core::{create_decoder, read_events_delimited, train_zstd_dictionary};
+
+    let mut samples: Vec<ProtoEvent> = Vec::new();
+    for input in inputs {
+        if samples.len() >= max_samples {
+            break;
+        }
+        let remaining = max_samples - samples.len();
+
+        if Codec::from_path(input).is_some() {
+            let file = File::open(input).context(format!("Failed to open {}", input.display()))?;
+            let decoder = create_decoder(file, None)
+                .context(format!("Failed to decode {}", input.display()))?;
+            samples.extend(
+                read_events_delimited(decoder)
+                    .filter_map(|event| event.ok())
+                    .take(remaining),
+            );
+        } else {
+            let reader = InputReader::with_options(input.to_str().unwrap(), true)?;
+            samples.extend(
+                reader
+                    .filter_map(|line| line.ok())
+                    .filter_map(|line| ProtoEvent::try_from(line.text.as_str()).ok())
+                    .take(remaining),
+            );
+        }
+        info!("Sampled {} events so far", samples.len());
+    }
+
+    if samples.is_empty() {
+        anyhow::bail!("No events found to train on");
+    }
+
+    println!("🧠 Training on {} events...", samples.len());
+    let dictionary = train_zstd_dictionary(&samples, format, max_size)
+        .context("Failed to train zstd dictionary")?;
+    std::fs::write(output, &dictionary)
+        .context(format!("Failed to write dictionary: {}", output.display()))?;
+
+    println!(
+        "✅ Wrote {} byte dictionary to {}",
+        dictionary.len(),
+        output.display()
+    );
+    println!(
+        "\n💡 Use it with: proton-beam convert --codec zstd --zstd-dictionary {}",
+        output.display()
+    );
+
+    Ok(())
+}
+
+/// Where `export` reads events from
+enum ExportSource {
+    /// Protobuf files, or directories of them, scanned in order
+    Files(Vec<PathBuf>),
+    /// An index query against the files of `pb_dir`, newest first
+    Index {
+        pb_dir: PathBuf,
+        index_path: PathBuf,
+    },
+}
+
+/// Write events matching `filter` to `output` as NIP-01 JSONL
+///
+/// Each event is one line in the canonical form of `proto_to_json`, so
+/// exporting the same events always produces the same bytes. Returns the
+/// number of events written.
+fn export_events(
+    source: &ExportSource,
+    output: &Path,
+    gzip: bool,
+    filter: &Filter,
+    zstd_dictionary: Option<&[u8]>,
+) -> Result<u64> {
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{BufWriter, Write};
+
+    let sink: Box<dyn Write> = if output == Path::new("-") {
+        Box::new(std::io::stdout().lock())
+    } else {
+        let file =
+            File::create(output).context(format!("Failed to create {}", output.display()))?;
+        Box::new(file)
+    };
+    let mut writer = BufWriter::new(sink);
+
+    let exported = if gzip {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        let exported = write_export(&mut encoder, source, filter, zstd_dictionary)?;
+        writer = encoder.finish()?;
+        exported
+    } else {
+        write_export(&mut writer, source, filter, zstd_dictionary)?
+    };
+    writer.flush()?;
+
+    Ok(exported)
+}
+
+/// Write the JSONL lines of `export_events` to `writer`
+fn write_export(
+    writer: &mut dyn std::io::Write,
+    source: &ExportSource,
+    filter: &Filter,
+    zstd_dictionary: Option<&[u8]>,
+) -> Result<u64> {
+    use proton_beam_core::{
+        EventIndex, create_decoder, is_event_file, proto_to_json, read_events_delimited,
+    };
+
+    /// Write matching events until the filter's limit is reached
+    fn write_jsonl(
+        writer: &mut dyn std::io::Write,
+        events: impl Iterator<Item = Result<ProtoEvent>>,
+        filter: &Filter,
+        exported: &mut u64,
+    ) -> Result<()> {
+        for event in events {
+            if filter.limit.is_some_and(|limit| *exported >= limit as u64) {
+                break;
+            }
+            let event = event?;
+            if filter.matches(&event) {
+                writer.write_all(proto_to_json(&event)?.as_bytes())?;
+                writer.write_all(b"\n")?;
+                *exported += 1;
+            }
+        }
+        Ok(())
+    }
+
+    let mut exported = 0u64;
+    match source {
+        ExportSource::Files(inputs) => {
+            let mut files = Vec::new();
+            for input in inputs {
+                if input.is_dir() {
+                    let mut dir_files: Vec<PathBuf> = std::fs::read_dir(input)
+                        .context(format!("Failed to read {}", input.display()))?
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|path| path.is_file() && is_event_file(path))
+                        .collect();
+                    dir_files.sort();
+                    files.extend(dir_files);
+                } else {
+                    files.push(input.clone());
+                }
+            }
+
+            for path in &files {
+                let file =
+                    File::open(path).context(format!("Failed to open {}", path.display()))?;
+                let decoder = create_decoder(BufReader::new(file), zstd_dictionary)
+                    .context(format!("Failed to decode {}", path.display()))?;
+                let events = read_events_delimited(decoder).map(|event| {
+                    event.with_context(|| format!("Failed to read {}", path.display()))
+                });
+                write_jsonl(writer, events, filter, &mut exported)?;
+                debug!("Exported {} events after {}", exported, path.display());
+            }
+        }
+        ExportSource::Index { pb_dir, index_path } => {
+            if !index_path.exists() {
+                anyhow::bail!(
+                    "Index not found: {}\nBuild it first with: proton-beam index rebuild {}",
+                    index_path.display(),
+                    pb_dir.display()
+                );
+            }
+            let mut index = EventIndex::new(index_path)
+                .context(format!("Failed to open index: {}", index_path.display()))?
+                .with_data_dir(pb_dir);
+            if let Some(dictionary) = zstd_dictionary {
+                index = index.with_zstd_dictionary(dictionary.to_vec());
+            }
+            let events = index
+                .events_matching(filter)?
+                .map(|event| event.context("Failed to read event"));
+            write_jsonl(writer, events, filter, &mut exported)?;
+        }
+    }
+
+    Ok(exported)
+}
@@ -0,0 +1,253 @@
+//! Dead-letter output for rejected events
+//!
+//! With `convert --rejects <path>`, every line that fails to parse, validate
+//! or store is written to a JSONL file alongside the logged error, so it can
+//! be inspected, fixed and converted again. A path ending in `.gz` is
+//! gzip-compressed. Each record holds the error category, the full error
+//! message, where the line came from and the raw line itself:
+//!
+//! ```json
+//! {"file":"events.jsonl","line":42,"category":"invalid_signature","error":"validation_error: ...","event_id":"...","raw":"{...}"}
+//! ```
+//!
+//! `line` is the line of `file` the event was read from, counting every
+//! line, and `file` is left out for stdin. Records of parallel conversions
+//! are written as threads reject lines, so they are not in input order.
+//! Chunked conversions of a file are the exception: each chunk writes its
+//! rejects to a temp file of its own ([`chunk_path`]), numbering lines from
+//! the start of the chunk. That file is checkpointed along with the chunk's
+//! temp event files, and the chunk files are copied into the rejects file in
+//! input order, with file line numbers, once every chunk is converted.
+
+use crate::storage::{ErrorCategory, LogErrorContext};
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Suffix of the rejects temp file of each chunk
+const CHUNK_FILE_SUFFIX: &str = "_rejects.jsonl.tmp";
+
+/// Path of the rejects temp file of chunk `chunk` in `temp_dir`
+///
+/// The name starts like the chunk's temp event files, so checkpoints record
+/// and restore it along with them.
+pub fn chunk_path(temp_dir: &Path, chunk: usize) -> PathBuf {
+    temp_dir.join(format!("thread_{}{}", chunk, CHUNK_FILE_SUFFIX))
+}
+
+/// Whether `path` is the rejects temp file of a chunk
+pub fn is_chunk_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(CHUNK_FILE_SUFFIX))
+}
+
+/// A rejected line, as written to the rejects file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RejectRecord<'a> {
+    /// Input file the line was read from
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<Cow<'a, str>>,
+    /// Line number in `file`, counting from 1
+    pub line: u64,
+    pub category: ErrorCategory,
+    /// The full error message
+    #[serde(borrow)]
+    pub error: Cow<'a, str>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<Cow<'a, str>>,
+    /// The rejected line, exactly as read
+    #[serde(borrow)]
+    pub raw: Cow<'a, str>,
+}
+
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+/// Writes rejected lines to a JSONL file
+pub struct RejectWriter {
+    sink: Sink,
+    count: u64,
+}
+
+impl RejectWriter {
+    /// Create the rejects file at `path`, or append to it with `append`
+    ///
+    /// Appending to a `.gz` file adds a gzip member, which readers of
+    /// concatenated members (such as `zcat` and `convert`) read through.
+    pub fn create(path: &Path, append: bool) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .context(format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .context(format!("Failed to create rejects file: {}", path.display()))?;
+        let writer = BufWriter::new(file);
+        let sink = if path.extension().is_some_and(|ext| ext == "gz") {
+            Sink::Gzip(GzEncoder::new(writer, Compression::default()))
+        } else {
+            Sink::Plain(writer)
+        };
+        Ok(Self { sink, count: 0 })
+    }
+
+    /// Write one rejected line
+    pub fn write(
+        &mut self,
+        context: LogErrorContext,
+        category: ErrorCategory,
+        error: &str,
+        event_id: Option<&str>,
+        raw: &str,
+    ) -> Result<()> {
+        self.write_record(&RejectRecord {
+            file: context.file.map(Cow::Borrowed),
+            line: context.line,
+            category,
+            error: Cow::Borrowed(error),
+            event_id: event_id.map(Cow::Borrowed),
+            raw: Cow::Borrowed(raw.trim_end_matches(['\n', '\r'])),
+        })
+    }
+
+    /// Copy the records of a chunk's rejects temp file, if it exists
+    ///
+    /// `first_line` is the number of lines in the input before the chunk.
+    pub fn append_chunk_file(&mut self, path: &Path, first_line: u64) -> Result<()> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context(format!("Failed to open {}", path.display())),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.context(format!("Failed to read {}", path.display()))?;
+            let mut record: RejectRecord = serde_json::from_str(&line)
+                .context(format!("Invalid rejects record in {}", path.display()))?;
+            record.line += first_line;
+            self.write_record(&record)?;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &RejectRecord) -> Result<()> {
+        let writer = self.writer();
+        serde_json::to_writer(&mut *writer, record)?;
+        writer.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match &mut self.sink {
+            Sink::Plain(writer) => writer,
+            Sink::Gzip(encoder) => encoder,
+        }
+    }
+
+    /// Number of lines written so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Flush what was written so far to the file
+    ///
+    /// A gzip stream is only complete once [`Self::finish`]ed, which is why
+    /// the checkpointed rejects files of chunks are plain JSONL.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer()
+            .flush()
+            .context("Failed to flush rejects file")
+    }
+
+    /// Flush the file, finishing the gzip stream
+    pub fn finish(self) -> Result<()> {
+        let mut writer = match self.sink {
+            Sink::Plain(writer) => writer,
+            Sink::Gzip(encoder) => encoder.finish()?,
+        };
+        writer.flush().context("Failed to flush rejects file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reject_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["rejects.jsonl", "rejects.jsonl.gz"] {
+            let path = temp_dir.path().join(name);
+            for append in [false, true] {
+                let mut writer = RejectWriter::create(&path, append).unwrap();
+                writer
+                    .write(
+                        LogErrorContext::new(7, 2)
+                            .with_file(Some("events.jsonl"))
+                            .with_chunk_offset(100),
+                        ErrorCategory::InvalidSignature,
+                        "validation_error: Invalid signature",
+                        Some("abc"),
+                        "{\"id\":\"abc\"}\n",
+                    )
+                    .unwrap();
+                writer
+                    .write(
+                        LogErrorContext::from_line(8),
+                        ErrorCategory::ParseError,
+                        "parse_error: Invalid JSON",
+                        None,
+                        "not json",
+                    )
+                    .unwrap();
+                assert_eq!(writer.count(), 2);
+                writer.finish().unwrap();
+            }
+
+            let mut content = String::new();
+            let file = File::open(&path).unwrap();
+            if name.ends_with(".gz") {
+                flate2::read::MultiGzDecoder::new(file)
+                    .read_to_string(&mut content)
+                    .unwrap();
+            } else {
+                content = std::fs::read_to_string(&path).unwrap();
+            }
+            let records: Vec<serde_json::Value> = content
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(records.len(), 4);
+            assert_eq!(
+                records[0],
+                serde_json::json!({
+                    "file": "events.jsonl", "line": 7,
+                    "category": "invalid_signature",
+                    "error": "validation_error: Invalid signature",
+                    "event_id": "abc", "raw": "{\"id\":\"abc\"}",
+                })
+            );
+            assert_eq!(
+                records[1],
+                serde_json::json!({
+                    "line": 8, "category": "parse_error",
+                    "error": "parse_error: Invalid JSON", "raw": "not json",
+                })
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use proton_beam_core::is_event_file;
 use std::path::Path;
 use tracing::{info, warn};
 
@@ -90,12 +91,8 @@ impl S3Uploader {
             let entry = entry?;
             let path = entry.path();
 
-            // Only upload .pb.gz files (skip index.db and logs)
-            if path.is_file()
-                && let Some(extension) = path.extension()
-                && extension == "gz"
-                && path.to_str().unwrap_or("").ends_with(".pb.gz")
-            {
+            // Only upload .pb.gz / .pb.zst files (skip index.db and logs)
+            if path.is_file() && is_event_file(&path) {
                 let file_name = path
                     .file_name()
                     .and_then(|n| n.to_str())
@@ -0,0 +1,469 @@
+//! Read-only NIP-01 relay serving events from the archive
+//!
+//! Nostr clients connect over WebSocket and query the archive with `REQ`
+//! and `COUNT` (NIP-45) as they would any relay. Stored events are looked
+//! up in the [`EventIndex`] and read from the event files; each `REQ` ends
+//! with `EOSE`. The archive does not change while it is served, so open
+//! subscriptions never receive further events, and `EVENT` submissions are
+//! rejected.
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use proton_beam_core::{EventIndex, Filter, ProtoEvent, proto_to_json};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+/// Default cap on the number of events returned per filter
+pub const DEFAULT_MAX_LIMIT: usize = 500;
+
+/// Where the archive lives and how much a client may ask for
+#[derive(Debug, Clone)]
+pub struct ServeConfig {
+    /// SQLite event index
+    pub index_path: PathBuf,
+    /// Directory the indexed file paths are relative to
+    pub data_dir: PathBuf,
+    /// zstd dictionary the event files were written with
+    pub zstd_dictionary: Option<Vec<u8>>,
+    /// Events returned per filter at most, whatever its `limit`
+    pub max_limit: usize,
+}
+
+impl ServeConfig {
+    /// Open the index for one client connection
+    fn open_index(&self) -> Result<EventIndex> {
+        let mut index = EventIndex::new(&self.index_path)
+            .context(format!(
+                "Failed to open index: {}",
+                self.index_path.display()
+            ))?
+            .with_data_dir(&self.data_dir);
+        if let Some(dictionary) = &self.zstd_dictionary {
+            index = index.with_zstd_dictionary(dictionary.clone());
+        }
+        Ok(index)
+    }
+}
+
+/// Messages sent from a client to a relay (NIP-01, NIP-45)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    /// `["REQ", <subscription_id>, <filters>...]`
+    Req {
+        subscription_id: String,
+        filters: Vec<Filter>,
+    },
+    /// `["COUNT", <subscription_id>, <filters>...]`
+    Count {
+        subscription_id: String,
+        filters: Vec<Filter>,
+    },
+    /// `["CLOSE", <subscription_id>]`
+    Close(String),
+    /// `["EVENT", <event JSON>]`
+    Event(Value),
+    /// Any other message type (AUTH, ...), which the archive does not support
+    Other(String),
+}
+
+impl ClientMessage {
+    /// Parse a client message from its JSON text
+    pub fn from_json(text: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(text).context("Client message is not JSON")?;
+        let mut parts = match value {
+            Value::Array(parts) => parts.into_iter(),
+            _ => anyhow::bail!("Client message is not a JSON array"),
+        };
+
+        let kind = match parts.next() {
+            Some(Value::String(kind)) => kind,
+            _ => anyhow::bail!("Client message has no type"),
+        };
+
+        let message = match kind.as_str() {
+            "REQ" | "COUNT" => {
+                let subscription_id = next_string(&mut parts, &kind, "subscription id")?;
+                let filters = parts
+                    .map(serde_json::from_value)
+                    .collect::<serde_json::Result<Vec<Filter>>>()
+                    .context(format!("{} message has an invalid filter", kind))?;
+                if kind == "REQ" {
+                    Self::Req {
+                        subscription_id,
+                        filters,
+                    }
+                } else {
+                    Self::Count {
+                        subscription_id,
+                        filters,
+                    }
+                }
+            }
+            "CLOSE" => Self::Close(next_string(&mut parts, &kind, "subscription id")?),
+            "EVENT" => Self::Event(parts.next().context("EVENT message is missing the event")?),
+            _ => Self::Other(kind),
+        };
+
+        Ok(message)
+    }
+}
+
+fn next_string(parts: &mut impl Iterator<Item = Value>, kind: &str, field: &str) -> Result<String> {
+    match parts.next() {
+        Some(Value::String(s)) => Ok(s),
+        _ => anyhow::bail!("{} message is missing {}", kind, field),
+    }
+}
+
+/// Build an `["EVENT", <subscription_id>, <event JSON>]` message
+fn event_message(subscription_id: &str, event: &ProtoEvent) -> Result<String> {
+    let event: Value = serde_json::from_str(&proto_to_json(event)?)?;
+    Ok(serde_json::json!(["EVENT", subscription_id, event]).to_string())
+}
+
+/// Look up the events matching any of `filters`, newest first
+///
+/// Each filter returns at most `max_limit` events, and events matching
+/// several filters are returned once.
+pub fn query_events(
+    index: &EventIndex,
+    filters: &[Filter],
+    max_limit: usize,
+) -> Result<Vec<ProtoEvent>> {
+    let mut seen = HashSet::new();
+    let mut events = Vec::new();
+    for filter in filters {
+        let mut filter = filter.clone();
+        filter.limit = Some(filter.limit.map_or(max_limit, |limit| limit.min(max_limit)));
+        for event in index.events_matching(&filter)? {
+            let event = event?;
+            if seen.insert(event.id.clone()) {
+                events.push(event);
+            }
+        }
+    }
+    events.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+    Ok(events)
+}
+
+/// Accept client connections until the listener fails
+pub async fn serve(listener: TcpListener, config: Arc<ServeConfig>) -> Result<()> {
+    loop {
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .context("Failed to accept connection")?;
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, config).await {
+                warn!(%peer, "Connection failed: {:#}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    config: Arc<ServeConfig>,
+) -> Result<()> {
+    let mut ws = accept_async(stream)
+        .await
+        .context("WebSocket handshake failed")?;
+    info!(%peer, "Client connected");
+
+    let index = {
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || config.open_index()).await??
+    };
+    let index = Arc::new(Mutex::new(index));
+
+    while let Some(frame) = ws.next().await {
+        let text = match frame.context("WebSocket error")? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let message = match ClientMessage::from_json(text.as_str()) {
+            Ok(message) => message,
+            Err(e) => {
+                // Close a subscription that cannot be served, so the client stops waiting
+                let reply = match subscription_of(text.as_str()) {
+                    Some(id) => serde_json::json!(["CLOSED", id, format!("invalid: {:#}", e)]),
+                    None => serde_json::json!(["NOTICE", format!("invalid: {:#}", e)]),
+                };
+                ws.send(Message::Text(reply.to_string().into())).await?;
+                continue;
+            }
+        };
+
+        for reply in respond(&index, &config, message).await {
+            ws.send(Message::Text(reply.into())).await?;
+        }
+    }
+
+    info!(%peer, "Client disconnected");
+    Ok(())
+}
+
+/// Subscription id of a REQ or COUNT message, even one that fails to parse
+fn subscription_of(text: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    match value.as_array()?.as_slice() {
+        [Value::String(kind), Value::String(id), ..] if kind == "REQ" || kind == "COUNT" => {
+            Some(id.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Run a query against the connection's index without blocking the runtime
+async fn with_index<T: Send + 'static>(
+    index: &Arc<Mutex<EventIndex>>,
+    query: impl FnOnce(&EventIndex) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let index = index.clone();
+    tokio::task::spawn_blocking(move || {
+        let index = index.lock().unwrap_or_else(|e| e.into_inner());
+        query(&index)
+    })
+    .await?
+}
+
+/// Messages to send back for one client message
+async fn respond(
+    index: &Arc<Mutex<EventIndex>>,
+    config: &ServeConfig,
+    message: ClientMessage,
+) -> Vec<String> {
+    match message {
+        ClientMessage::Req {
+            subscription_id,
+            filters,
+        } => {
+            debug!(subscription = %subscription_id, "REQ with {} filters", filters.len());
+            let max_limit = config.max_limit;
+            let replies = with_index(index, move |index| query_events(index, &filters, max_limit))
+                .await
+                .and_then(|events| {
+                    events
+                        .iter()
+                        .map(|event| event_message(&subscription_id, event))
+                        .collect::<Result<Vec<_>>>()
+                });
+
+            match replies {
+                Ok(mut replies) => {
+                    replies.push(serde_json::json!(["EOSE", subscription_id]).to_string());
+                    replies
+                }
+                Err(e) => vec![closed_message(&subscription_id, &e)],
+            }
+        }
+        ClientMessage::Count {
+            subscription_id,
+            filters,
+        } => match with_index(index, move |index| Ok(index.count(&filters)?)).await {
+            Ok(count) => {
+                vec![serde_json::json!(["COUNT", subscription_id, { "count": count }]).to_string()]
+            }
+            Err(e) => vec![closed_message(&subscription_id, &e)],
+        },
+        // Subscriptions end at EOSE, so there is nothing to close
+        ClientMessage::Close(_) => Vec::new(),
+        ClientMessage::Event(event) => {
+            let id = event.get("id").and_then(Value::as_str).unwrap_or_default();
+            vec![
+                serde_json::json!(["OK", id, false, "blocked: this relay is read-only"])
+                    .to_string(),
+            ]
+        }
+        ClientMessage::Other(kind) => {
+            vec![serde_json::json!(["NOTICE", format!("unsupported: {}", kind)]).to_string()]
+        }
+    }
+}
+
+/// Build a `["CLOSED", <subscription_id>, "error: ..."]` message
+fn closed_message(subscription_id: &str, error: &anyhow::Error) -> String {
+    warn!(subscription = %subscription_id, "Query failed: {:#}", error);
+    serde_json::json!(["CLOSED", subscription_id, format!("error: {:#}", error)]).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_beam_core::{Codec, ProtoEventBuilder, create_encoder, write_events_delimited};
+    use tempfile::TempDir;
+
+    /// Read the next text frame as JSON
+    async fn next_json<S>(ws: &mut S) -> Value
+    where
+        S: StreamExt<Item = tokio_tungstenite::tungstenite::Result<Message>> + Unpin,
+    {
+        match ws.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(text.as_str()).unwrap(),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    fn create_archive(events: &[ProtoEvent]) -> (Arc<ServeConfig>, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let mut file = Vec::new();
+        let mut encoder = create_encoder(&mut file, Codec::Gzip, 6, None).unwrap();
+        write_events_delimited(&mut encoder, events).unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(temp_dir.path().join("events.pb.gz"), &file).unwrap();
+
+        let index_path = temp_dir.path().join("index.db");
+        let mut index = EventIndex::new(&index_path).unwrap();
+        let rows: Vec<_> = events.iter().map(|e| (e, "events.pb.gz")).collect();
+        index.insert_batch(&rows).unwrap();
+
+        let config = ServeConfig {
+            index_path,
+            data_dir: temp_dir.path().to_path_buf(),
+            zstd_dictionary: None,
+            max_limit: 3,
+        };
+        (Arc::new(config), temp_dir)
+    }
+
+    fn create_test_events() -> Vec<ProtoEvent> {
+        (0..6)
+            .map(|i| {
+                ProtoEventBuilder::new()
+                    .id(format!("event_{}", i))
+                    .kind(i % 2)
+                    .pubkey("pubkey_1")
+                    .created_at(1000 + i as i64)
+                    .content(format!("note {}", i))
+                    .sig("sig")
+                    .build()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_client_messages() {
+        assert_eq!(
+            ClientMessage::from_json(r#"["REQ","sub1",{"kinds":[1]},{"limit":5}]"#).unwrap(),
+            ClientMessage::Req {
+                subscription_id: "sub1".to_string(),
+                filters: vec![
+                    Filter {
+                        kinds: vec![1],
+                        ..Filter::default()
+                    },
+                    Filter {
+                        limit: Some(5),
+                        ..Filter::default()
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            ClientMessage::from_json(r#"["COUNT","sub1",{}]"#).unwrap(),
+            ClientMessage::Count {
+                subscription_id: "sub1".to_string(),
+                filters: vec![Filter::default()],
+            }
+        );
+        assert_eq!(
+            ClientMessage::from_json(r#"["CLOSE","sub1"]"#).unwrap(),
+            ClientMessage::Close("sub1".to_string())
+        );
+        assert_eq!(
+            ClientMessage::from_json(r#"["AUTH","challenge"]"#).unwrap(),
+            ClientMessage::Other("AUTH".to_string())
+        );
+        assert!(ClientMessage::from_json("not json").is_err());
+        assert!(ClientMessage::from_json(r#"["REQ"]"#).is_err());
+        assert!(ClientMessage::from_json(r##"["REQ","sub1",{"#tag":["x"]}]"##).is_err());
+        assert!(ClientMessage::from_json(r#"["EVENT"]"#).is_err());
+    }
+
+    #[test]
+    fn test_query_events() {
+        let events = create_test_events();
+        let (config, _temp_dir) = create_archive(&events);
+        let index = config.open_index().unwrap();
+
+        let ids = |filters: &[Filter]| -> Vec<String> {
+            query_events(&index, filters, config.max_limit)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.id)
+                .collect()
+        };
+
+        // Each filter is capped at max_limit, and overlaps are returned once
+        let kind_0 = Filter::try_from(r#"{"kinds": [0]}"#).unwrap();
+        let newest = Filter::try_from(r#"{"limit": 2}"#).unwrap();
+        assert_eq!(ids(&[Filter::default()]), ["event_5", "event_4", "event_3"]);
+        assert_eq!(
+            ids(&[kind_0, newest]),
+            ["event_5", "event_4", "event_2", "event_0"]
+        );
+        assert!(ids(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_serve_req_and_count() {
+        let events = create_test_events();
+        let (config, _temp_dir) = create_archive(&events);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, config));
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+        let req = r#"["REQ","sub1",{"kinds":[1],"limit":2}]"#;
+        ws.send(Message::Text(req.into())).await.unwrap();
+        for id in ["event_5", "event_3"] {
+            let message = next_json(&mut ws).await;
+            assert_eq!(message[0], "EVENT");
+            assert_eq!(message[1], "sub1");
+            assert_eq!(message[2]["id"], id);
+        }
+        assert_eq!(
+            next_json(&mut ws).await,
+            serde_json::json!(["EOSE", "sub1"])
+        );
+
+        let count = r#"["COUNT","sub2",{"kinds":[0]}]"#;
+        ws.send(Message::Text(count.into())).await.unwrap();
+        assert_eq!(
+            next_json(&mut ws).await,
+            serde_json::json!(["COUNT", "sub2", {"count": 3}])
+        );
+
+        // Filters this relay cannot parse close the subscription
+        for invalid in [
+            r#"["REQ","sub3",{"search":"nostr"}]"#,
+            r##"["COUNT","sub3",{"#ab":["x"]}]"##,
+        ] {
+            ws.send(Message::Text(invalid.into())).await.unwrap();
+            let message = next_json(&mut ws).await;
+            assert_eq!(message[0], "CLOSED");
+            assert_eq!(message[1], "sub3");
+            assert!(message[2].as_str().unwrap().starts_with("invalid: "));
+        }
+        ws.send(Message::Text(r#"["REQ"]"#.into())).await.unwrap();
+        assert_eq!(next_json(&mut ws).await[0], "NOTICE");
+
+        let event = r#"["EVENT",{"id":"abc"}]"#;
+        ws.send(Message::Text(event.into())).await.unwrap();
+        let message = next_json(&mut ws).await;
+        assert_eq!(message[0], "OK");
+        assert_eq!(message[2], false);
+    }
+}
@@ -1,21 +1,27 @@
+use crate::rejects::RejectWriter;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use proton_beam_core::{
-    EventIndex, ProtoEvent, create_gzip_encoder_with_level, write_event_delimited,
+    BlockDecoder, Codec, Encoder, EventIndex, FormatVersion, ProtoEvent, SeekTable,
+    ValidationError, create_encoder, read_events_delimited, scan_blocks, write_event_delimited_as,
+    write_format_header,
 };
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tracing::{debug, error};
 
 // Buffer size for storage writers (512KB for optimal compression)
 const STORAGE_WRITER_BUFFER_SIZE: usize = 512 * 1024;
 
-type GzipWriter = BufWriter<flate2::write::GzEncoder<File>>;
+type EventWriter = BufWriter<Encoder<File>>;
 
 /// Error categories for tracking conversion failures
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ErrorCategory {
     /// JSON parsing errors
     ParseError,
@@ -23,6 +29,8 @@ pub enum ErrorCategory {
     InvalidTagValue,
     /// Invalid event kind (out of range)
     InvalidKind,
+    /// Required event fields missing
+    MissingField,
     /// Invalid signature
     InvalidSignature,
     /// Invalid event ID hash
@@ -42,6 +50,7 @@ impl ErrorCategory {
             Self::ParseError => "Parse Errors",
             Self::InvalidTagValue => "Invalid Tag Values",
             Self::InvalidKind => "Invalid Event Kinds",
+            Self::MissingField => "Missing Fields",
             Self::InvalidSignature => "Invalid Signatures",
             Self::InvalidEventId => "Invalid Event IDs",
             Self::HashError => "Hash Computation Errors",
@@ -50,31 +59,32 @@ impl ErrorCategory {
         }
     }
 
-    /// Determine error category from error message
+    /// Category of an error from parsing or validating an event
     ///
-    /// Check more specific patterns first before falling back to generic ones
-    pub fn from_error_message(msg: &str) -> Self {
-        // Check specific error patterns first (most specific to least specific)
-        if msg.contains("Invalid tag value") {
-            Self::InvalidTagValue
-        } else if msg.contains("kind") && msg.contains("out of valid range") {
-            Self::InvalidKind
-        } else if msg.contains("Signature verification failed") || msg.contains("Invalid signature")
-        {
-            Self::InvalidSignature
-        } else if msg.contains("Event ID") || msg.contains("EventIdMismatch") {
-            Self::InvalidEventId
-        } else if msg.contains("hash_error") {
-            Self::HashError
-        } else if msg.contains("storage_error") {
-            Self::StorageError
-        } else if msg.contains("validation_error") {
-            Self::ValidationError
-        } else if msg.contains("parse_error") {
-            // Parse error is a catch-all for JSON/conversion issues
-            Self::ParseError
-        } else {
-            Self::ValidationError
+    /// Hash computation and write failures usually do not surface as a core
+    /// error; callers pass [`Self::HashError`] and [`Self::StorageError`]
+    /// for those directly.
+    pub fn from_error(error: &proton_beam_core::Error) -> Self {
+        use proton_beam_core::Error;
+
+        match error {
+            Error::Validation(error) => match error {
+                ValidationError::InvalidTagValue { .. } => Self::InvalidTagValue,
+                ValidationError::InvalidKind(_) => Self::InvalidKind,
+                ValidationError::MissingField(_) => Self::MissingField,
+                ValidationError::InvalidSignature(_) | ValidationError::SignatureParse(_) => {
+                    Self::InvalidSignature
+                }
+                ValidationError::EventIdMismatch { .. } => Self::InvalidEventId,
+                ValidationError::InvalidHex(_)
+                | ValidationError::InvalidTimestamp(_)
+                | ValidationError::PubkeyParse(_) => Self::ValidationError,
+            },
+            Error::JsonParse(_) | Error::Conversion(_) | Error::ProtobufDecode(_) => {
+                Self::ParseError
+            }
+            Error::ProtobufEncode(_) | Error::Io(_) => Self::StorageError,
+            Error::InvalidEvent(_) => Self::ValidationError,
         }
     }
 }
@@ -139,6 +149,10 @@ pub struct StorageManager {
     output_dir: PathBuf,
     batch_size: usize,
     compression_level: u32,
+    codec: Codec,
+    zstd_dictionary: Option<Vec<u8>>,
+    format: FormatVersion,
+    seekable: bool,
     index: Option<EventIndex>,
 
     // Optional prefix for temp file names (used for parallel processing)
@@ -147,11 +161,17 @@ pub struct StorageManager {
     // Map of date string (YYYY_MM_DD) to buffered events
     buffers: HashMap<String, Vec<ProtoEvent>>,
 
-    // Keep writers open for reuse (map of date -> writer)
-    writers: HashMap<String, GzipWriter>,
+    // Keep writers open for reuse (map of date -> writer and offset of its block)
+    writers: HashMap<String, (EventWriter, u64)>,
+
+    // Seek tables of the seekable zstd files written to, appended on close
+    seek_tables: HashMap<PathBuf, SeekTable>,
 
     // Error statistics
     error_stats: ErrorStats,
+
+    // Where rejected lines are written, shared between parallel managers
+    rejects: Option<Arc<Mutex<RejectWriter>>>,
 }
 
 impl StorageManager {
@@ -164,16 +184,22 @@ impl StorageManager {
             output_dir: output_dir.to_path_buf(),
             batch_size,
             compression_level,
+            codec: Codec::default(),
+            zstd_dictionary: None,
+            format: FormatVersion::default(),
+            seekable: false,
             index: None,
             file_prefix: None,
             buffers: HashMap::new(),
             writers: HashMap::new(),
+            seek_tables: HashMap::new(),
             error_stats: ErrorStats::new(),
+            rejects: None,
         })
     }
 
     /// Create a new storage manager with a file prefix for parallel processing
-    /// Files will be named: {prefix}_{date}.pb.gz.tmp
+    /// Files will be named: {prefix}_{date}.pb.{gz,zst}.tmp
     pub fn new_with_prefix(
         output_dir: &Path,
         batch_size: usize,
@@ -187,14 +213,79 @@ impl StorageManager {
             output_dir: output_dir.to_path_buf(),
             batch_size,
             compression_level,
+            codec: Codec::default(),
+            zstd_dictionary: None,
+            format: FormatVersion::default(),
+            seekable: false,
             index: None,
             file_prefix: Some(format!("thread_{}", thread_id)),
             buffers: HashMap::new(),
             writers: HashMap::new(),
+            seek_tables: HashMap::new(),
             error_stats: ErrorStats::new(),
+            rejects: None,
         })
     }
 
+    /// Write every line passed to [`Self::reject`] to a rejects file
+    pub fn with_rejects(mut self, rejects: Arc<Mutex<RejectWriter>>) -> Self {
+        self.rejects = Some(rejects);
+        self
+    }
+
+    /// Attach an event index that is updated every time a batch is flushed
+    ///
+    /// Only final `.pb.gz`/`.pb.zst` files are indexed; prefixed temp files are skipped.
+    pub fn with_index(mut self, index: EventIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Write events in `format` instead of the default `nostr.ProtoEvent`
+    ///
+    /// Each new gzip member starts with a header for `format`, so files
+    /// appended to across runs with different formats stay readable.
+    pub fn with_format(mut self, format: FormatVersion) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Compress files with `codec` instead of gzip
+    ///
+    /// Files are named after the codec (`.pb.gz` or `.pb.zst`).
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Compress with a trained zstd dictionary (only valid with [`Codec::Zstd`])
+    pub fn with_zstd_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.zstd_dictionary = Some(dictionary);
+        self
+    }
+
+    /// Write every flushed batch as a block of its own
+    ///
+    /// Blocks are complete gzip members or zstd frames, so the index can
+    /// point at the block holding each event and a single event can be read
+    /// by decompressing only that block. Without this, a block spans
+    /// everything one writer wrote before it was closed. Seekable zstd files
+    /// also end with a seek table listing their blocks.
+    pub fn with_seekable(mut self, seekable: bool) -> Self {
+        self.seekable = seekable;
+        self
+    }
+
+    /// Get a reference to the attached event index, if any
+    pub fn index(&self) -> Option<&EventIndex> {
+        self.index.as_ref()
+    }
+
+    /// Get a mutable reference to the attached event index, if any
+    pub fn index_mut(&mut self) -> Option<&mut EventIndex> {
+        self.index.as_mut()
+    }
+
     /// Get a reference to the error statistics
     pub fn error_stats(&self) -> &ErrorStats {
         &self.error_stats
@@ -241,52 +332,107 @@ impl StorageManager {
             _ => return Ok(()), // Nothing to flush
         };
 
+        let extension = self.codec.extension();
         let (filename, index_target): (String, Option<String>) =
             if let Some(ref prefix) = self.file_prefix {
-                (format!("{}_{}.pb.gz.tmp", prefix, date_str), None)
+                (
+                    format!("{}_{}.pb.{}.tmp", prefix, date_str, extension),
+                    None,
+                )
             } else {
                 (
-                    format!("{}.pb.gz", date_str),
-                    Some(format!("{}.pb.gz", date_str)),
+                    format!("{}.pb.{}", date_str, extension),
+                    Some(format!("{}.pb.{}", date_str, extension)),
                 )
             };
 
         // Get or create writer for this date
         let output_path = self.output_dir.join(&filename);
         if !self.writers.contains_key(date_str) {
+            let block_offset = self.start_block(&output_path)?;
             let writer = self.create_writer(&output_path)?;
-            self.writers.insert(date_str.to_string(), writer);
+            self.writers
+                .insert(date_str.to_string(), (writer, block_offset));
         }
 
-        let writer = self
+        let (writer, block_offset) = self
             .writers
             .get_mut(date_str)
             .expect("Writer should exist after insert");
-        let mut index_batch: Vec<(ProtoEvent, String)> = Vec::new();
+        let block_offset = *block_offset;
 
-        for event in buffer {
-            write_event_delimited(writer, &event).context("Failed to write event")?;
-            if let Some(ref file_name) = index_target {
-                index_batch.push((event, file_name.clone()));
-            }
+        for event in &buffer {
+            write_event_delimited_as(writer, event, self.format)
+                .context("Failed to write event")?;
         }
 
         // Flush writer periodically but keep it open
         writer.flush().context("Failed to flush writer")?;
 
-        if let (Some(index), Some(_)) = (&mut self.index, index_target)
-            && !index_batch.is_empty()
-        {
-            let batch_refs: Vec<_> = index_batch
+        if self.seekable {
+            // Finish the block so the next batch starts a new one
+            let (writer, _) = self
+                .writers
+                .remove(date_str)
+                .expect("Writer should exist after insert");
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())
+                .context("Failed to flush writer")?
+                .finish()
+                .context(format!("Failed to finish block for {}", date_str))?;
+        }
+
+        if let (Some(index), Some(file_name)) = (&mut self.index, index_target) {
+            let batch_refs: Vec<_> = buffer
                 .iter()
-                .map(|(event, path)| (event, path.as_str()))
+                .map(|event| (event, file_name.as_str(), block_offset))
                 .collect();
-            index.insert_batch(&batch_refs)?;
+            index.insert_batch_at(&batch_refs)?;
         }
 
         Ok(())
     }
 
+    /// Offset at which the next block of `path` starts
+    ///
+    /// For seekable zstd files, the block is also added to the file's seek
+    /// table, which is taken off the end of the file until it is closed.
+    fn start_block(&mut self, path: &Path) -> Result<u64> {
+        if self.seekable && self.codec == Codec::Zstd && !self.seek_tables.contains_key(path) {
+            let table = take_seek_table(path, self.zstd_dictionary.as_deref())?;
+            self.seek_tables.insert(path.to_path_buf(), table);
+        }
+
+        let block_offset = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => {
+                return Err(e).context(format!("Failed to read metadata of {}", path.display()));
+            }
+        };
+        if let Some(table) = self.seek_tables.get_mut(path) {
+            table.block_offsets.push(block_offset);
+        }
+        Ok(block_offset)
+    }
+
+    /// Append the seek table of every seekable zstd file written to
+    fn write_seek_tables(&mut self) -> Result<()> {
+        for (path, table) in self.seek_tables.drain() {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .context(format!("Failed to open {}", path.display()))?;
+            table
+                .write_to(&mut file)
+                .context(format!("Failed to write seek table to {}", path.display()))?;
+            file.sync_all()
+                .context(format!("Failed to sync file {}", path.display()))?;
+        }
+        Ok(())
+    }
+
     /// Flush all buffers to disk
     pub fn flush(&mut self) -> Result<()> {
         let date_strings: Vec<String> = self.buffers.keys().cloned().collect();
@@ -298,15 +444,73 @@ impl StorageManager {
         Ok(())
     }
 
+    /// Flush all buffers and finish every open compressed stream
+    ///
+    /// Unlike `Drop`, errors are reported and each file is synced to disk, so
+    /// once this returns every file ends with a complete gzip member or zstd
+    /// frame (followed by its seek table in seekable zstd files).
+    /// The manager can keep storing events afterwards; new writers are opened
+    /// on demand. The rejects file, if any, is flushed too.
+    pub fn close(&mut self) -> Result<()> {
+        self.flush()?;
+        if let Some(rejects) = &self.rejects {
+            rejects.lock().unwrap().flush()?;
+        }
+
+        for (date, (writer, _)) in self.writers.drain() {
+            let encoder = writer
+                .into_inner()
+                .map_err(|e| e.into_error())
+                .context(format!("Failed to flush writer for {}", date))?;
+            let file = encoder
+                .finish()
+                .context(format!("Failed to finish compressed stream for {}", date))?;
+            file.sync_all()
+                .context(format!("Failed to sync file for {}", date))?;
+        }
+
+        self.write_seek_tables()
+    }
+
+    /// Log the error of a rejected input line, and write the line to the
+    /// rejects file if there is one
+    pub fn reject<'a, C>(
+        &mut self,
+        context: C,
+        category: ErrorCategory,
+        error_reason: &str,
+        event_id: Option<&str>,
+        raw_line: &str,
+    ) where
+        C: Into<LogErrorContext<'a>>,
+    {
+        let context = context.into();
+        self.log_error(context, category, error_reason, event_id);
+
+        if let Some(rejects) = &self.rejects
+            && let Err(e) =
+                rejects
+                    .lock()
+                    .unwrap()
+                    .write(context, category, error_reason, event_id, raw_line)
+        {
+            error!("Failed to write rejected line {}: {}", context.line, e);
+        }
+    }
+
     /// Log an error using tracing (compact format) and track statistics
-    pub fn log_error<C>(&mut self, context: C, error_reason: &str, event_id: Option<&str>)
-    where
-        C: Into<LogErrorContext>,
+    pub fn log_error<'a, C>(
+        &mut self,
+        context: C,
+        category: ErrorCategory,
+        error_reason: &str,
+        event_id: Option<&str>,
+    ) where
+        C: Into<LogErrorContext<'a>>,
     {
         let context = context.into();
 
-        // Categorize and track the error
-        let category = ErrorCategory::from_error_message(error_reason);
+        // Track the error
         self.error_stats.increment(category);
 
         // Truncate long error messages for compactness (keep first 100 chars)
@@ -328,6 +532,7 @@ impl StorageManager {
                 // Truncate ID to first 8 chars for compactness
                 let short_id = if id.len() > 8 { &id[..8] } else { id };
                 error!(
+                    file = context.file,
                     line = context.line,
                     thread = context.thread_id,
                     chunk_start = context.chunk_start,
@@ -337,6 +542,7 @@ impl StorageManager {
                 );
             } else {
                 error!(
+                    file = context.file,
                     line = context.line,
                     thread = context.thread_id,
                     chunk_start = context.chunk_start,
@@ -349,6 +555,7 @@ impl StorageManager {
             if let Some(id) = event_id {
                 let short_id = if id.len() > 8 { &id[..8] } else { id };
                 debug!(
+                    file = context.file,
                     line = context.line,
                     thread = context.thread_id,
                     chunk_start = context.chunk_start,
@@ -359,6 +566,7 @@ impl StorageManager {
                 );
             } else {
                 debug!(
+                    file = context.file,
                     line = context.line,
                     thread = context.thread_id,
                     chunk_start = context.chunk_start,
@@ -373,16 +581,18 @@ impl StorageManager {
 
 /// Structured context for logging conversion errors
 #[derive(Debug, Clone, Copy, Default)]
-pub struct LogErrorContext {
+pub struct LogErrorContext<'a> {
+    pub file: Option<&'a str>,
     pub line: u64,
     pub thread_id: Option<usize>,
     pub chunk_start: Option<u64>,
     pub bytes_in_chunk: Option<u64>,
 }
 
-impl LogErrorContext {
+impl<'a> LogErrorContext<'a> {
     pub fn new(line: u64, thread_id: usize) -> Self {
         Self {
+            file: None,
             line,
             thread_id: Some(thread_id),
             chunk_start: None,
@@ -392,6 +602,7 @@ impl LogErrorContext {
 
     pub fn from_line(line: u64) -> Self {
         Self {
+            file: None,
             line,
             thread_id: None,
             chunk_start: None,
@@ -399,6 +610,11 @@ impl LogErrorContext {
         }
     }
 
+    pub fn with_file(mut self, file: Option<&'a str>) -> Self {
+        self.file = file;
+        self
+    }
+
     pub fn with_chunk_offset(mut self, offset: u64) -> Self {
         self.chunk_start = Some(offset);
         self
@@ -410,7 +626,7 @@ impl LogErrorContext {
     }
 }
 
-impl From<u64> for LogErrorContext {
+impl From<u64> for LogErrorContext<'_> {
     fn from(line: u64) -> Self {
         LogErrorContext::from_line(line)
     }
@@ -428,18 +644,22 @@ impl Drop for StorageManager {
         }
 
         // Close all writers (flush and drop them)
-        for (date, mut writer) in self.writers.drain() {
+        for (date, (mut writer, _)) in self.writers.drain() {
             if let Err(e) = writer.flush() {
                 tracing::error!("❌ CRITICAL: Failed to flush writer for {}: {}", date, e);
                 eprintln!("❌ CRITICAL: Failed to flush writer for {}: {}", date, e);
             }
-            // Writer's Drop will finish the gzip encoding
+            // Encoder's Drop will finish the compressed stream
+        }
+
+        if let Err(e) = self.write_seek_tables() {
+            tracing::error!("Failed to write seek tables on drop: {}", e);
         }
     }
 }
 
 impl StorageManager {
-    fn create_writer(&self, output_path: &Path) -> Result<GzipWriter> {
+    fn create_writer(&self, output_path: &Path) -> Result<EventWriter> {
         let file = OpenOptions::new()
             .create(true)
             .append(true)
@@ -448,17 +668,164 @@ impl StorageManager {
                 "Failed to open output file: {} (check disk space and permissions)",
                 output_path.display()
             ))?;
-        Ok(BufWriter::with_capacity(
-            STORAGE_WRITER_BUFFER_SIZE,
-            create_gzip_encoder_with_level(file, self.compression_level),
-        ))
+        let encoder = create_encoder(
+            file,
+            self.codec,
+            self.compression_level,
+            self.zstd_dictionary.as_deref(),
+        )?;
+        let mut writer = BufWriter::with_capacity(STORAGE_WRITER_BUFFER_SIZE, encoder);
+        write_format_header(&mut writer, self.format).context("Failed to write format header")?;
+        Ok(writer)
+    }
+}
+
+/// Result of checking an event file for a truncated compressed tail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileRepair {
+    /// Every gzip member or zstd frame in the file is complete
+    Intact,
+    /// The file ended mid-stream and was rewritten with the recoverable events
+    Repaired { recovered_events: u64 },
+}
+
+/// Detect and repair a truncated compressed tail left behind by a crash
+///
+/// A writer that is killed before finishing its gzip member (or zstd frame)
+/// leaves a file whose last member has no trailer. Everything before it
+/// still decodes, but members appended later become unreachable. If the file
+/// does not decode cleanly, the complete members are kept byte for byte and
+/// the complete events of the damaged one are re-encoded into a fresh member
+/// at the same offset, so block offsets in the index stay valid. The result
+/// atomically replaces the original. `zstd_dictionary` must match the one the
+/// file was written with.
+pub fn repair_truncated_file(
+    path: &Path,
+    compression_level: u32,
+    zstd_dictionary: Option<&[u8]>,
+) -> Result<FileRepair> {
+    let open = || File::open(path).context(format!("Failed to open file: {}", path.display()));
+
+    let mut block_events = Vec::new();
+    let scan = scan_blocks(open()?, zstd_dictionary, |_, events| {
+        block_events.push(events.take_while(|event| event.is_ok()).count() as u64);
+        Ok(())
+    })?;
+    if scan.complete {
+        return Ok(FileRepair::Intact);
+    }
+
+    let file_name = path
+        .file_name()
+        .context("Path has no file name")?
+        .to_string_lossy();
+    let repair_path = path.with_file_name(format!("{}.repair", file_name));
+
+    let mut recovered_events: u64 = block_events[..scan.block_offsets.len()].iter().sum();
+    {
+        let mut source = open()?;
+        let mut file = File::create(&repair_path).context(format!(
+            "Failed to create repair file: {}",
+            repair_path.display()
+        ))?;
+        std::io::copy(&mut (&mut source).take(scan.valid_len), &mut file)
+            .context("Failed to copy complete blocks")?;
+
+        // Anything after the complete blocks that is not a block is dropped
+        source.seek(SeekFrom::Start(scan.valid_len))?;
+        if let Ok(decoder) = BlockDecoder::new(BufReader::new(source), zstd_dictionary) {
+            let codec = decoder.codec();
+            let mut writer = None;
+
+            // A partially written record at the tail surfaces as an error too.
+            // Events keep the format they were written in, and the new member
+            // announces it even for V1 since earlier members may be V2.
+            let mut events = read_events_delimited(StopAtError(decoder));
+            let mut format = None;
+            while let Some(Ok(event)) = events.next() {
+                if writer.is_none() {
+                    let encoder = create_encoder(
+                        &file,
+                        codec,
+                        compression_level,
+                        zstd_dictionary.filter(|_| codec == Codec::Zstd),
+                    )?;
+                    writer = Some(BufWriter::with_capacity(
+                        STORAGE_WRITER_BUFFER_SIZE,
+                        encoder,
+                    ));
+                }
+                let writer = writer.as_mut().expect("Writer should exist after insert");
+                if format != Some(events.format()) {
+                    format = Some(events.format());
+                    write_format_header(writer, events.format())
+                        .context("Failed to write format header")?;
+                }
+                write_event_delimited_as(writer, &event, events.format())
+                    .context("Failed to write event")?;
+                recovered_events += 1;
+            }
+
+            if let Some(writer) = writer {
+                writer
+                    .into_inner()
+                    .map_err(|e| e.into_error())
+                    .context("Failed to flush repair file")?
+                    .finish()
+                    .context("Failed to finish repair file")?;
+            }
+        }
+        file.sync_all().context("Failed to sync repair file")?;
+    }
+
+    std::fs::rename(&repair_path, path).context(format!(
+        "Failed to replace {} with repaired file",
+        path.display()
+    ))?;
+
+    Ok(FileRepair::Repaired { recovered_events })
+}
+
+/// Take the seek table off the end of a zstd file and return it
+///
+/// Files without one, such as files written before they were seekable or
+/// cut short by a crash, are scanned for their blocks instead.
+fn take_seek_table(path: &Path, zstd_dictionary: Option<&[u8]>) -> Result<SeekTable> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(SeekTable::default()),
+        Err(e) => return Err(e).context(format!("Failed to open {}", path.display())),
+    };
+
+    if let Some(table) = SeekTable::read_from(&mut file)? {
+        let len = file.metadata()?.len();
+        file.set_len(len - table.encoded_len())
+            .context(format!("Failed to truncate {}", path.display()))?;
+        return Ok(table);
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    let scan = scan_blocks(file, zstd_dictionary, |_, _| Ok(()))?;
+    Ok(SeekTable {
+        block_offsets: scan.block_offsets,
+    })
+}
+
+/// Reader that reports end-of-stream instead of the first error
+struct StopAtError<R>(R);
+
+impl<R: Read> Read for StopAtError<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.0.read(buf).unwrap_or(0))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use proton_beam_core::ProtoEventBuilder;
+    use proton_beam_core::{
+        ProtoEventBuilder, create_decoder, create_gzip_decoder, train_zstd_dictionary,
+    };
     use tempfile::TempDir;
 
     #[test]
@@ -508,6 +875,237 @@ mod tests {
         assert!(pb_file.exists());
     }
 
+    fn test_event(i: u64) -> ProtoEvent {
+        ProtoEventBuilder::new()
+            .id(format!("{:064x}", i))
+            .pubkey("0000000000000000000000000000000000000000000000000000000000000000")
+            .created_at(1758960000)
+            .kind(1)
+            .content(format!("test {}", i))
+            .sig("0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")
+            .build()
+    }
+
+    fn read_file(path: &Path) -> Vec<ProtoEvent> {
+        let file = File::open(path).unwrap();
+        read_events_delimited(create_gzip_decoder(file))
+            .collect::<proton_beam_core::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_close_finishes_gzip_members() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = StorageManager::new(temp_dir.path(), 10, 6).unwrap();
+
+        manager.store_event(test_event(1)).unwrap();
+        manager.close().unwrap();
+        manager.store_event(test_event(2)).unwrap();
+        manager.close().unwrap();
+
+        let pb_file = temp_dir.path().join("2025_09_27.pb.gz");
+        assert_eq!(read_file(&pb_file).len(), 2);
+        assert_eq!(
+            repair_truncated_file(&pb_file, 6, None).unwrap(),
+            FileRepair::Intact
+        );
+    }
+
+    #[test]
+    fn test_v2_format_appends_and_repairs() {
+        let temp_dir = TempDir::new().unwrap();
+        let pb_file = temp_dir.path().join("2025_09_27.pb.gz");
+
+        let mut manager = StorageManager::new(temp_dir.path(), 10, 6).unwrap();
+        manager.store_event(test_event(1)).unwrap();
+        manager.close().unwrap();
+
+        let mut manager = StorageManager::new(temp_dir.path(), 10, 6)
+            .unwrap()
+            .with_format(FormatVersion::V2);
+        manager.store_event(test_event(2)).unwrap();
+        manager.close().unwrap();
+
+        // Flushed but never finished, as if the process was killed
+        manager.store_event(test_event(3)).unwrap();
+        manager.flush().unwrap();
+        std::mem::forget(manager);
+
+        assert_eq!(
+            repair_truncated_file(&pb_file, 6, None).unwrap(),
+            FileRepair::Repaired {
+                recovered_events: 3
+            }
+        );
+
+        let file = File::open(&pb_file).unwrap();
+        let mut events = read_events_delimited(create_gzip_decoder(file));
+        let read: Vec<_> = events.by_ref().map(|r| r.unwrap()).collect();
+        assert_eq!(read, vec![test_event(1), test_event(2), test_event(3)]);
+        assert_eq!(events.format(), FormatVersion::V2);
+    }
+
+    #[test]
+    fn test_v1_format_appends_after_v2_and_repairs() {
+        let temp_dir = TempDir::new().unwrap();
+        let pb_file = temp_dir.path().join("2025_09_27.pb.gz");
+
+        let mut manager = StorageManager::new(temp_dir.path(), 10, 6)
+            .unwrap()
+            .with_format(FormatVersion::V2);
+        manager.store_event(test_event(1)).unwrap();
+        manager.close().unwrap();
+
+        let mut manager = StorageManager::new(temp_dir.path(), 10, 6).unwrap();
+        manager.store_event(test_event(2)).unwrap();
+        manager.close().unwrap();
+
+        // Flushed but never finished, as if the process was killed
+        manager.store_event(test_event(3)).unwrap();
+        manager.flush().unwrap();
+        std::mem::forget(manager);
+
+        assert_eq!(
+            repair_truncated_file(&pb_file, 6, None).unwrap(),
+            FileRepair::Repaired {
+                recovered_events: 3
+            }
+        );
+
+        let file = File::open(&pb_file).unwrap();
+        let mut events = read_events_delimited(create_gzip_decoder(file));
+        let read: Vec<_> = events.by_ref().map(|r| r.unwrap()).collect();
+        assert_eq!(read, vec![test_event(1), test_event(2), test_event(3)]);
+        assert_eq!(events.format(), FormatVersion::V1);
+    }
+
+    #[test]
+    fn test_zstd_codec_with_dictionary() {
+        let temp_dir = TempDir::new().unwrap();
+        let pb_file = temp_dir.path().join("2025_09_27.pb.zst");
+        let samples: Vec<ProtoEvent> = (0..200).map(test_event).collect();
+        let dictionary = train_zstd_dictionary(&samples, FormatVersion::V1, 4096).unwrap();
+        let manager = || {
+            StorageManager::new(temp_dir.path(), 10, 3)
+                .unwrap()
+                .with_codec(Codec::Zstd)
+                .with_zstd_dictionary(dictionary.clone())
+        };
+
+        let mut first = manager();
+        first.store_event(test_event(1)).unwrap();
+        first.close().unwrap();
+
+        // Flushed but never finished, as if the process was killed
+        let mut second = manager();
+        second.store_event(test_event(2)).unwrap();
+        second.flush().unwrap();
+        std::mem::forget(second);
+
+        assert!(!temp_dir.path().join("2025_09_27.pb.gz").exists());
+        assert_eq!(
+            repair_truncated_file(&pb_file, 3, Some(&dictionary)).unwrap(),
+            FileRepair::Repaired {
+                recovered_events: 2
+            }
+        );
+
+        let file = File::open(&pb_file).unwrap();
+        let decoder = create_decoder(file, Some(&dictionary)).unwrap();
+        assert_eq!(decoder.codec(), Codec::Zstd);
+        let events: Vec<_> = read_events_delimited(decoder).map(|r| r.unwrap()).collect();
+        assert_eq!(events, vec![test_event(1), test_event(2)]);
+    }
+
+    #[test]
+    fn test_repair_truncated_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let pb_file = temp_dir.path().join("2025_09_27.pb.gz");
+
+        {
+            let mut manager = StorageManager::new(temp_dir.path(), 10, 6).unwrap();
+            for i in 0..3 {
+                manager.store_event(test_event(i)).unwrap();
+            }
+            manager.close().unwrap();
+
+            // Flushed but never finished, as if the process was killed
+            for i in 3..6 {
+                manager.store_event(test_event(i)).unwrap();
+            }
+            manager.flush().unwrap();
+            std::mem::forget(manager);
+        }
+
+        assert_eq!(
+            repair_truncated_file(&pb_file, 6, None).unwrap(),
+            FileRepair::Repaired {
+                recovered_events: 6
+            }
+        );
+        assert_eq!(
+            repair_truncated_file(&pb_file, 6, None).unwrap(),
+            FileRepair::Intact
+        );
+
+        // Members appended after the repair are readable again
+        let mut manager = StorageManager::new(temp_dir.path(), 10, 6).unwrap();
+        manager.store_event(test_event(99)).unwrap();
+        manager.close().unwrap();
+        assert_eq!(read_file(&pb_file).len(), 7);
+    }
+
+    #[test]
+    fn test_seekable_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let pb_file = temp_dir.path().join("2025_09_27.pb.zst");
+        let index_path = temp_dir.path().join("index.db");
+        let manager = || {
+            StorageManager::new(temp_dir.path(), 10, 3)
+                .unwrap()
+                .with_codec(Codec::Zstd)
+                .with_seekable(true)
+                .with_index(EventIndex::new(&index_path).unwrap())
+        };
+
+        let mut first = manager();
+        for i in 0..25 {
+            first.store_event(test_event(i)).unwrap();
+        }
+        first.close().unwrap();
+
+        // Appending picks up the existing seek table
+        let mut second = manager();
+        for i in 25..30 {
+            second.store_event(test_event(i)).unwrap();
+        }
+        second.close().unwrap();
+        drop(second);
+
+        let table = SeekTable::read_from(&mut File::open(&pb_file).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(table.block_offsets.len(), 4);
+
+        let index = EventIndex::new(&index_path).unwrap();
+        for (i, block) in [(0, 0), (15, 1), (24, 2), (29, 3)] {
+            let id = test_event(i).id;
+            let record = index.get(&id).unwrap().unwrap();
+            assert_eq!(record.block_offset, Some(table.block_offsets[block]));
+            assert_eq!(index.fetch_event(&id).unwrap(), Some(test_event(i)));
+        }
+
+        let file = File::open(&pb_file).unwrap();
+        let events: Vec<_> = read_events_delimited(create_decoder(file, None).unwrap())
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(events, (0..30).map(test_event).collect::<Vec<_>>());
+        assert_eq!(
+            repair_truncated_file(&pb_file, 3, None).unwrap(),
+            FileRepair::Intact
+        );
+    }
+
     #[test]
     fn test_error_logging() {
         // Initialize test logging
@@ -519,6 +1117,7 @@ mod tests {
         // Test error logging with event ID
         manager.log_error(
             LogErrorContext::from_line(42),
+            ErrorCategory::MissingField,
             "parse_error: missing field 'id'",
             Some("abcd1234"),
         );
@@ -526,6 +1125,7 @@ mod tests {
         // Test error logging without event ID
         manager.log_error(
             LogErrorContext::from_line(43),
+            ErrorCategory::InvalidSignature,
             "validation_error: invalid signature",
             None,
         );
@@ -535,5 +1135,53 @@ mod tests {
 
         // Verify error stats were tracked
         assert_eq!(manager.error_stats().total(), 2);
+        assert_eq!(manager.error_stats().get(ErrorCategory::MissingField), 1);
+    }
+
+    #[test]
+    fn test_error_category_from_error() {
+        let parse = |json: &str| ProtoEvent::try_from(json).unwrap_err();
+        let event = |kind: &str, tags: &str| {
+            format!(
+                r#"{{"id":"{}","pubkey":"{}","created_at":1,"kind":{},"tags":{},"content":"","sig":"{}"}}"#,
+                "a".repeat(64),
+                "b".repeat(64),
+                kind,
+                tags,
+                "c".repeat(128)
+            )
+        };
+
+        for (error, category) in [
+            (parse("not json"), ErrorCategory::ParseError),
+            (parse(r#"{"id":"abc"}"#), ErrorCategory::MissingField),
+            (parse(&event("70000", "[]")), ErrorCategory::InvalidKind),
+            (
+                parse(&event("1", r#"[["t",1]]"#)),
+                ErrorCategory::InvalidTagValue,
+            ),
+            (
+                ValidationError::EventIdMismatch {
+                    expected: "a".into(),
+                    actual: "b".into(),
+                }
+                .into(),
+                ErrorCategory::InvalidEventId,
+            ),
+            (
+                ValidationError::InvalidSignature("bad".into()).into(),
+                ErrorCategory::InvalidSignature,
+            ),
+            (
+                ValidationError::InvalidHex("id".into()).into(),
+                ErrorCategory::ValidationError,
+            ),
+            (
+                std::io::Error::other("disk full").into(),
+                ErrorCategory::StorageError,
+            ),
+        ] {
+            assert_eq!(ErrorCategory::from_error(&error), category, "{}", error);
+        }
     }
 }
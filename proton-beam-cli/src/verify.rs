@@ -0,0 +1,396 @@
+//! Integrity check of an archive and its index
+//!
+//! Every record of every event file is decoded again, and each event's ID
+//! (and optionally its signature) is recomputed. When an [`EventIndex`] is
+//! given, its rows are cross-checked against the files in both directions:
+//! each row must point at a file that holds the event, in the block it
+//! names, and each stored event must be indexed somewhere. Problems are
+//! collected in a [`VerifyReport`] rather than stopping the check.
+
+use anyhow::{Context, Result};
+use proton_beam_core::{
+    EventIndex, compute_event_hash, is_event_file, scan_blocks, validate_basic_fields,
+    validate_event_id_from_hash, validate_signature_from_hash,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Findings of [`verify_archive`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    /// Event files checked
+    pub files: u64,
+    /// Records decoded
+    pub events: u64,
+    /// Whether the index was cross-checked against the files
+    pub index_checked: bool,
+    /// Records or blocks that could not be decoded
+    pub corrupted_frames: Vec<CorruptedFrame>,
+    /// Decoded events whose fields, ID or signature do not check out
+    pub invalid_events: Vec<InvalidEvent>,
+    /// Index rows that do not match the files
+    pub orphaned_index_rows: Vec<OrphanedRow>,
+    /// Stored events the index does not know about
+    pub missing_index_rows: Vec<MissingRow>,
+}
+
+impl VerifyReport {
+    /// Total number of problems found
+    pub fn problems(&self) -> usize {
+        self.corrupted_frames.len()
+            + self.invalid_events.len()
+            + self.orphaned_index_rows.len()
+            + self.missing_index_rows.len()
+    }
+}
+
+/// A record or block that could not be decoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorruptedFrame {
+    /// Event file name
+    pub file: String,
+    /// Offset of the block, or `None` if the file could not be read at all
+    pub block_offset: Option<u64>,
+    /// Position of the record within the block, or `None` for the whole block
+    pub record: Option<u64>,
+    /// What went wrong
+    pub error: String,
+}
+
+/// A decoded event that fails validation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InvalidEvent {
+    /// Event file name
+    pub file: String,
+    /// Offset of the block holding the event
+    pub block_offset: u64,
+    /// Event ID as stored
+    pub id: String,
+    /// What went wrong
+    pub error: String,
+}
+
+/// Why an index row does not match the files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanReason {
+    /// The file the row points at does not exist
+    FileMissing,
+    /// The file exists but does not hold the event
+    EventMissing,
+    /// The event is in the file, but not in the block the row names
+    WrongBlock,
+}
+
+/// An index row that does not match the files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrphanedRow {
+    /// Event ID
+    pub id: String,
+    /// File the row points at
+    pub file: String,
+    /// Block offset the row points at, if any
+    pub block_offset: Option<u64>,
+    /// What does not match
+    pub reason: OrphanReason,
+}
+
+/// A stored event with no index row at all
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingRow {
+    /// Event ID
+    pub id: String,
+    /// File holding the event
+    pub file: String,
+    /// Offset of the block holding the event
+    pub block_offset: u64,
+}
+
+/// Check every event file in `pb_dir`, and `index` against them if given
+///
+/// Index rows are expected to hold file names relative to `pb_dir`, as
+/// `index rebuild` writes them. An event stored in several files only needs
+/// to be indexed once.
+pub fn verify_archive(
+    pb_dir: &Path,
+    index: Option<&EventIndex>,
+    zstd_dictionary: Option<&[u8]>,
+    check_signatures: bool,
+) -> Result<VerifyReport> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(pb_dir)
+        .and_then(|entries| entries.map(|entry| entry.map(|e| e.path())).collect())
+        .context(format!("Failed to read {}", pb_dir.display()))?;
+    paths.retain(|path| path.is_file() && is_event_file(path));
+    paths.sort();
+
+    let mut report = VerifyReport {
+        index_checked: index.is_some(),
+        ..VerifyReport::default()
+    };
+    let mut seen_files = HashSet::new();
+
+    for path in &paths {
+        let file = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
+        debug!("Verifying {}", file);
+        let located = verify_file(path, &file, zstd_dictionary, check_signatures, &mut report);
+        report.files += 1;
+
+        if let Some(index) = index {
+            cross_check_file(index, &file, &located, &mut report)?;
+        }
+        seen_files.insert(file);
+    }
+
+    // Rows pointing at files that are gone altogether
+    if let Some(index) = index {
+        for file in index.files()? {
+            if seen_files.contains(&file) {
+                continue;
+            }
+            warn!("Index points at missing file {}", file);
+            for row in index.query_by_file(&file)? {
+                report.orphaned_index_rows.push(OrphanedRow {
+                    id: row.id,
+                    file: row.file_path,
+                    block_offset: row.block_offset,
+                    reason: OrphanReason::FileMissing,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Decode and validate every record of one file
+///
+/// Returns the block offset of each event found, by ID.
+fn verify_file(
+    path: &Path,
+    file: &str,
+    zstd_dictionary: Option<&[u8]>,
+    check_signatures: bool,
+    report: &mut VerifyReport,
+) -> HashMap<String, u64> {
+    let mut located = HashMap::new();
+    let corrupted_before = report.corrupted_frames.len();
+
+    let scan = File::open(path).map_err(Into::into).and_then(|reader| {
+        scan_blocks(reader, zstd_dictionary, |block_offset, events| {
+            for (record, result) in events.enumerate() {
+                let event = match result {
+                    Ok(event) => event,
+                    Err(e) => {
+                        report.corrupted_frames.push(CorruptedFrame {
+                            file: file.to_string(),
+                            block_offset: Some(block_offset),
+                            record: Some(record as u64),
+                            error: e.to_string(),
+                        });
+                        break;
+                    }
+                };
+                report.events += 1;
+
+                let valid = validate_basic_fields(&event).and_then(|()| {
+                    let hash = compute_event_hash(&event)?;
+                    validate_event_id_from_hash(&event, &hash)?;
+                    if check_signatures {
+                        validate_signature_from_hash(&event, &hash)?;
+                    }
+                    Ok(())
+                });
+                if let Err(e) = valid {
+                    report.invalid_events.push(InvalidEvent {
+                        file: file.to_string(),
+                        block_offset,
+                        id: event.id.clone(),
+                        error: e.to_string(),
+                    });
+                }
+                located.entry(event.id).or_insert(block_offset);
+            }
+            Ok(())
+        })
+    });
+
+    match scan {
+        Ok(scan) if !scan.complete => {
+            // A record error already accounts for a block that broke off mid-way
+            let reported = report.corrupted_frames[corrupted_before..]
+                .iter()
+                .any(|frame| frame.block_offset == Some(scan.valid_len));
+            if !reported {
+                report.corrupted_frames.push(CorruptedFrame {
+                    file: file.to_string(),
+                    block_offset: Some(scan.valid_len),
+                    record: None,
+                    error: "Truncated or unreadable block".to_string(),
+                });
+            }
+        }
+        Ok(_) => {}
+        Err(e) => report.corrupted_frames.push(CorruptedFrame {
+            file: file.to_string(),
+            block_offset: None,
+            record: None,
+            error: e.to_string(),
+        }),
+    }
+
+    located
+}
+
+/// Compare the index rows of one file with the events found in it
+fn cross_check_file(
+    index: &EventIndex,
+    file: &str,
+    located: &HashMap<String, u64>,
+    report: &mut VerifyReport,
+) -> Result<()> {
+    let mut indexed = HashSet::new();
+    for row in index.query_by_file(file)? {
+        let reason = match (located.get(&row.id), row.block_offset) {
+            (None, _) => Some(OrphanReason::EventMissing),
+            (Some(actual), Some(offset)) if *actual != offset => Some(OrphanReason::WrongBlock),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            report.orphaned_index_rows.push(OrphanedRow {
+                id: row.id.clone(),
+                file: row.file_path,
+                block_offset: row.block_offset,
+                reason,
+            });
+        }
+        indexed.insert(row.id);
+    }
+
+    // Events indexed under another file are duplicates, not missing
+    let mut missing: Vec<_> = located
+        .iter()
+        .filter(|(id, _)| !indexed.contains(*id))
+        .collect();
+    missing.sort();
+    for (id, block_offset) in missing {
+        if !index.contains(id)? {
+            report.missing_index_rows.push(MissingRow {
+                id: id.clone(),
+                file: file.to_string(),
+                block_offset: *block_offset,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_beam_core::{
+        Codec, ProtoEvent, ProtoEventBuilder, create_encoder, write_events_delimited,
+    };
+    use tempfile::TempDir;
+
+    /// Event with a correct ID and a signature that does not verify
+    fn test_event(i: i64) -> ProtoEvent {
+        let mut event = ProtoEventBuilder::new()
+            .kind(1)
+            .pubkey(format!("{:064x}", i % 3))
+            .created_at(1_700_000_000 + i)
+            .content(format!("note {}", i))
+            .sig("0".repeat(128))
+            .build();
+        let hash = compute_event_hash(&event).unwrap();
+        event.id = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        event
+    }
+
+    fn write_file(path: &Path, events: &[ProtoEvent]) {
+        let mut file = Vec::new();
+        let mut encoder = create_encoder(&mut file, Codec::Gzip, 6, None).unwrap();
+        write_events_delimited(&mut encoder, events).unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(path, &file).unwrap();
+    }
+
+    #[test]
+    fn test_verify_archive() {
+        let temp_dir = TempDir::new().unwrap();
+        let events: Vec<_> = (0..4).map(test_event).collect();
+        let mut tampered = test_event(4);
+        tampered.content = "edited".to_string();
+        let mut day_1 = events[..3].to_vec();
+        day_1.push(tampered.clone());
+        write_file(&temp_dir.path().join("day_1.pb.gz"), &day_1);
+
+        // A second file cut off right after the gzip header
+        write_file(&temp_dir.path().join("day_2.pb.gz"), &events[3..]);
+        let day_2 = std::fs::read(temp_dir.path().join("day_2.pb.gz")).unwrap();
+        std::fs::write(temp_dir.path().join("day_2.pb.gz"), &day_2[..20]).unwrap();
+
+        // Fully indexed files check out
+        let mut index = EventIndex::new(&temp_dir.path().join("index.db")).unwrap();
+        let rows: Vec<_> = day_1.iter().map(|e| (e, "day_1.pb.gz", 0)).collect();
+        index.insert_batch_at(&rows).unwrap();
+        let report = verify_archive(temp_dir.path(), Some(&index), None, false).unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!(report.events, 4);
+        assert!(report.index_checked);
+        assert_eq!(report.invalid_events.len(), 1);
+        assert_eq!(report.invalid_events[0].id, tampered.id);
+        assert_eq!(report.corrupted_frames.len(), 1);
+        assert_eq!(report.corrupted_frames[0].file, "day_2.pb.gz");
+        assert!(report.orphaned_index_rows.is_empty());
+        assert!(report.missing_index_rows.is_empty());
+
+        // Signatures are only checked on request
+        let report = verify_archive(temp_dir.path(), None, None, true).unwrap();
+        assert!(!report.index_checked);
+        assert_eq!(report.invalid_events.len(), 4);
+
+        // Rows that point nowhere, or at the wrong block, and an unindexed event
+        assert!(index.remove(&events[2].id).unwrap());
+        let stray = test_event(5);
+        let moved = test_event(6);
+        index
+            .insert_batch_at(&[
+                (&stray, "day_1.pb.gz", 0),
+                (&moved, "day_0.pb.gz", 0),
+                (&events[3], "day_2.pb.gz", 0),
+            ])
+            .unwrap();
+        index
+            .upsert_batch_at(&[(&events[1], "day_1.pb.gz", 42)])
+            .unwrap();
+
+        let report = verify_archive(temp_dir.path(), Some(&index), None, false).unwrap();
+        let orphans: Vec<_> = report
+            .orphaned_index_rows
+            .iter()
+            .map(|row| (row.id.as_str(), row.reason))
+            .collect();
+        assert_eq!(orphans.len(), 4);
+        assert!(orphans.contains(&(events[1].id.as_str(), OrphanReason::WrongBlock)));
+        assert!(orphans.contains(&(stray.id.as_str(), OrphanReason::EventMissing)));
+        assert!(orphans.contains(&(events[3].id.as_str(), OrphanReason::EventMissing)));
+        assert!(orphans.contains(&(moved.id.as_str(), OrphanReason::FileMissing)));
+        assert_eq!(
+            report.missing_index_rows,
+            [MissingRow {
+                id: events[2].id.clone(),
+                file: "day_1.pb.gz".to_string(),
+                block_offset: 0,
+            }]
+        );
+        assert_eq!(report.problems(), 7);
+    }
+}
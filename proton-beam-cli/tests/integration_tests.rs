@@ -1,6 +1,10 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
-use proton_beam_core::EventIndex;
+use proton_beam_core::{
+    EventIndex, SeekTable, create_decoder, create_gzip_decoder, is_event_file,
+    read_events_delimited,
+};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
@@ -560,3 +564,1101 @@ fn test_parallel_vs_sequential_consistency() {
         "Both outputs should contain valid event counts"
     );
 }
+
+#[test]
+fn test_convert_with_filter() {
+    for threads in ["1", "4"] {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut cmd = Command::cargo_bin("proton-beam").unwrap();
+        cmd.arg("convert")
+            .arg(sample_events_path())
+            .arg("--output-dir")
+            .arg(temp_dir.path())
+            .arg("--filter")
+            .arg(r##"{"kinds":[1],"#p":[]}"##)
+            .arg("-j")
+            .arg(threads)
+            .arg("--no-progress");
+
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("Filtered out:"));
+
+        let mut kinds = HashSet::new();
+        for entry in fs::read_dir(temp_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.to_string_lossy().ends_with(".pb.gz") {
+                let gz = create_gzip_decoder(fs::File::open(&path).unwrap());
+                for event in read_events_delimited(gz) {
+                    kinds.insert(event.unwrap().kind);
+                }
+            }
+        }
+        assert_eq!(kinds, HashSet::from([1]), "threads: {}", threads);
+    }
+}
+
+#[test]
+fn test_convert_rejects_invalid_filter() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("proton-beam").unwrap();
+    cmd.arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--filter")
+        .arg(r#"{"kinds":"1"}"#);
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid filter"));
+}
+
+/// Read every event from the final event files in a directory, sorted by id
+fn read_output_events(dir: &Path) -> Vec<proton_beam_core::ProtoEvent> {
+    read_output_events_with_dictionary(dir, None)
+}
+
+fn read_output_events_with_dictionary(
+    dir: &Path,
+    dictionary: Option<&[u8]>,
+) -> Vec<proton_beam_core::ProtoEvent> {
+    let mut events = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if is_event_file(&path) {
+            let decoder = create_decoder(fs::File::open(&path).unwrap(), dictionary).unwrap();
+            events.extend(read_events_delimited(decoder).map(|e| e.unwrap()));
+        }
+    }
+    events.sort_by(|a, b| a.id.cmp(&b.id));
+    events
+}
+
+#[test]
+fn test_convert_v2_format() {
+    let v1_dir = TempDir::new().unwrap();
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(v1_dir.path())
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    for threads in ["1", "4"] {
+        let v2_dir = TempDir::new().unwrap();
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(sample_events_path())
+            .arg("--output-dir")
+            .arg(v2_dir.path())
+            .arg("--format")
+            .arg("v2")
+            .arg("-j")
+            .arg(threads)
+            .arg("--no-progress")
+            .assert()
+            .success();
+
+        assert_eq!(
+            read_output_events(v2_dir.path()),
+            read_output_events(v1_dir.path()),
+            "threads: {}",
+            threads
+        );
+    }
+}
+
+#[test]
+fn test_convert_zstd_codec() {
+    let gzip_dir = TempDir::new().unwrap();
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(gzip_dir.path())
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    for threads in ["1", "4"] {
+        let zstd_dir = TempDir::new().unwrap();
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(sample_events_path())
+            .arg("--output-dir")
+            .arg(zstd_dir.path())
+            .arg("--codec")
+            .arg("zstd")
+            .arg("-j")
+            .arg(threads)
+            .arg("--no-progress")
+            .assert()
+            .success();
+
+        let has_gzip = fs::read_dir(zstd_dir.path())
+            .unwrap()
+            .any(|e| e.unwrap().path().to_string_lossy().ends_with(".pb.gz"));
+        assert!(!has_gzip, "threads: {}", threads);
+        assert_eq!(
+            read_output_events(zstd_dir.path()),
+            read_output_events(gzip_dir.path()),
+            "threads: {}",
+            threads
+        );
+    }
+}
+
+#[test]
+fn test_zstd_dictionary_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+    let dictionary_path = temp_dir.path().join("nostr.dict");
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("train-dictionary")
+        .arg(sample_events_path())
+        .arg("--output")
+        .arg(&dictionary_path)
+        .arg("--max-size")
+        .arg("4096")
+        .assert()
+        .success();
+
+    // A dictionary only applies to zstd
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--zstd-dictionary")
+        .arg(&dictionary_path)
+        .arg("--no-progress")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("requires --codec zstd"));
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--codec")
+        .arg("zstd")
+        .arg("--zstd-dictionary")
+        .arg(&dictionary_path)
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    let dictionary = fs::read(&dictionary_path).unwrap();
+    let events = read_output_events_with_dictionary(&pb_dir, Some(&dictionary));
+    assert!(!events.is_empty());
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .arg("--zstd-dictionary")
+        .arg(&dictionary_path)
+        .assert()
+        .success();
+
+    let index = EventIndex::new(&pb_dir.join("index.db")).unwrap();
+    assert_eq!(index.stats().unwrap().total_events, events.len() as u64);
+}
+
+#[test]
+fn test_seekable_convert_and_fetch() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--codec")
+        .arg("zstd")
+        .arg("--seekable")
+        .arg("--batch-size")
+        .arg("10")
+        .arg("--parallel")
+        .arg("2")
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .assert()
+        .success();
+
+    let events = read_output_events(&pb_dir);
+    assert!(events.len() > 10);
+
+    let index = EventIndex::new(&pb_dir.join("index.db")).unwrap();
+    let mut block_offsets = HashSet::new();
+    for event in &events {
+        let record = index.get(&event.id).unwrap().unwrap();
+        let block_offset = record.block_offset.expect("rebuild records block offsets");
+        block_offsets.insert((record.file_path, block_offset));
+        assert_eq!(index.fetch_event(&event.id).unwrap().as_ref(), Some(event));
+    }
+    assert!(block_offsets.len() >= events.len() / 10);
+
+    for entry in fs::read_dir(&pb_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if is_event_file(&path) {
+            let table = SeekTable::read_from(&mut fs::File::open(&path).unwrap()).unwrap();
+            assert!(table.is_some(), "{} has no seek table", path.display());
+        }
+    }
+}
+
+#[test]
+fn test_index_rebuild_with_tags() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .arg("--tags")
+        .assert()
+        .success();
+
+    let events = read_output_events(&pb_dir);
+    let (name, value) = events
+        .iter()
+        .flat_map(|event| &event.tags)
+        .find_map(|tag| match tag.values.as_slice() {
+            [name, value, ..] if name.len() == 1 => Some((name.clone(), value.clone())),
+            _ => None,
+        })
+        .expect("sample events have single-letter tags");
+    let tagged: HashSet<_> = events
+        .iter()
+        .filter(|event| {
+            event
+                .tags
+                .iter()
+                .any(|tag| tag.values.len() >= 2 && tag.values[0] == name && tag.values[1] == value)
+        })
+        .map(|event| event.id.clone())
+        .collect();
+
+    let index = EventIndex::new(&pb_dir.join("index.db")).unwrap();
+    assert!(index.tag_index_enabled());
+    let found: HashSet<_> = index
+        .query_by_tag(&name, &value)
+        .unwrap()
+        .into_iter()
+        .map(|record| record.id)
+        .collect();
+    assert_eq!(found, tagged);
+}
+
+#[test]
+fn test_serve_requires_index() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("serve")
+        .arg(temp_dir.path())
+        .arg("--listen")
+        .arg("127.0.0.1:0")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Index not found"))
+        .stderr(predicate::str::contains("index rebuild"));
+}
+
+fn export(args: &[&str]) -> String {
+    let output = Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("export")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "export failed: {:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_export_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    let exported = export(&[pb_dir.to_str().unwrap()]);
+    let events = read_output_events(&pb_dir);
+    assert_eq!(exported.lines().count(), events.len());
+    let exported_ids: HashSet<String> = exported
+        .lines()
+        .map(|line| {
+            let event: serde_json::Value = serde_json::from_str(line).unwrap();
+            event["id"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(
+        exported_ids,
+        events.iter().map(|event| event.id.clone()).collect()
+    );
+
+    // Converting the export and exporting again yields the same bytes
+    let jsonl_path = temp_dir.path().join("exported.jsonl");
+    fs::write(&jsonl_path, &exported).unwrap();
+    let pb_dir_2 = temp_dir.path().join("pb_data_2");
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(&jsonl_path)
+        .arg("--output-dir")
+        .arg(&pb_dir_2)
+        .arg("--no-progress")
+        .assert()
+        .success();
+    let mut first: Vec<_> = exported.lines().collect();
+    let second = export(&[pb_dir_2.to_str().unwrap()]);
+    let mut second: Vec<_> = second.lines().collect();
+    first.sort();
+    second.sort();
+    assert_eq!(first, second);
+
+    // Filters apply to the scan and to the index, which exports newest first
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .assert()
+        .success();
+    let kind = events[0].kind.to_string();
+    let expected: HashSet<_> = events
+        .iter()
+        .filter(|event| event.kind == events[0].kind)
+        .map(|event| event.id.clone())
+        .collect();
+    for args in [
+        vec![pb_dir.to_str().unwrap(), "--kind", kind.as_str()],
+        vec![
+            pb_dir.to_str().unwrap(),
+            "--kind",
+            kind.as_str(),
+            "--use-index",
+        ],
+    ] {
+        let lines = export(&args);
+        let ids: HashSet<String> = lines
+            .lines()
+            .map(|line| {
+                let event: serde_json::Value = serde_json::from_str(line).unwrap();
+                event["id"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(ids, expected, "args: {:?}", args);
+    }
+
+    // Gzip output
+    let gz_path = temp_dir.path().join("events.jsonl.gz");
+    export(&[
+        pb_dir.to_str().unwrap(),
+        "--output",
+        gz_path.to_str().unwrap(),
+    ]);
+    let mut decompressed = String::new();
+    std::io::Read::read_to_string(
+        &mut create_gzip_decoder(fs::File::open(&gz_path).unwrap()),
+        &mut decompressed,
+    )
+    .unwrap();
+    assert_eq!(decompressed, exported);
+}
+
+#[test]
+fn test_convert_with_process_deletions() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+
+    // Converting twice merges into existing files, moving indexed events
+    for _ in 0..2 {
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(sample_events_path())
+            .arg("--output-dir")
+            .arg(&pb_dir)
+            .arg("--parallel")
+            .arg("2")
+            .arg("--process-deletions")
+            .arg("--no-progress")
+            .assert()
+            .success();
+    }
+
+    let events = read_output_events(&pb_dir);
+    let index = EventIndex::new(&pb_dir.join("index.db")).unwrap();
+    assert!(index.deletion_processing_enabled());
+    assert_eq!(index.stats().unwrap().total_events, events.len() as u64);
+    for event in &events {
+        assert_eq!(index.fetch_event(&event.id).unwrap().as_ref(), Some(event));
+    }
+}
+
+#[test]
+fn test_compact() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+    let pubkey = "7776c32d4b1d1e8bf2a96babeb43ad9ade157bd363d89b87fb63e6f145558888";
+    let event = |id: char, kind: u16, created_at: i64, tags: serde_json::Value| {
+        serde_json::json!({
+            "id": id.to_string().repeat(64),
+            "pubkey": pubkey,
+            "created_at": created_at,
+            "kind": kind,
+            "tags": tags,
+            "content": "",
+            "sig": "0".repeat(128),
+        })
+        .to_string()
+    };
+    let note = 'a'.to_string().repeat(64);
+    let lines = [
+        event('a', 1, 1_700_000_050, serde_json::json!([])),
+        event('b', 0, 1_700_000_000, serde_json::json!([])),
+        event('c', 0, 1_700_000_100, serde_json::json!([])),
+        event('d', 5, 1_700_000_200, serde_json::json!([["e", note]])),
+        event('e', 1, 1_700_000_010, serde_json::json!([])),
+    ];
+    let input = temp_dir.path().join("events.jsonl");
+    fs::write(&input, lines.join("\n")).unwrap();
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(&input)
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--validate-signatures=false")
+        .arg("--validate-event-ids=false")
+        .arg("--no-progress")
+        .assert()
+        .success();
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .arg("--deletions")
+        .assert()
+        .success();
+
+    // The deleted note and the superseded profile go; the rest is sorted by created_at
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("compact")
+        .arg(&pb_dir)
+        .arg("--codec")
+        .arg("zstd")
+        .arg("--since")
+        .arg("2023-11-14")
+        .arg("--until")
+        .arg("2023-11-14")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("dropped: 2"));
+
+    assert!(!pb_dir.join("2023_11_14.pb.gz").exists());
+    let compacted = pb_dir.join("2023_11_14.pb.zst");
+    let decoder = create_decoder(fs::File::open(&compacted).unwrap(), None).unwrap();
+    let ids: Vec<String> = read_events_delimited(decoder)
+        .map(|event| event.unwrap().id[..1].to_string())
+        .collect();
+    assert_eq!(ids, vec!["e", "c", "d"]);
+
+    let index = EventIndex::new(&pb_dir.join("index.db")).unwrap();
+    assert_eq!(index.stats().unwrap().total_events, 3);
+    for event in read_output_events(&pb_dir) {
+        let record = index.get(&event.id).unwrap().unwrap();
+        assert_eq!(record.file_path, "2023_11_14.pb.zst");
+        assert_eq!(index.fetch_event(&event.id).unwrap(), Some(event));
+    }
+}
+
+#[test]
+fn test_compact_leaves_unreadable_day_untouched() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--no-progress")
+        .assert()
+        .success();
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .arg("--deletions")
+        .assert()
+        .success();
+
+    // Cut the day short, as a crash mid-write would
+    let day = fs::read_dir(&pb_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| is_event_file(path))
+        .unwrap();
+    let mut bytes = fs::read(&day).unwrap();
+    bytes.truncate(bytes.len() / 2);
+    fs::write(&day, &bytes).unwrap();
+    let date = day.file_name().unwrap().to_str().unwrap()[..10].replace('_', "-");
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("compact")
+        .arg(&pb_dir)
+        .arg("--since")
+        .arg(&date)
+        .arg("--until")
+        .arg(&date)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("could not be read"));
+
+    assert_eq!(fs::read(&day).unwrap(), bytes);
+    let leftovers: Vec<_> = fs::read_dir(&pb_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "temp files left: {:?}", leftovers);
+}
+
+#[test]
+fn test_verify() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(sample_events_path())
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--no-progress")
+        .assert()
+        .success();
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .assert()
+        .success();
+
+    let verify = || {
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("verify")
+            .arg(&pb_dir)
+            .assert()
+    };
+    let output = verify().success().get_output().stdout.clone();
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(report["events"], read_output_events(&pb_dir).len());
+    assert_eq!(report["index_checked"], true);
+    for problems in [
+        "corrupted_frames",
+        "invalid_events",
+        "orphaned_index_rows",
+        "missing_index_rows",
+    ] {
+        assert_eq!(report[problems], serde_json::json!([]), "{}", problems);
+    }
+
+    // Garbage after the last block of a file is reported, and fails the check
+    let file = fs::read_dir(&pb_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| is_event_file(path))
+        .unwrap();
+    let mut bytes = fs::read(&file).unwrap();
+    let valid_len = bytes.len();
+    bytes.extend_from_slice(b"garbage");
+    fs::write(&file, bytes).unwrap();
+
+    let output = verify().failure().get_output().stdout.clone();
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let frames = report["corrupted_frames"].as_array().unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(
+        frames[0]["file"],
+        file.file_name().unwrap().to_str().unwrap()
+    );
+    assert_eq!(frames[0]["block_offset"], valid_len);
+}
+
+#[test]
+fn test_parallel_merge_keeps_index_offsets_valid() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+    let lines = sample_event_lines(150);
+    let convert = |name: &str, lines: &[String], parallel: &str| {
+        let path = temp_dir.path().join(name);
+        fs::write(&path, lines.join("\n")).unwrap();
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(&path)
+            .arg("--output-dir")
+            .arg(&pb_dir)
+            .arg("--parallel")
+            .arg(parallel)
+            .arg("--no-progress")
+            .assert()
+            .success();
+    };
+
+    // Two sequential runs leave days with several blocks
+    convert("a.jsonl", &lines[..50], "1");
+    convert("b.jsonl", &lines[50..100], "1");
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .assert()
+        .success();
+
+    // A parallel run rewrites those days while merging
+    convert("c.jsonl", &lines[100..], "2");
+
+    let report = Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("verify")
+        .arg(&pb_dir)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&report).unwrap();
+    assert_eq!(report["orphaned_index_rows"], serde_json::json!([]));
+    assert_eq!(report["missing_index_rows"], serde_json::json!([]));
+
+    let exported = export(&[pb_dir.to_str().unwrap(), "--use-index"]);
+    assert_eq!(exported.lines().count(), read_output_events(&pb_dir).len());
+}
+
+#[test]
+fn test_codec_switch_keeps_index_valid() {
+    let temp_dir = TempDir::new().unwrap();
+    let pb_dir = temp_dir.path().join("pb_data");
+    let lines = sample_event_lines(150);
+    let convert = |name: &str, lines: &[String], codec: &str| {
+        let path = temp_dir.path().join(name);
+        fs::write(&path, lines.join("\n")).unwrap();
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(&path)
+            .arg("--output-dir")
+            .arg(&pb_dir)
+            .arg("--codec")
+            .arg(codec)
+            .arg("--parallel")
+            .arg("2")
+            .arg("--no-progress")
+            .assert()
+            .success();
+    };
+
+    convert("a.jsonl", &lines[..100], "gzip");
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("index")
+        .arg("rebuild")
+        .arg(&pb_dir)
+        .assert()
+        .success();
+
+    // Every gzip day is folded into a zstd file and removed
+    convert("b.jsonl", &lines, "zstd");
+    let gzip_files: Vec<_> = fs::read_dir(&pb_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_str().unwrap().ends_with(".pb.gz"))
+        .collect();
+    assert!(gzip_files.is_empty(), "gzip files left: {:?}", gzip_files);
+
+    let report = Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("verify")
+        .arg(&pb_dir)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let report: serde_json::Value = serde_json::from_slice(&report).unwrap();
+    assert_eq!(report["orphaned_index_rows"], serde_json::json!([]));
+    assert_eq!(report["missing_index_rows"], serde_json::json!([]));
+
+    let exported = export(&[pb_dir.to_str().unwrap(), "--use-index"]);
+    assert_eq!(exported.lines().count(), read_output_events(&pb_dir).len());
+}
+
+#[test]
+fn test_convert_resume() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("events.jsonl");
+    fs::copy(sample_events_path(), &input).unwrap();
+    let content = fs::read_to_string(&input).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    let mid: usize = lines[..75].iter().map(|line| line.len() + 1).sum();
+    let rejects = temp_dir.path().join("rejects.jsonl");
+
+    // What the first half of the input converts to on its own
+    let first_half = temp_dir.path().join("first_half.jsonl");
+    fs::write(&first_half, &content[..mid]).unwrap();
+    let expected_dir = temp_dir.path().join("expected");
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(&first_half)
+        .arg("--output-dir")
+        .arg(&expected_dir)
+        .arg("--no-progress")
+        .arg("--parallel")
+        .arg("1")
+        .assert()
+        .success();
+
+    let convert = |output_dir: &Path, resume: bool| {
+        let mut cmd = Command::cargo_bin("proton-beam").unwrap();
+        cmd.arg("convert")
+            .arg(&input)
+            .arg("--output-dir")
+            .arg(output_dir)
+            .arg("--no-progress")
+            .arg("--parallel")
+            .arg("2")
+            .arg("--rejects")
+            .arg(&rejects);
+        if resume {
+            cmd.arg("--resume");
+        }
+        cmd.assert()
+    };
+
+    let output_dir = temp_dir.path().join("pb_data");
+    convert(&output_dir, true)
+        .failure()
+        .stderr(predicate::str::contains("No checkpoint to resume from"));
+
+    // A run that died early in the first chunk, after finishing the second
+    let tmp_dir = output_dir.join("tmp");
+    let chunk = |start: usize, end: usize, position: usize, done: bool| {
+        serde_json::json!({
+            "start": start, "end": end, "position": position, "lines": 0,
+            "valid_events": 0, "invalid_events": 0, "skipped_lines": 0, "filtered_events": 0,
+            "files": {}, "done": done,
+        })
+    };
+    let checkpoint = serde_json::json!({
+        "input": fs::canonicalize(&input).unwrap(),
+        "input_size": content.len(),
+        "codec": "gzip",
+        "chunks": [chunk(0, mid, 0, false), chunk(mid, content.len(), content.len(), true)],
+    });
+    fs::create_dir_all(&tmp_dir).unwrap();
+    fs::write(tmp_dir.join("checkpoint.json"), checkpoint.to_string()).unwrap();
+    fs::write(tmp_dir.join("thread_0_2020_01_01.pb.gz.tmp"), "partial").unwrap();
+    let reject = |line: u64| {
+        let record = serde_json::json!({
+            "line": line, "category": "parse_error", "error": "parse_error: test", "raw": "x",
+        });
+        format!("{}\n", record)
+    };
+    fs::write(tmp_dir.join("thread_0_rejects.jsonl.tmp"), reject(1)).unwrap();
+    fs::write(tmp_dir.join("thread_1_rejects.jsonl.tmp"), reject(2)).unwrap();
+
+    convert(&output_dir, false)
+        .failure()
+        .stderr(predicate::str::contains("--resume"));
+
+    // Only the unfinished chunk is converted again, without the partial write
+    convert(&output_dir, true).success();
+    assert!(!tmp_dir.exists(), "Temp directory was not cleaned up");
+    let ids = |dir: &Path| -> Vec<String> {
+        read_output_events(dir)
+            .into_iter()
+            .map(|event| event.id)
+            .collect()
+    };
+    assert_eq!(ids(&output_dir), ids(&expected_dir));
+
+    // Rejects written after the first chunk's checkpoint are dropped, not repeated,
+    // and the chunks' rejects follow in input order
+    let records: Vec<serde_json::Value> = fs::read_to_string(&rejects)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let (last, reconverted) = records.split_last().unwrap();
+    assert_eq!(last["raw"], "x");
+    assert_eq!(last["line"], 75 + 2);
+    assert!(!reconverted.is_empty());
+    let lines: Vec<_> = reconverted
+        .iter()
+        .map(|r| r["line"].as_u64().unwrap())
+        .collect();
+    assert!(lines.windows(2).all(|pair| pair[0] < pair[1]));
+    for (record, &line) in reconverted.iter().zip(&lines) {
+        assert_eq!(
+            record["raw"],
+            content.lines().nth(line as usize - 1).unwrap()
+        );
+    }
+}
+
+fn sample_event_lines(count: usize) -> Vec<String> {
+    fs::read_to_string(sample_events_path())
+        .unwrap()
+        .lines()
+        .take(count)
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_convert_relay_messages() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("messages.jsonl");
+    let pb_dir = temp_dir.path().join("pb_data");
+    let events = sample_event_lines(3);
+    let messages = [
+        format!(r#"["EVENT","sub1",{}]"#, events[0]),
+        format!(r#"["EVENT",{}]"#, events[1]),
+        r#"["EOSE","sub1"]"#.to_string(),
+        events[2].clone(),
+        r#"["NOTICE","slow down"]"#.to_string(),
+    ];
+    fs::write(&input, messages.join("\n")).unwrap();
+
+    for parallel in ["1", "2"] {
+        let _ = fs::remove_dir_all(&pb_dir);
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(&input)
+            .arg("--output-dir")
+            .arg(&pb_dir)
+            .arg("--parallel")
+            .arg(parallel)
+            .arg("--no-progress")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Skipped lines:      2"));
+        assert_eq!(read_output_events(&pb_dir).len(), 3);
+    }
+}
+
+#[test]
+fn test_convert_relay_database() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("nostr.db");
+    let pb_dir = temp_dir.path().join("pb_data");
+
+    // The parts of the nostr-rs-relay schema the reader relies on
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE event (id INTEGER PRIMARY KEY, kind INTEGER, hidden INTEGER, content TEXT NOT NULL);",
+    )
+    .unwrap();
+    let events = sample_event_lines(4);
+    for (i, event) in events.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO event (kind, hidden, content) VALUES (1, ?1, ?2)",
+            rusqlite::params![i == 3, event],
+        )
+        .unwrap();
+    }
+    drop(conn);
+
+    Command::cargo_bin("proton-beam")
+        .unwrap()
+        .arg("convert")
+        .arg(&db_path)
+        .arg("--output-dir")
+        .arg(&pb_dir)
+        .arg("--no-progress")
+        .assert()
+        .success();
+
+    // The hidden (deleted) event is left out
+    let converted: HashSet<String> = read_output_events(&pb_dir)
+        .into_iter()
+        .map(|event| event.id)
+        .collect();
+    let expected: HashSet<String> = events[..3]
+        .iter()
+        .map(|json| {
+            proton_beam_core::ProtoEvent::try_from(json.as_str())
+                .unwrap()
+                .id
+        })
+        .collect();
+    assert_eq!(converted, expected);
+}
+
+#[test]
+fn test_parallel_compressed_and_stdin_inputs() {
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().unwrap();
+    let content = fs::read(sample_events_path()).unwrap();
+    let input = temp_dir.path().join("events.jsonl.gz");
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(&content).unwrap();
+    fs::write(&input, encoder.finish().unwrap()).unwrap();
+
+    let ids = |dir: &Path| -> HashSet<String> {
+        read_output_events(dir)
+            .into_iter()
+            .map(|event| event.id)
+            .collect()
+    };
+    let convert = |name: &str, input: &str, threads: &str| -> PathBuf {
+        let output_dir = temp_dir.path().join(name);
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(input)
+            .arg("--output-dir")
+            .arg(&output_dir)
+            .arg("--parallel")
+            .arg(threads)
+            .arg("--no-progress")
+            .write_stdin(content.clone())
+            .assert()
+            .success();
+        assert!(!output_dir.join("tmp").exists());
+        output_dir
+    };
+
+    let expected = ids(&convert("sequential", input.to_str().unwrap(), "1"));
+    assert!(!expected.is_empty());
+    assert_eq!(
+        ids(&convert("compressed", input.to_str().unwrap(), "4")),
+        expected
+    );
+    assert_eq!(ids(&convert("stdin", "-", "3")), expected);
+}
+
+#[test]
+fn test_convert_rejects() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("events.jsonl");
+    let pb_dir = temp_dir.path().join("pb_data");
+    let events = sample_event_lines(2);
+    let bad_sig = events[1].replace("\"sig\": \"8", "\"sig\": \"9");
+    // The relay message without an event still counts as a line
+    let lines = [
+        r#"["EOSE", "sub1"]"#.to_string(),
+        events[0].clone(),
+        r#"{"id": "invalid", "not": "complete"}"#.to_string(),
+        bad_sig.clone(),
+    ];
+    fs::write(&input, lines.join("\n")).unwrap();
+
+    for (parallel, rejects) in [("1", "rejects.jsonl"), ("2", "rejects.jsonl.gz")] {
+        let _ = fs::remove_dir_all(&pb_dir);
+        let rejects = temp_dir.path().join(rejects);
+        Command::cargo_bin("proton-beam")
+            .unwrap()
+            .arg("convert")
+            .arg(&input)
+            .arg("--output-dir")
+            .arg(&pb_dir)
+            .arg("--parallel")
+            .arg(parallel)
+            .arg("--rejects")
+            .arg(&rejects)
+            .arg("--no-progress")
+            .assert()
+            .success();
+
+        let content = fs::read(&rejects).unwrap();
+        let content = if rejects.extension().unwrap() == "gz" {
+            let mut decoded = String::new();
+            std::io::Read::read_to_string(
+                &mut flate2::read::GzDecoder::new(&content[..]),
+                &mut decoded,
+            )
+            .unwrap();
+            decoded
+        } else {
+            String::from_utf8(content).unwrap()
+        };
+        let mut records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        records.sort_by_key(|record| record["raw"].as_str().unwrap().len());
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["category"], "missing_field");
+        assert_eq!(records[0]["raw"], lines[2]);
+        assert_eq!(records[1]["category"], "invalid_signature");
+        assert_eq!(records[1]["raw"], bad_sig);
+        assert!(records[1]["event_id"].is_string());
+        for (record, line) in records.iter().zip([3, 4]) {
+            assert_eq!(record["file"], input.display().to_string());
+            assert_eq!(record["line"], line);
+        }
+    }
+}
@@ -7,7 +7,7 @@ fn main() -> Result<()> {
     // Derive Eq for all messages (PartialEq is already derived by prost)
     config.type_attribute(".", "#[derive(Eq)]");
 
-    // Compile the protobuf schema
-    config.compile_protos(&["proto/nostr.proto"], &["proto/"])?;
+    // Compile the protobuf schemas
+    config.compile_protos(&["proto/nostr.proto", "proto/nostr_v2.proto"], &["proto/"])?;
     Ok(())
 }
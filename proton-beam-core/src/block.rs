@@ -0,0 +1,378 @@
+//! Seekable block layout for event files
+//!
+//! An event file is a sequence of independently compressed blocks: gzip
+//! members or zstd frames, each holding a run of length-delimited events
+//! that starts with its own format header when needed. Given its byte
+//! offset, any block can be decoded on its own, which is what lets
+//! [`EventIndex::fetch_event`](crate::EventIndex::fetch_event) return an
+//! event without decompressing the rest of the file.
+//!
+//! Seekable zstd files end with a [`SeekTable`] listing every block offset.
+//! It is stored in a skippable frame, so every zstd decoder (including
+//! `zstd -d`) ignores it. Gzip has no such frame type; gzip files are
+//! scanned with [`scan_blocks`] instead.
+
+use crate::storage::{Codec, EventIterator, read_events_delimited};
+use crate::{Error, ProtoEvent, Result};
+use flate2::bufread::GzDecoder;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+/// Magic number of the skippable zstd frame holding a [`SeekTable`]
+const SEEK_TABLE_FRAME_MAGIC: u32 = 0x184D_2A5E;
+
+/// Last four bytes of a file that ends with a [`SeekTable`]
+const SEEK_TABLE_FOOTER_MAGIC: [u8; 4] = *b"PBST";
+
+/// Whether `bytes` start with any skippable zstd frame (magic 0x184D2A5?)
+fn is_skippable_frame(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && bytes[0] & 0xF0 == 0x50 && bytes[1..4] == [0x2A, 0x4D, 0x18]
+}
+
+/// Byte offsets of the blocks in a seekable zstd file
+///
+/// Encoded as a skippable frame, all integers little endian:
+/// `[magic u32][frame size u32][offset u64]...[block count u32]["PBST"]`.
+/// The footer sits at the very end so the table can be found by seeking
+/// backwards from the end of the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    /// Offset of every block, in file order
+    pub block_offsets: Vec<u64>,
+}
+
+impl SeekTable {
+    /// Size of the encoded table in bytes
+    pub fn encoded_len(&self) -> u64 {
+        16 + 8 * self.block_offsets.len() as u64
+    }
+
+    /// Append the table to `writer`
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let count = u32::try_from(self.block_offsets.len())
+            .map_err(|_| Error::Conversion("Too many blocks for a seek table".to_string()))?;
+        let mut buf = Vec::with_capacity(self.encoded_len() as usize);
+        buf.extend_from_slice(&SEEK_TABLE_FRAME_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&(8 * count + 8).to_le_bytes());
+        for offset in &self.block_offsets {
+            buf.extend_from_slice(&offset.to_le_bytes());
+        }
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(&SEEK_TABLE_FOOTER_MAGIC);
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Read the table at the end of `reader`, if the stream ends with one
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        if len < 16 {
+            return Ok(None);
+        }
+
+        let mut footer = [0u8; 8];
+        reader.seek(SeekFrom::End(-8))?;
+        reader.read_exact(&mut footer)?;
+        if footer[4..] != SEEK_TABLE_FOOTER_MAGIC {
+            return Ok(None);
+        }
+        let count = u32::from_le_bytes(footer[..4].try_into().unwrap()) as u64;
+        let table_len = 16 + 8 * count;
+        if table_len > len {
+            return Ok(None);
+        }
+
+        let mut table = vec![0u8; table_len as usize];
+        reader.seek(SeekFrom::Start(len - table_len))?;
+        reader.read_exact(&mut table)?;
+        let frame_size = u32::from_le_bytes(table[4..8].try_into().unwrap()) as u64;
+        if table[..4] != SEEK_TABLE_FRAME_MAGIC.to_le_bytes() || frame_size != 8 * count + 8 {
+            return Ok(None);
+        }
+
+        let block_offsets = table[8..table.len() - 8]
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok(Some(Self { block_offsets }))
+    }
+}
+
+/// Decompressing reader for a single block
+pub enum BlockDecoder<R: BufRead> {
+    /// One gzip member
+    Gzip(GzDecoder<R>),
+    /// One zstd frame
+    Zstd(zstd::stream::read::Decoder<'static, R>),
+}
+
+impl<R: BufRead> BlockDecoder<R> {
+    /// Decode the block at the current position of `reader`
+    ///
+    /// The codec is detected from the block's magic bytes. Only that block
+    /// is consumed from `reader`.
+    pub fn new(mut reader: R, dictionary: Option<&[u8]>) -> Result<Self> {
+        let codec = Codec::from_magic(reader.fill_buf()?).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "No gzip member or zstd frame at this offset",
+            )
+        })?;
+
+        Ok(match codec {
+            Codec::Gzip => Self::Gzip(GzDecoder::new(reader)),
+            Codec::Zstd => Self::Zstd(
+                zstd::stream::read::Decoder::with_dictionary(
+                    reader,
+                    dictionary.unwrap_or_default(),
+                )?
+                .single_frame(),
+            ),
+        })
+    }
+
+    /// Codec of the block being decoded
+    pub fn codec(&self) -> Codec {
+        match self {
+            Self::Gzip(_) => Codec::Gzip,
+            Self::Zstd(_) => Codec::Zstd,
+        }
+    }
+}
+
+impl<R: BufRead> Read for BlockDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Read the events of the block starting at `offset`
+///
+/// # Example
+///
+/// ```no_run
+/// use proton_beam_core::read_block;
+/// use std::fs::File;
+///
+/// for result in read_block(File::open("2025_01_01.pb.zst")?, 4096, None)? {
+///     println!("Event ID: {}", result?.id);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn read_block<R: Read + Seek>(
+    mut reader: R,
+    offset: u64,
+    dictionary: Option<&[u8]>,
+) -> Result<EventIterator<BlockDecoder<BufReader<R>>>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let decoder = BlockDecoder::new(BufReader::new(reader), dictionary)?;
+    Ok(read_events_delimited(decoder))
+}
+
+/// Outcome of [`scan_blocks`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockScan {
+    /// Offset of every complete block, in file order
+    pub block_offsets: Vec<u64>,
+    /// Length of the stream up to the end of the last complete block
+    pub valid_len: u64,
+    /// Whether the stream ended right after a complete block
+    pub complete: bool,
+}
+
+/// Walk the blocks of a stream in order, handing each one's events to `visit`
+///
+/// `visit` receives the block offset and an iterator over its events; it does
+/// not have to consume all of them. Skippable zstd frames, such as old seek
+/// tables, are stepped over. The walk stops at the first block that is
+/// truncated or not a block at all, which [`BlockScan::complete`] reports.
+pub fn scan_blocks<R, F>(reader: R, dictionary: Option<&[u8]>, mut visit: F) -> Result<BlockScan>
+where
+    R: Read,
+    F: FnMut(u64, &mut dyn Iterator<Item = Result<ProtoEvent>>) -> Result<()>,
+{
+    let mut reader = BufReader::new(CountingReader {
+        inner: reader,
+        count: 0,
+    });
+    let mut scan = BlockScan::default();
+
+    loop {
+        let offset = reader.get_ref().count - reader.buffer().len() as u64;
+        let head = reader.fill_buf()?;
+        if head.is_empty() {
+            scan.complete = true;
+            return Ok(scan);
+        }
+
+        if is_skippable_frame(head) {
+            let mut header = [0u8; 8];
+            if reader.read_exact(&mut header).is_err() {
+                return Ok(scan);
+            }
+            let size = u32::from_le_bytes(header[4..].try_into().unwrap()) as u64;
+            if std::io::copy(&mut (&mut reader).take(size), &mut std::io::sink())? < size {
+                return Ok(scan);
+            }
+            scan.valid_len = offset + 8 + size;
+            continue;
+        }
+        if Codec::from_magic(head).is_none() {
+            return Ok(scan);
+        }
+
+        let decoder = BlockDecoder::new(&mut reader, dictionary)?;
+        let mut events = read_events_delimited(ErrorLatch {
+            inner: decoder,
+            failed: false,
+        });
+        visit(offset, &mut events)?;
+        // Stop at the first error: a failed decoder may keep failing
+        while let Some(Ok(_)) = events.next() {}
+
+        let mut latch = events.into_inner();
+        let drained = std::io::copy(&mut latch, &mut std::io::sink()).is_ok();
+        if latch.failed || !drained {
+            return Ok(scan);
+        }
+        drop(latch);
+
+        scan.block_offsets.push(offset);
+        scan.valid_len = reader.get_ref().count - reader.buffer().len() as u64;
+    }
+}
+
+/// Reader that counts the bytes read through it
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// Reader that remembers whether the inner reader ever failed
+///
+/// [`EventIterator`] treats an unexpected end of stream as the end of the
+/// events, which would hide a truncated block.
+struct ErrorLatch<R> {
+    inner: R,
+    failed: bool,
+}
+
+impl<R: Read> Read for ErrorLatch<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).inspect_err(|_| self.failed = true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProtoEventBuilder, create_encoder, write_events_delimited};
+    use std::io::Cursor;
+
+    fn test_event(i: u64) -> ProtoEvent {
+        ProtoEventBuilder::new()
+            .id(format!("{:064x}", i))
+            .pubkey(format!("{:064x}", i % 3))
+            .created_at(1_700_000_000 + i as i64)
+            .kind(1)
+            .content(format!("event {}", i))
+            .sig(format!("{:0128x}", i))
+            .build()
+    }
+
+    /// Write `blocks` as separate blocks followed by a seek table
+    fn write_blocks(codec: Codec, blocks: &[Vec<ProtoEvent>]) -> (Vec<u8>, SeekTable) {
+        let mut file = Vec::new();
+        let mut table = SeekTable::default();
+        for block in blocks {
+            table.block_offsets.push(file.len() as u64);
+            let mut encoder = create_encoder(&mut file, codec, 3, None).unwrap();
+            write_events_delimited(&mut encoder, block).unwrap();
+            encoder.finish().unwrap();
+        }
+        (file, table)
+    }
+
+    fn test_blocks() -> Vec<Vec<ProtoEvent>> {
+        (0..3)
+            .map(|b| (b * 10..b * 10 + 10).map(test_event).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_block_at_offset() {
+        let blocks = test_blocks();
+        for codec in [Codec::Gzip, Codec::Zstd] {
+            let (file, table) = write_blocks(codec, &blocks);
+
+            let events: Vec<ProtoEvent> =
+                read_block(Cursor::new(&file), table.block_offsets[1], None)
+                    .unwrap()
+                    .collect::<Result<_>>()
+                    .unwrap();
+            assert_eq!(events, blocks[1], "{}", codec);
+
+            assert!(read_block(Cursor::new(&file), 1, None).is_err());
+        }
+    }
+
+    #[test]
+    fn test_seek_table_round_trip() {
+        let blocks = test_blocks();
+        let (mut file, table) = write_blocks(Codec::Zstd, &blocks);
+        assert_eq!(SeekTable::read_from(&mut Cursor::new(&file)).unwrap(), None);
+
+        table.write_to(&mut file).unwrap();
+        assert_eq!(
+            SeekTable::read_from(&mut Cursor::new(&file)).unwrap(),
+            Some(table.clone())
+        );
+
+        // Plain zstd readers skip the table
+        let decoder = crate::create_decoder(Cursor::new(&file), None).unwrap();
+        let events: Vec<ProtoEvent> = read_events_delimited(decoder)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(events, blocks.concat());
+    }
+
+    #[test]
+    fn test_scan_blocks() {
+        let blocks = test_blocks();
+        for codec in [Codec::Gzip, Codec::Zstd] {
+            let (mut file, table) = write_blocks(codec, &blocks);
+            if codec == Codec::Zstd {
+                table.write_to(&mut file).unwrap();
+            }
+
+            let mut seen = Vec::new();
+            let scan = scan_blocks(Cursor::new(&file), None, |offset, events| {
+                seen.push((offset, events.count()));
+                Ok(())
+            })
+            .unwrap();
+            assert!(scan.complete, "{}", codec);
+            assert_eq!(scan.block_offsets, table.block_offsets);
+            assert_eq!(scan.valid_len, file.len() as u64);
+            assert!(seen.iter().all(|(_, count)| *count == 10));
+
+            // A truncated last block is not reported
+            let (mut file, _) = write_blocks(codec, &blocks);
+            file.truncate(file.len() - 4);
+            let scan = scan_blocks(Cursor::new(&file), None, |_, _| Ok(())).unwrap();
+            assert!(!scan.complete, "{}", codec);
+            assert_eq!(scan.block_offsets, table.block_offsets[..2]);
+            assert_eq!(scan.valid_len, table.block_offsets[2]);
+        }
+    }
+}
@@ -3,7 +3,20 @@
 //! This module provides idiomatic Rust trait implementations for converting
 //! between JSON strings, nostr-sdk Events, and ProtoEvents.
 
-use crate::{ProtoEvent, Tag, error::Result};
+use crate::error::{Result, ValidationError};
+use crate::proto::v2;
+use crate::{ProtoEvent, Tag};
+
+/// Fields every NIP-01 event must have
+const REQUIRED_FIELDS: [&str; 7] = [
+    "id",
+    "pubkey",
+    "created_at",
+    "kind",
+    "tags",
+    "content",
+    "sig",
+];
 
 // ============================================================================
 // From/TryFrom Trait Implementations
@@ -47,8 +60,16 @@ impl TryFrom<&str> for ProtoEvent {
 
     fn try_from(json: &str) -> Result<Self> {
         // Parse JSON to serde_json::Value first for validation (single parse path)
-        let value: serde_json::Value = serde_json::from_str(json)
-            .map_err(|e| crate::error::Error::Conversion(format!("Invalid JSON: {}", e)))?;
+        let value: serde_json::Value = serde_json::from_str(json)?;
+
+        // Report absent fields by name rather than through nostr-sdk's message
+        if let Some(object) = value.as_object()
+            && let Some(field) = REQUIRED_FIELDS
+                .into_iter()
+                .find(|field| !object.contains_key(*field))
+        {
+            return Err(ValidationError::MissingField(field).into());
+        }
 
         // Pre-validate the kind field before passing to nostr-sdk
         // This prevents nostr-sdk from silently truncating invalid kind values
@@ -57,10 +78,7 @@ impl TryFrom<&str> for ProtoEvent {
             .and_then(|k| k.as_i64())
             .filter(|k| !(0..=65535).contains(k))
         {
-            return Err(crate::error::Error::Conversion(format!(
-                "Event kind {} is out of valid range (0-65535). Nostr event kinds must fit in a u16.",
-                kind
-            )));
+            return Err(ValidationError::InvalidKind(kind).into());
         }
 
         // Validate tags: check that all tag values are strings
@@ -83,10 +101,12 @@ impl TryFrom<&str> for ProtoEvent {
                             } else {
                                 "unknown"
                             };
-                            return Err(crate::error::Error::Conversion(format!(
-                                "Invalid tag value: tags[{}][{}] is {}, expected string. All Nostr tag values must be strings.",
-                                tag_idx, elem_idx, type_name
-                            )));
+                            return Err(ValidationError::InvalidTagValue {
+                                tag: tag_idx,
+                                index: elem_idx,
+                                found: type_name,
+                            }
+                            .into());
                         }
                     }
                 }
@@ -101,8 +121,6 @@ impl TryFrom<&str> for ProtoEvent {
             // Try to identify which field caused the issue
             let hint = if msg.contains("expected a string") {
                 " (hint: ensure id, pubkey, sig are hex strings and all tag values are strings)"
-            } else if msg.contains("missing field") {
-                " (required Nostr event fields: id, pubkey, created_at, kind, tags, content, sig)"
             } else {
                 ""
             };
@@ -124,6 +142,10 @@ impl TryFrom<String> for ProtoEvent {
 
 /// Convert from a ProtoEvent reference to a JSON string (fallible)
 ///
+/// The output is canonical: compact, with fields in NIP-01 order (`id`,
+/// `pubkey`, `created_at`, `kind`, `tags`, `content`, `sig`), so the same
+/// event always serializes to the same bytes.
+///
 /// # Example
 ///
 /// ```no_run
@@ -146,24 +168,60 @@ impl TryFrom<&ProtoEvent> for String {
     type Error = crate::error::Error;
 
     fn try_from(event: &ProtoEvent) -> Result<Self> {
-        // Convert protobuf tags to JSON array format
-        let tags: Vec<Vec<String>> = event.tags.iter().map(|tag| tag.values.clone()).collect();
-
-        // Build JSON object manually to match Nostr format exactly
-        let json_obj = serde_json::json!({
-            "id": event.id,
-            "pubkey": event.pubkey,
-            "created_at": event.created_at,
-            "kind": event.kind,
-            "tags": tags,
-            "content": event.content,
-            "sig": event.sig,
-        });
-
-        Ok(serde_json::to_string(&json_obj)?)
+        // The Serialize impl writes the fields in NIP-01 order
+        Ok(serde_json::to_string(event)?)
+    }
+}
+
+/// Convert a ProtoEvent to the compact `nostr.v2` format (fallible)
+///
+/// id, pubkey and sig must be lowercase hex (or empty) so that converting
+/// back yields the exact same strings.
+impl TryFrom<&ProtoEvent> for v2::ProtoEvent {
+    type Error = crate::error::Error;
+
+    fn try_from(event: &ProtoEvent) -> Result<Self> {
+        Ok(v2::ProtoEvent {
+            id: hex_to_bytes("id", &event.id)?,
+            pubkey: hex_to_bytes("pubkey", &event.pubkey)?,
+            created_at: event.created_at,
+            kind: event.kind,
+            tags: event.tags.clone(),
+            content: event.content.clone(),
+            sig: hex_to_bytes("sig", &event.sig)?,
+        })
     }
 }
 
+/// Convert a compact `nostr.v2` event back to a ProtoEvent (infallible)
+impl From<v2::ProtoEvent> for ProtoEvent {
+    fn from(event: v2::ProtoEvent) -> Self {
+        ProtoEvent {
+            id: hex::encode(event.id),
+            pubkey: hex::encode(event.pubkey),
+            created_at: event.created_at,
+            kind: event.kind,
+            tags: event.tags,
+            content: event.content,
+            sig: hex::encode(event.sig),
+        }
+    }
+}
+
+/// Decode a hex field, rejecting anything that would not re-encode identically
+fn hex_to_bytes(field: &str, value: &str) -> Result<Vec<u8>> {
+    if value.bytes().any(|b| b.is_ascii_uppercase()) {
+        return Err(ValidationError::InvalidHex(format!(
+            "{} is not lowercase hex: {}",
+            field, value
+        ))
+        .into());
+    }
+    hex::decode(value).map_err(|e| {
+        ValidationError::InvalidHex(format!("{} is not valid hex: {}", field, e)).into()
+    })
+}
+
 // ============================================================================
 // Convenience Functions (for ergonomics)
 // ============================================================================
@@ -369,6 +427,24 @@ mod tests {
         assert_eq!(parsed["tags"][0][1], "event_id");
     }
 
+    #[test]
+    fn test_proto_to_json_is_canonical() {
+        let canonical = r#"{"id":"abc","pubkey":"def","created_at":1234567890,"kind":1,"tags":[["e","x"],["t","a\"b"]],"content":"line\nbreak","sig":"123"}"#;
+
+        // Key order and whitespace of the input do not matter
+        let reordered = r#"{ "sig": "123", "content": "line\nbreak", "kind": 1,
+            "tags": [["e", "x"], ["t", "a\"b"]], "created_at": 1234567890,
+            "pubkey": "def", "id": "abc" }"#;
+        for json in [canonical, reordered] {
+            let event: ProtoEvent = serde_json::from_str(json).unwrap();
+            assert_eq!(proto_to_json(&event).unwrap(), canonical);
+        }
+
+        let original = json_to_proto(SAMPLE_EVENT_JSON).unwrap();
+        let json = proto_to_json(&original).unwrap();
+        assert_eq!(proto_to_json(&json_to_proto(&json).unwrap()).unwrap(), json);
+    }
+
     #[test]
     fn test_round_trip_conversion() {
         // Convert JSON -> Proto -> JSON and verify they match
@@ -417,6 +493,45 @@ mod tests {
         assert!(err_msg.contains("out of valid range"));
     }
 
+    #[test]
+    fn test_structured_conversion_errors() {
+        use crate::error::{Error, ValidationError};
+
+        let event = |kind: &str, tags: &str| {
+            format!(
+                r#"{{"id":"{}","pubkey":"{}","created_at":1,"kind":{},"tags":{},"content":"","sig":"{}"}}"#,
+                "a".repeat(64),
+                "b".repeat(64),
+                kind,
+                tags,
+                "c".repeat(128)
+            )
+        };
+
+        assert!(matches!(
+            json_to_proto("not valid json"),
+            Err(Error::JsonParse(_))
+        ));
+        assert!(matches!(
+            json_to_proto(r#"{"id":"abc","pubkey":"def"}"#),
+            Err(Error::Validation(ValidationError::MissingField(
+                "created_at"
+            )))
+        ));
+        assert!(matches!(
+            json_to_proto(&event("70202", "[]")),
+            Err(Error::Validation(ValidationError::InvalidKind(70202)))
+        ));
+        assert!(matches!(
+            json_to_proto(&event("1", r#"[["t","x"],["e",null]]"#)),
+            Err(Error::Validation(ValidationError::InvalidTagValue {
+                tag: 1,
+                index: 1,
+                found: "null"
+            }))
+        ));
+    }
+
     #[test]
     fn test_kind_max_valid() {
         // Test that kind = 65535 is accepted
@@ -489,4 +604,44 @@ mod tests {
         assert_eq!(parsed["tags"][1].as_array().unwrap().len(), 2);
         assert_eq!(parsed["tags"][2].as_array().unwrap().len(), 2);
     }
+
+    // ========================================================================
+    // Tests for nostr.v2 conversion
+    // ========================================================================
+
+    #[test]
+    fn test_v2_round_trip() {
+        let event = ProtoEvent::try_from(SAMPLE_EVENT_JSON).unwrap();
+        let compact = v2::ProtoEvent::try_from(&event).unwrap();
+
+        assert_eq!(compact.id.len(), 32);
+        assert_eq!(compact.pubkey.len(), 32);
+        assert_eq!(compact.sig.len(), 64);
+        assert_eq!(ProtoEvent::from(compact), event);
+    }
+
+    #[test]
+    fn test_v2_rejects_lossy_hex() {
+        let event = ProtoEvent::try_from(SAMPLE_EVENT_JSON).unwrap();
+
+        for bad in [
+            ProtoEvent {
+                id: event.id.to_uppercase(),
+                ..event.clone()
+            },
+            ProtoEvent {
+                pubkey: "not hex".to_string(),
+                ..event.clone()
+            },
+            ProtoEvent {
+                sig: "abc".to_string(),
+                ..event.clone()
+            },
+        ] {
+            assert!(matches!(
+                v2::ProtoEvent::try_from(&bad),
+                Err(crate::Error::Validation(ValidationError::InvalidHex(_)))
+            ));
+        }
+    }
 }
@@ -56,9 +56,25 @@ pub enum ValidationError {
     #[error("Invalid timestamp: {0}")]
     InvalidTimestamp(i64),
 
-    /// Invalid kind
-    #[error("Invalid kind: {0}")]
-    InvalidKind(i32),
+    /// Kind outside the u16 range (0-65535)
+    #[error("Event kind {0} is out of valid range (0-65535). Nostr event kinds must fit in a u16.")]
+    InvalidKind(i64),
+
+    /// Tag value that is not a string
+    #[error(
+        "Invalid tag value: tags[{tag}][{index}] is {found}, expected string. All Nostr tag values must be strings."
+    )]
+    InvalidTagValue {
+        tag: usize,
+        index: usize,
+        found: &'static str,
+    },
+
+    /// Required event field that is absent
+    #[error(
+        "Missing field: {0} (required Nostr event fields: id, pubkey, created_at, kind, tags, content, sig)"
+    )]
+    MissingField(&'static str),
 
     /// Schnorr signature parsing error
     #[error("Invalid signature: {0}")]
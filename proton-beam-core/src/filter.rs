@@ -0,0 +1,269 @@
+//! NIP-01 subscription filters
+//!
+//! A [`Filter`] describes a set of events the same way a relay `REQ` does:
+//! by ids, authors, kinds, `#x` tag values and a `created_at` range. It
+//! serializes to and from the NIP-01 JSON form, and also deserializes from
+//! a TOML-style table where tag filters live in a `tags` sub-table.
+//!
+//! # Examples
+//!
+//! ```
+//! use proton_beam_core::{Filter, ProtoEventBuilder};
+//!
+//! let filter = Filter::try_from(r##"{"kinds": [1], "#t": ["nostr"], "since": 1000}"##)?;
+//!
+//! let event = ProtoEventBuilder::new()
+//!     .kind(1)
+//!     .created_at(2000)
+//!     .add_tag(vec!["t", "nostr"])
+//!     .build();
+//! assert!(filter.matches(&event));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::{Error, ProtoEvent, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A NIP-01 filter
+///
+/// Empty lists and `None` bounds do not constrain anything, so the default
+/// filter matches every event. `limit` only caps how many events a query
+/// returns; [`Filter::matches`] ignores it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Event IDs (hex-encoded)
+    pub ids: Vec<String>,
+    /// Author public keys (hex-encoded)
+    pub authors: Vec<String>,
+    /// Event kinds
+    pub kinds: Vec<u16>,
+    /// Tag filters keyed by single-letter tag name (without the `#`)
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// Oldest `created_at` to match (inclusive)
+    pub since: Option<i64>,
+    /// Newest `created_at` to match (inclusive)
+    pub until: Option<i64>,
+    /// Maximum number of events to return
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    /// Check whether an event matches every condition of the filter
+    ///
+    /// A tag filter matches if the event has a tag with that name whose
+    /// first value is one of the listed values.
+    pub fn matches(&self, event: &ProtoEvent) -> bool {
+        if !self.ids.is_empty() && !self.ids.iter().any(|id| id == &event.id) {
+            return false;
+        }
+        if !self.authors.is_empty() && !self.authors.iter().any(|a| a == &event.pubkey) {
+            return false;
+        }
+        if !self.kinds.is_empty() && !self.kinds.iter().any(|&k| i32::from(k) == event.kind) {
+            return false;
+        }
+        if self.since.is_some_and(|since| event.created_at < since)
+            || self.until.is_some_and(|until| event.created_at > until)
+        {
+            return false;
+        }
+        self.tags.iter().all(|(name, values)| {
+            values.is_empty()
+                || event.tags.iter().any(|tag| {
+                    tag.values.len() >= 2
+                        && tag.values[0] == *name
+                        && values.iter().any(|v| v == &tag.values[1])
+                })
+        })
+    }
+
+    /// Serialize to NIP-01 JSON, e.g. for a `REQ` message
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Tag filter names must be a single ASCII letter
+fn tag_name(key: &str) -> Option<&str> {
+    let name = key.strip_prefix('#').unwrap_or(key);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(name),
+        _ => None,
+    }
+}
+
+impl TryFrom<&str> for Filter {
+    type Error = Error;
+
+    /// Parse a NIP-01 filter from JSON
+    fn try_from(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+impl Serialize for Filter {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        if !self.ids.is_empty() {
+            map.serialize_entry("ids", &self.ids)?;
+        }
+        if !self.authors.is_empty() {
+            map.serialize_entry("authors", &self.authors)?;
+        }
+        if !self.kinds.is_empty() {
+            map.serialize_entry("kinds", &self.kinds)?;
+        }
+        for (name, values) in &self.tags {
+            if !values.is_empty() {
+                map.serialize_entry(&format!("#{}", name), values)?;
+            }
+        }
+        if let Some(since) = self.since {
+            map.serialize_entry("since", &since)?;
+        }
+        if let Some(until) = self.until {
+            map.serialize_entry("until", &until)?;
+        }
+        if let Some(limit) = self.limit {
+            map.serialize_entry("limit", &limit)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error as _;
+
+        #[derive(Deserialize)]
+        struct FilterHelper {
+            #[serde(default)]
+            ids: Vec<String>,
+            #[serde(default)]
+            authors: Vec<String>,
+            #[serde(default)]
+            kinds: Vec<u16>,
+            #[serde(default)]
+            tags: BTreeMap<String, Vec<String>>,
+            since: Option<i64>,
+            until: Option<i64>,
+            limit: Option<usize>,
+            /// NIP-01 `#x` keys
+            #[serde(flatten)]
+            tag_keys: BTreeMap<String, Vec<String>>,
+        }
+
+        let helper = FilterHelper::deserialize(deserializer)?;
+
+        let mut tags = BTreeMap::new();
+        for (key, values) in helper.tags {
+            let name = tag_name(&key)
+                .ok_or_else(|| D::Error::custom(format!("invalid tag filter name: {}", key)))?;
+            tags.insert(name.to_string(), values);
+        }
+        for (key, values) in helper.tag_keys {
+            let name = key
+                .strip_prefix('#')
+                .and_then(tag_name)
+                .ok_or_else(|| D::Error::custom(format!("unknown filter field: {}", key)))?;
+            tags.insert(name.to_string(), values);
+        }
+
+        Ok(Filter {
+            ids: helper.ids,
+            authors: helper.authors,
+            kinds: helper.kinds,
+            tags,
+            since: helper.since,
+            until: helper.until,
+            limit: helper.limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtoEventBuilder;
+
+    fn test_event() -> ProtoEvent {
+        ProtoEventBuilder::new()
+            .id("id1")
+            .pubkey("alice")
+            .kind(1)
+            .created_at(1000)
+            .add_tag(vec!["e", "abc", "wss://relay.example.com"])
+            .add_tag(vec!["t", "nostr"])
+            .build()
+    }
+
+    #[test]
+    fn test_default_matches_everything() {
+        assert!(Filter::default().matches(&test_event()));
+    }
+
+    #[test]
+    fn test_matches_each_field() {
+        let event = test_event();
+        let matching = Filter::try_from(
+            r##"{"ids": ["id1"], "authors": ["alice", "bob"], "kinds": [1, 7],
+                 "#e": ["abc"], "#t": ["bitcoin", "nostr"], "since": 1000, "until": 1000}"##,
+        )
+        .unwrap();
+        assert!(matching.matches(&event));
+
+        for json in [
+            r#"{"ids": ["id2"]}"#,
+            r#"{"authors": ["bob"]}"#,
+            r#"{"kinds": [7]}"#,
+            r##"{"#e": ["def"]}"##,
+            r##"{"#p": ["abc"]}"##,
+            r#"{"since": 1001}"#,
+            r#"{"until": 999}"#,
+        ] {
+            let filter = Filter::try_from(json).unwrap();
+            assert!(!filter.matches(&event), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json =
+            r##"{"ids":["id1"],"kinds":[1],"#e":["abc"],"#t":["nostr"],"since":10,"limit":5}"##;
+        let filter = Filter::try_from(json).unwrap();
+        assert_eq!(filter.limit, Some(5));
+        assert_eq!(filter.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn test_tags_table() {
+        let filter: Filter =
+            serde_json::from_str(r##"{"tags": {"e": ["abc"], "#t": ["nostr"]}}"##).unwrap();
+        assert_eq!(filter.tags["e"], vec!["abc"]);
+        assert_eq!(filter.tags["t"], vec!["nostr"]);
+        assert!(filter.matches(&test_event()));
+    }
+
+    #[test]
+    fn test_rejects_invalid_filters() {
+        for json in [
+            r#"{"kinds": [70000]}"#,
+            r#"{"search": "nostr"}"#,
+            r##"{"#long": ["x"]}"##,
+            r#"{"tags": {"": ["x"]}}"#,
+            r#"{"since": "yesterday"}"#,
+        ] {
+            assert!(Filter::try_from(json).is_err(), "{}", json);
+        }
+    }
+}
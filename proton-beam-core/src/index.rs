@@ -3,7 +3,14 @@
 //! This module provides a SQLite-based index for Nostr events, enabling:
 //! - Fast event deduplication by ID
 //! - Queries by kind, pubkey, or date range
-//! - Event ID to file path mapping
+//! - Composite NIP-01 filter queries with cursor-based pagination
+//! - Event ID to file path mapping, down to the block holding the event
+//! - Relay provenance: which relays delivered each event, and when
+//! - Optional tag index for queries such as "all replies to event X"
+//! - Latest version of each replaceable and addressable event
+//! - Optional NIP-09 deletion processing, tombstoning deleted events
+//! - Dead (deleted or superseded) events per file, for compaction
+//! - Full event retrieval, reading only the blocks that hold the events
 //!
 //! # Examples
 //!
@@ -39,13 +46,60 @@
 //! # }
 //! ```
 
-use crate::{Error, ProtoEvent, Result};
-use rusqlite::{Connection, OptionalExtension, params};
-use std::path::Path;
+use crate::{
+    Error, EventAddress, Filter, ProtoEvent, Result, create_decoder, read_block,
+    read_events_delimited,
+};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, Row, params, params_from_iter};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Columns selected for an [`EventRecord`], in the order `record_from_row` expects
+const RECORD_COLUMNS: &str =
+    "id, kind, pubkey, created_at, file_path, indexed_at, block_offset, deleted_by";
+
+/// Condition on `events` matching a tag name and value
+const TAG_CONDITION: &str = "id IN (SELECT event_id FROM event_tags WHERE name = ? AND value = ?)";
+
+/// Record an event as the latest version at its address unless a newer one
+/// is already there; on equal timestamps the lowest ID wins, as in NIP-01
+const UPSERT_LATEST: &str = "INSERT INTO latest_versions (address, event_id, created_at)
+     VALUES (?1, ?2, ?3)
+     ON CONFLICT (address) DO UPDATE SET
+         event_id = excluded.event_id,
+         created_at = excluded.created_at
+     WHERE excluded.created_at > latest_versions.created_at
+        OR (excluded.created_at = latest_versions.created_at
+            AND excluded.event_id < latest_versions.event_id)";
+
+/// Kind of NIP-09 deletion requests
+const DELETION_KIND: i32 = 5;
+
+/// Find a recorded deletion request that applies to an event: one by its
+/// author naming its ID, or one naming its address that is not older than it
+const PENDING_DELETION: &str = "SELECT deletion_id FROM deletions
+     WHERE (target = ?1 AND pubkey = ?2) OR (target = ?3 AND created_at >= ?4)
+     ORDER BY created_at, deletion_id LIMIT 1";
+
+/// Index records read per query while streaming events
+const STREAM_PAGE_SIZE: usize = 500;
 
 /// SQLite-based event index for deduplication and queries
 pub struct EventIndex {
     conn: Connection,
+    /// Directory that indexed file paths are relative to
+    data_dir: PathBuf,
+    /// zstd dictionary the indexed files were compressed with
+    zstd_dictionary: Option<Vec<u8>>,
+    /// Whether tags of inserted events go into `event_tags`
+    index_tags: bool,
+    /// Whether inserted kind 5 events tombstone the events they delete
+    process_deletions: bool,
+    /// Whether queries return tombstoned events
+    include_deleted: bool,
 }
 
 /// Record returned from index queries
@@ -63,6 +117,65 @@ pub struct EventRecord {
     pub file_path: String,
     /// Unix timestamp when the event was indexed
     pub indexed_at: i64,
+    /// Byte offset of the gzip member or zstd frame holding the event, if known
+    pub block_offset: Option<u64>,
+    /// ID of the NIP-09 deletion request that deleted the event, if any
+    pub deleted_by: Option<String>,
+}
+
+/// Map a row selected with [`RECORD_COLUMNS`] to an [`EventRecord`]
+fn record_from_row(row: &Row<'_>) -> rusqlite::Result<EventRecord> {
+    Ok(EventRecord {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        pubkey: row.get(2)?,
+        created_at: row.get(3)?,
+        file_path: row.get(4)?,
+        indexed_at: row.get(5)?,
+        block_offset: row.get(6)?,
+        deleted_by: row.get(7)?,
+    })
+}
+
+/// Position in a newest-first query, just past the last record returned
+///
+/// Events are ordered by `created_at` and then by ID, so a cursor still
+/// works when many events share a timestamp.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryCursor {
+    /// `created_at` of the last record returned
+    pub created_at: i64,
+    /// ID of the last record returned
+    pub id: String,
+}
+
+impl From<&EventRecord> for QueryCursor {
+    fn from(record: &EventRecord) -> Self {
+        Self {
+            created_at: record.created_at,
+            id: record.id.clone(),
+        }
+    }
+}
+
+/// One page of [`EventIndex::query`] results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPage {
+    /// Matching records, newest first
+    pub records: Vec<EventRecord>,
+    /// Cursor for the next page, or `None` if this was the last page
+    pub next: Option<QueryCursor>,
+}
+
+/// A relay that delivered an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelaySighting {
+    /// Event ID (hex-encoded)
+    pub event_id: String,
+    /// URL of the relay the event was received from
+    pub relay_url: String,
+    /// Unix timestamp when the relay first delivered the event
+    pub received_at: i64,
 }
 
 /// Statistics about the event index
@@ -110,7 +223,7 @@ impl EventIndex {
         // Create schema if needed
         Self::create_schema(&conn)?;
 
-        Ok(Self { conn })
+        Self::with_connection(conn, db_path)
     }
 
     /// Create or open an event index with bulk insert optimizations
@@ -146,7 +259,146 @@ impl EventIndex {
         // Create schema if needed
         Self::create_schema(&conn)?;
 
-        Ok(Self { conn })
+        Self::with_connection(conn, db_path)
+    }
+
+    fn with_connection(conn: Connection, db_path: &Path) -> Result<Self> {
+        let index_tags = Self::read_setting(&conn, "tag_index")?;
+        let process_deletions = Self::read_setting(&conn, "deletions")?;
+
+        Ok(Self {
+            conn,
+            data_dir: db_path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            zstd_dictionary: None,
+            index_tags,
+            process_deletions,
+            include_deleted: false,
+        })
+    }
+
+    /// Read an on/off setting stored in the database
+    fn read_setting(conn: &Connection, key: &str) -> Result<bool> {
+        Ok(conn
+            .query_row(
+                "SELECT value FROM index_settings WHERE key = ?",
+                params![key],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to read index settings: {}", e)))?
+            .is_some_and(|value| value == "1"))
+    }
+
+    /// Resolve indexed file paths against `data_dir`
+    ///
+    /// Defaults to the directory holding the database, which is where the
+    /// CLI and daemon put it.
+    pub fn with_data_dir(mut self, data_dir: &Path) -> Self {
+        self.data_dir = data_dir.to_path_buf();
+        self
+    }
+
+    /// Read zstd files with the dictionary they were compressed with
+    pub fn with_zstd_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.zstd_dictionary = Some(dictionary);
+        self
+    }
+
+    /// Return events deleted by NIP-09 requests from queries
+    ///
+    /// By default queries skip them; [`get`](Self::get),
+    /// [`fetch_event`](Self::fetch_event), [`contains`](Self::contains) and
+    /// [`query_by_file`](Self::query_by_file) always include them.
+    pub fn with_deleted_events(mut self, include: bool) -> Self {
+        self.include_deleted = include;
+        self
+    }
+
+    /// Whether the tags of inserted events are indexed
+    pub fn tag_index_enabled(&self) -> bool {
+        self.index_tags
+    }
+
+    /// Turn the tag index on or off
+    ///
+    /// The setting is stored in the database, so it sticks when the index is
+    /// reopened. Single-letter tags with a value (`e`, `p`, `t`, ...) are
+    /// indexed, the same ones NIP-01 filters can match. Enabling it does not
+    /// backfill events that are already indexed (rebuild the index for that);
+    /// disabling it drops all tag rows.
+    pub fn set_tag_index(&mut self, enabled: bool) -> Result<()> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to start transaction: {}", e)))?;
+
+        tx.execute(
+            "INSERT OR REPLACE INTO index_settings (key, value) VALUES ('tag_index', ?)",
+            params![if enabled { "1" } else { "0" }],
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to update index settings: {}", e)))?;
+        if !enabled {
+            tx.execute("DELETE FROM event_tags", [])
+                .map_err(|e| Error::InvalidEvent(format!("Failed to drop tag index: {}", e)))?;
+        }
+
+        tx.commit()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to commit transaction: {}", e)))?;
+
+        self.index_tags = enabled;
+        Ok(())
+    }
+
+    /// Whether inserted NIP-09 deletion requests are acted on
+    pub fn deletion_processing_enabled(&self) -> bool {
+        self.process_deletions
+    }
+
+    /// Turn NIP-09 deletion processing on or off
+    ///
+    /// While it is on, every inserted kind 5 event tombstones the events it
+    /// names, through `e` tags (event IDs) or `a` tags (addresses, covering
+    /// versions up to the request's `created_at`), as long as they have the
+    /// same author as the request. Requests are remembered, so targets
+    /// indexed later are tombstoned too. Tombstoned events stay in the index
+    /// and in their files, but queries skip them.
+    ///
+    /// The setting is stored in the database. Enabling it does not process
+    /// requests that are already indexed (rebuild the index for that);
+    /// disabling it clears all tombstones.
+    pub fn set_deletion_processing(&mut self, enabled: bool) -> Result<()> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to start transaction: {}", e)))?;
+
+        tx.execute(
+            "INSERT OR REPLACE INTO index_settings (key, value) VALUES ('deletions', ?)",
+            params![if enabled { "1" } else { "0" }],
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to update index settings: {}", e)))?;
+        if !enabled {
+            tx.execute_batch(
+                "DELETE FROM deletions;
+                 UPDATE events SET deleted_by = NULL WHERE deleted_by IS NOT NULL;",
+            )
+            .map_err(|e| Error::InvalidEvent(format!("Failed to clear deletions: {}", e)))?;
+        }
+
+        tx.commit()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to commit transaction: {}", e)))?;
+
+        self.process_deletions = enabled;
+        Ok(())
+    }
+
+    /// Condition appended to queries that hides tombstoned events
+    fn visibility(&self) -> &'static str {
+        if self.include_deleted {
+            ""
+        } else {
+            " AND deleted_by IS NULL"
+        }
     }
 
     /// Configure standard connection settings for normal operations
@@ -240,17 +492,92 @@ impl EventIndex {
                 pubkey TEXT NOT NULL,
                 created_at INTEGER NOT NULL,
                 file_path TEXT NOT NULL,
-                indexed_at INTEGER NOT NULL
+                indexed_at INTEGER NOT NULL,
+                block_offset INTEGER,
+                address TEXT,
+                deleted_by TEXT
             );
 
             CREATE INDEX IF NOT EXISTS idx_kind ON events(kind);
             CREATE INDEX IF NOT EXISTS idx_pubkey ON events(pubkey);
             CREATE INDEX IF NOT EXISTS idx_created_at ON events(created_at);
             CREATE INDEX IF NOT EXISTS idx_file_path ON events(file_path);
+
+            CREATE TABLE IF NOT EXISTS event_relays (
+                event_id TEXT NOT NULL,
+                relay_url TEXT NOT NULL,
+                received_at INTEGER NOT NULL,
+                PRIMARY KEY (event_id, relay_url)
+            ) WITHOUT ROWID;
+
+            CREATE TABLE IF NOT EXISTS event_tags (
+                event_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (name, value, event_id)
+            ) WITHOUT ROWID;
+
+            CREATE INDEX IF NOT EXISTS idx_event_tags_event_id ON event_tags(event_id);
+
+            CREATE TABLE IF NOT EXISTS index_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            ) WITHOUT ROWID;
+
+            CREATE TABLE IF NOT EXISTS latest_versions (
+                address TEXT PRIMARY KEY,
+                event_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            ) WITHOUT ROWID;
+
+            CREATE TABLE IF NOT EXISTS deletions (
+                target TEXT NOT NULL,
+                deletion_id TEXT NOT NULL,
+                pubkey TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (target, deletion_id)
+            ) WITHOUT ROWID;
+
+            CREATE INDEX IF NOT EXISTS idx_deletions_deletion_id ON deletions(deletion_id);
             "#,
         )
         .map_err(|e| Error::InvalidEvent(format!("Failed to create schema: {}", e)))?;
 
+        // Indexes created before block offsets were recorded lack the column
+        if conn
+            .prepare("SELECT block_offset FROM events LIMIT 0")
+            .is_err()
+        {
+            conn.execute("ALTER TABLE events ADD COLUMN block_offset INTEGER", [])
+                .map_err(|e| Error::InvalidEvent(format!("Failed to migrate schema: {}", e)))?;
+        }
+
+        // Nor do they record addresses; rebuilding the index fills them in
+        if conn.prepare("SELECT address FROM events LIMIT 0").is_err() {
+            conn.execute("ALTER TABLE events ADD COLUMN address TEXT", [])
+                .map_err(|e| Error::InvalidEvent(format!("Failed to migrate schema: {}", e)))?;
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_address ON events(address) WHERE address IS NOT NULL",
+            [],
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to create schema: {}", e)))?;
+
+        // Nor do they record deletions
+        if conn
+            .prepare("SELECT deleted_by FROM events LIMIT 0")
+            .is_err()
+        {
+            conn.execute("ALTER TABLE events ADD COLUMN deleted_by TEXT", [])
+                .map_err(|e| Error::InvalidEvent(format!("Failed to migrate schema: {}", e)))?;
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_deleted_by ON events(deleted_by)
+             WHERE deleted_by IS NOT NULL",
+            [],
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to create schema: {}", e)))?;
+
         Ok(())
     }
 
@@ -316,26 +643,7 @@ impl EventIndex {
     /// # }
     /// ```
     pub fn insert(&mut self, event: &ProtoEvent, file_path: &str) -> Result<()> {
-        let indexed_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-
-        self.conn
-            .execute(
-                "INSERT OR IGNORE INTO events (id, kind, pubkey, created_at, file_path, indexed_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                params![
-                    &event.id,
-                    event.kind,
-                    &event.pubkey,
-                    event.created_at,
-                    file_path,
-                    indexed_at
-                ],
-            )
-            .map_err(|e| Error::InvalidEvent(format!("Failed to insert event: {}", e)))?;
-
+        self.insert_rows(std::iter::once((event, file_path, None)), false)?;
         Ok(())
     }
 
@@ -378,52 +686,74 @@ impl EventIndex {
     /// # }
     /// ```
     pub fn insert_batch(&mut self, events: &[(&ProtoEvent, &str)]) -> Result<(usize, usize)> {
+        self.insert_rows(
+            events
+                .iter()
+                .map(|&(event, file_path)| (event, file_path, None)),
+            false,
+        )
+    }
+
+    /// Insert multiple events along with the offset of the block holding each one
+    ///
+    /// Like [`insert_batch`](Self::insert_batch), but [`fetch_event`](Self::fetch_event)
+    /// can then decode just that block instead of the whole file.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - Slice of (event, file_path, block_offset) tuples to index
+    pub fn insert_batch_at(
+        &mut self,
+        events: &[(&ProtoEvent, &str, u64)],
+    ) -> Result<(usize, usize)> {
+        self.insert_rows(
+            events
+                .iter()
+                .map(|&(event, file_path, block_offset)| (event, file_path, Some(block_offset))),
+            false,
+        )
+    }
+
+    /// Index events of a rewritten file along with their new block offsets
+    ///
+    /// Like [`insert_batch_at`](Self::insert_batch_at), but events that are
+    /// already indexed are pointed at `file_path` and `block_offset` instead
+    /// of being skipped. Returns (inserted, relocated).
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - Slice of (event, file_path, block_offset) tuples to index
+    pub fn upsert_batch_at(
+        &mut self,
+        events: &[(&ProtoEvent, &str, u64)],
+    ) -> Result<(usize, usize)> {
+        self.insert_rows(
+            events
+                .iter()
+                .map(|&(event, file_path, block_offset)| (event, file_path, Some(block_offset))),
+            true,
+        )
+    }
+
+    /// Insert rows in a single transaction, returning (inserted, duplicates)
+    ///
+    /// With `relocate`, duplicates are moved to the given file and block.
+    fn insert_rows<'a>(
+        &mut self,
+        rows: impl Iterator<Item = (&'a ProtoEvent, &'a str, Option<u64>)>,
+        relocate: bool,
+    ) -> Result<(usize, usize)> {
         let tx = self
             .conn
             .transaction()
             .map_err(|e| Error::InvalidEvent(format!("Failed to start transaction: {}", e)))?;
 
-        let indexed_at = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-
-        let mut inserted = 0usize;
-        let mut duplicates = 0usize;
-
-        {
-            let mut stmt = tx
-                .prepare_cached(
-                    "INSERT OR IGNORE INTO events (id, kind, pubkey, created_at, file_path, indexed_at)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                )
-                .map_err(|e| Error::InvalidEvent(format!("Failed to prepare insert: {}", e)))?;
-
-            for (event, file_path) in events {
-                let rows = stmt
-                    .execute(params![
-                        &event.id,
-                        event.kind,
-                        &event.pubkey,
-                        event.created_at,
-                        file_path,
-                        indexed_at
-                    ])
-                    .map_err(|e| {
-                        Error::InvalidEvent(format!("Failed to insert event in batch: {}", e))
-                    })?;
-                if rows == 0 {
-                    duplicates += 1;
-                } else {
-                    inserted += 1;
-                }
-            }
-        }
+        let counts = insert_rows_in(&tx, rows, relocate, self.index_tags, self.process_deletions)?;
 
         tx.commit()
             .map_err(|e| Error::InvalidEvent(format!("Failed to commit transaction: {}", e)))?;
 
-        Ok((inserted, duplicates))
+        Ok(counts)
     }
 
     /// Get statistics about the index
@@ -506,23 +836,15 @@ impl EventIndex {
     pub fn query_by_kind(&self, kind: i32) -> Result<Vec<EventRecord>> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT id, kind, pubkey, created_at, file_path, indexed_at
-                 FROM events WHERE kind = ? ORDER BY created_at DESC",
-            )
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE kind = ?{} ORDER BY created_at DESC",
+                RECORD_COLUMNS,
+                self.visibility()
+            ))
             .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
         let records = stmt
-            .query_map(params![kind], |row| {
-                Ok(EventRecord {
-                    id: row.get(0)?,
-                    kind: row.get(1)?,
-                    pubkey: row.get(2)?,
-                    created_at: row.get(3)?,
-                    file_path: row.get(4)?,
-                    indexed_at: row.get(5)?,
-                })
-            })
+            .query_map(params![kind], record_from_row)
             .map_err(|e| Error::InvalidEvent(format!("Failed to query by kind: {}", e)))?
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
@@ -551,23 +873,15 @@ impl EventIndex {
     pub fn query_by_pubkey(&self, pubkey: &str) -> Result<Vec<EventRecord>> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT id, kind, pubkey, created_at, file_path, indexed_at
-                 FROM events WHERE pubkey = ? ORDER BY created_at DESC",
-            )
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE pubkey = ?{} ORDER BY created_at DESC",
+                RECORD_COLUMNS,
+                self.visibility()
+            ))
             .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
         let records = stmt
-            .query_map(params![pubkey], |row| {
-                Ok(EventRecord {
-                    id: row.get(0)?,
-                    kind: row.get(1)?,
-                    pubkey: row.get(2)?,
-                    created_at: row.get(3)?,
-                    file_path: row.get(4)?,
-                    indexed_at: row.get(5)?,
-                })
-            })
+            .query_map(params![pubkey], record_from_row)
             .map_err(|e| Error::InvalidEvent(format!("Failed to query by pubkey: {}", e)))?
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
@@ -597,23 +911,15 @@ impl EventIndex {
     pub fn query_by_date_range(&self, start: i64, end: i64) -> Result<Vec<EventRecord>> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT id, kind, pubkey, created_at, file_path, indexed_at
-                 FROM events WHERE created_at >= ? AND created_at <= ? ORDER BY created_at DESC",
-            )
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE created_at >= ? AND created_at <= ?{} ORDER BY created_at DESC",
+                RECORD_COLUMNS,
+                self.visibility()
+            ))
             .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
         let records = stmt
-            .query_map(params![start, end], |row| {
-                Ok(EventRecord {
-                    id: row.get(0)?,
-                    kind: row.get(1)?,
-                    pubkey: row.get(2)?,
-                    created_at: row.get(3)?,
-                    file_path: row.get(4)?,
-                    indexed_at: row.get(5)?,
-                })
-            })
+            .query_map(params![start, end], record_from_row)
             .map_err(|e| Error::InvalidEvent(format!("Failed to query by date range: {}", e)))?
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
@@ -621,13 +927,13 @@ impl EventIndex {
         Ok(records)
     }
 
-    /// Get an event record by ID
-    ///
-    /// Returns `None` if the event is not in the index.
+    /// Query events stored in a specific file
     ///
     /// # Arguments
     ///
-    /// * `event_id` - Event ID to look up (hex-encoded)
+    /// * `file_path` - File path exactly as it was passed to `insert`
+    ///
+    /// Deleted events are included, since they are still in the file.
     ///
     /// # Examples
     ///
@@ -636,109 +942,1126 @@ impl EventIndex {
     /// # use std::path::Path;
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
-    /// if let Some(record) = index.get("event_id_123")? {
-    ///     println!("Event found in file: {}", record.file_path);
-    /// }
+    /// let events = index.query_by_file("2025_10_13.pb.gz")?;
+    /// println!("Found {} events in file", events.len());
     /// # Ok(())
     /// # }
     /// ```
-    pub fn get(&self, event_id: &str) -> Result<Option<EventRecord>> {
+    pub fn query_by_file(&self, file_path: &str) -> Result<Vec<EventRecord>> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT id, kind, pubkey, created_at, file_path, indexed_at
-                 FROM events WHERE id = ?",
-            )
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE file_path = ? ORDER BY created_at DESC",
+                RECORD_COLUMNS
+            ))
             .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
-        let record = stmt
-            .query_row(params![event_id], |row| {
-                Ok(EventRecord {
-                    id: row.get(0)?,
-                    kind: row.get(1)?,
-                    pubkey: row.get(2)?,
-                    created_at: row.get(3)?,
-                    file_path: row.get(4)?,
-                    indexed_at: row.get(5)?,
-                })
-            })
-            .optional()
-            .map_err(|e| Error::InvalidEvent(format!("Failed to get event: {}", e)))?;
+        let records = stmt
+            .query_map(params![file_path], record_from_row)
+            .map_err(|e| Error::InvalidEvent(format!("Failed to query by file: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
 
-        Ok(record)
+        Ok(records)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ProtoEventBuilder;
-    use tempfile::TempDir;
 
-    fn create_test_index() -> (EventIndex, TempDir) {
-        let temp_dir = TempDir::new().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let index = EventIndex::new(&db_path).unwrap();
-        (index, temp_dir)
-    }
+    /// Every file path the index points at, in order
+    pub fn files(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT file_path FROM events ORDER BY file_path")
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
-    fn create_test_event(id: &str, kind: i32, pubkey: &str, created_at: i64) -> ProtoEvent {
-        ProtoEventBuilder::new()
-            .id(id)
-            .kind(kind)
-            .pubkey(pubkey)
-            .created_at(created_at)
-            .content("test content")
-            .sig("test_sig")
-            .build()
-    }
+        let files = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| Error::InvalidEvent(format!("Failed to query files: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
 
-    #[test]
-    fn test_create_index() {
-        let (index, _temp_dir) = create_test_index();
-        let stats = index.stats().unwrap();
-        assert_eq!(stats.total_events, 0);
+        Ok(files)
     }
 
-    #[test]
-    fn test_insert_and_contains() {
-        let (mut index, _temp_dir) = create_test_index();
+    /// IDs of events in a file that no longer need to be stored
+    ///
+    /// These are events deleted by NIP-09 requests (see
+    /// [`set_deletion_processing`](Self::set_deletion_processing)) and
+    /// replaceable or addressable events superseded by a newer version.
+    /// Deletion requests themselves are kept, so that the events they delete
+    /// stay deleted if they turn up again.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path exactly as it was passed to `insert`
+    pub fn dead_events(&self, file_path: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT id FROM events
+                 WHERE file_path = ?
+                   AND (deleted_by IS NOT NULL
+                        OR (address IS NOT NULL AND NOT EXISTS (
+                            SELECT 1 FROM latest_versions
+                            WHERE latest_versions.address = events.address
+                              AND latest_versions.event_id = events.id)))
+                 ORDER BY id",
+            )
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
-        let event = create_test_event("event_1", 1, "pubkey_1", 1234567890);
+        let ids = stmt
+            .query_map(params![file_path], |row| row.get(0))
+            .map_err(|e| Error::InvalidEvent(format!("Failed to query dead events: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
 
-        assert!(!index.contains("event_1").unwrap());
-        index.insert(&event, "2025_10_13.pb").unwrap();
-        assert!(index.contains("event_1").unwrap());
+        Ok(ids)
     }
 
-    #[test]
-    fn test_insert_duplicate() {
-        let (mut index, _temp_dir) = create_test_index();
-
-        let event = create_test_event("event_1", 1, "pubkey_1", 1234567890);
+    /// Query events carrying a tag, such as all replies to an event (`"e"`)
+    /// or all events mentioning a pubkey (`"p"`)
+    ///
+    /// Only finds events indexed while the tag index was enabled (see
+    /// [`set_tag_index`](Self::set_tag_index)).
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Single-letter tag name
+    /// * `value` - First tag value, e.g. an event ID or pubkey
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::EventIndex;
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// let replies = index.query_by_tag("e", "event_id_123")?;
+    /// println!("Found {} replies", replies.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_by_tag(&self, name: &str, value: &str) -> Result<Vec<EventRecord>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE {}{} ORDER BY created_at DESC",
+                RECORD_COLUMNS,
+                TAG_CONDITION,
+                self.visibility()
+            ))
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
 
-        index.insert(&event, "2025_10_13.pb").unwrap();
-        index.insert(&event, "2025_10_13.pb").unwrap(); // Should not error
+        let records = stmt
+            .query_map(params![name, value], record_from_row)
+            .map_err(|e| Error::InvalidEvent(format!("Failed to query by tag: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
 
-        let stats = index.stats().unwrap();
-        assert_eq!(stats.total_events, 1); // Only one event should be stored
+        Ok(records)
     }
 
-    #[test]
-    fn test_insert_batch() {
-        let (mut index, _temp_dir) = create_test_index();
-
-        let event1 = create_test_event("event_1", 1, "pubkey_1", 1234567890);
-        let event2 = create_test_event("event_2", 1, "pubkey_2", 1234567891);
-        let event3 = create_test_event("event_3", 3, "pubkey_3", 1234567892);
-
-        let events = vec![
-            (&event1, "2025_10_13.pb"),
-            (&event2, "2025_10_13.pb"),
-            (&event3, "2025_10_14.pb"),
-        ];
-
-        index.insert_batch(&events).unwrap();
+    /// Query records matching a NIP-01 filter, newest first
+    ///
+    /// All of the filter's conditions are combined into one SQL query.
+    /// `filter.limit` sets the page size; pass the returned
+    /// [`QueryPage::next`] cursor back in to fetch the following page.
+    /// Without a limit every matching record is returned in one page.
+    ///
+    /// Tag filters (`#e`, `#p`, ...) need the tag index (see
+    /// [`set_tag_index`](Self::set_tag_index)).
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Conditions records must match
+    /// * `cursor` - Where the previous page ended, or `None` for the first page
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::{EventIndex, Filter};
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// let filter = Filter::try_from(r#"{"kinds": [1], "since": 1700000000, "limit": 100}"#)?;
+    ///
+    /// let mut page = index.query(&filter, None)?;
+    /// loop {
+    ///     for record in &page.records {
+    ///         println!("{} {}", record.created_at, record.id);
+    ///     }
+    ///     match page.next {
+    ///         Some(cursor) => page = index.query(&filter, Some(&cursor))?,
+    ///         None => break,
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query(&self, filter: &Filter, cursor: Option<&QueryCursor>) -> Result<QueryPage> {
+        let (condition, params) = self.filter_condition(filter)?;
+        let records = self.query_page(&condition, &params, cursor, filter.limit)?;
+        let next = match filter.limit {
+            Some(limit) if records.len() == limit => records.last().map(QueryCursor::from),
+            _ => None,
+        };
+        Ok(QueryPage { records, next })
+    }
+
+    /// Count events matching any of `filters`
+    ///
+    /// Events matching several filters are counted once, and `limit` is
+    /// ignored, as for a NIP-45 `COUNT`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::{EventIndex, Filter};
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// let filter = Filter::try_from(r#"{"kinds": [7]}"#)?;
+    /// println!("{} reactions", index.count(&[filter])?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn count(&self, filters: &[Filter]) -> Result<u64> {
+        if filters.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conditions = Vec::with_capacity(filters.len());
+        let mut params = Vec::new();
+        for filter in filters {
+            let (condition, filter_params) = self.filter_condition(filter)?;
+            conditions.push(format!("({})", condition));
+            params.extend(filter_params);
+        }
+
+        let sql = format!(
+            "SELECT COUNT(*) FROM events WHERE ({}){}",
+            conditions.join(" OR "),
+            self.visibility()
+        );
+        let count: i64 = self
+            .conn
+            .query_row(&sql, params_from_iter(params), |row| row.get(0))
+            .map_err(|e| Error::InvalidEvent(format!("Failed to count events: {}", e)))?;
+
+        Ok(count as u64)
+    }
+
+    /// Get the latest version of a replaceable or addressable event
+    ///
+    /// The latest version is kept up to date as events are inserted, so
+    /// this answers "current profile" or "current follow list" without
+    /// scanning older versions. Indexes created before addresses were
+    /// recorded need `index rebuild` first. If the latest version was
+    /// deleted, there is none.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::{EventAddress, EventIndex};
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// // Current profile (kind 0) of a pubkey
+    /// let address = EventAddress::new(0, "pubkey_abc", "");
+    /// if let Some(record) = index.latest(&address)? {
+    ///     let profile = index.fetch_event(&record.id)?;
+    ///     println!("{:?}", profile.map(|event| event.content));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn latest(&self, address: &EventAddress) -> Result<Option<EventRecord>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events
+                 WHERE id = (SELECT event_id FROM latest_versions WHERE address = ?){}",
+                RECORD_COLUMNS,
+                self.visibility()
+            ))
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
+
+        stmt.query_row(params![address.to_string()], record_from_row)
+            .optional()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to get latest version: {}", e)))
+    }
+
+    /// Remove an event from the index
+    ///
+    /// Returns `true` if the event was indexed. This only touches the index;
+    /// the event stays in its `.pb.gz` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - Event ID to remove (hex-encoded)
+    pub fn remove(&mut self, event_id: &str) -> Result<bool> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to start transaction: {}", e)))?;
+
+        let removed = remove_in(&tx, event_id)?;
+
+        tx.commit()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(removed)
+    }
+
+    /// Point the index at a rewritten file, in a single transaction
+    ///
+    /// `events` are indexed at their new file and block, moving rows that
+    /// pointed at the old ones, and the `removed` events, which the rewrite
+    /// dropped, leave the index. Either all of it happens or none of it
+    /// does, so readers never see a half-updated file. Returns the number of
+    /// events removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - Slice of (event, file_path, block_offset) tuples, as written
+    /// * `removed` - IDs of events that are no longer stored anywhere
+    pub fn rewrite_file(
+        &mut self,
+        events: &[(&ProtoEvent, &str, u64)],
+        removed: &[String],
+    ) -> Result<usize> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to start transaction: {}", e)))?;
+
+        insert_rows_in(
+            &tx,
+            events
+                .iter()
+                .map(|&(event, file_path, block_offset)| (event, file_path, Some(block_offset))),
+            true,
+            self.index_tags,
+            self.process_deletions,
+        )?;
+        let mut count = 0usize;
+        for event_id in removed {
+            if remove_in(&tx, event_id)? {
+                count += 1;
+            }
+        }
+
+        tx.commit()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(count)
+    }
+
+    /// Point every event indexed in `from` at `to`, which replaced it
+    ///
+    /// Block offsets are cleared, so the moved events are found by scanning
+    /// `to` until its file is indexed again. Returns the number of events
+    /// moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - File path of the removed file, as it was indexed
+    /// * `to` - File path of the file now holding its events
+    pub fn move_file(&mut self, from: &str, to: &str) -> Result<usize> {
+        let moved = self
+            .conn
+            .execute(
+                "UPDATE events SET file_path = ?, block_offset = NULL WHERE file_path = ?",
+                params![to, from],
+            )
+            .map_err(|e| Error::InvalidEvent(format!("Failed to move file: {}", e)))?;
+
+        Ok(moved)
+    }
+
+    /// Record which relays delivered which events, in a single transaction
+    ///
+    /// Only the first delivery per event and relay is kept, so sightings
+    /// should be recorded in the order they happened. Events do not need to
+    /// be indexed yet. Returns the number of new sightings.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::{EventIndex, RelaySighting};
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let mut index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// index.insert_relays(&[RelaySighting {
+    ///     event_id: "event_id_123".to_string(),
+    ///     relay_url: "wss://relay.damus.io".to_string(),
+    ///     received_at: 1697000000,
+    /// }])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn insert_relays(&mut self, sightings: &[RelaySighting]) -> Result<usize> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to start transaction: {}", e)))?;
+
+        let mut inserted = 0usize;
+        {
+            let mut stmt = tx
+                .prepare_cached(
+                    "INSERT OR IGNORE INTO event_relays (event_id, relay_url, received_at)
+                     VALUES (?1, ?2, ?3)",
+                )
+                .map_err(|e| Error::InvalidEvent(format!("Failed to prepare insert: {}", e)))?;
+
+            for sighting in sightings {
+                inserted += stmt
+                    .execute(params![
+                        &sighting.event_id,
+                        &sighting.relay_url,
+                        sighting.received_at
+                    ])
+                    .map_err(|e| {
+                        Error::InvalidEvent(format!("Failed to insert event relay: {}", e))
+                    })?;
+            }
+        }
+
+        tx.commit()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(inserted)
+    }
+
+    /// Query the relays an event was received from, earliest first
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - Event ID to look up (hex-encoded)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::EventIndex;
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// for sighting in index.query_relays("event_id_123")? {
+    ///     println!("Seen on {} at {}", sighting.relay_url, sighting.received_at);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_relays(&self, event_id: &str) -> Result<Vec<RelaySighting>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT event_id, relay_url, received_at
+                 FROM event_relays WHERE event_id = ? ORDER BY received_at, relay_url",
+            )
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
+
+        let sightings = stmt
+            .query_map(params![event_id], |row| {
+                Ok(RelaySighting {
+                    event_id: row.get(0)?,
+                    relay_url: row.get(1)?,
+                    received_at: row.get(2)?,
+                })
+            })
+            .map_err(|e| Error::InvalidEvent(format!("Failed to query event relays: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
+
+        Ok(sightings)
+    }
+
+    /// Get an event record by ID
+    ///
+    /// Returns `None` if the event is not in the index. Deleted events are
+    /// returned too, with [`EventRecord::deleted_by`] set.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - Event ID to look up (hex-encoded)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::EventIndex;
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// if let Some(record) = index.get("event_id_123")? {
+    ///     println!("Event found in file: {}", record.file_path);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get(&self, event_id: &str) -> Result<Option<EventRecord>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE id = ?",
+                RECORD_COLUMNS
+            ))
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
+
+        let record = stmt
+            .query_row(params![event_id], record_from_row)
+            .optional()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to get event: {}", e)))?;
+
+        Ok(record)
+    }
+
+    /// Read a full event from the file it is stored in
+    ///
+    /// Only the block at the record's `block_offset` is decompressed; events
+    /// indexed without one are found by scanning their whole file. Returns
+    /// `None` if the event is not in the index, or no longer in its file.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - Event ID to look up (hex-encoded)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::EventIndex;
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// if let Some(event) = index.fetch_event("event_id_123")? {
+    ///     println!("{}", event.content);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fetch_event(&self, event_id: &str) -> Result<Option<ProtoEvent>> {
+        let Some(record) = self.get(event_id)? else {
+            return Ok(None);
+        };
+
+        for event in self.open_events(&record.file_path, record.block_offset)? {
+            let event = event?;
+            if event.id == event_id {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Stream full events of a kind, newest first
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::EventIndex;
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// for event in index.events_by_kind(1) {
+    ///     println!("{}", event?.content);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn events_by_kind(&self, kind: i32) -> EventStream<'_> {
+        EventStream::new(self, "kind = ?".into(), vec![Value::Integer(kind.into())])
+    }
+
+    /// Stream full events by an author, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - Public key to query (hex-encoded)
+    pub fn events_by_pubkey(&self, pubkey: &str) -> EventStream<'_> {
+        EventStream::new(
+            self,
+            "pubkey = ?".into(),
+            vec![Value::Text(pubkey.to_string())],
+        )
+    }
+
+    /// Stream full events created within a range, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start timestamp (inclusive)
+    /// * `end` - End timestamp (inclusive)
+    pub fn events_by_date_range(&self, start: i64, end: i64) -> EventStream<'_> {
+        EventStream::new(
+            self,
+            "created_at >= ? AND created_at <= ?".into(),
+            vec![Value::Integer(start), Value::Integer(end)],
+        )
+    }
+
+    /// Stream full events carrying a tag, newest first
+    ///
+    /// Like [`query_by_tag`](Self::query_by_tag), this needs the tag index.
+    pub fn events_by_tag(&self, name: &str, value: &str) -> EventStream<'_> {
+        EventStream::new(
+            self,
+            TAG_CONDITION.into(),
+            vec![
+                Value::Text(name.to_string()),
+                Value::Text(value.to_string()),
+            ],
+        )
+    }
+
+    /// Stream full events matching a NIP-01 filter, newest first
+    ///
+    /// Unlike [`query`](Self::query), `filter.limit` caps the total number
+    /// of events streamed rather than the size of a page.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use proton_beam_core::{EventIndex, Filter};
+    /// # use std::path::Path;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let index = EventIndex::new(Path::new("./pb_data/index.db"))?;
+    /// let filter = Filter::try_from(r#"{"authors": ["pubkey_abc"], "limit": 20}"#)?;
+    /// for event in index.events_matching(&filter)? {
+    ///     println!("{}", event?.content);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn events_matching(&self, filter: &Filter) -> Result<EventStream<'_>> {
+        let (condition, params) = self.filter_condition(filter)?;
+        let mut stream = EventStream::new(self, condition, params);
+        stream.remaining = filter.limit;
+        Ok(stream)
+    }
+
+    /// Build the SQL condition and parameters for a filter
+    fn filter_condition(&self, filter: &Filter) -> Result<(String, Vec<Value>)> {
+        fn placeholders(count: usize) -> String {
+            vec!["?"; count].join(", ")
+        }
+
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        if !filter.ids.is_empty() {
+            conditions.push(format!("id IN ({})", placeholders(filter.ids.len())));
+            params.extend(filter.ids.iter().cloned().map(Value::Text));
+        }
+        if !filter.authors.is_empty() {
+            conditions.push(format!(
+                "pubkey IN ({})",
+                placeholders(filter.authors.len())
+            ));
+            params.extend(filter.authors.iter().cloned().map(Value::Text));
+        }
+        if !filter.kinds.is_empty() {
+            conditions.push(format!("kind IN ({})", placeholders(filter.kinds.len())));
+            params.extend(filter.kinds.iter().map(|&kind| Value::Integer(kind.into())));
+        }
+        if let Some(since) = filter.since {
+            conditions.push("created_at >= ?".to_string());
+            params.push(Value::Integer(since));
+        }
+        if let Some(until) = filter.until {
+            conditions.push("created_at <= ?".to_string());
+            params.push(Value::Integer(until));
+        }
+        for (name, values) in &filter.tags {
+            if values.is_empty() {
+                continue;
+            }
+            if !self.index_tags {
+                return Err(Error::InvalidEvent(format!(
+                    "Filtering by #{} needs the tag index (rebuild the index with --tags)",
+                    name
+                )));
+            }
+            conditions.push(format!(
+                "id IN (SELECT event_id FROM event_tags WHERE name = ? AND value IN ({}))",
+                placeholders(values.len())
+            ));
+            params.push(Value::Text(name.clone()));
+            params.extend(values.iter().cloned().map(Value::Text));
+        }
+
+        if conditions.is_empty() {
+            conditions.push("1".to_string());
+        }
+        Ok((conditions.join(" AND "), params))
+    }
+
+    /// Query a page of records matching `condition`, newest first
+    ///
+    /// Records come after `cursor`, the last record of the previous page.
+    /// Without a `limit` every remaining record is returned.
+    fn query_page(
+        &self,
+        condition: &str,
+        params: &[Value],
+        cursor: Option<&QueryCursor>,
+        limit: Option<usize>,
+    ) -> Result<Vec<EventRecord>> {
+        let mut sql = format!(
+            "SELECT {} FROM events WHERE ({}){}",
+            RECORD_COLUMNS,
+            condition,
+            self.visibility()
+        );
+        let mut params = params.to_vec();
+        if let Some(cursor) = cursor {
+            sql.push_str(" AND (created_at < ? OR (created_at = ? AND id < ?))");
+            params.extend([
+                Value::Integer(cursor.created_at),
+                Value::Integer(cursor.created_at),
+                Value::Text(cursor.id.clone()),
+            ]);
+        }
+        sql.push_str(" ORDER BY created_at DESC, id DESC");
+        if let Some(limit) = limit {
+            sql.push_str(" LIMIT ?");
+            params.push(Value::Integer(limit as i64));
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare_cached(&sql)
+            .map_err(|e| Error::InvalidEvent(format!("Failed to prepare query: {}", e)))?;
+
+        let records = stmt
+            .query_map(params_from_iter(params), record_from_row)
+            .map_err(|e| Error::InvalidEvent(format!("Failed to query events: {}", e)))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidEvent(format!("Failed to collect results: {}", e)))?;
+
+        Ok(records)
+    }
+
+    /// Read the events behind `records`, in the same order
+    ///
+    /// Each block, or each file for records without a block offset, is
+    /// decoded once. Records whose event is missing from its file are skipped.
+    fn load_events(&self, records: &[EventRecord]) -> Result<Vec<ProtoEvent>> {
+        let mut wanted: HashMap<(&str, Option<u64>), HashSet<&str>> = HashMap::new();
+        for record in records {
+            wanted
+                .entry((record.file_path.as_str(), record.block_offset))
+                .or_default()
+                .insert(record.id.as_str());
+        }
+
+        let mut found: HashMap<String, ProtoEvent> = HashMap::with_capacity(records.len());
+        for ((file_path, block_offset), mut ids) in wanted {
+            for event in self.open_events(file_path, block_offset)? {
+                let event = event?;
+                if ids.remove(event.id.as_str()) {
+                    found.insert(event.id.clone(), event);
+                    if ids.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(records
+            .iter()
+            .filter_map(|record| found.remove(&record.id))
+            .collect())
+    }
+
+    /// Open the events of the block at `block_offset` in `file_path`, or of the whole file
+    fn open_events(
+        &self,
+        file_path: &str,
+        block_offset: Option<u64>,
+    ) -> Result<Box<dyn Iterator<Item = Result<ProtoEvent>>>> {
+        let file = File::open(self.data_dir.join(file_path))?;
+        let dictionary = self.zstd_dictionary.as_deref();
+        Ok(match block_offset {
+            Some(offset) => Box::new(read_block(file, offset, dictionary)?),
+            None => Box::new(read_events_delimited(create_decoder(
+                BufReader::new(file),
+                dictionary,
+            )?)),
+        })
+    }
+}
+
+/// Iterator over full events matching an index query, newest first
+///
+/// Returned by [`EventIndex::events_by_kind`], [`EventIndex::events_matching`]
+/// and friends. Records are read
+/// from the index a page at a time and their events are loaded from the
+/// event files, so memory use stays bounded however many events match.
+pub struct EventStream<'a> {
+    index: &'a EventIndex,
+    condition: String,
+    params: Vec<Value>,
+    page_size: usize,
+    /// Records still to read, if the stream is limited
+    remaining: Option<usize>,
+    cursor: Option<QueryCursor>,
+    events: std::vec::IntoIter<ProtoEvent>,
+    exhausted: bool,
+}
+
+impl<'a> EventStream<'a> {
+    fn new(index: &'a EventIndex, condition: String, params: Vec<Value>) -> Self {
+        Self {
+            index,
+            condition,
+            params,
+            page_size: STREAM_PAGE_SIZE,
+            remaining: None,
+            cursor: None,
+            events: Vec::new().into_iter(),
+            exhausted: false,
+        }
+    }
+
+    /// Load the events of the next page of records
+    fn next_page(&mut self) -> Result<Vec<ProtoEvent>> {
+        let page_size = self
+            .remaining
+            .map_or(self.page_size, |remaining| remaining.min(self.page_size));
+        let records = self.index.query_page(
+            &self.condition,
+            &self.params,
+            self.cursor.as_ref(),
+            Some(page_size),
+        )?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= records.len();
+        }
+        if records.len() < page_size || self.remaining == Some(0) {
+            self.exhausted = true;
+        }
+        if let Some(last) = records.last() {
+            self.cursor = Some(QueryCursor::from(last));
+        }
+        self.index.load_events(&records)
+    }
+}
+
+impl Iterator for EventStream<'_> {
+    type Item = Result<ProtoEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.next() {
+                return Some(Ok(event));
+            }
+            if self.exhausted {
+                return None;
+            }
+            match self.next_page() {
+                Ok(events) => self.events = events.into_iter(),
+                Err(e) => {
+                    self.exhausted = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Insert rows within the caller's transaction, returning (inserted, duplicates)
+fn insert_rows_in<'a>(
+    conn: &Connection,
+    rows: impl Iterator<Item = (&'a ProtoEvent, &'a str, Option<u64>)>,
+    relocate: bool,
+    index_tags: bool,
+    process_deletions: bool,
+) -> Result<(usize, usize)> {
+    let indexed_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let mut inserted = 0usize;
+    let mut duplicates = 0usize;
+
+    let mut stmt = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO events
+             (id, kind, pubkey, created_at, file_path, indexed_at, block_offset, address)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to prepare insert: {}", e)))?;
+
+    let mut tag_stmt = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO event_tags (event_id, name, value) VALUES (?1, ?2, ?3)",
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to prepare insert: {}", e)))?;
+
+    let mut latest_stmt = conn
+        .prepare_cached(UPSERT_LATEST)
+        .map_err(|e| Error::InvalidEvent(format!("Failed to prepare insert: {}", e)))?;
+
+    let mut relocate_stmt = conn
+        .prepare_cached("UPDATE events SET file_path = ?2, block_offset = ?3 WHERE id = ?1")
+        .map_err(|e| Error::InvalidEvent(format!("Failed to prepare update: {}", e)))?;
+
+    for (event, file_path, block_offset) in rows {
+        let address = event.address().map(|address| address.to_string());
+        let rows = stmt
+            .execute(params![
+                &event.id,
+                event.kind,
+                &event.pubkey,
+                event.created_at,
+                file_path,
+                indexed_at,
+                block_offset,
+                &address
+            ])
+            .map_err(|e| Error::InvalidEvent(format!("Failed to insert event in batch: {}", e)))?;
+        if rows == 0 {
+            if relocate {
+                relocate_stmt
+                    .execute(params![&event.id, file_path, block_offset])
+                    .map_err(|e| Error::InvalidEvent(format!("Failed to relocate event: {}", e)))?;
+            }
+            duplicates += 1;
+            continue;
+        }
+        inserted += 1;
+
+        if let Some(address) = &address {
+            latest_stmt
+                .execute(params![address, &event.id, event.created_at])
+                .map_err(|e| {
+                    Error::InvalidEvent(format!("Failed to update latest version: {}", e))
+                })?;
+        }
+
+        if index_tags {
+            for (name, value) in indexable_tags(event) {
+                tag_stmt
+                    .execute(params![&event.id, name, value])
+                    .map_err(|e| {
+                        Error::InvalidEvent(format!("Failed to insert event tag: {}", e))
+                    })?;
+            }
+        }
+
+        if process_deletions {
+            apply_deletions(conn, event, address.as_deref())
+                .map_err(|e| Error::InvalidEvent(format!("Failed to process deletion: {}", e)))?;
+        }
+    }
+
+    Ok((inserted, duplicates))
+}
+
+/// Remove an event within the caller's transaction
+fn remove_in(conn: &Connection, event_id: &str) -> Result<bool> {
+    let address: Option<String> = conn
+        .query_row(
+            "SELECT address FROM events WHERE id = ?",
+            params![event_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| Error::InvalidEvent(format!("Failed to remove event: {}", e)))?
+        .flatten();
+    let rows = conn
+        .execute("DELETE FROM events WHERE id = ?", params![event_id])
+        .map_err(|e| Error::InvalidEvent(format!("Failed to remove event: {}", e)))?;
+    conn.execute(
+        "DELETE FROM event_relays WHERE event_id = ?",
+        params![event_id],
+    )
+    .map_err(|e| Error::InvalidEvent(format!("Failed to remove event relays: {}", e)))?;
+    conn.execute(
+        "DELETE FROM event_tags WHERE event_id = ?",
+        params![event_id],
+    )
+    .map_err(|e| Error::InvalidEvent(format!("Failed to remove event tags: {}", e)))?;
+
+    // Events deleted by this request stay deleted only if another one covers them
+    conn.execute(
+        "DELETE FROM deletions WHERE deletion_id = ?",
+        params![event_id],
+    )
+    .map_err(|e| Error::InvalidEvent(format!("Failed to remove deletion: {}", e)))?;
+    conn.execute(
+        "UPDATE events SET deleted_by = (
+             SELECT deletion_id FROM deletions
+             WHERE (target = events.id AND pubkey = events.pubkey)
+                OR (target = events.address AND created_at >= events.created_at)
+             ORDER BY created_at, deletion_id LIMIT 1)
+         WHERE deleted_by = ?",
+        params![event_id],
+    )
+    .map_err(|e| Error::InvalidEvent(format!("Failed to remove deletion: {}", e)))?;
+
+    // If this was the latest version, the next newest one takes its place
+    if let Some(address) = address {
+        conn.execute(
+            "DELETE FROM latest_versions WHERE address = ? AND event_id = ?",
+            params![address, event_id],
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to update latest version: {}", e)))?;
+        conn.execute(
+            "INSERT OR IGNORE INTO latest_versions (address, event_id, created_at)
+             SELECT address, id, created_at FROM events WHERE address = ?
+             ORDER BY created_at DESC, id ASC LIMIT 1",
+            params![address],
+        )
+        .map_err(|e| Error::InvalidEvent(format!("Failed to update latest version: {}", e)))?;
+    }
+
+    Ok(rows > 0)
+}
+
+/// Act on a newly indexed event as NIP-09 describes
+///
+/// A deletion request tombstones the events it names that have its author,
+/// and is recorded so that targets indexed later are tombstoned too. Any
+/// other event is tombstoned if a recorded request already names it.
+/// Deletion requests themselves cannot be deleted.
+fn apply_deletions(
+    conn: &Connection,
+    event: &ProtoEvent,
+    address: Option<&str>,
+) -> rusqlite::Result<()> {
+    if event.kind != DELETION_KIND {
+        let deletion_id: Option<String> = conn
+            .prepare_cached(PENDING_DELETION)?
+            .query_row(
+                params![&event.id, &event.pubkey, address, event.created_at],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(deletion_id) = deletion_id {
+            conn.prepare_cached("UPDATE events SET deleted_by = ?2 WHERE id = ?1")?
+                .execute(params![&event.id, deletion_id])?;
+        }
+        return Ok(());
+    }
+
+    let mut record = conn.prepare_cached(
+        "INSERT OR IGNORE INTO deletions (target, deletion_id, pubkey, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    let mut delete_id = conn.prepare_cached(
+        "UPDATE events SET deleted_by = ?2
+         WHERE id = ?1 AND pubkey = ?3 AND kind != 5 AND deleted_by IS NULL",
+    )?;
+    let mut delete_address = conn.prepare_cached(
+        "UPDATE events SET deleted_by = ?2
+         WHERE address = ?1 AND created_at <= ?3 AND deleted_by IS NULL",
+    )?;
+    for tag in &event.tags {
+        match tag.values.as_slice() {
+            [name, id, ..] if name == "e" => {
+                record.execute(params![id, &event.id, &event.pubkey, event.created_at])?;
+                delete_id.execute(params![id, &event.id, &event.pubkey])?;
+            }
+            [name, target, ..] if name == "a" => {
+                // Addresses name their author, who must be the requester
+                let Ok(target) = target.parse::<EventAddress>() else {
+                    continue;
+                };
+                if target.pubkey != event.pubkey {
+                    continue;
+                }
+                let target = target.to_string();
+                record.execute(params![&target, &event.id, &event.pubkey, event.created_at])?;
+                delete_address.execute(params![&target, &event.id, event.created_at])?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// (name, value) pairs of the tags that go into the tag index
+///
+/// Only single-letter tags with a value are indexed, as NIP-01 filters do.
+fn indexable_tags(event: &ProtoEvent) -> impl Iterator<Item = (&str, &str)> {
+    event
+        .tags
+        .iter()
+        .filter_map(|tag| match tag.values.as_slice() {
+            [name, value, ..] if name.chars().count() == 1 => Some((name.as_str(), value.as_str())),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, ProtoEventBuilder, create_encoder, write_events_delimited};
+    use tempfile::TempDir;
+
+    fn create_test_index() -> (EventIndex, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let index = EventIndex::new(&db_path).unwrap();
+        (index, temp_dir)
+    }
+
+    fn create_test_event(id: &str, kind: i32, pubkey: &str, created_at: i64) -> ProtoEvent {
+        ProtoEventBuilder::new()
+            .id(id)
+            .kind(kind)
+            .pubkey(pubkey)
+            .created_at(created_at)
+            .content("test content")
+            .sig("test_sig")
+            .build()
+    }
+
+    #[test]
+    fn test_create_index() {
+        let (index, _temp_dir) = create_test_index();
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.total_events, 0);
+    }
+
+    #[test]
+    fn test_insert_and_contains() {
+        let (mut index, _temp_dir) = create_test_index();
+
+        let event = create_test_event("event_1", 1, "pubkey_1", 1234567890);
+
+        assert!(!index.contains("event_1").unwrap());
+        index.insert(&event, "2025_10_13.pb").unwrap();
+        assert!(index.contains("event_1").unwrap());
+    }
+
+    #[test]
+    fn test_insert_duplicate() {
+        let (mut index, _temp_dir) = create_test_index();
+
+        let event = create_test_event("event_1", 1, "pubkey_1", 1234567890);
+
+        index.insert(&event, "2025_10_13.pb").unwrap();
+        index.insert(&event, "2025_10_13.pb").unwrap(); // Should not error
+
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.total_events, 1); // Only one event should be stored
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let (mut index, _temp_dir) = create_test_index();
+
+        let event1 = create_test_event("event_1", 1, "pubkey_1", 1234567890);
+        let event2 = create_test_event("event_2", 1, "pubkey_2", 1234567891);
+        let event3 = create_test_event("event_3", 3, "pubkey_3", 1234567892);
+
+        let events = vec![
+            (&event1, "2025_10_13.pb"),
+            (&event2, "2025_10_13.pb"),
+            (&event3, "2025_10_14.pb"),
+        ];
+
+        index.insert_batch(&events).unwrap();
 
         let stats = index.stats().unwrap();
         assert_eq!(stats.total_events, 3);
@@ -850,6 +2173,575 @@ mod tests {
         assert!(missing.is_none());
     }
 
+    #[test]
+    fn test_query_by_file_and_remove() {
+        let (mut index, _temp_dir) = create_test_index();
+
+        let event1 = create_test_event("event_1", 1, "pubkey_1", 1000);
+        let event2 = create_test_event("event_2", 1, "pubkey_2", 2000);
+        index.insert(&event1, "file1.pb.gz").unwrap();
+        index.insert(&event2, "file2.pb.gz").unwrap();
+
+        let file1_events = index.query_by_file("file1.pb.gz").unwrap();
+        assert_eq!(file1_events.len(), 1);
+        assert_eq!(file1_events[0].id, "event_1");
+        assert_eq!(index.files().unwrap(), ["file1.pb.gz", "file2.pb.gz"]);
+
+        assert!(index.remove("event_1").unwrap());
+        assert!(!index.remove("event_1").unwrap());
+        assert!(!index.contains("event_1").unwrap());
+        assert!(index.query_by_file("file1.pb.gz").unwrap().is_empty());
+        assert_eq!(index.files().unwrap(), ["file2.pb.gz"]);
+    }
+
+    #[test]
+    fn test_relay_provenance() {
+        let (mut index, _temp_dir) = create_test_index();
+        let sighting = |relay_url: &str, received_at| RelaySighting {
+            event_id: "event_1".to_string(),
+            relay_url: relay_url.to_string(),
+            received_at,
+        };
+
+        let inserted = index
+            .insert_relays(&[sighting("wss://b", 200), sighting("wss://a", 100)])
+            .unwrap();
+        assert_eq!(inserted, 2);
+
+        // Later deliveries from the same relay keep the first timestamp
+        assert_eq!(index.insert_relays(&[sighting("wss://a", 300)]).unwrap(), 0);
+        assert_eq!(
+            index.query_relays("event_1").unwrap(),
+            vec![sighting("wss://a", 100), sighting("wss://b", 200)]
+        );
+
+        index
+            .insert(
+                &create_test_event("event_1", 1, "pubkey_1", 1000),
+                "file.pb.gz",
+            )
+            .unwrap();
+        index.remove("event_1").unwrap();
+        assert!(index.query_relays("event_1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fetch_event() {
+        let (index, temp_dir) = create_test_index();
+        let mut index = index.with_data_dir(temp_dir.path());
+        let events: Vec<ProtoEvent> = (0..6)
+            .map(|i| create_test_event(&format!("event_{}", i), 1, "pubkey_1", 1000 + i))
+            .collect();
+
+        // Two blocks; only the second one is indexed with its offset
+        let mut file = Vec::new();
+        let mut offsets = Vec::new();
+        for block in events.chunks(3) {
+            offsets.push(file.len() as u64);
+            let mut encoder = create_encoder(&mut file, Codec::Zstd, 3, None).unwrap();
+            write_events_delimited(&mut encoder, block).unwrap();
+            encoder.finish().unwrap();
+        }
+        std::fs::write(temp_dir.path().join("file.pb.zst"), &file).unwrap();
+
+        index.insert(&events[0], "file.pb.zst").unwrap();
+        let second_block: Vec<_> = events[3..]
+            .iter()
+            .map(|event| (event, "file.pb.zst", offsets[1]))
+            .collect();
+        assert_eq!(index.insert_batch_at(&second_block).unwrap(), (3, 0));
+
+        assert_eq!(index.get("event_0").unwrap().unwrap().block_offset, None);
+        assert_eq!(
+            index.get("event_4").unwrap().unwrap().block_offset,
+            Some(offsets[1])
+        );
+        assert_eq!(
+            index.fetch_event("event_0").unwrap(),
+            Some(events[0].clone())
+        );
+        assert_eq!(
+            index.fetch_event("event_4").unwrap(),
+            Some(events[4].clone())
+        );
+        assert_eq!(index.fetch_event("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_event_streams() {
+        let (index, temp_dir) = create_test_index();
+        let mut index = index.with_data_dir(temp_dir.path());
+        let events: Vec<ProtoEvent> = (0..9)
+            .map(|i| {
+                let pubkey = if i % 3 == 0 { "pubkey_a" } else { "pubkey_b" };
+                // Two events per timestamp to exercise the cursor's tiebreak
+                create_test_event(&format!("event_{}", i), (i % 2) as i32, pubkey, i / 2)
+            })
+            .collect();
+
+        // A gzip file indexed without block offsets...
+        let mut file = Vec::new();
+        let mut encoder = create_encoder(&mut file, Codec::Gzip, 6, None).unwrap();
+        write_events_delimited(&mut encoder, &events[..4]).unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(temp_dir.path().join("a.pb.gz"), &file).unwrap();
+        let rows: Vec<_> = events[..4].iter().map(|e| (e, "a.pb.gz")).collect();
+        index.insert_batch(&rows).unwrap();
+
+        // ...and a zstd file with one block per two events
+        let mut file = Vec::new();
+        let mut rows = Vec::new();
+        for block in events[4..].chunks(2) {
+            let offset = file.len() as u64;
+            let mut encoder = create_encoder(&mut file, Codec::Zstd, 3, None).unwrap();
+            write_events_delimited(&mut encoder, block).unwrap();
+            encoder.finish().unwrap();
+            rows.extend(block.iter().map(|e| (e, "b.pb.zst", offset)));
+        }
+        std::fs::write(temp_dir.path().join("b.pb.zst"), &file).unwrap();
+        index.insert_batch_at(&rows).unwrap();
+
+        let newest_first = |filter: &dyn Fn(&ProtoEvent) -> bool| -> Vec<ProtoEvent> {
+            let mut expected: Vec<_> = events.iter().filter(|e| filter(e)).cloned().collect();
+            expected.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+            expected
+        };
+        let collect = |mut stream: EventStream<'_>| -> Vec<ProtoEvent> {
+            stream.page_size = 2;
+            stream.collect::<Result<_>>().unwrap()
+        };
+
+        assert_eq!(
+            collect(index.events_by_kind(1)),
+            newest_first(&|e| e.kind == 1)
+        );
+        assert_eq!(
+            collect(index.events_by_pubkey("pubkey_a")),
+            newest_first(&|e| e.pubkey == "pubkey_a")
+        );
+        assert_eq!(
+            collect(index.events_by_date_range(1, 3)),
+            newest_first(&|e| (1..=3).contains(&e.created_at))
+        );
+        assert_eq!(collect(index.events_by_date_range(0, 100)).len(), 9);
+        assert!(collect(index.events_by_kind(7)).is_empty());
+    }
+
+    #[test]
+    fn test_migrate_block_offset_column() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE events (
+                    id TEXT PRIMARY KEY,
+                    kind INTEGER NOT NULL,
+                    pubkey TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    file_path TEXT NOT NULL,
+                    indexed_at INTEGER NOT NULL
+                );
+                INSERT INTO events VALUES ('event_1', 1, 'pubkey_1', 1000, 'file.pb.gz', 1000);",
+            )
+            .unwrap();
+
+        let mut index = EventIndex::new(&db_path).unwrap();
+        assert_eq!(index.get("event_1").unwrap().unwrap().block_offset, None);
+
+        let event = create_test_event("event_2", 1, "pubkey_1", 2000);
+        index
+            .insert_batch_at(&[(&event, "file.pb.gz", 42)])
+            .unwrap();
+        assert_eq!(
+            index.get("event_2").unwrap().unwrap().block_offset,
+            Some(42)
+        );
+    }
+
+    #[test]
+    fn test_tag_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let reply = |id: &str, created_at| {
+            ProtoEventBuilder::new()
+                .id(id)
+                .kind(1)
+                .pubkey("pubkey_1")
+                .created_at(created_at)
+                .add_tag(vec!["e", "root_event"])
+                .add_tag(vec!["p", "pubkey_2"])
+                .add_tag(vec!["client", "x"])
+                .build()
+        };
+
+        let mut index = EventIndex::new(&db_path).unwrap();
+        assert!(!index.tag_index_enabled());
+        index.insert(&reply("reply_0", 1000), "file.pb.gz").unwrap();
+        assert!(index.query_by_tag("e", "root_event").unwrap().is_empty());
+
+        index.set_tag_index(true).unwrap();
+        index.insert(&reply("reply_1", 1001), "file.pb.gz").unwrap();
+        drop(index);
+
+        // The setting is kept in the database
+        let mut index = EventIndex::new(&db_path).unwrap();
+        assert!(index.tag_index_enabled());
+        index
+            .insert_batch(&[(&reply("reply_2", 1002), "file.pb.gz")])
+            .unwrap();
+
+        let replies = index.query_by_tag("e", "root_event").unwrap();
+        let ids: Vec<_> = replies.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["reply_2", "reply_1"]);
+        assert_eq!(index.query_by_tag("p", "pubkey_2").unwrap().len(), 2);
+        assert!(index.query_by_tag("client", "x").unwrap().is_empty());
+
+        index.remove("reply_2").unwrap();
+        assert_eq!(index.query_by_tag("e", "root_event").unwrap().len(), 1);
+
+        index.set_tag_index(false).unwrap();
+        assert!(index.query_by_tag("e", "root_event").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_filter() {
+        let (mut index, temp_dir) = create_test_index();
+        index.set_tag_index(true).unwrap();
+        let mut index = index.with_data_dir(temp_dir.path());
+        let events: Vec<ProtoEvent> = (0..12)
+            .map(|i| {
+                let mut builder = ProtoEventBuilder::new()
+                    .id(format!("event_{:02}", i))
+                    .kind(i % 3)
+                    .pubkey(if i % 2 == 0 { "pubkey_a" } else { "pubkey_b" })
+                    .created_at(1000 + (i / 2) as i64);
+                if i % 4 == 0 {
+                    builder = builder.add_tag(vec!["t", "nostr"]);
+                }
+                builder.build()
+            })
+            .collect();
+
+        let mut file = Vec::new();
+        let mut encoder = create_encoder(&mut file, Codec::Gzip, 6, None).unwrap();
+        write_events_delimited(&mut encoder, &events).unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(temp_dir.path().join("a.pb.gz"), &file).unwrap();
+        let rows: Vec<_> = events.iter().map(|e| (e, "a.pb.gz")).collect();
+        index.insert_batch(&rows).unwrap();
+
+        let expected = |filter: &Filter| -> Vec<String> {
+            let mut matching: Vec<_> = events.iter().filter(|e| filter.matches(e)).collect();
+            matching.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+            matching.into_iter().map(|e| e.id.clone()).collect()
+        };
+        let ids = |records: &[EventRecord]| -> Vec<String> {
+            records.iter().map(|r| r.id.clone()).collect()
+        };
+
+        for json in [
+            r#"{}"#,
+            r#"{"kinds": [0, 2]}"#,
+            r#"{"authors": ["pubkey_a"], "kinds": [0, 1]}"#,
+            r#"{"ids": ["event_03", "event_04", "missing"]}"#,
+            r##"{"#t": ["nostr"], "since": 1001}"##,
+            r#"{"since": 1002, "until": 1004, "authors": ["pubkey_b"]}"#,
+        ] {
+            let filter = Filter::try_from(json).unwrap();
+            let page = index.query(&filter, None).unwrap();
+            assert_eq!(ids(&page.records), expected(&filter), "filter: {}", json);
+            assert_eq!(page.next, None);
+        }
+
+        // Pages of 5 walk every match exactly once, across shared timestamps
+        let filter = Filter {
+            limit: Some(5),
+            ..Filter::default()
+        };
+        let mut cursor = None;
+        let mut paged = Vec::new();
+        loop {
+            let page = index.query(&filter, cursor.as_ref()).unwrap();
+            assert!(page.records.len() <= 5);
+            paged.extend(ids(&page.records));
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(paged, expected(&Filter::default()));
+
+        // A stream honors the limit as a total
+        let filter = Filter::try_from(r#"{"authors": ["pubkey_b"], "limit": 4}"#).unwrap();
+        let mut stream = index.events_matching(&filter).unwrap();
+        stream.page_size = 3;
+        let streamed: Vec<String> = stream.map(|e| e.unwrap().id).collect();
+        assert_eq!(streamed, &expected(&filter)[..4]);
+
+        // Counts cover the union of the filters and ignore limits
+        let filters = [
+            Filter::try_from(r#"{"kinds": [0], "limit": 1}"#).unwrap(),
+            Filter::try_from(r#"{"authors": ["pubkey_a"]}"#).unwrap(),
+        ];
+        let union = events
+            .iter()
+            .filter(|e| filters.iter().any(|f| f.matches(e)))
+            .count();
+        assert_eq!(index.count(&filters).unwrap(), union as u64);
+        assert_eq!(index.count(&[]).unwrap(), 0);
+
+        // Tag filters need the tag index
+        let (index, _temp_dir) = create_test_index();
+        let filter = Filter::try_from(r##"{"#e": ["event_00"]}"##).unwrap();
+        assert!(index.query(&filter, None).is_err());
+        assert!(index.query(&Filter::default(), None).is_ok());
+    }
+
+    #[test]
+    fn test_latest_versions() {
+        let (mut index, _temp_dir) = create_test_index();
+        let version = |id: &str, kind, created_at, d_tag: Option<&str>| {
+            let mut builder = ProtoEventBuilder::new()
+                .id(id)
+                .kind(kind)
+                .pubkey("pubkey_1")
+                .created_at(created_at);
+            if let Some(d_tag) = d_tag {
+                builder = builder.add_tag(vec!["d", d_tag]);
+            }
+            builder.build()
+        };
+        let latest_id = |index: &EventIndex, address: &EventAddress| {
+            index.latest(address).unwrap().map(|record| record.id)
+        };
+        let profile = EventAddress::new(0, "pubkey_1", "");
+        let list = EventAddress::new(30_000, "pubkey_1", "friends");
+
+        // Out-of-order inserts still leave the newest version
+        index
+            .insert(&version("profile_b", 0, 2000, None), "f")
+            .unwrap();
+        index
+            .insert(&version("profile_a", 0, 1000, None), "f")
+            .unwrap();
+        index
+            .insert_batch(&[
+                (&version("list_1", 30_000, 1000, Some("friends")), "f"),
+                (&version("other_list", 30_000, 3000, Some("family")), "f"),
+                (&version("note", 1, 5000, None), "f"),
+            ])
+            .unwrap();
+        assert_eq!(latest_id(&index, &profile).as_deref(), Some("profile_b"));
+        assert_eq!(latest_id(&index, &list).as_deref(), Some("list_1"));
+        assert_eq!(
+            latest_id(&index, &EventAddress::new(1, "pubkey_1", "")),
+            None
+        );
+
+        // On equal timestamps the lowest ID wins
+        index
+            .insert(&version("profile_c", 0, 2000, None), "f")
+            .unwrap();
+        index
+            .insert(&version("profile_0", 0, 2000, None), "f")
+            .unwrap();
+        assert_eq!(latest_id(&index, &profile).as_deref(), Some("profile_0"));
+
+        // Removing the latest version falls back to the next newest
+        index.remove("profile_0").unwrap();
+        assert_eq!(latest_id(&index, &profile).as_deref(), Some("profile_b"));
+        index.remove("profile_a").unwrap();
+        assert_eq!(latest_id(&index, &profile).as_deref(), Some("profile_b"));
+        index.remove("list_1").unwrap();
+        assert_eq!(latest_id(&index, &list), None);
+    }
+
+    #[test]
+    fn test_deletions() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let event = |id: &str, kind, pubkey: &str, created_at, tags: Vec<Vec<&str>>| {
+            tags.into_iter()
+                .fold(
+                    ProtoEventBuilder::new()
+                        .id(id)
+                        .kind(kind)
+                        .pubkey(pubkey)
+                        .created_at(created_at),
+                    |builder, tag| builder.add_tag(tag),
+                )
+                .build()
+        };
+        let list = |id: &str, pubkey: &str, created_at| {
+            event(id, 30_000, pubkey, created_at, vec![vec!["d", "x"]])
+        };
+        let ids = |records: Vec<EventRecord>| -> Vec<String> {
+            records.into_iter().map(|r| r.id).collect()
+        };
+
+        let mut index = EventIndex::new(&db_path).unwrap();
+        assert!(!index.deletion_processing_enabled());
+        index.set_deletion_processing(true).unwrap();
+        index
+            .insert_batch(&[
+                (&event("note_1", 1, "alice", 1000, vec![]), "f"),
+                (&event("note_2", 1, "alice", 1001, vec![]), "f"),
+                (&event("note_bob", 1, "bob", 1002, vec![]), "f"),
+                (&list("list_v1", "alice", 1000), "f"),
+            ])
+            .unwrap();
+
+        // Targets by ID and by address; bob's note and address are not alice's to delete
+        index
+            .insert(
+                &event(
+                    "deletion_1",
+                    5,
+                    "alice",
+                    2000,
+                    vec![
+                        vec!["e", "note_1"],
+                        vec!["e", "note_bob"],
+                        vec!["e", "note_later"],
+                        vec!["a", "30000:alice:x"],
+                        vec!["a", "30000:bob:x"],
+                    ],
+                ),
+                "f",
+            )
+            .unwrap();
+        // Targets indexed after the request are deleted as they arrive,
+        // except versions newer than the request
+        index
+            .insert_batch(&[
+                (&event("note_later", 1, "alice", 1500, vec![]), "f"),
+                (&list("list_v2", "alice", 1999), "f"),
+                (&list("list_v3", "alice", 2001), "f"),
+                (&list("list_bob", "bob", 1000), "f"),
+            ])
+            .unwrap();
+        drop(index);
+
+        // The setting is kept in the database
+        let index = EventIndex::new(&db_path).unwrap();
+        assert!(index.deletion_processing_enabled());
+        assert_eq!(
+            ids(index.query_by_pubkey("alice").unwrap()),
+            vec!["list_v3", "deletion_1", "note_2"]
+        );
+        assert_eq!(
+            ids(index.query_by_pubkey("bob").unwrap()),
+            vec!["note_bob", "list_bob"]
+        );
+        assert_eq!(index.count(&[Filter::default()]).unwrap(), 5);
+        assert_eq!(
+            index.get("note_1").unwrap().unwrap().deleted_by.as_deref(),
+            Some("deletion_1")
+        );
+        assert!(index.contains("list_v2").unwrap());
+        assert_eq!(index.query_by_file("f").unwrap().len(), 9);
+
+        // Queries can ask for deleted events
+        let mut index = index.with_deleted_events(true);
+        assert_eq!(index.query_by_pubkey("alice").unwrap().len(), 7);
+        assert_eq!(index.count(&[Filter::default()]).unwrap(), 9);
+
+        // Removing the request brings its targets back
+        index.remove("deletion_1").unwrap();
+        let mut index = index.with_deleted_events(false);
+        assert_eq!(index.count(&[Filter::default()]).unwrap(), 8);
+
+        // As does turning deletion processing off
+        index
+            .insert(
+                &event("deletion_2", 5, "alice", 3000, vec![vec!["e", "note_2"]]),
+                "f",
+            )
+            .unwrap();
+        assert_eq!(index.count(&[Filter::default()]).unwrap(), 8);
+        index.set_deletion_processing(false).unwrap();
+        assert_eq!(index.count(&[Filter::default()]).unwrap(), 9);
+        index
+            .insert(
+                &event("deletion_3", 5, "alice", 3000, vec![vec!["e", "note_1"]]),
+                "f",
+            )
+            .unwrap();
+        assert_eq!(index.count(&[Filter::default()]).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_dead_events_and_rewrite_file() {
+        let (mut index, _temp_dir) = create_test_index();
+        index.set_deletion_processing(true).unwrap();
+        let note = create_test_event("note", 1, "pubkey_1", 1000);
+        let old_profile = create_test_event("profile_old", 0, "pubkey_1", 1000);
+        let new_profile = create_test_event("profile_new", 0, "pubkey_1", 2000);
+        let other = create_test_event("other", 1, "pubkey_2", 1000);
+        let deletion = ProtoEventBuilder::new()
+            .id("deletion")
+            .kind(5)
+            .pubkey("pubkey_1")
+            .created_at(3000)
+            .add_tag(vec!["e", "note"])
+            .build();
+        let rows: Vec<_> = [&note, &old_profile, &new_profile, &other, &deletion]
+            .into_iter()
+            .map(|event| (event, "day.pb.gz"))
+            .collect();
+        index.insert_batch(&rows).unwrap();
+
+        let dead = index.dead_events("day.pb.gz").unwrap();
+        assert_eq!(dead, vec!["note", "profile_old"]);
+        assert!(index.dead_events("other.pb.gz").unwrap().is_empty());
+
+        // Survivors move to the rewritten file and the dead leave the index
+        let kept: Vec<_> = [&new_profile, &other, &deletion]
+            .into_iter()
+            .map(|event| (event, "day.pb.zst", 0))
+            .collect();
+        assert_eq!(index.rewrite_file(&kept, &dead).unwrap(), 2);
+        assert!(!index.contains("note").unwrap());
+        assert!(!index.contains("profile_old").unwrap());
+        assert!(index.query_by_file("day.pb.gz").unwrap().is_empty());
+        assert_eq!(index.query_by_file("day.pb.zst").unwrap().len(), 3);
+        assert_eq!(index.get("other").unwrap().unwrap().block_offset, Some(0));
+        assert_eq!(
+            index
+                .latest(&EventAddress::new(0, "pubkey_1", ""))
+                .unwrap()
+                .map(|record| record.id),
+            Some("profile_new".to_string())
+        );
+
+        // The deletion request still applies if its target turns up again
+        index.insert(&note, "day.pb.zst").unwrap();
+        assert!(index.get("note").unwrap().unwrap().deleted_by.is_some());
+    }
+
+    #[test]
+    fn test_move_file() {
+        let (mut index, _temp_dir) = create_test_index();
+        let first = create_test_event("first", 1, "pubkey_1", 1000);
+        let second = create_test_event("second", 1, "pubkey_1", 2000);
+        let other = create_test_event("other", 1, "pubkey_1", 3000);
+        index
+            .insert_batch_at(&[
+                (&first, "day.pb.gz", 0),
+                (&second, "day.pb.gz", 120),
+                (&other, "next.pb.gz", 0),
+            ])
+            .unwrap();
+
+        assert_eq!(index.move_file("day.pb.gz", "day.pb.zst").unwrap(), 2);
+        assert!(index.query_by_file("day.pb.gz").unwrap().is_empty());
+        let record = index.get("second").unwrap().unwrap();
+        assert_eq!(record.file_path, "day.pb.zst");
+        assert_eq!(record.block_offset, None);
+        assert_eq!(index.get("other").unwrap().unwrap().block_offset, Some(0));
+    }
+
     #[test]
     fn test_reopen_index() {
         let temp_dir = TempDir::new().unwrap();
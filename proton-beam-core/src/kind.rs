@@ -0,0 +1,211 @@
+//! NIP-01 kind ranges and event addresses
+//!
+//! Relays keep every regular event, but only the latest version of a
+//! replaceable event (per kind and pubkey) or an addressable event (per
+//! kind, pubkey and `d` tag). Ephemeral events are not expected to be
+//! stored at all. An [`EventAddress`] names the slot that successive
+//! versions replace each other in, in the `<kind>:<pubkey>:<d tag>` form
+//! used by `a` tags.
+//!
+//! # Examples
+//!
+//! ```
+//! use proton_beam_core::{KindClass, ProtoEventBuilder};
+//!
+//! let article = ProtoEventBuilder::new()
+//!     .kind(30023)
+//!     .pubkey("abc")
+//!     .add_tag(vec!["d", "my-article"])
+//!     .build();
+//!
+//! assert_eq!(article.kind_class(), KindClass::Addressable);
+//! assert_eq!(article.address().unwrap().to_string(), "30023:abc:my-article");
+//! ```
+
+use crate::{Error, ProtoEvent, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// How relays treat events of a kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KindClass {
+    /// Every event is kept
+    Regular,
+    /// Kinds 0, 3 and 10000–19999: only the latest event per pubkey is kept
+    Replaceable,
+    /// Kinds 20000–29999: events are not stored
+    Ephemeral,
+    /// Kinds 30000–39999: only the latest event per pubkey and `d` tag is kept
+    Addressable,
+}
+
+impl KindClass {
+    /// Classify an event kind
+    pub fn of(kind: i32) -> Self {
+        match kind {
+            0 | 3 | 10_000..20_000 => Self::Replaceable,
+            20_000..30_000 => Self::Ephemeral,
+            30_000..40_000 => Self::Addressable,
+            _ => Self::Regular,
+        }
+    }
+
+    /// Whether newer events of the kind replace older ones
+    pub fn is_replaceable(self) -> bool {
+        matches!(self, Self::Replaceable | Self::Addressable)
+    }
+}
+
+/// Address of a replaceable or addressable event: `<kind>:<pubkey>:<d tag>`
+///
+/// The identifier is empty for replaceable kinds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EventAddress {
+    /// Event kind
+    pub kind: u16,
+    /// Author public key (hex-encoded)
+    pub pubkey: String,
+    /// Value of the `d` tag, or empty
+    pub identifier: String,
+}
+
+impl EventAddress {
+    /// Create an address from its parts
+    pub fn new(kind: u16, pubkey: impl Into<String>, identifier: impl Into<String>) -> Self {
+        Self {
+            kind,
+            pubkey: pubkey.into(),
+            identifier: identifier.into(),
+        }
+    }
+}
+
+impl fmt::Display for EventAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.kind, self.pubkey, self.identifier)
+    }
+}
+
+impl FromStr for EventAddress {
+    type Err = Error;
+
+    /// Parse an `a` tag coordinate; the identifier may itself contain `:`
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(pubkey), identifier) if !pubkey.is_empty() => {
+                let kind = kind
+                    .parse()
+                    .map_err(|_| Error::Conversion(format!("Invalid event address: {}", s)))?;
+                Ok(Self::new(kind, pubkey, identifier.unwrap_or_default()))
+            }
+            _ => Err(Error::Conversion(format!("Invalid event address: {}", s))),
+        }
+    }
+}
+
+impl ProtoEvent {
+    /// How relays treat this event's kind
+    pub fn kind_class(&self) -> KindClass {
+        KindClass::of(self.kind)
+    }
+
+    /// Value of the first `d` tag, or empty if there is none
+    pub fn identifier(&self) -> &str {
+        self.tags
+            .iter()
+            .find(|tag| tag.values.first().is_some_and(|name| name == "d"))
+            .and_then(|tag| tag.values.get(1))
+            .map_or("", String::as_str)
+    }
+
+    /// Address that newer versions of this event replace it at
+    ///
+    /// `None` for regular and ephemeral events.
+    pub fn address(&self) -> Option<EventAddress> {
+        let kind = u16::try_from(self.kind).ok()?;
+        match self.kind_class() {
+            KindClass::Replaceable => Some(EventAddress::new(kind, &self.pubkey, "")),
+            KindClass::Addressable => {
+                Some(EventAddress::new(kind, &self.pubkey, self.identifier()))
+            }
+            KindClass::Regular | KindClass::Ephemeral => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtoEventBuilder;
+
+    #[test]
+    fn test_kind_class() {
+        for kind in [0, 3, 10_000, 10_002, 19_999] {
+            assert_eq!(KindClass::of(kind), KindClass::Replaceable, "kind {}", kind);
+        }
+        for kind in [1, 2, 4, 7, 1_000, 9_999, 40_000, 65_535] {
+            assert_eq!(KindClass::of(kind), KindClass::Regular, "kind {}", kind);
+        }
+        assert_eq!(KindClass::of(20_000), KindClass::Ephemeral);
+        assert_eq!(KindClass::of(29_999), KindClass::Ephemeral);
+        assert_eq!(KindClass::of(30_000), KindClass::Addressable);
+        assert_eq!(KindClass::of(39_999), KindClass::Addressable);
+        assert!(KindClass::Addressable.is_replaceable());
+        assert!(!KindClass::Ephemeral.is_replaceable());
+    }
+
+    #[test]
+    fn test_event_address() {
+        let event = |kind, tags: Vec<Vec<&str>>| {
+            tags.into_iter()
+                .fold(
+                    ProtoEventBuilder::new().kind(kind).pubkey("abc"),
+                    |builder, tag| builder.add_tag(tag),
+                )
+                .build()
+        };
+
+        assert_eq!(
+            event(0, vec![]).address(),
+            Some(EventAddress::new(0, "abc", ""))
+        );
+        // The d tag only counts for addressable kinds
+        assert_eq!(
+            event(3, vec![vec!["d", "x"]]).address(),
+            Some(EventAddress::new(3, "abc", ""))
+        );
+        assert_eq!(
+            event(
+                30_000,
+                vec![vec!["p", "def"], vec!["d", "friends"], vec!["d", "x"]]
+            )
+            .address(),
+            Some(EventAddress::new(30_000, "abc", "friends"))
+        );
+        assert_eq!(
+            event(30_023, vec![]).address(),
+            Some(EventAddress::new(30_023, "abc", ""))
+        );
+        assert_eq!(event(1, vec![vec!["d", "x"]]).address(), None);
+        assert_eq!(event(20_001, vec![]).address(), None);
+    }
+
+    #[test]
+    fn test_parse_event_address() {
+        let address: EventAddress = "30023:abc:a:b".parse().unwrap();
+        assert_eq!(address, EventAddress::new(30_023, "abc", "a:b"));
+        assert_eq!(address.to_string(), "30023:abc:a:b");
+        assert_eq!(
+            "0:abc:".parse::<EventAddress>().unwrap(),
+            EventAddress::new(0, "abc", "")
+        );
+        assert_eq!(
+            "10002:abc".parse::<EventAddress>().unwrap(),
+            EventAddress::new(10_002, "abc", "")
+        );
+        assert!("x:abc:".parse::<EventAddress>().is_err());
+        assert!("1".parse::<EventAddress>().is_err());
+        assert!("1::d".parse::<EventAddress>().is_err());
+    }
+}
@@ -8,8 +8,12 @@
 //! - JSON ↔ Protobuf conversion for Nostr events (using idiomatic `TryFrom`/`From` traits)
 //! - Event ID validation (SHA-256 verification)
 //! - Schnorr signature verification
-//! - Length-delimited protobuf I/O for streaming
+//! - Length-delimited protobuf I/O for streaming, gzip or zstd compressed
+//! - Compact `nostr.v2` format with binary id/pubkey/sig, detected automatically on read
 //! - SQLite index for event deduplication and fast lookups
+//! - Seekable block layout so one event can be fetched without decompressing its whole file
+//! - NIP-01 subscription filters for matching events
+//! - Kind classification and addresses of replaceable/addressable events
 //! - Fluent builder pattern for constructing events
 //! - Serde support for direct JSON serialization
 //! - `Display` trait for human-readable output
@@ -68,30 +72,45 @@
 // Include the generated protobuf code
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/nostr.rs"));
+
+    /// Compact event format with binary id, pubkey and sig
+    pub mod v2 {
+        include!(concat!(env!("OUT_DIR"), "/nostr.v2.rs"));
+    }
 }
 
 // Re-export main types
 pub use proto::{EventBatch, ProtoEvent, Tag};
 
 // Public modules
+pub mod block;
 pub mod builder;
 pub mod conversion;
 pub mod display;
 pub mod error;
+pub mod filter;
 pub mod index;
 pub mod iter;
+pub mod kind;
 pub mod serde_support;
 pub mod storage;
 pub mod validation;
 
 // Re-export commonly used types and functions
+pub use block::{BlockDecoder, BlockScan, SeekTable, read_block, scan_blocks};
 pub use builder::ProtoEventBuilder;
 pub use conversion::{json_to_proto, proto_to_json};
-pub use error::{Error, Result};
-pub use index::{EventIndex, EventRecord, IndexStats};
+pub use error::{Error, Result, ValidationError};
+pub use filter::Filter;
+pub use index::{
+    EventIndex, EventRecord, EventStream, IndexStats, QueryCursor, QueryPage, RelaySighting,
+};
+pub use kind::{EventAddress, KindClass};
 pub use storage::{
-    create_gzip_decoder, create_gzip_encoder, create_gzip_encoder_with_level,
-    read_events_delimited, write_event_delimited, write_events_delimited,
+    Codec, Decoder, Encoder, FormatVersion, create_decoder, create_encoder, create_gzip_decoder,
+    create_gzip_encoder, create_gzip_encoder_with_level, is_event_file, read_events_delimited,
+    train_zstd_dictionary, write_event_delimited, write_event_delimited_as, write_events_delimited,
+    write_format_header,
 };
 pub use validation::{
     compute_event_hash, validate_basic_fields, validate_event, validate_event_id_from_hash,
@@ -1,11 +1,101 @@
-//! Storage I/O for length-delimited protobuf events with optional gzip compression
-
-use crate::{ProtoEvent, error::Result};
+//! Storage I/O for length-delimited protobuf events with gzip or zstd compression
+//!
+//! Streams hold `nostr.ProtoEvent` records unless a [`StreamHeader`] record
+//! announces another [`FormatVersion`]. Readers switch format whenever they
+//! meet a header, so appending a differently formatted gzip member to an
+//! existing file is fine as long as the new member starts with a header.
+//!
+//! Files are compressed with a [`Codec`]; [`create_encoder`] writes either
+//! one, and [`create_decoder`] detects which one from the magic bytes.
+
+use crate::proto::{StreamHeader, v2};
+use crate::{
+    ProtoEvent,
+    error::{Error, Result},
+};
 use flate2::Compression;
 use flate2::read::MultiGzDecoder;
 use flate2::write::GzEncoder;
 use prost::Message;
-use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// First byte of an encoded [`StreamHeader`] (field 15, length-delimited)
+///
+/// Event messages never start with it, since prost writes fields in order
+/// and events only use fields 1 to 7.
+const STREAM_HEADER_TAG: u8 = (15 << 3) | 2;
+
+/// Event message format of a length-delimited stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatVersion {
+    /// `nostr.ProtoEvent` with hex-encoded id, pubkey and sig
+    #[default]
+    V1,
+    /// `nostr.v2.ProtoEvent` with binary id, pubkey and sig
+    V2,
+}
+
+impl FormatVersion {
+    /// Protobuf package of the event message, as written in stream headers
+    pub fn package(self) -> &'static str {
+        match self {
+            Self::V1 => "nostr",
+            Self::V2 => "nostr.v2",
+        }
+    }
+
+    fn from_package(package: &str) -> Option<Self> {
+        match package {
+            "nostr" => Some(Self::V1),
+            "nostr.v2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V1 => write!(f, "v1"),
+            Self::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+impl FromStr for FormatVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "v1" => Ok(Self::V1),
+            "v2" => Ok(Self::V2),
+            _ => Err(Error::Conversion(format!(
+                "Unknown format version: {} (expected v1 or v2)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Write a [`StreamHeader`] announcing the format of the records that follow
+///
+/// Streams without a header are read as [`FormatVersion::V1`], but readers
+/// keep the last format they saw across concatenated gzip members and zstd
+/// frames, so writers that may append to an existing file should start every
+/// member with a header, V1 included.
+pub fn write_format_header<W: Write>(writer: &mut W, format: FormatVersion) -> Result<()> {
+    let header = StreamHeader {
+        format: format.package().to_string(),
+    };
+    let mut buf = DelimitedBuffer::default();
+    header.encode(&mut buf.event_buf)?;
+    buf.write_to(writer)
+}
 
 /// Write a single event in length-delimited format
 ///
@@ -35,7 +125,22 @@ use std::io::{Read, Write};
 /// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
 pub fn write_event_delimited<W: Write>(writer: &mut W, event: &ProtoEvent) -> Result<()> {
-    write_event_delimited_with_buf(writer, event, &mut DelimitedBuffer::default())
+    write_event_delimited_as(writer, event, FormatVersion::V1)
+}
+
+/// Write a single event in length-delimited format using `format`
+///
+/// The stream must already have a header for `format` (see
+/// [`write_format_header`]) unless it is a fresh [`FormatVersion::V1`]
+/// stream. Fails
+/// without writing anything if the event cannot be represented losslessly
+/// in `format`.
+pub fn write_event_delimited_as<W: Write>(
+    writer: &mut W,
+    event: &ProtoEvent,
+    format: FormatVersion,
+) -> Result<()> {
+    write_event_delimited_with_buf(writer, event, format, &mut DelimitedBuffer::default())
 }
 
 /// Write multiple events in length-delimited format
@@ -44,7 +149,7 @@ pub fn write_event_delimited<W: Write>(writer: &mut W, event: &ProtoEvent) -> Re
 pub fn write_events_delimited<W: Write>(writer: &mut W, events: &[ProtoEvent]) -> Result<()> {
     let mut buffer = DelimitedBuffer::default();
     for event in events {
-        write_event_delimited_with_buf(writer, event, &mut buffer)?;
+        write_event_delimited_with_buf(writer, event, FormatVersion::V1, &mut buffer)?;
     }
     Ok(())
 }
@@ -52,7 +157,8 @@ pub fn write_events_delimited<W: Write>(writer: &mut W, events: &[ProtoEvent]) -
 /// Read events from a length-delimited protobuf stream
 ///
 /// Returns an iterator that yields events one at a time, allowing
-/// memory-efficient processing of large files.
+/// memory-efficient processing of large files. The format is detected from
+/// stream headers; events are always returned as [`ProtoEvent`].
 ///
 /// # Example
 ///
@@ -75,6 +181,7 @@ pub fn read_events_delimited<R: Read>(reader: R) -> EventIterator<R> {
 pub struct EventIterator<R: Read> {
     reader: R,
     buffer: Vec<u8>,
+    format: FormatVersion,
 }
 
 impl<R: Read> EventIterator<R> {
@@ -82,39 +189,69 @@ impl<R: Read> EventIterator<R> {
         Self {
             reader,
             buffer: Vec::new(),
+            format: FormatVersion::V1,
         }
     }
+
+    /// Format of the records read most recently
+    pub fn format(&self) -> FormatVersion {
+        self.format
+    }
+
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Switch format according to the stream header in the buffer
+    fn apply_header(&mut self) -> Result<()> {
+        let header = StreamHeader::decode(&self.buffer[..])?;
+        self.format = FormatVersion::from_package(&header.format).ok_or_else(|| {
+            Error::Conversion(format!("Unsupported stream format: {}", header.format))
+        })?;
+        Ok(())
+    }
 }
 
 impl<R: Read> Iterator for EventIterator<R> {
     type Item = Result<ProtoEvent>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Read varint length
-        let length = match read_varint(&mut self.reader) {
-            Ok(len) => len as usize,
-            Err(e) => {
-                // Check if this is EOF (expected end of iteration)
-                if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                    return None;
+        loop {
+            // Read varint length
+            let length = match read_varint(&mut self.reader) {
+                Ok(len) => len as usize,
+                Err(e) => {
+                    // Check if this is EOF (expected end of iteration)
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                        return None;
+                    }
+                    return Some(Err(e.into()));
                 }
+            };
+
+            // Prepare buffer
+            self.buffer.clear();
+            self.buffer.resize(length, 0);
+
+            // Read message bytes
+            if let Err(e) = self.reader.read_exact(&mut self.buffer) {
                 return Some(Err(e.into()));
             }
-        };
-
-        // Prepare buffer
-        self.buffer.clear();
-        self.buffer.resize(length, 0);
 
-        // Read message bytes
-        if let Err(e) = self.reader.read_exact(&mut self.buffer) {
-            return Some(Err(e.into()));
-        }
+            if self.buffer.first() == Some(&STREAM_HEADER_TAG) {
+                if let Err(e) = self.apply_header() {
+                    return Some(Err(e));
+                }
+                continue;
+            }
 
-        // Decode event
-        match ProtoEvent::decode(&self.buffer[..]) {
-            Ok(event) => Some(Ok(event)),
-            Err(e) => Some(Err(e.into())),
+            // Decode event
+            let event = match self.format {
+                FormatVersion::V1 => ProtoEvent::decode(&self.buffer[..]),
+                FormatVersion::V2 => v2::ProtoEvent::decode(&self.buffer[..]).map(ProtoEvent::from),
+            };
+            return Some(event.map_err(Into::into));
         }
     }
 }
@@ -178,26 +315,302 @@ pub fn create_gzip_encoder_with_level<W: Write>(writer: W, level: u32) -> GzEnco
     GzEncoder::new(writer, Compression::new(level))
 }
 
+/// Magic bytes at the start of a gzip member
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Magic bytes at the start of a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression codec of a protobuf event file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// gzip (`.pb.gz`), readable by standard tools
+    #[default]
+    Gzip,
+    /// Zstandard (`.pb.zst`), several times faster to decompress
+    Zstd,
+}
+
+impl Codec {
+    /// File extension after `.pb`, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+
+    /// Codec of an event file name such as `2025_01_01.pb.zst`
+    ///
+    /// A trailing `.tmp` or `.repair` suffix is ignored.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let name = name
+            .strip_suffix(".tmp")
+            .or_else(|| name.strip_suffix(".repair"))
+            .unwrap_or(name);
+        [Self::Gzip, Self::Zstd]
+            .into_iter()
+            .find(|codec| name.ends_with(&format!(".pb.{}", codec.extension())))
+    }
+
+    /// Codec of a stream starting with `bytes`
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gzip => write!(f, "gzip"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            _ => Err(Error::Conversion(format!(
+                "Unknown codec: {} (expected gzip or zstd)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Whether `path` is a final protobuf event file (`.pb.gz` or `.pb.zst`)
+pub fn is_event_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| {
+            [Codec::Gzip, Codec::Zstd]
+                .iter()
+                .any(|codec| name.ends_with(&format!(".pb.{}", codec.extension())))
+        })
+}
+
+/// Compressing writer for either [`Codec`]
+///
+/// Like a gzip encoder, a zstd stream is finished when the encoder is
+/// dropped; call [`Encoder::finish`] to see errors and get the writer back.
+pub struct Encoder<W: Write> {
+    inner: Option<EncoderInner<W>>,
+}
+
+enum EncoderInner<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn inner_mut(&mut self) -> &mut EncoderInner<W> {
+        self.inner.as_mut().expect("encoder used after finish")
+    }
+
+    /// Finish the compressed stream and return the underlying writer
+    pub fn finish(mut self) -> std::io::Result<W> {
+        match self.inner.take().expect("encoder used after finish") {
+            EncoderInner::Gzip(encoder) => encoder.finish(),
+            EncoderInner::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.inner_mut() {
+            EncoderInner::Gzip(encoder) => encoder.write(buf),
+            EncoderInner::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.inner_mut() {
+            EncoderInner::Gzip(encoder) => encoder.flush(),
+            EncoderInner::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        // GzEncoder finishes itself on drop; zstd's encoder does not
+        if let Some(EncoderInner::Zstd(encoder)) = self.inner.take() {
+            let _ = encoder.finish();
+        }
+    }
+}
+
+/// Create an encoder for `codec`
+///
+/// `level` is the gzip level (0-9) or the zstd level (0 = zstd default).
+/// A zstd `dictionary` (see [`train_zstd_dictionary`]) improves the ratio
+/// on small batches; readers need the same dictionary.
+///
+/// # Example
+///
+/// ```no_run
+/// use proton_beam_core::{Codec, create_encoder, write_event_delimited, ProtoEvent};
+/// use std::fs::File;
+///
+/// let mut encoder = create_encoder(File::create("events.pb.zst")?, Codec::Zstd, 3, None)?;
+/// write_event_delimited(&mut encoder, &ProtoEvent::default())?;
+/// encoder.finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn create_encoder<W: Write>(
+    writer: W,
+    codec: Codec,
+    level: u32,
+    dictionary: Option<&[u8]>,
+) -> Result<Encoder<W>> {
+    let inner = match (codec, dictionary) {
+        (Codec::Gzip, None) => EncoderInner::Gzip(create_gzip_encoder_with_level(writer, level)),
+        (Codec::Gzip, Some(_)) => {
+            return Err(Error::Conversion(
+                "Compression dictionaries are only supported with zstd".to_string(),
+            ));
+        }
+        (Codec::Zstd, dictionary) => {
+            EncoderInner::Zstd(zstd::stream::write::Encoder::with_dictionary(
+                writer,
+                level as i32,
+                dictionary.unwrap_or_default(),
+            )?)
+        }
+    };
+    Ok(Encoder { inner: Some(inner) })
+}
+
+/// Decompressing reader for either [`Codec`]
+pub enum Decoder<R: Read> {
+    /// Concatenated gzip members
+    Gzip(MultiGzDecoder<BufReader<R>>),
+    /// Concatenated zstd frames
+    Zstd(zstd::stream::read::Decoder<'static, BufReader<R>>),
+}
+
+impl<R: Read> Decoder<R> {
+    /// Codec of the stream being decoded
+    pub fn codec(&self) -> Codec {
+        match self {
+            Self::Gzip(_) => Codec::Gzip,
+            Self::Zstd(_) => Codec::Zstd,
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Create a decoder, detecting the codec from the stream's magic bytes
+///
+/// Gzip members and zstd frames may each be concatenated, but not mixed
+/// within one stream. An empty stream decodes to nothing. `dictionary` is
+/// only used for zstd streams and must match the one they were written with.
+///
+/// # Example
+///
+/// ```no_run
+/// use proton_beam_core::{create_decoder, read_events_delimited};
+/// use std::fs::File;
+///
+/// for result in read_events_delimited(create_decoder(File::open("events.pb.zst")?, None)?) {
+///     println!("Event ID: {}", result?.id);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn create_decoder<R: Read>(reader: R, dictionary: Option<&[u8]>) -> Result<Decoder<R>> {
+    let mut reader = BufReader::new(reader);
+    let head = reader.fill_buf()?;
+    let codec = if head.is_empty() {
+        Codec::Gzip
+    } else {
+        Codec::from_magic(head).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Unrecognized compression format (expected gzip or zstd)",
+            )
+        })?
+    };
+
+    Ok(match codec {
+        Codec::Gzip => Decoder::Gzip(MultiGzDecoder::new(reader)),
+        Codec::Zstd => Decoder::Zstd(zstd::stream::read::Decoder::with_dictionary(
+            reader,
+            dictionary.unwrap_or_default(),
+        )?),
+    })
+}
+
+/// Train a zstd dictionary on sample events
+///
+/// Samples are encoded the way they are stored in `format`. A few thousand
+/// events are usually enough; `max_size` caps the dictionary size in bytes
+/// (around 100 KiB works well for Nostr events).
+pub fn train_zstd_dictionary<'a>(
+    events: impl IntoIterator<Item = &'a ProtoEvent>,
+    format: FormatVersion,
+    max_size: usize,
+) -> Result<Vec<u8>> {
+    let mut buf = DelimitedBuffer::default();
+    let mut samples = Vec::new();
+    for event in events {
+        let mut sample = Vec::new();
+        write_event_delimited_with_buf(&mut sample, event, format, &mut buf)?;
+        samples.push(sample);
+    }
+    Ok(zstd::dict::from_samples(&samples, max_size)?)
+}
+
 #[derive(Default)]
 struct DelimitedBuffer {
     len_buf: Vec<u8>,
     event_buf: Vec<u8>,
 }
 
+impl DelimitedBuffer {
+    /// Write the encoded message in `event_buf` with its length prefix
+    fn write_to<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        self.len_buf.clear();
+        prost::encoding::encode_varint(self.event_buf.len() as u64, &mut self.len_buf);
+        writer.write_all(&self.len_buf)?;
+        writer.write_all(&self.event_buf)?;
+        Ok(())
+    }
+}
+
 fn write_event_delimited_with_buf<W: Write>(
     writer: &mut W,
     event: &ProtoEvent,
+    format: FormatVersion,
     buf: &mut DelimitedBuffer,
 ) -> Result<()> {
     buf.event_buf.clear();
-    event.encode(&mut buf.event_buf)?;
-
-    buf.len_buf.clear();
-    prost::encoding::encode_varint(buf.event_buf.len() as u64, &mut buf.len_buf);
-    writer.write_all(&buf.len_buf)?;
-    writer.write_all(&buf.event_buf)?;
-
-    Ok(())
+    match format {
+        FormatVersion::V1 => event.encode(&mut buf.event_buf)?,
+        FormatVersion::V2 => v2::ProtoEvent::try_from(event)?.encode(&mut buf.event_buf)?,
+    }
+    buf.write_to(writer)
 }
 
 /// Read a varint from a reader
@@ -503,4 +916,180 @@ mod tests {
             ratio
         );
     }
+
+    fn create_hex_event(seed: u8) -> ProtoEvent {
+        ProtoEvent {
+            id: hex::encode([seed; 32]),
+            pubkey: hex::encode([0xab; 32]),
+            created_at: 1234567890,
+            kind: 1,
+            tags: vec![Tag {
+                values: vec!["p".to_string(), hex::encode([0xcd; 32])],
+            }],
+            content: format!("Test content {}", seed),
+            sig: hex::encode([0xef; 64]),
+        }
+    }
+
+    #[test]
+    fn test_v2_round_trip_and_size() {
+        let events: Vec<ProtoEvent> = (0..10).map(create_hex_event).collect();
+
+        let mut v1 = Vec::new();
+        write_events_delimited(&mut v1, &events).unwrap();
+
+        let mut v2 = Vec::new();
+        write_format_header(&mut v2, FormatVersion::V2).unwrap();
+        for event in &events {
+            write_event_delimited_as(&mut v2, event, FormatVersion::V2).unwrap();
+        }
+
+        // 32 + 32 + 64 bytes saved per event, minus the one-off header
+        assert!(v1.len() - v2.len() > 10 * 120);
+
+        let mut iter = read_events_delimited(Cursor::new(v2));
+        let read: Vec<ProtoEvent> = iter.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(read, events);
+        assert_eq!(iter.format(), FormatVersion::V2);
+    }
+
+    #[test]
+    fn test_mixed_format_members() {
+        let mut buffer = Vec::new();
+        write_event_delimited(&mut buffer, &create_hex_event(1)).unwrap();
+        write_format_header(&mut buffer, FormatVersion::V2).unwrap();
+        write_event_delimited_as(&mut buffer, &create_hex_event(2), FormatVersion::V2).unwrap();
+        write_format_header(&mut buffer, FormatVersion::V1).unwrap();
+        write_event_delimited(&mut buffer, &create_hex_event(3)).unwrap();
+
+        let events: Vec<ProtoEvent> = read_events_delimited(Cursor::new(buffer))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                create_hex_event(1),
+                create_hex_event(2),
+                create_hex_event(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_v2_rejects_non_hex_event() {
+        let mut buffer = Vec::new();
+        let result =
+            write_event_delimited_as(&mut buffer, &create_test_event("event1"), FormatVersion::V2);
+        assert!(result.is_err());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_stream_format() {
+        let mut buffer = Vec::new();
+        let header = StreamHeader {
+            format: "nostr.v99".to_string(),
+        };
+        let mut buf = DelimitedBuffer::default();
+        header.encode(&mut buf.event_buf).unwrap();
+        buf.write_to(&mut buffer).unwrap();
+
+        let result: Result<Vec<ProtoEvent>> = read_events_delimited(Cursor::new(buffer)).collect();
+        assert!(result.is_err());
+        assert_eq!("v2".parse::<FormatVersion>().unwrap(), FormatVersion::V2);
+        assert!("v3".parse::<FormatVersion>().is_err());
+    }
+
+    /// Compress `events` as two concatenated streams, like a file appended to twice
+    fn compress_twice(events: &[ProtoEvent], codec: Codec, dictionary: Option<&[u8]>) -> Vec<u8> {
+        let (first, second) = events.split_at(events.len() / 2);
+        let mut compressed = Vec::new();
+        for part in [first, second] {
+            let mut encoder = create_encoder(&mut compressed, codec, 3, dictionary).unwrap();
+            write_events_delimited(&mut encoder, part).unwrap();
+            encoder.finish().unwrap();
+        }
+        compressed
+    }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let events: Vec<ProtoEvent> = (0..20).map(create_hex_event).collect();
+
+        for codec in [Codec::Gzip, Codec::Zstd] {
+            let compressed = compress_twice(&events, codec, None);
+            assert_eq!(Codec::from_magic(&compressed), Some(codec));
+
+            let decoder = create_decoder(Cursor::new(compressed), None).unwrap();
+            assert_eq!(decoder.codec(), codec);
+            let read: Vec<ProtoEvent> = read_events_delimited(decoder)
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(read, events, "{}", codec);
+        }
+    }
+
+    #[test]
+    fn test_zstd_dropped_encoder_finishes() {
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = create_encoder(&mut compressed, Codec::Zstd, 3, None).unwrap();
+            write_event_delimited(&mut encoder, &create_hex_event(1)).unwrap();
+        }
+
+        let decoder = create_decoder(Cursor::new(compressed), None).unwrap();
+        let read: Vec<ProtoEvent> = read_events_delimited(decoder)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read, vec![create_hex_event(1)]);
+    }
+
+    #[test]
+    fn test_truncated_zstd_stream() {
+        let events: Vec<ProtoEvent> = (0..20).map(create_hex_event).collect();
+        let mut compressed = compress_twice(&events, Codec::Zstd, None);
+        compressed.truncate(compressed.len() - 8);
+
+        let mut decoder = create_decoder(Cursor::new(compressed), None).unwrap();
+        assert!(std::io::copy(&mut decoder, &mut std::io::sink()).is_err());
+    }
+
+    #[test]
+    fn test_zstd_dictionary() {
+        let events: Vec<ProtoEvent> = (0..=255).map(create_hex_event).collect();
+        let dictionary = train_zstd_dictionary(&events, FormatVersion::V1, 4096).unwrap();
+
+        let compressed = compress_twice(&events[..10], Codec::Zstd, Some(&dictionary));
+        let decoder = create_decoder(Cursor::new(compressed.clone()), Some(&dictionary)).unwrap();
+        let read: Vec<ProtoEvent> = read_events_delimited(decoder)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(read, events[..10]);
+
+        // Without the dictionary the frames cannot be decoded
+        let decoder = create_decoder(Cursor::new(compressed), None).unwrap();
+        assert!(read_events_delimited(decoder).any(|r| r.is_err()));
+
+        assert!(create_encoder(Vec::new(), Codec::Gzip, 6, Some(&dictionary)).is_err());
+    }
+
+    #[test]
+    fn test_codec_detection() {
+        assert_eq!(
+            Codec::from_path(Path::new("2025_01_01.pb.zst")),
+            Some(Codec::Zstd)
+        );
+        assert_eq!(
+            Codec::from_path(Path::new("thread_0_2025_01_01.pb.gz.tmp")),
+            Some(Codec::Gzip)
+        );
+        assert_eq!(Codec::from_path(Path::new("events.jsonl")), None);
+        assert!(is_event_file(Path::new("dir/2025_01_01.pb.zst")));
+        assert!(!is_event_file(Path::new("dir/2025_01_01.pb.zst.tmp")));
+
+        assert!(create_decoder(Cursor::new(b"{\"id\":1}".to_vec()), None).is_err());
+        let empty = create_decoder(Cursor::new(Vec::new()), None).unwrap();
+        assert_eq!(read_events_delimited(empty).count(), 0);
+        assert_eq!("zstd".parse::<Codec>().unwrap(), Codec::Zstd);
+    }
 }
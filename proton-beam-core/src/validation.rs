@@ -76,7 +76,7 @@ pub fn validate_basic_fields(event: &ProtoEvent) -> Result<()> {
 
     // Check kind is in valid range (0-65535)
     if event.kind < 0 || event.kind > 65535 {
-        return Err(ValidationError::InvalidKind(event.kind).into());
+        return Err(ValidationError::InvalidKind(event.kind.into()).into());
     }
 
     Ok(())
@@ -0,0 +1,426 @@
+//! Historical backfill: paging backwards through a relay's stored events
+//!
+//! Each relay gets its own connection. Pages are requested newest first with
+//! `since`/`until`/`limit` filters; after every page the events are synced to
+//! disk and the cursor is checkpointed in the state database, so an
+//! interrupted backfill resumes from the last completed page. A pass ends
+//! when a page brings nothing new. Later runs only fetch the gap between the
+//! end of the last completed pass and their own start time.
+
+use crate::pipeline::IngestMessage;
+use crate::relay::{RelayContext, RelayMessage, close_message, req_message, shutdown_requested};
+use crate::state::StateStore;
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+/// Subscription ID used for backfill pages
+pub const BACKFILL_SUBSCRIPTION_ID: &str = "proton-beam-backfill";
+
+/// Give up on a page if the relay goes quiet for this long before EOSE
+const PAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time range and page size for a backfill
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackfillWindow {
+    /// Oldest `created_at` to request (0 = no lower bound)
+    pub since: i64,
+    /// Newest `created_at` to request
+    pub until: i64,
+    /// `limit` sent with each page
+    pub page_size: usize,
+}
+
+/// Progress of a relay's backfill, persisted after every page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackfillCheckpoint {
+    /// `since` the backfill was started with
+    pub since: i64,
+    /// Everything from `since` up to this timestamp has been fetched
+    pub covered_until: Option<i64>,
+    /// Newest timestamp of the current pass
+    pub pass_upper: i64,
+    /// `until` of the next page to request
+    pub cursor: i64,
+}
+
+/// What one page brought back
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageSummary {
+    /// Events received for the page
+    pub events: usize,
+    /// Events that were not already in the previous page
+    pub new_events: usize,
+    /// Oldest `created_at` in the page
+    pub oldest: Option<i64>,
+}
+
+impl BackfillCheckpoint {
+    /// Resume a stored checkpoint, or start a pass from `until` down to `since`
+    ///
+    /// A stored checkpoint for a different `since` is discarded.
+    pub fn resume(stored: Option<Self>, since: i64, until: i64) -> Self {
+        match stored {
+            Some(checkpoint) if checkpoint.since == since => checkpoint,
+            _ => Self {
+                since,
+                covered_until: None,
+                pass_upper: until,
+                cursor: until,
+            },
+        }
+    }
+
+    /// Make sure a pass is running that reaches up to `until`
+    ///
+    /// Returns `false` once everything up to `until` has been fetched.
+    pub fn next_pass(&mut self, until: i64) -> bool {
+        let in_progress = self.covered_until != Some(self.pass_upper);
+        if in_progress {
+            return true;
+        }
+        if self.pass_upper >= until {
+            return false;
+        }
+        self.pass_upper = until;
+        self.cursor = until;
+        true
+    }
+
+    /// Oldest timestamp the current pass needs to reach
+    pub fn lower_bound(&self) -> i64 {
+        self.covered_until.unwrap_or(self.since).max(self.since)
+    }
+
+    /// Move the cursor past a page; returns `true` when the pass is finished
+    pub fn advance(&mut self, page: &PageSummary) -> bool {
+        let Some(oldest) = page.oldest.filter(|_| page.events > 0) else {
+            self.finish_pass();
+            return true;
+        };
+
+        // `until` is inclusive, so stay on the oldest second while it still
+        // yields new events and only step past it once it does not
+        self.cursor = if page.new_events > 0 {
+            oldest.min(self.cursor)
+        } else {
+            oldest.min(self.cursor) - 1
+        };
+        if self.cursor < self.lower_bound() {
+            self.finish_pass();
+            return true;
+        }
+        false
+    }
+
+    fn finish_pass(&mut self) {
+        self.covered_until = Some(self.pass_upper);
+        self.cursor = self.pass_upper;
+    }
+}
+
+/// Why a backfill pass stopped
+enum PassEnd {
+    Completed,
+    Stopped,
+}
+
+/// Backfill one relay until `window.until` is covered or shutdown is requested
+///
+/// `ctx.filter` is the base filter; `since`, `until` and `limit` are added
+/// for each page.
+pub async fn run_backfill(
+    url: String,
+    ctx: RelayContext,
+    state: Arc<Mutex<StateStore>>,
+    window: BackfillWindow,
+) {
+    if window.until < window.since {
+        return;
+    }
+
+    let stored = state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .load_checkpoint(&url);
+    let mut checkpoint = match stored {
+        Ok(stored) => BackfillCheckpoint::resume(stored, window.since, window.until),
+        Err(e) => {
+            warn!(relay = %url, "Skipping backfill: {:#}", e);
+            return;
+        }
+    };
+
+    let mut shutdown = ctx.shutdown.clone();
+    while checkpoint.next_pass(window.until) {
+        if *shutdown.borrow() {
+            return;
+        }
+        info!(
+            relay = %url,
+            "Backfilling {} to {}",
+            checkpoint.lower_bound(),
+            checkpoint.cursor
+        );
+
+        match backfill_pass(
+            &url,
+            &ctx,
+            &state,
+            &mut checkpoint,
+            window.page_size,
+            &mut shutdown,
+        )
+        .await
+        {
+            Ok(PassEnd::Completed) => {}
+            Ok(PassEnd::Stopped) => return,
+            Err(e) => {
+                warn!(relay = %url, "Backfill interrupted: {:#}", e);
+                let delay = ctx.health.reconnect_delay(&url);
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = shutdown_requested(&mut shutdown) => return,
+                }
+            }
+        }
+    }
+
+    info!(relay = %url, "Backfill complete up to {}", window.until);
+}
+
+async fn backfill_pass(
+    url: &str,
+    ctx: &RelayContext,
+    state: &Mutex<StateStore>,
+    checkpoint: &mut BackfillCheckpoint,
+    page_size: usize,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<PassEnd> {
+    let started = Instant::now();
+    let connected = tokio::select! {
+        connected = connect_async(url) => connected,
+        _ = shutdown_requested(shutdown) => return Ok(PassEnd::Stopped),
+    };
+    let (mut ws, _) = match connected {
+        Ok(connected) => connected,
+        Err(e) => {
+            ctx.health.record_failure(url);
+            return Err(e).context(format!("Failed to connect to {}", url));
+        }
+    };
+    ctx.health
+        .record_connected(url, started.elapsed(), chrono::Utc::now().timestamp());
+
+    let mut previous_ids = HashSet::new();
+    loop {
+        let mut filter = ctx.filter.clone();
+        if checkpoint.lower_bound() > 0 {
+            filter["since"] = checkpoint.lower_bound().into();
+        }
+        filter["until"] = checkpoint.cursor.into();
+        filter["limit"] = page_size.into();
+
+        ws.send(Message::Text(
+            req_message(BACKFILL_SUBSCRIPTION_ID, &[filter]).into(),
+        ))
+        .await
+        .context("Failed to send REQ")?;
+
+        let mut page = PageSummary::default();
+        let mut page_ids = HashSet::new();
+        loop {
+            let frame = tokio::select! {
+                frame = tokio::time::timeout(PAGE_TIMEOUT, ws.next()) => frame,
+                _ = shutdown_requested(shutdown) => {
+                    let _ = ws.close(None).await;
+                    return Ok(PassEnd::Stopped);
+                }
+            };
+            let text = match frame.context("Timed out waiting for EOSE")? {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) | None => anyhow::bail!("Relay closed the connection"),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e).context("WebSocket error"),
+            };
+
+            match RelayMessage::from_json(text.as_str()) {
+                Ok(RelayMessage::Event {
+                    subscription_id,
+                    event,
+                }) if subscription_id == BACKFILL_SUBSCRIPTION_ID => {
+                    record_page_event(&event, &previous_ids, &mut page, &mut page_ids);
+                    ctx.metrics.received.fetch_add(1, Ordering::Relaxed);
+                    let message = IngestMessage::Event {
+                        relay_url: url.to_string(),
+                        event_json: event.to_string(),
+                    };
+                    if ctx.tx.send(message).await.is_err() {
+                        return Ok(PassEnd::Stopped);
+                    }
+                }
+                Ok(RelayMessage::Eose(subscription_id))
+                    if subscription_id == BACKFILL_SUBSCRIPTION_ID =>
+                {
+                    break;
+                }
+                Ok(RelayMessage::Closed {
+                    subscription_id,
+                    message,
+                }) if subscription_id == BACKFILL_SUBSCRIPTION_ID => {
+                    anyhow::bail!("Backfill subscription closed by relay: {}", message);
+                }
+                Ok(_) => {}
+                Err(e) => debug!(relay = %url, "Ignoring malformed relay message: {}", e),
+            }
+        }
+        let _ = ws
+            .send(Message::Text(
+                close_message(BACKFILL_SUBSCRIPTION_ID).into(),
+            ))
+            .await;
+
+        // Only move the checkpoint once the page is safely on disk
+        let (ack_tx, ack_rx) = oneshot::channel();
+        if ctx.tx.send(IngestMessage::Sync(ack_tx)).await.is_err() || ack_rx.await.is_err() {
+            return Ok(PassEnd::Stopped);
+        }
+
+        debug!(
+            relay = %url,
+            "Backfill page: {} events, {} new, until {}",
+            page.events,
+            page.new_events,
+            checkpoint.cursor
+        );
+        let finished = checkpoint.advance(&page);
+        state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .save_checkpoint(url, checkpoint)?;
+
+        if finished {
+            let _ = ws.close(None).await;
+            return Ok(PassEnd::Completed);
+        }
+        previous_ids = page_ids;
+    }
+}
+
+fn record_page_event(
+    event: &Value,
+    previous_ids: &HashSet<String>,
+    page: &mut PageSummary,
+    page_ids: &mut HashSet<String>,
+) {
+    page.events += 1;
+    if let Some(id) = event["id"].as_str() {
+        if !previous_ids.contains(id) {
+            page.new_events += 1;
+        }
+        page_ids.insert(id.to_string());
+    }
+    if let Some(created_at) = event["created_at"].as_i64() {
+        page.oldest = Some(
+            page.oldest
+                .map_or(created_at, |oldest| oldest.min(created_at)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(events: usize, new_events: usize, oldest: i64) -> PageSummary {
+        PageSummary {
+            events,
+            new_events,
+            oldest: Some(oldest),
+        }
+    }
+
+    #[test]
+    fn test_pages_backwards_until_nothing_new() {
+        let mut checkpoint = BackfillCheckpoint::resume(None, 100, 1000);
+        assert!(checkpoint.next_pass(1000));
+        assert_eq!(checkpoint.cursor, 1000);
+
+        assert!(!checkpoint.advance(&page(10, 10, 700)));
+        assert_eq!(checkpoint.cursor, 700);
+
+        // Only the boundary second came back again, so step past it
+        assert!(!checkpoint.advance(&page(2, 0, 700)));
+        assert_eq!(checkpoint.cursor, 699);
+
+        assert!(checkpoint.advance(&PageSummary::default()));
+        assert_eq!(checkpoint.covered_until, Some(1000));
+        assert!(!checkpoint.next_pass(1000));
+    }
+
+    #[test]
+    fn test_pass_ends_at_since() {
+        let mut checkpoint = BackfillCheckpoint::resume(None, 500, 1000);
+        assert!(checkpoint.advance(&page(1, 1, 400)));
+        assert_eq!(checkpoint.covered_until, Some(1000));
+    }
+
+    #[test]
+    fn test_resume_and_fill_gap() {
+        let mut checkpoint = BackfillCheckpoint::resume(None, 100, 1000);
+        checkpoint.advance(&page(5, 5, 600));
+
+        // Interrupted mid-pass: resume where it stopped, whatever the new `until`
+        let mut resumed = BackfillCheckpoint::resume(Some(checkpoint), 100, 2000);
+        assert!(resumed.next_pass(2000));
+        assert_eq!(resumed.cursor, 600);
+        assert_eq!(resumed.lower_bound(), 100);
+        assert!(resumed.advance(&PageSummary::default()));
+
+        // The next pass only covers the time since the finished one
+        assert!(resumed.next_pass(2000));
+        assert_eq!(resumed.cursor, 2000);
+        assert_eq!(resumed.lower_bound(), 1000);
+
+        // A different `since` starts over
+        let restarted = BackfillCheckpoint::resume(Some(resumed), 50, 2000);
+        assert_eq!(restarted.covered_until, None);
+    }
+
+    #[test]
+    fn test_record_page_event() {
+        let previous: HashSet<String> = ["a".to_string()].into_iter().collect();
+        let mut page = PageSummary::default();
+        let mut ids = HashSet::new();
+
+        record_page_event(
+            &serde_json::json!({"id": "a", "created_at": 20}),
+            &previous,
+            &mut page,
+            &mut ids,
+        );
+        record_page_event(
+            &serde_json::json!({"id": "b", "created_at": 10}),
+            &previous,
+            &mut page,
+            &mut ids,
+        );
+
+        assert_eq!(
+            page,
+            PageSummary {
+                events: 2,
+                new_events: 1,
+                oldest: Some(10),
+            }
+        );
+        assert_eq!(ids.len(), 2);
+    }
+}
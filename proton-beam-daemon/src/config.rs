@@ -0,0 +1,300 @@
+//! Daemon configuration loaded from `config.toml`
+//!
+//! Every section and field is optional; missing values fall back to the
+//! defaults documented in `examples/config.toml`.
+
+use anyhow::{Context, Result};
+use proton_beam_core::{Codec, Filter, FormatVersion};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Top-level daemon configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub daemon: DaemonConfig,
+    pub relays: RelaysConfig,
+    /// `[filters]` section: a NIP-01 filter, sent to relays and re-checked
+    /// locally. Its `limit` is ignored.
+    pub filters: Filter,
+    pub historical: HistoricalConfig,
+    pub storage: StorageConfig,
+}
+
+/// `[daemon]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Directory where protobuf event files will be stored
+    pub output_dir: PathBuf,
+    /// Number of events to buffer per day before writing to disk
+    pub batch_size: usize,
+    /// Logging level ("trace", "debug", "info", "warn", "error")
+    pub log_level: String,
+    /// Compression level (0-9)
+    pub compression_level: u32,
+    /// Flush buffered events at least this often, even if the batch is not full
+    pub flush_interval_secs: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from("./nostr_events"),
+            batch_size: 500,
+            log_level: "info".to_string(),
+            compression_level: 6,
+            flush_interval_secs: 5,
+        }
+    }
+}
+
+/// `[relays]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RelaysConfig {
+    /// Initial list of relay URLs to connect to
+    pub urls: Vec<String>,
+    /// Discover new relays from event tags
+    pub auto_discover: bool,
+    /// Maximum number of concurrent relay connections (0 = unlimited)
+    pub max_relays: usize,
+    /// Also connect to discovered relays on `localhost` or private networks
+    pub discover_private_hosts: bool,
+}
+
+impl Default for RelaysConfig {
+    fn default() -> Self {
+        Self {
+            urls: Vec::new(),
+            auto_discover: false,
+            max_relays: 50,
+            discover_private_hosts: false,
+        }
+    }
+}
+
+/// `[historical]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoricalConfig {
+    /// Backfill historical events from the configured relays on startup
+    pub enabled: bool,
+    /// Only request events after this Unix timestamp (0 = all)
+    pub since_timestamp: i64,
+    /// Only request events before this Unix timestamp (0 = daemon start time)
+    pub until_timestamp: i64,
+    /// `limit` sent with each backfill page; should exceed the number of
+    /// events a relay holds for any single second
+    pub page_size: usize,
+}
+
+impl Default for HistoricalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            since_timestamp: 0,
+            until_timestamp: 0,
+            page_size: 500,
+        }
+    }
+}
+
+/// `[storage]` section
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Deduplicate events by ID across all relays
+    pub deduplicate: bool,
+    /// Maintain the SQLite index alongside the protobuf files
+    pub use_index: bool,
+    /// Record in the index which relays delivered each event (needs `use_index`)
+    pub track_relays: bool,
+    /// Index single-letter tags for tag queries (needs `use_index`)
+    pub index_tags: bool,
+    /// Tombstone events deleted by NIP-09 requests from their author (needs `use_index`)
+    pub process_deletions: bool,
+    /// Event format of the protobuf files
+    pub format: FormatVersion,
+    /// Compression codec of the protobuf files
+    pub codec: Codec,
+    /// Trained zstd dictionary to compress with (needs `codec = "zstd"`)
+    pub zstd_dictionary: Option<PathBuf>,
+    /// Write every batch as its own block so single events can be fetched
+    pub seekable: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            deduplicate: true,
+            use_index: true,
+            track_relays: true,
+            index_tags: false,
+            process_deletions: false,
+            format: FormatVersion::V1,
+            codec: Codec::Gzip,
+            zstd_dictionary: None,
+            seekable: false,
+        }
+    }
+}
+
+impl Config {
+    /// Load and validate a configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("Failed to read config file: {}", path.display()))?;
+        Self::from_toml(&contents).context(format!("Invalid config file: {}", path.display()))
+    }
+
+    /// Parse and validate a configuration from a TOML string
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let config: Config = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check values that TOML parsing alone cannot
+    pub fn validate(&self) -> Result<()> {
+        if self.daemon.batch_size == 0 {
+            anyhow::bail!("daemon.batch_size must be greater than 0");
+        }
+        if self.daemon.compression_level > 9 {
+            anyhow::bail!(
+                "daemon.compression_level must be between 0 and 9, got {}",
+                self.daemon.compression_level
+            );
+        }
+        if self.storage.zstd_dictionary.is_some() && self.storage.codec != Codec::Zstd {
+            anyhow::bail!("storage.zstd_dictionary requires storage.codec = \"zstd\"");
+        }
+        if self.relays.urls.is_empty() && !self.relays.auto_discover {
+            anyhow::bail!("relays.urls is empty and relays.auto_discover is disabled");
+        }
+        if self.historical.enabled && self.historical.page_size == 0 {
+            anyhow::bail!("historical.page_size must be greater than 0");
+        }
+        if self.historical.until_timestamp > 0
+            && self.historical.until_timestamp < self.historical.since_timestamp
+        {
+            anyhow::bail!(
+                "historical.until_timestamp must not be before historical.since_timestamp"
+            );
+        }
+        for url in &self.relays.urls {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                anyhow::bail!("Relay URL must start with ws:// or wss://: {}", url);
+            }
+        }
+        Ok(())
+    }
+
+    /// Path to the SQLite index inside the output directory
+    pub fn index_path(&self) -> PathBuf {
+        self.daemon.output_dir.join("index.db")
+    }
+
+    /// Read the trained zstd dictionary, if one is configured
+    pub fn zstd_dictionary(&self) -> Result<Option<Vec<u8>>> {
+        self.storage
+            .zstd_dictionary
+            .as_ref()
+            .map(|path| {
+                std::fs::read(path).context(format!(
+                    "Failed to read zstd dictionary: {}",
+                    path.display()
+                ))
+            })
+            .transpose()
+    }
+
+    /// Path to the daemon's own state database inside the output directory
+    pub fn state_path(&self) -> PathBuf {
+        self.daemon.output_dir.join("daemon_state.db")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace_root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .to_path_buf()
+    }
+
+    #[test]
+    fn test_load_example_config() {
+        let config = Config::load(&workspace_root().join("examples/config.toml")).unwrap();
+
+        assert_eq!(config.daemon.output_dir, PathBuf::from("./nostr_events"));
+        assert_eq!(config.daemon.batch_size, 500);
+        assert_eq!(config.relays.urls.len(), 5);
+        assert!(config.relays.auto_discover);
+        assert_eq!(config.relays.max_relays, 50);
+        assert!(config.filters.kinds.is_empty());
+        assert!(!config.historical.enabled);
+        assert!(config.storage.deduplicate);
+        assert!(config.storage.use_index);
+        assert!(config.storage.track_relays);
+        assert_eq!(config.storage.format, FormatVersion::V1);
+        assert_eq!(config.storage.codec, Codec::Gzip);
+        assert!(config.storage.zstd_dictionary.is_none());
+    }
+
+    #[test]
+    fn test_defaults_for_missing_sections() {
+        let config = Config::from_toml(
+            r#"
+            [relays]
+            urls = ["ws://localhost:7777"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.daemon.batch_size, 500);
+        assert_eq!(config.daemon.flush_interval_secs, 5);
+        assert!(config.storage.use_index);
+    }
+
+    #[test]
+    fn test_rejects_invalid_values() {
+        assert!(Config::from_toml("[relays]\nurls = [\"https://example.com\"]").is_err());
+        assert!(Config::from_toml("[relays]\nurls = []").is_err());
+        assert!(
+            Config::from_toml("[daemon]\nbatch_size = 0\n[relays]\nurls = [\"ws://a\"]").is_err()
+        );
+        assert!(
+            Config::from_toml(
+                "[relays]\nurls = [\"ws://a\"]\n[storage]\nzstd_dictionary = \"nostr.dict\""
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_filters_section() {
+        let config = Config::from_toml(
+            r#"
+            [relays]
+            urls = ["ws://localhost:7777"]
+
+            [filters]
+            kinds = [0, 1]
+            since = 1697000000
+
+            [filters.tags]
+            t = ["nostr"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.filters.kinds, vec![0, 1]);
+        assert_eq!(config.filters.since, Some(1697000000));
+        assert_eq!(config.filters.tags["t"], vec!["nostr"]);
+        assert!(config.filters.authors.is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+//! Daemon orchestration: relay connections feeding the ingest pipeline
+
+use crate::backfill::{self, BackfillWindow};
+use crate::config::Config;
+use crate::discovery::{RelayDiscovery, normalize_relay_url};
+use crate::health::HealthTracker;
+use crate::metrics::Metrics;
+use crate::pipeline::{EventPipeline, IngestMessage};
+use crate::pool::RelayPool;
+use crate::recovery::RunMarker;
+use crate::relay::RelayContext;
+use crate::state::StateStore;
+use anyhow::{Context, Result};
+use proton_beam_core::Filter;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{info, warn};
+
+/// Capacity of the channel between relay connections and the writer thread
+const INGEST_CHANNEL_CAPACITY: usize = 10_000;
+
+/// How long relay connections get to drain before they are aborted
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often relay health is persisted and the pool rebalanced
+const HEALTH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Relay monitoring daemon
+pub struct Daemon {
+    config: Config,
+    metrics: Arc<Metrics>,
+}
+
+impl Daemon {
+    /// Create a daemon from a validated configuration
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+
+    /// Shared counters, updated while the daemon runs
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Configured relay URLs in order, without duplicates
+    fn relay_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for url in &self.config.relays.urls {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        urls
+    }
+
+    /// The filter sent in every live REQ
+    ///
+    /// `limit: 0` asks relays for new events only; stored events are fetched
+    /// by the historical backfill instead.
+    fn req_filter(&self) -> serde_json::Value {
+        let mut filter = self.base_filter();
+        filter["limit"] = 0.into();
+        filter
+    }
+
+    /// The `[filters]` section as a NIP-01 filter object, without `limit`
+    fn base_filter(&self) -> serde_json::Value {
+        serde_json::json!(Filter {
+            limit: None,
+            ..self.config.filters.clone()
+        })
+    }
+
+    /// Time range for the historical backfill, ending now unless configured
+    fn backfill_window(&self) -> BackfillWindow {
+        let historical = &self.config.historical;
+        BackfillWindow {
+            since: historical.since_timestamp,
+            until: match historical.until_timestamp {
+                0 => chrono::Utc::now().timestamp(),
+                until => until,
+            },
+            page_size: historical.page_size,
+        }
+    }
+
+    /// Run until `shutdown` completes, then drain relays and close every file
+    ///
+    /// On shutdown each relay subscription is closed and events already in
+    /// flight are still archived. The writer then flushes its buffers,
+    /// finishes every compressed stream and commits the pending index batch. If the
+    /// previous run was killed instead, truncated files are repaired first.
+    /// If the writer fails, for example because a flush hit a full disk, the
+    /// daemon shuts down the same way and returns its error.
+    pub async fn run<F>(self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let marker = RunMarker::acquire(&self.config)?;
+        let state = StateStore::open(&self.config.state_path())?;
+        let health = Arc::new(HealthTracker::from_entries(state.load_health()?));
+        let state = Arc::new(Mutex::new(state));
+        let mut pipeline = EventPipeline::new(&self.config, Arc::clone(&self.metrics))?
+            .with_health(Arc::clone(&health));
+        let (tx, rx) = mpsc::channel(INGEST_CHANNEL_CAPACITY);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let urls = self.relay_urls();
+        let (discovered_tx, mut discovered_rx) = mpsc::unbounded_channel();
+        if self.config.relays.auto_discover {
+            let discovery = RelayDiscovery::new(&urls)
+                .with_private_hosts(self.config.relays.discover_private_hosts);
+            pipeline = pipeline.with_discovery(discovery, discovered_tx);
+        } else {
+            drop(discovered_tx);
+        }
+
+        // The writer only stops early if it failed; `writer_done` tells the loop below
+        let (writer_done_tx, mut writer_done) = oneshot::channel::<()>();
+        let writer = std::thread::Builder::new()
+            .name("proton-beam-writer".to_string())
+            .spawn(move || {
+                let result = pipeline.run(rx);
+                let _ = writer_done_tx.send(());
+                result
+            })
+            .context("Failed to spawn writer thread")?;
+
+        let ctx = RelayContext {
+            filter: self.req_filter(),
+            tx: tx.clone(),
+            metrics: Arc::clone(&self.metrics),
+            health: Arc::clone(&health),
+            shutdown: shutdown_rx.clone(),
+        };
+        let mut pool = RelayPool::new(self.config.relays.max_relays, ctx.clone());
+        for url in &urls {
+            pool.add(url);
+        }
+        if pool.len() < urls.len() {
+            warn!(
+                "{} relays configured, connected to {} (relays.max_relays)",
+                urls.len(),
+                pool.len()
+            );
+        }
+        info!("Connecting to {} relays", pool.len());
+
+        // Backfill the configured relays over separate connections
+        let mut backfills = JoinSet::new();
+        if self.config.historical.enabled {
+            let window = self.backfill_window();
+            let backfill_ctx = RelayContext {
+                filter: self.base_filter(),
+                ..ctx
+            };
+            for url in urls.iter().filter_map(|url| normalize_relay_url(url)) {
+                if pool.contains(&url) {
+                    backfills.spawn(backfill::run_backfill(
+                        url,
+                        backfill_ctx.clone(),
+                        Arc::clone(&state),
+                        window,
+                    ));
+                }
+            }
+            info!(
+                "Backfilling {} relays from {} to {}",
+                backfills.len(),
+                window.since,
+                window.until
+            );
+        } else {
+            drop(ctx);
+        }
+
+        let flush_task = self.spawn_flush_task(tx, shutdown_rx);
+
+        // Grow and rebalance the pool until shutdown is requested
+        let mut health_interval = tokio::time::interval(HEALTH_INTERVAL);
+        health_interval.tick().await;
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = &mut writer_done => {
+                    warn!("Writer thread stopped, shutting down");
+                    break;
+                }
+                Some(url) = discovered_rx.recv() => {
+                    self.metrics.discovered.fetch_add(1, Ordering::Relaxed);
+                    pool.offer(&url);
+                }
+                _ = health_interval.tick() => {
+                    pool.rebalance();
+                    if let Err(e) = lock(&state).save_health(&health.entries()) {
+                        warn!("Failed to save relay health: {:#}", e);
+                    }
+                }
+            }
+        }
+
+        info!("Shutting down, draining relay connections");
+        let _ = shutdown_tx.send(true);
+
+        let drain = async {
+            pool.join().await;
+            while backfills.join_next().await.is_some() {}
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, drain).await.is_err() {
+            warn!(
+                "Relays did not drain within {}s, aborting remaining connections",
+                SHUTDOWN_TIMEOUT.as_secs()
+            );
+            pool.abort().await;
+            backfills.abort_all();
+            while backfills.join_next().await.is_some() {}
+        }
+        let _ = flush_task.await;
+        drop(pool);
+
+        // All senders are gone now, so the writer drains the channel and closes storage
+        tokio::task::spawn_blocking(move || writer.join())
+            .await
+            .context("Failed to join writer thread")?
+            .map_err(|_| anyhow::anyhow!("Writer thread panicked"))??;
+        lock(&state).save_health(&health.entries())?;
+        marker.release()?;
+
+        let snapshot = self.metrics.snapshot();
+        info!(
+            "Stopped: {} received, {} stored, {} duplicates, {} invalid, {} filtered, {} storage errors, {} relays discovered",
+            snapshot.received,
+            snapshot.stored,
+            snapshot.duplicates,
+            snapshot.invalid,
+            snapshot.filtered,
+            snapshot.storage_errors,
+            snapshot.discovered
+        );
+
+        Ok(())
+    }
+
+    /// Periodically ask the writer to flush, so quiet days still reach disk
+    fn spawn_flush_task(
+        &self,
+        tx: mpsc::Sender<IngestMessage>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> JoinHandle<()> {
+        let flush_interval = Duration::from_secs(self.config.daemon.flush_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(flush_interval);
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown.wait_for(|&stop| stop) => return,
+                }
+                if tx.send(IngestMessage::Flush).await.is_err() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+fn lock(state: &Mutex<StateStore>) -> MutexGuard<'_, StateStore> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,331 @@
+//! Relay discovery from archived events
+//!
+//! Relay URLs show up in NIP-65 relay lists (kind 10002 `r` tags), in `r` and
+//! `relay` tags on other events, and as the relay hint in the third element
+//! of `e`, `p` and `a` tags. When `relays.auto_discover` is enabled, every
+//! stored event is scanned and new URLs are handed to the relay pool.
+//!
+//! Anyone can sign an event carrying any relay hint, so discovered URLs that
+//! point at `localhost` or at loopback, private, link-local or unspecified
+//! IP addresses are ignored unless `relays.discover_private_hosts` is set.
+
+use proton_beam_core::ProtoEvent;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Upper bound on distinct URLs remembered, so spam cannot grow it forever
+const MAX_KNOWN_RELAYS: usize = 10_000;
+
+/// Normalize a relay URL so that trivially different spellings compare equal
+///
+/// The scheme and host are lowercased, default ports and trailing slashes are
+/// dropped, and fragments are removed. Returns `None` for anything that is not
+/// a plausible `ws://` or `wss://` URL.
+pub fn normalize_relay_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "ws" => ":80",
+        "wss" => ":443",
+        _ => return None,
+    };
+
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+
+    let mut host = authority.to_ascii_lowercase();
+    if let Some(stripped) = host.strip_suffix(default_port) {
+        host = stripped.to_string();
+    }
+    if host.is_empty()
+        || host.contains('@')
+        || host.starts_with(['.', ':'])
+        || host.ends_with(['.', ':'])
+        || !host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+    {
+        return None;
+    }
+
+    let path = match path.split_once('?') {
+        Some((path, query)) => format!("{}?{}", path.trim_end_matches('/'), query),
+        None => path.trim_end_matches('/').to_string(),
+    };
+    if path.chars().any(char::is_whitespace) {
+        return None;
+    }
+
+    Some(format!("{}://{}{}", scheme, host, path))
+}
+
+/// Whether a normalized relay URL points at this machine or a private network
+///
+/// Host names other than `localhost` are not resolved, and numeric hosts
+/// that are not dotted IPv4 addresses (`ws://2130706433`) count as private,
+/// since WebSocket clients may still read them as IPv4.
+pub fn is_private_relay_url(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+
+    if host == "localhost" || host.ends_with(".localhost") {
+        return true;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => is_private_ipv4(ip),
+        Ok(IpAddr::V6(ip)) => is_private_ipv6(ip),
+        // A numeric last label makes URL parsers treat the host as IPv4
+        Err(_) => host
+            .rsplit('.')
+            .next()
+            .is_some_and(|label| label.starts_with(|c: char| c.is_ascii_digit())),
+    }
+}
+
+fn is_private_ipv4(ip: Ipv4Addr) -> bool {
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+}
+
+fn is_private_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_private_ipv4(ip);
+    }
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local (fc00::/7) and link-local (fe80::/10)
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+/// Raw relay URLs referenced by an event, before normalization
+pub fn relay_hints(event: &ProtoEvent) -> impl Iterator<Item = &str> {
+    event.tags.iter().filter_map(|tag| {
+        let name = tag.values.first()?;
+        let position = match name.as_str() {
+            "r" | "relay" => 1,
+            "e" | "p" | "a" => 2,
+            _ => return None,
+        };
+        tag.values
+            .get(position)
+            .map(String::as_str)
+            .filter(|url| !url.is_empty())
+    })
+}
+
+/// Remembers every relay URL seen so far and reports new ones
+#[derive(Debug, Default)]
+pub struct RelayDiscovery {
+    known: HashSet<String>,
+    /// Report URLs on this machine or a private network too
+    private_hosts: bool,
+}
+
+impl RelayDiscovery {
+    /// Create a discovery set that already knows `urls` (e.g. configured relays)
+    pub fn new<I, S>(urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let known = urls
+            .into_iter()
+            .filter_map(|url| normalize_relay_url(url.as_ref()))
+            .collect();
+        Self {
+            known,
+            private_hosts: false,
+        }
+    }
+
+    /// Also report URLs for which [`is_private_relay_url`] holds
+    ///
+    /// Only meant for private deployments and tests: otherwise any signed
+    /// event could point the daemon at services on its own network.
+    pub fn with_private_hosts(mut self, enabled: bool) -> Self {
+        self.private_hosts = enabled;
+        self
+    }
+
+    /// Scan an event and return the normalized URLs not seen before
+    pub fn observe(&mut self, event: &ProtoEvent) -> Vec<String> {
+        let mut discovered = Vec::new();
+        for url in relay_hints(event).filter_map(normalize_relay_url) {
+            if !self.private_hosts && is_private_relay_url(&url) {
+                continue;
+            }
+            if self.known.len() >= MAX_KNOWN_RELAYS {
+                break;
+            }
+            if self.known.insert(url.clone()) {
+                discovered.push(url);
+            }
+        }
+        discovered
+    }
+
+    /// Number of distinct relay URLs seen so far
+    pub fn len(&self) -> usize {
+        self.known.len()
+    }
+
+    /// Whether no relay URL has been seen yet
+    pub fn is_empty(&self) -> bool {
+        self.known.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_beam_core::ProtoEventBuilder;
+
+    #[test]
+    fn test_normalize_relay_url() {
+        assert_eq!(
+            normalize_relay_url("wss://relay.damus.io"),
+            Some("wss://relay.damus.io".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("  WSS://Relay.Damus.IO/  "),
+            Some("wss://relay.damus.io".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("wss://nos.lol:443/"),
+            Some("wss://nos.lol".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("ws://127.0.0.1:7777"),
+            Some("ws://127.0.0.1:7777".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("wss://relay.example.com/nostr/#frag"),
+            Some("wss://relay.example.com/nostr".to_string())
+        );
+        assert_eq!(
+            normalize_relay_url("wss://filter.nostr.wine/?global=all"),
+            Some("wss://filter.nostr.wine?global=all".to_string())
+        );
+    }
+
+    #[test]
+    fn test_normalize_rejects_invalid_urls() {
+        for url in [
+            "",
+            "relay.damus.io",
+            "https://relay.damus.io",
+            "wss://",
+            "wss://user@relay.example.com",
+            "wss://relay example.com",
+            "wss://relay.example.com/a b",
+            "wss://.example.com",
+        ] {
+            assert_eq!(normalize_relay_url(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_relay_hints() {
+        let event = ProtoEventBuilder::new()
+            .kind(1)
+            .add_tag(vec!["e", "abc", "wss://e.example.com"])
+            .add_tag(vec!["p", "def", "wss://p.example.com", "alice"])
+            .add_tag(vec!["a", "30023:def:slug", ""])
+            .add_tag(vec!["relay", "wss://relay.example.com"])
+            .add_tag(vec!["t", "wss://not-a-hint.example.com"])
+            .add_tag(vec!["e", "no-hint"])
+            .build();
+
+        let hints: Vec<_> = relay_hints(&event).collect();
+        assert_eq!(
+            hints,
+            vec![
+                "wss://e.example.com",
+                "wss://p.example.com",
+                "wss://relay.example.com"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discovery_dedupes_and_skips_known() {
+        let mut discovery = RelayDiscovery::new(["wss://relay.damus.io"]);
+
+        let relay_list = ProtoEventBuilder::new()
+            .kind(10002)
+            .add_tag(vec!["r", "wss://relay.damus.io/"])
+            .add_tag(vec!["r", "wss://nos.lol", "write"])
+            .add_tag(vec!["r", "WSS://NOS.LOL/", "read"])
+            .add_tag(vec!["r", "https://example.com"])
+            .build();
+
+        assert_eq!(discovery.observe(&relay_list), vec!["wss://nos.lol"]);
+        assert!(discovery.observe(&relay_list).is_empty());
+        assert_eq!(discovery.len(), 2);
+    }
+
+    #[test]
+    fn test_private_relay_urls() {
+        for url in [
+            "ws://localhost:7777",
+            "ws://relay.localhost",
+            "ws://127.0.0.1:7777",
+            "wss://10.1.2.3",
+            "wss://172.16.0.1",
+            "wss://192.168.1.10/nostr",
+            "ws://169.254.169.254",
+            "ws://0.0.0.0",
+            "ws://[::1]:7777",
+            "ws://[::]",
+            "ws://[fd00::1]",
+            "ws://[fe80::1]",
+            "ws://[::ffff:127.0.0.1]",
+            "ws://2130706433",
+            "ws://0x7f.1",
+        ] {
+            let url = normalize_relay_url(url).unwrap();
+            assert!(is_private_relay_url(&url), "{}", url);
+        }
+        for url in [
+            "wss://relay.damus.io",
+            "wss://nos.lol:4848",
+            "wss://1.1.1.1",
+            "wss://[2606:4700::1111]",
+            "wss://relay.example.com?localhost=1",
+            "wss://relay1.example.com/127.0.0.1",
+        ] {
+            let url = normalize_relay_url(url).unwrap();
+            assert!(!is_private_relay_url(&url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_discovery_skips_private_hosts() {
+        let relay_list = ProtoEventBuilder::new()
+            .kind(10002)
+            .add_tag(vec!["r", "ws://127.0.0.1:7777"])
+            .add_tag(vec!["r", "ws://localhost"])
+            .add_tag(vec!["r", "wss://192.168.1.10"])
+            .add_tag(vec!["r", "wss://nos.lol"])
+            .build();
+
+        let mut discovery = RelayDiscovery::new(Vec::<String>::new());
+        assert_eq!(discovery.observe(&relay_list), vec!["wss://nos.lol"]);
+
+        let mut discovery = RelayDiscovery::new(Vec::<String>::new()).with_private_hosts(true);
+        assert_eq!(discovery.observe(&relay_list).len(), 4);
+    }
+}
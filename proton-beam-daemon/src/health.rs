@@ -0,0 +1,263 @@
+//! Per-relay health statistics, reconnect backoff and scoring
+//!
+//! Relay connections record connection attempts, connect latency and time to
+//! EOSE; the ingest pipeline records how many of each relay's events were
+//! invalid or duplicates. The resulting score decides which discovered
+//! relays keep a slot in the pool, and consecutive failures drive an
+//! exponential reconnect backoff.
+
+use crate::pipeline::Outcome;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Reconnect delay after a clean disconnect or a first failure
+pub const BASE_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Upper bound for the exponential reconnect backoff
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Weight of the newest sample in latency moving averages
+const EWMA_WEIGHT: f64 = 0.2;
+
+/// Connection attempts or events needed before a relay's score is trusted
+const ESTABLISHED_ATTEMPTS: u64 = 3;
+const ESTABLISHED_EVENTS: u64 = 100;
+
+/// Health statistics for one relay
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RelayHealth {
+    /// Connection attempts, successful or not
+    pub connect_attempts: u64,
+    /// Connections that completed the WebSocket handshake
+    pub connect_successes: u64,
+    /// Failed attempts since the last successful connection
+    pub consecutive_failures: u32,
+    /// Moving average of the WebSocket handshake time
+    pub connect_latency_ms: Option<f64>,
+    /// Moving average of the time from REQ to EOSE
+    pub eose_time_ms: Option<f64>,
+    /// Events received and processed by the pipeline
+    pub events_received: u64,
+    /// Events that failed parsing or id/signature validation
+    pub events_invalid: u64,
+    /// Events that had already been stored from another relay
+    pub events_duplicate: u64,
+    /// Unix timestamp of the last successful connection
+    pub last_connected_at: Option<i64>,
+}
+
+impl RelayHealth {
+    /// Share of connection attempts that succeeded
+    ///
+    /// Smoothed towards 0.5 so that a single attempt does not decide.
+    pub fn success_rate(&self) -> f64 {
+        (self.connect_successes as f64 + 1.0) / (self.connect_attempts as f64 + 2.0)
+    }
+
+    /// Share of received events that were invalid
+    pub fn invalid_rate(&self) -> f64 {
+        ratio(self.events_invalid, self.events_received)
+    }
+
+    /// Share of received events that were duplicates
+    pub fn duplicate_ratio(&self) -> f64 {
+        ratio(self.events_duplicate, self.events_received)
+    }
+
+    /// Overall score between 0 and 1, higher is better
+    ///
+    /// Invalid events and failed connections weigh heavily; duplicates only
+    /// mildly, since some overlap between relays is expected. Slow handshakes
+    /// cost a little.
+    pub fn score(&self) -> f64 {
+        let latency_secs = self.connect_latency_ms.unwrap_or(0.0) / 1000.0;
+        self.success_rate() * (1.0 - self.invalid_rate()) * (1.0 - 0.5 * self.duplicate_ratio())
+            / (1.0 + 0.1 * latency_secs)
+    }
+
+    /// Whether enough has been observed for the score to be meaningful
+    pub fn is_established(&self) -> bool {
+        self.connect_attempts >= ESTABLISHED_ATTEMPTS || self.events_received >= ESTABLISHED_EVENTS
+    }
+
+    /// Delay before the next connection attempt
+    ///
+    /// Doubles with every consecutive failure, up to `MAX_RECONNECT_DELAY`.
+    pub fn reconnect_delay(&self) -> Duration {
+        let exponent = self.consecutive_failures.saturating_sub(1).min(16);
+        BASE_RECONNECT_DELAY
+            .saturating_mul(1 << exponent)
+            .min(MAX_RECONNECT_DELAY)
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+fn ewma(average: Option<f64>, sample: Duration) -> Option<f64> {
+    let sample = sample.as_secs_f64() * 1000.0;
+    Some(match average {
+        Some(average) => average * (1.0 - EWMA_WEIGHT) + sample * EWMA_WEIGHT,
+        None => sample,
+    })
+}
+
+/// Thread-safe collection of [`RelayHealth`] keyed by relay URL
+#[derive(Debug, Default)]
+pub struct HealthTracker {
+    relays: Mutex<HashMap<String, RelayHealth>>,
+}
+
+impl HealthTracker {
+    /// Start from previously persisted statistics
+    pub fn from_entries(entries: HashMap<String, RelayHealth>) -> Self {
+        Self {
+            relays: Mutex::new(entries),
+        }
+    }
+
+    fn update(&self, url: &str, f: impl FnOnce(&mut RelayHealth)) {
+        let mut relays = self.relays.lock().unwrap_or_else(|e| e.into_inner());
+        f(relays.entry(url.to_string()).or_default());
+    }
+
+    /// Statistics for one relay (default if it has never been seen)
+    pub fn get(&self, url: &str) -> RelayHealth {
+        let relays = self.relays.lock().unwrap_or_else(|e| e.into_inner());
+        relays.get(url).cloned().unwrap_or_default()
+    }
+
+    /// Copy of all statistics, e.g. for persisting
+    pub fn entries(&self) -> HashMap<String, RelayHealth> {
+        self.relays
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Record a completed WebSocket handshake
+    pub fn record_connected(&self, url: &str, latency: Duration, now: i64) {
+        self.update(url, |health| {
+            health.connect_attempts += 1;
+            health.connect_successes += 1;
+            health.consecutive_failures = 0;
+            health.connect_latency_ms = ewma(health.connect_latency_ms, latency);
+            health.last_connected_at = Some(now);
+        });
+    }
+
+    /// Record a failed connection attempt
+    pub fn record_failure(&self, url: &str) {
+        self.update(url, |health| {
+            health.connect_attempts += 1;
+            health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+        });
+    }
+
+    /// Record the time from sending REQ to receiving EOSE
+    pub fn record_eose(&self, url: &str, elapsed: Duration) {
+        self.update(url, |health| {
+            health.eose_time_ms = ewma(health.eose_time_ms, elapsed);
+        });
+    }
+
+    /// Record what the pipeline did with an event from `url`
+    pub fn record_outcome(&self, url: &str, outcome: &Outcome) {
+        // A local write failure says nothing about the relay
+        if let Outcome::StorageFailed(_) = outcome {
+            return;
+        }
+        self.update(url, |health| {
+            health.events_received += 1;
+            match outcome {
+                Outcome::Invalid(_) => health.events_invalid += 1,
+                Outcome::Duplicate => health.events_duplicate += 1,
+                Outcome::Stored | Outcome::Filtered | Outcome::StorageFailed(_) => {}
+            }
+        });
+    }
+
+    /// Delay before reconnecting to `url`
+    pub fn reconnect_delay(&self, url: &str) -> Duration {
+        self.get(url).reconnect_delay()
+    }
+
+    /// Score for `url`; relays never seen get the neutral default score
+    pub fn score(&self, url: &str) -> f64 {
+        self.get(url).score()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let tracker = HealthTracker::default();
+        assert_eq!(tracker.reconnect_delay("wss://a"), BASE_RECONNECT_DELAY);
+
+        tracker.record_failure("wss://a");
+        assert_eq!(tracker.reconnect_delay("wss://a"), BASE_RECONNECT_DELAY);
+        tracker.record_failure("wss://a");
+        assert_eq!(tracker.reconnect_delay("wss://a"), BASE_RECONNECT_DELAY * 2);
+        tracker.record_failure("wss://a");
+        assert_eq!(tracker.reconnect_delay("wss://a"), BASE_RECONNECT_DELAY * 4);
+
+        for _ in 0..100 {
+            tracker.record_failure("wss://a");
+        }
+        assert_eq!(tracker.reconnect_delay("wss://a"), MAX_RECONNECT_DELAY);
+
+        tracker.record_connected("wss://a", Duration::from_millis(100), 0);
+        assert_eq!(tracker.reconnect_delay("wss://a"), BASE_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn test_event_rates() {
+        let tracker = HealthTracker::default();
+        tracker.record_outcome("wss://a", &Outcome::Stored);
+        tracker.record_outcome("wss://a", &Outcome::Duplicate);
+        tracker.record_outcome("wss://a", &Outcome::Duplicate);
+        tracker.record_outcome("wss://a", &Outcome::Invalid("bad sig".to_string()));
+
+        let health = tracker.get("wss://a");
+        assert_eq!(health.events_received, 4);
+        assert_eq!(health.invalid_rate(), 0.25);
+        assert_eq!(health.duplicate_ratio(), 0.5);
+    }
+
+    #[test]
+    fn test_latency_moving_average() {
+        let tracker = HealthTracker::default();
+        tracker.record_eose("wss://a", Duration::from_millis(100));
+        assert_eq!(tracker.get("wss://a").eose_time_ms, Some(100.0));
+        tracker.record_eose("wss://a", Duration::from_millis(200));
+        assert_eq!(tracker.get("wss://a").eose_time_ms, Some(120.0));
+    }
+
+    #[test]
+    fn test_score_prefers_healthy_relays() {
+        let tracker = HealthTracker::default();
+        for _ in 0..5 {
+            tracker.record_connected("wss://good", Duration::from_millis(50), 0);
+            tracker.record_outcome("wss://good", &Outcome::Stored);
+            tracker.record_failure("wss://flaky");
+            tracker.record_connected("wss://spammy", Duration::from_millis(50), 0);
+            tracker.record_outcome("wss://spammy", &Outcome::Invalid(String::new()));
+        }
+
+        let unknown = tracker.score("wss://unknown");
+        assert!(tracker.score("wss://good") > unknown);
+        assert!(tracker.score("wss://flaky") < unknown);
+        assert!(tracker.score("wss://spammy") < unknown);
+        assert!(tracker.get("wss://good").is_established());
+        assert!(!tracker.get("wss://unknown").is_established());
+    }
+}
@@ -0,0 +1,20 @@
+//! Proton Beam Daemon Library
+//!
+//! Connects to Nostr relays, subscribes to events and archives them as
+//! date-partitioned, length-delimited protobuf files.
+
+pub mod backfill;
+pub mod config;
+pub mod daemon;
+pub mod discovery;
+pub mod health;
+pub mod metrics;
+pub mod pipeline;
+pub mod pool;
+pub mod recovery;
+pub mod relay;
+pub mod state;
+
+pub use config::Config;
+pub use daemon::Daemon;
+pub use metrics::{Metrics, MetricsSnapshot};
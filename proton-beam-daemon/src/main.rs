@@ -1,4 +1,91 @@
-// Placeholder for Phase 4: Daemon implementation
-fn main() {
-    println!("Proton Beam Daemon - Coming in Phase 4");
+use anyhow::Result;
+use clap::Parser;
+use proton_beam_daemon::{Config, Daemon};
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Parser, Debug)]
+#[command(name = "proton-beam-daemon")]
+#[command(about = "Monitor Nostr relays and archive events as Protocol Buffers", long_about = None)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Parser, Debug)]
+enum Commands {
+    /// Connect to the configured relays and start archiving events
+    Start {
+        /// Path to the configuration file
+        #[arg(short, long, default_value = "config.toml")]
+        config: PathBuf,
+
+        /// Backfill historical events since this Unix timestamp
+        /// (enables `[historical]` and overrides `since_timestamp`)
+        #[arg(long)]
+        since: Option<i64>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::Start { config, since } => {
+            let config_path = config;
+            let mut config = Config::load(&config_path)?;
+            if let Some(since) = since {
+                config.historical.enabled = true;
+                config.historical.since_timestamp = since;
+                config.validate()?;
+            }
+            init_logging(&config.daemon.log_level);
+
+            info!("Starting Proton Beam Daemon");
+            info!("Config: {}", config_path.display());
+            info!("Output directory: {}", config.daemon.output_dir.display());
+
+            Daemon::new(config).run(shutdown_signal()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve on SIGINT (Ctrl+C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to install SIGTERM handler: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+fn init_logging(log_level: &str) {
+    use tracing_subscriber::EnvFilter;
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .try_init();
 }
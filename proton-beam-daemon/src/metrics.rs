@@ -0,0 +1,59 @@
+//! Counters shared between relay connections and the ingest pipeline
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Lock-free daemon counters
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// EVENT messages received from all relays
+    pub received: AtomicU64,
+    /// Events written to storage
+    pub stored: AtomicU64,
+    /// Events skipped because they were already stored
+    pub duplicates: AtomicU64,
+    /// Events that failed parsing or validation
+    pub invalid: AtomicU64,
+    /// Events rejected by the configured filters
+    pub filtered: AtomicU64,
+    /// Valid events that could not be written to storage
+    pub storage_errors: AtomicU64,
+    /// New relay URLs found in stored events
+    pub discovered: AtomicU64,
+}
+
+/// Point-in-time copy of [`Metrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub received: u64,
+    pub stored: u64,
+    pub duplicates: u64,
+    pub invalid: u64,
+    pub filtered: u64,
+    pub storage_errors: u64,
+    pub discovered: u64,
+}
+
+impl Metrics {
+    /// Take a snapshot of all counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            received: self.received.load(Ordering::Relaxed),
+            stored: self.stored.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            storage_errors: self.storage_errors.load(Ordering::Relaxed),
+            discovered: self.discovered.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Number of received events that have been fully processed
+    pub fn processed(&self) -> u64 {
+        let snapshot = self.snapshot();
+        snapshot.stored
+            + snapshot.duplicates
+            + snapshot.invalid
+            + snapshot.filtered
+            + snapshot.storage_errors
+    }
+}
@@ -0,0 +1,442 @@
+//! Event ingest pipeline: parse, filter, deduplicate, validate and store
+//!
+//! The pipeline runs on a dedicated writer thread because `StorageManager`
+//! and `EventIndex` do blocking file and SQLite I/O. Relay connections hand
+//! events to it over a bounded channel.
+
+use crate::config::Config;
+use crate::discovery::RelayDiscovery;
+use crate::health::HealthTracker;
+use crate::metrics::Metrics;
+use anyhow::{Context, Result};
+use proton_beam_cli::storage::StorageManager;
+use proton_beam_core::{EventIndex, Filter, ProtoEvent, RelaySighting, validate_event};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, warn};
+
+/// Number of recently stored event IDs kept in memory for deduplication
+///
+/// Events that are still buffered are not in the index yet, and the same
+/// event usually arrives from several relays within seconds.
+const RECENT_IDS_CAPACITY: usize = 100_000;
+
+/// Messages consumed by the writer thread
+#[derive(Debug)]
+pub enum IngestMessage {
+    /// Raw event JSON received from a relay
+    Event {
+        relay_url: String,
+        event_json: String,
+    },
+    /// Write all buffered events to disk
+    Flush,
+    /// Write all buffered events to disk, then acknowledge
+    ///
+    /// Lets a sender wait until everything it sent earlier is on disk.
+    Sync(oneshot::Sender<()>),
+}
+
+/// Result of processing one event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Stored,
+    Duplicate,
+    Filtered,
+    Invalid(String),
+    /// The event is valid but could not be written; the relay is not to blame
+    StorageFailed(String),
+}
+
+/// Bounded set of recently seen event IDs (oldest evicted first)
+struct RecentIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl RecentIds {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ids: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    fn insert(&mut self, id: String) {
+        if !self.ids.insert(id.clone()) {
+            return;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.capacity
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.ids.remove(&oldest);
+        }
+    }
+}
+
+/// Processes events received from relays and writes them to storage
+pub struct EventPipeline {
+    storage: StorageManager,
+    filters: Filter,
+    deduplicate: bool,
+    recent: RecentIds,
+    /// Relay provenance waiting to be written to the index
+    sightings: Option<Vec<RelaySighting>>,
+    batch_size: usize,
+    discovery: Option<(RelayDiscovery, mpsc::UnboundedSender<String>)>,
+    health: Option<Arc<HealthTracker>>,
+    metrics: Arc<Metrics>,
+}
+
+impl EventPipeline {
+    /// Create a pipeline writing to `config.daemon.output_dir`
+    pub fn new(config: &Config, metrics: Arc<Metrics>) -> Result<Self> {
+        let mut storage = StorageManager::new(
+            &config.daemon.output_dir,
+            config.daemon.batch_size,
+            config.daemon.compression_level,
+        )?
+        .with_format(config.storage.format)
+        .with_codec(config.storage.codec)
+        .with_seekable(config.storage.seekable);
+        if let Some(dictionary) = config.zstd_dictionary()? {
+            storage = storage.with_zstd_dictionary(dictionary);
+        }
+
+        if config.storage.use_index {
+            let index_path = config.index_path();
+            let mut index = EventIndex::new(&index_path).context(format!(
+                "Failed to open event index: {}",
+                index_path.display()
+            ))?;
+            // Never turned off here: that would drop tags indexed by earlier runs
+            if config.storage.index_tags && !index.tag_index_enabled() {
+                index
+                    .set_tag_index(true)
+                    .context("Failed to enable tag index")?;
+            }
+            // Likewise, turning it off would bring deleted events back
+            if config.storage.process_deletions && !index.deletion_processing_enabled() {
+                index
+                    .set_deletion_processing(true)
+                    .context("Failed to enable deletion processing")?;
+            }
+            storage = storage.with_index(index);
+        }
+
+        Ok(Self {
+            storage,
+            filters: config.filters.clone(),
+            deduplicate: config.storage.deduplicate,
+            recent: RecentIds::new(RECENT_IDS_CAPACITY),
+            sightings: (config.storage.use_index && config.storage.track_relays).then(Vec::new),
+            batch_size: config.daemon.batch_size,
+            discovery: None,
+            health: None,
+            metrics,
+        })
+    }
+
+    /// Record every outcome against the relay the event came from
+    pub fn with_health(mut self, health: Arc<HealthTracker>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Report relay URLs referenced by stored events on `discovered`
+    ///
+    /// Only events that passed validation are scanned. That does not make
+    /// the hints trustworthy, since anyone can sign an event pointing at any
+    /// relay; `discovery` decides which URLs are acceptable.
+    pub fn with_discovery(
+        mut self,
+        discovery: RelayDiscovery,
+        discovered: mpsc::UnboundedSender<String>,
+    ) -> Self {
+        self.discovery = Some((discovery, discovered));
+        self
+    }
+
+    /// Consume messages until every sender has been dropped, then close storage
+    pub fn run(mut self, mut rx: mpsc::Receiver<IngestMessage>) -> Result<()> {
+        while let Some(message) = rx.blocking_recv() {
+            match message {
+                IngestMessage::Event {
+                    relay_url,
+                    event_json,
+                } => {
+                    self.process(&relay_url, &event_json);
+                }
+                IngestMessage::Flush => self.flush()?,
+                IngestMessage::Sync(ack) => {
+                    self.flush()?;
+                    let _ = ack.send(());
+                }
+            }
+        }
+        self.close()
+    }
+
+    /// Run one event through the pipeline
+    pub fn process(&mut self, relay_url: &str, event_json: &str) -> Outcome {
+        let outcome = self.process_inner(relay_url, event_json);
+
+        let counter = match &outcome {
+            Outcome::Stored => &self.metrics.stored,
+            Outcome::Duplicate => &self.metrics.duplicates,
+            Outcome::Filtered => &self.metrics.filtered,
+            Outcome::Invalid(reason) => {
+                debug!(relay = relay_url, "Rejected event: {}", reason);
+                &self.metrics.invalid
+            }
+            Outcome::StorageFailed(reason) => {
+                error!(relay = relay_url, "Failed to store event: {}", reason);
+                &self.metrics.storage_errors
+            }
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(health) = &self.health {
+            health.record_outcome(relay_url, &outcome);
+        }
+
+        outcome
+    }
+
+    fn process_inner(&mut self, relay_url: &str, event_json: &str) -> Outcome {
+        let event = match ProtoEvent::try_from(event_json) {
+            Ok(event) => event,
+            Err(e) => return Outcome::Invalid(format!("parse_error: {}", e)),
+        };
+
+        if !self.filters.matches(&event) {
+            return Outcome::Filtered;
+        }
+
+        if self.deduplicate && self.is_duplicate(&event.id) {
+            // Only a genuine copy proves the relay has the event
+            if self.sightings.is_some() {
+                if let Err(e) = validate_event(&event) {
+                    return Outcome::Invalid(format!("validation_error: {}", e));
+                }
+                self.record_sighting(&event.id, relay_url);
+            }
+            return Outcome::Duplicate;
+        }
+
+        if let Err(e) = validate_event(&event) {
+            return Outcome::Invalid(format!("validation_error: {}", e));
+        }
+
+        if let Some((discovery, discovered)) = &mut self.discovery {
+            for url in discovery.observe(&event) {
+                let _ = discovered.send(url);
+            }
+        }
+
+        let id = event.id.clone();
+        if let Err(e) = self.storage.store_event(event) {
+            return Outcome::StorageFailed(format!("{:#}", e));
+        }
+        self.record_sighting(&id, relay_url);
+        self.recent.insert(id);
+
+        Outcome::Stored
+    }
+
+    fn record_sighting(&mut self, event_id: &str, relay_url: &str) {
+        let Some(sightings) = &mut self.sightings else {
+            return;
+        };
+        sightings.push(RelaySighting {
+            event_id: event_id.to_string(),
+            relay_url: relay_url.to_string(),
+            received_at: chrono::Utc::now().timestamp(),
+        });
+        if sightings.len() >= self.batch_size
+            && let Err(e) = self.flush_sightings()
+        {
+            warn!("Failed to record relay provenance: {:#}", e);
+        }
+    }
+
+    fn flush_sightings(&mut self) -> Result<()> {
+        if let (Some(sightings), Some(index)) = (&mut self.sightings, self.storage.index_mut())
+            && !sightings.is_empty()
+        {
+            let result = index.insert_relays(sightings);
+            sightings.clear();
+            result.context("Failed to record relay provenance")?;
+        }
+        Ok(())
+    }
+
+    fn is_duplicate(&self, event_id: &str) -> bool {
+        if self.recent.contains(event_id) {
+            return true;
+        }
+
+        match self.storage.index().map(|index| index.contains(event_id)) {
+            Some(Ok(exists)) => exists,
+            Some(Err(e)) => {
+                warn!("Index lookup failed for {}: {}", event_id, e);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Write all buffered events to disk and update the index
+    pub fn flush(&mut self) -> Result<()> {
+        self.storage.flush()?;
+        self.flush_sightings()
+    }
+
+    /// Flush, finish every compressed stream and sync the files to disk
+    pub fn close(&mut self) -> Result<()> {
+        self.flush_sightings()?;
+        self.storage.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const VALID_EVENT: &str = r#"{"id": "859501854a0e2b63383db18f187f8d2a7f988651793687215a6549f2da380528", "sig": "d693cca65af7df2619be909042f5b11a4e4bbe32932d5aa6ac22eb20c6e0551ab6e34690eddcbc76d893d64e60b6bf1c9838b02dea0eb1c05b38b28a700061cf", "kind": 7, "tags": [["e", "43f5606a0ceff70c40800855ffc24f2690d04c99d28a76cbdfdfe0c16737d7b4", "wss://relay.nostr.band/", "f9c8838736f5a0b611ed2c458a8ae7a480802e4ec38e52e96483986ca44ce612"], ["p", "f9c8838736f5a0b611ed2c458a8ae7a480802e4ec38e52e96483986ca44ce612"]], "pubkey": "7776c32d4b1d1e8bf2a96babeb43ad9ade157bd363d89b87fb63e6f145558888", "content": "🤙", "created_at": 1758991030}"#;
+
+    fn test_pipeline(temp_dir: &TempDir, use_index: bool) -> EventPipeline {
+        let mut config = Config::default();
+        config.daemon.output_dir = temp_dir.path().to_path_buf();
+        config.storage.use_index = use_index;
+        EventPipeline::new(&config, Arc::new(Metrics::default())).unwrap()
+    }
+
+    #[test]
+    fn test_store_and_deduplicate() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = test_pipeline(&temp_dir, true);
+
+        assert_eq!(pipeline.process("ws://a", VALID_EVENT), Outcome::Stored);
+        assert_eq!(pipeline.process("ws://b", VALID_EVENT), Outcome::Duplicate);
+        pipeline.flush().unwrap();
+
+        assert!(temp_dir.path().join("2025_09_27.pb.gz").exists());
+        let index = EventIndex::new(&temp_dir.path().join("index.db")).unwrap();
+        assert!(
+            index
+                .contains("859501854a0e2b63383db18f187f8d2a7f988651793687215a6549f2da380528")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_records_relay_provenance() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = test_pipeline(&temp_dir, true);
+
+        pipeline.process("ws://a", VALID_EVENT);
+        pipeline.process("ws://b", VALID_EVENT);
+        pipeline.process("ws://a", VALID_EVENT);
+        let tampered = VALID_EVENT.replace("🤙", "👎");
+        assert!(matches!(
+            pipeline.process("ws://c", &tampered),
+            Outcome::Invalid(_)
+        ));
+        pipeline.flush().unwrap();
+
+        let index = EventIndex::new(&temp_dir.path().join("index.db")).unwrap();
+        let relays: Vec<_> = index
+            .query_relays("859501854a0e2b63383db18f187f8d2a7f988651793687215a6549f2da380528")
+            .unwrap()
+            .into_iter()
+            .map(|sighting| sighting.relay_url)
+            .collect();
+        assert_eq!(relays, vec!["ws://a", "ws://b"]);
+    }
+
+    #[test]
+    fn test_deduplicates_against_existing_index() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let mut pipeline = test_pipeline(&temp_dir, true);
+            pipeline.process("ws://a", VALID_EVENT);
+            pipeline.flush().unwrap();
+        }
+
+        // A fresh pipeline has an empty in-memory set and must consult the index
+        let mut pipeline = test_pipeline(&temp_dir, true);
+        assert_eq!(pipeline.process("ws://a", VALID_EVENT), Outcome::Duplicate);
+    }
+
+    #[test]
+    fn test_rejects_invalid_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = test_pipeline(&temp_dir, false);
+
+        let tampered = VALID_EVENT.replace("🤙", "👎");
+        assert!(matches!(
+            pipeline.process("ws://a", &tampered),
+            Outcome::Invalid(_)
+        ));
+        assert!(matches!(
+            pipeline.process("ws://a", "{\"id\": 1}"),
+            Outcome::Invalid(_)
+        ));
+        assert_eq!(pipeline.metrics.invalid.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_storage_failures_are_not_invalid() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.daemon.output_dir = temp_dir.path().to_path_buf();
+        config.daemon.batch_size = 1;
+        config.storage.use_index = false;
+        let health = Arc::new(HealthTracker::default());
+        let mut pipeline = EventPipeline::new(&config, Arc::new(Metrics::default()))
+            .unwrap()
+            .with_health(Arc::clone(&health));
+
+        // A directory where the day file belongs makes every write fail
+        std::fs::create_dir(temp_dir.path().join("2025_09_27.pb.gz")).unwrap();
+        assert!(matches!(
+            pipeline.process("ws://a", VALID_EVENT),
+            Outcome::StorageFailed(_)
+        ));
+        assert_eq!(pipeline.metrics.storage_errors.load(Ordering::Relaxed), 1);
+        assert_eq!(pipeline.metrics.invalid.load(Ordering::Relaxed), 0);
+        assert_eq!(health.get("ws://a").events_invalid, 0);
+    }
+
+    #[test]
+    fn test_applies_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut pipeline = test_pipeline(&temp_dir, false);
+        pipeline.filters.kinds = vec![1];
+
+        assert_eq!(pipeline.process("ws://a", VALID_EVENT), Outcome::Filtered);
+    }
+
+    #[test]
+    fn test_recent_ids_eviction() {
+        let mut recent = RecentIds::new(2);
+        recent.insert("a".to_string());
+        recent.insert("b".to_string());
+        recent.insert("c".to_string());
+
+        assert!(!recent.contains("a"));
+        assert!(recent.contains("b"));
+        assert!(recent.contains("c"));
+    }
+}
@@ -0,0 +1,269 @@
+//! The set of relays the daemon is connected to
+//!
+//! Configured relays always keep their slot. Discovered relays fill the
+//! remaining slots up to `relays.max_relays`; once the pool is full, further
+//! discoveries wait as candidates and [`RelayPool::rebalance`] swaps the
+//! worst established discovered relay for a better-scoring candidate.
+
+use crate::discovery::normalize_relay_url;
+use crate::relay::{self, RelayContext};
+use std::collections::{HashMap, HashSet};
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::{debug, info};
+
+/// Upper bound on discovered relays waiting for a free slot
+const MAX_CANDIDATES: usize = 1_000;
+
+/// A candidate must beat a connected relay's score by this much to replace it
+const REPLACE_MARGIN: f64 = 0.1;
+
+/// A connected relay
+struct PoolEntry {
+    configured: bool,
+    abort: AbortHandle,
+}
+
+/// Relay connections sharing one subscription filter and ingest channel
+pub struct RelayPool {
+    relays: HashMap<String, PoolEntry>,
+    candidates: HashSet<String>,
+    max_relays: usize,
+    ctx: RelayContext,
+    tasks: JoinSet<()>,
+}
+
+impl RelayPool {
+    /// Create an empty pool
+    ///
+    /// `max_relays` caps the number of connections (0 = unlimited).
+    pub fn new(max_relays: usize, ctx: RelayContext) -> Self {
+        Self {
+            relays: HashMap::new(),
+            candidates: HashSet::new(),
+            max_relays: match max_relays {
+                0 => usize::MAX,
+                n => n,
+            },
+            ctx,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Number of relays in the pool
+    pub fn len(&self) -> usize {
+        self.relays.len()
+    }
+
+    /// Whether the pool has no relays
+    pub fn is_empty(&self) -> bool {
+        self.relays.is_empty()
+    }
+
+    /// Whether the pool has reached `max_relays`
+    pub fn is_full(&self) -> bool {
+        self.relays.len() >= self.max_relays
+    }
+
+    /// Whether `url` is connected
+    pub fn contains(&self, url: &str) -> bool {
+        normalize_relay_url(url).is_some_and(|url| self.relays.contains_key(&url))
+    }
+
+    /// Number of discovered relays waiting for a slot
+    pub fn candidates(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Connect to a configured relay unless the pool is full
+    ///
+    /// Returns `true` if a new connection was started.
+    pub fn add(&mut self, url: &str) -> bool {
+        match normalize_relay_url(url) {
+            Some(url) if !self.is_full() && !self.relays.contains_key(&url) => {
+                self.connect(url, true);
+                true
+            }
+            Some(_) => false,
+            None => {
+                debug!("Ignoring invalid relay URL: {}", url);
+                false
+            }
+        }
+    }
+
+    /// Offer a discovered relay
+    ///
+    /// It is connected right away if there is a free slot, otherwise kept as
+    /// a candidate for [`RelayPool::rebalance`]. Returns `true` if a new
+    /// connection was started.
+    pub fn offer(&mut self, url: &str) -> bool {
+        let Some(url) = normalize_relay_url(url) else {
+            return false;
+        };
+        if self.relays.contains_key(&url) {
+            return false;
+        }
+        if self.is_full() {
+            if self.candidates.len() < MAX_CANDIDATES {
+                self.candidates.insert(url);
+            }
+            return false;
+        }
+        self.candidates.remove(&url);
+        self.connect(url, false);
+        true
+    }
+
+    /// Fill free slots with the best candidates, then replace at most one
+    /// established discovered relay that a candidate clearly outscores
+    ///
+    /// Returns the number of connections started.
+    pub fn rebalance(&mut self) -> usize {
+        let mut started = 0;
+
+        while !self.is_full() {
+            let Some(best) = self.best_candidate() else {
+                return started;
+            };
+            self.candidates.remove(&best);
+            self.connect(best, false);
+            started += 1;
+        }
+
+        let Some(best) = self.best_candidate() else {
+            return started;
+        };
+        let worst = self
+            .relays
+            .iter()
+            .filter(|(_, entry)| !entry.configured)
+            .map(|(url, _)| (url.clone(), self.ctx.health.get(url)))
+            .filter(|(_, health)| health.is_established())
+            .min_by(|(_, a), (_, b)| a.score().total_cmp(&b.score()));
+
+        if let Some((worst, health)) = worst
+            && self.ctx.health.score(&best) > health.score() + REPLACE_MARGIN
+        {
+            info!(
+                relay = %worst,
+                "Replacing relay (score {:.2}) with {}",
+                health.score(),
+                best
+            );
+            if let Some(entry) = self.relays.remove(&worst) {
+                entry.abort.abort();
+            }
+            self.candidates.remove(&best);
+            self.candidates.insert(worst);
+            self.connect(best, false);
+            started += 1;
+        }
+
+        started
+    }
+
+    fn best_candidate(&self) -> Option<String> {
+        self.candidates
+            .iter()
+            .max_by(|a, b| {
+                self.ctx
+                    .health
+                    .score(a)
+                    .total_cmp(&self.ctx.health.score(b))
+            })
+            .cloned()
+    }
+
+    fn connect(&mut self, url: String, configured: bool) {
+        info!(relay = %url, "Adding relay ({} connected)", self.relays.len() + 1);
+        let abort = self
+            .tasks
+            .spawn(relay::run_relay(url.clone(), self.ctx.clone()));
+        self.relays.insert(url, PoolEntry { configured, abort });
+    }
+
+    /// Wait for every connection to finish draining
+    ///
+    /// Connections only return once shutdown has been signalled or the
+    /// pipeline is gone.
+    pub async fn join(&mut self) {
+        while self.tasks.join_next().await.is_some() {}
+    }
+
+    /// Abort connections that are still running
+    pub async fn abort(&mut self) {
+        self.tasks.abort_all();
+        self.join().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthTracker;
+    use crate::metrics::Metrics;
+    use crate::pipeline::Outcome;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::{mpsc, watch};
+
+    /// The returned sender keeps connections in their reconnect loop
+    fn test_pool(
+        max_relays: usize,
+        health: Arc<HealthTracker>,
+    ) -> (RelayPool, watch::Sender<bool>) {
+        let (tx, _rx) = mpsc::channel(1);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let pool = RelayPool::new(
+            max_relays,
+            RelayContext {
+                filter: serde_json::json!({}),
+                tx,
+                metrics: Arc::new(Metrics::default()),
+                health,
+                shutdown,
+            },
+        );
+        (pool, shutdown_tx)
+    }
+
+    #[tokio::test]
+    async fn test_offer_fills_free_slots_then_queues() {
+        let (mut pool, _shutdown) = test_pool(2, Arc::new(HealthTracker::default()));
+
+        assert!(pool.add("ws://127.0.0.1:1"));
+        assert!(!pool.add("ws://127.0.0.1:1/"));
+        assert!(pool.offer("ws://127.0.0.1:2"));
+        assert!(!pool.offer("ws://127.0.0.1:3"));
+        assert!(!pool.offer("not a relay"));
+
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.candidates(), 1);
+        pool.abort().await;
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_replaces_bad_discovered_relay() {
+        let health = Arc::new(HealthTracker::default());
+        for _ in 0..10 {
+            health.record_connected("ws://127.0.0.1:2", Duration::ZERO, 0);
+            health.record_outcome("ws://127.0.0.1:2", &Outcome::Invalid(String::new()));
+            health.record_connected("ws://127.0.0.1:3", Duration::ZERO, 0);
+            health.record_outcome("ws://127.0.0.1:3", &Outcome::Stored);
+        }
+
+        let (mut pool, _shutdown) = test_pool(2, Arc::clone(&health));
+        pool.add("ws://127.0.0.1:1");
+        pool.offer("ws://127.0.0.1:2");
+        pool.offer("ws://127.0.0.1:3");
+
+        assert_eq!(pool.rebalance(), 1);
+        assert!(pool.contains("ws://127.0.0.1:1"));
+        assert!(pool.contains("ws://127.0.0.1:3"));
+        assert!(!pool.contains("ws://127.0.0.1:2"));
+
+        // The evicted relay does not come straight back
+        assert_eq!(pool.rebalance(), 0);
+        pool.abort().await;
+    }
+}
@@ -0,0 +1,229 @@
+//! Crash recovery for the daemon's output directory
+//!
+//! While the daemon runs, a marker file sits in the output directory. A clean
+//! shutdown removes it, so finding it on startup means the previous run was
+//! killed before it could finish its compressed streams. Every `.pb.gz` or
+//! `.pb.zst` written since the marker was created is then checked and, if its
+//! tail is truncated, rewritten with the events that survived. Index rows for
+//! events that were lost with the tail are removed so they can be archived
+//! again.
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use proton_beam_cli::storage::{FileRepair, repair_truncated_file};
+use proton_beam_core::{EventIndex, create_decoder, is_event_file, read_events_delimited};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// Name of the marker file written while the daemon is running
+const RUN_MARKER: &str = ".proton-beam-daemon.running";
+
+/// Marks the output directory as in use until the daemon shuts down cleanly
+pub struct RunMarker {
+    path: PathBuf,
+}
+
+impl RunMarker {
+    /// Repair files left over from an unclean shutdown, then create the marker
+    pub fn acquire(config: &Config) -> Result<Self> {
+        let output_dir = &config.daemon.output_dir;
+        std::fs::create_dir_all(output_dir).context("Failed to create output directory")?;
+
+        let path = output_dir.join(RUN_MARKER);
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            warn!("Previous run did not shut down cleanly, checking output files");
+            let since = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            recover(config, since)?;
+        }
+
+        std::fs::write(&path, std::process::id().to_string())
+            .context(format!("Failed to create run marker: {}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    /// Remove the marker once every file has been closed
+    pub fn release(self) -> Result<()> {
+        std::fs::remove_file(&self.path).context(format!(
+            "Failed to remove run marker: {}",
+            self.path.display()
+        ))
+    }
+}
+
+/// Check every event file modified at or after `since` and repair truncated tails
+///
+/// Returns the number of files that had to be repaired.
+pub fn recover(config: &Config, since: SystemTime) -> Result<usize> {
+    let output_dir = &config.daemon.output_dir;
+    let mut index = if config.storage.use_index && config.index_path().exists() {
+        Some(EventIndex::new(&config.index_path()).context("Failed to open event index")?)
+    } else {
+        None
+    };
+    let zstd_dictionary = config.zstd_dictionary()?;
+
+    let mut repaired = 0;
+    for entry in std::fs::read_dir(output_dir).context("Failed to read output directory")? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        // Left behind if a previous repair was interrupted; the original is untouched
+        if name
+            .strip_suffix(".repair")
+            .is_some_and(|original| is_event_file(Path::new(original)))
+        {
+            std::fs::remove_file(&path).context(format!("Failed to remove {}", path.display()))?;
+            continue;
+        }
+        if !is_event_file(&path) {
+            continue;
+        }
+
+        let modified = std::fs::metadata(&path)?
+            .modified()
+            .unwrap_or(SystemTime::now());
+        if modified < since {
+            continue;
+        }
+
+        match repair_truncated_file(
+            &path,
+            config.daemon.compression_level,
+            zstd_dictionary.as_deref(),
+        )? {
+            FileRepair::Intact => {}
+            FileRepair::Repaired { recovered_events } => {
+                warn!(
+                    "Repaired truncated file {} ({} events recovered)",
+                    path.display(),
+                    recovered_events
+                );
+                repaired += 1;
+                if let Some(index) = index.as_mut() {
+                    reconcile_index(
+                        index,
+                        &path,
+                        name,
+                        recovered_events,
+                        zstd_dictionary.as_deref(),
+                    )?;
+                }
+            }
+        }
+    }
+
+    if repaired > 0 {
+        info!("Recovered {} truncated files", repaired);
+    }
+    Ok(repaired)
+}
+
+/// Drop index rows for events that did not survive the repair of `path`
+fn reconcile_index(
+    index: &mut EventIndex,
+    path: &Path,
+    file_name: &str,
+    recovered_events: u64,
+    zstd_dictionary: Option<&[u8]>,
+) -> Result<()> {
+    let records = index.query_by_file(file_name)?;
+    if records.len() as u64 <= recovered_events {
+        return Ok(());
+    }
+
+    let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let decoder = create_decoder(BufReader::new(file), zstd_dictionary)?;
+    let recovered: HashSet<String> = read_events_delimited(decoder)
+        .map(|event| event.map(|e| e.id))
+        .collect::<proton_beam_core::Result<_>>()?;
+
+    let mut removed = 0;
+    for record in records.iter().filter(|r| !recovered.contains(&r.id)) {
+        if index.remove(&record.id)? {
+            removed += 1;
+        }
+    }
+    warn!(
+        "Removed {} index entries for events lost from {}",
+        removed, file_name
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_beam_cli::storage::StorageManager;
+    use proton_beam_core::ProtoEventBuilder;
+    use tempfile::TempDir;
+
+    fn test_event(i: u64) -> proton_beam_core::ProtoEvent {
+        ProtoEventBuilder::new()
+            .id(format!("{:064x}", i))
+            .pubkey("0000000000000000000000000000000000000000000000000000000000000000")
+            .created_at(1758960000)
+            .kind(1)
+            .build()
+    }
+
+    fn test_config(temp_dir: &TempDir) -> Config {
+        let mut config = Config::default();
+        config.daemon.output_dir = temp_dir.path().to_path_buf();
+        config
+    }
+
+    #[test]
+    fn test_clean_shutdown_skips_recovery() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+
+        let marker = RunMarker::acquire(&config).unwrap();
+        assert!(temp_dir.path().join(RUN_MARKER).exists());
+        marker.release().unwrap();
+        assert!(!temp_dir.path().join(RUN_MARKER).exists());
+    }
+
+    #[test]
+    fn test_recovers_after_crash() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = test_config(&temp_dir);
+        let pb_file = temp_dir.path().join("2025_09_27.pb.gz");
+
+        let marker = RunMarker::acquire(&config).unwrap();
+        {
+            let index = EventIndex::new(&config.index_path()).unwrap();
+            let mut storage = StorageManager::new(temp_dir.path(), 10, 6)
+                .unwrap()
+                .with_index(index);
+            storage.store_event(test_event(1)).unwrap();
+            storage.flush().unwrap();
+            // Killed before the gzip member was finished
+            std::mem::forget(storage);
+        }
+        std::mem::forget(marker);
+
+        // An indexed event whose bytes never reached the file
+        let mut index = EventIndex::new(&config.index_path()).unwrap();
+        index.insert(&test_event(2), "2025_09_27.pb.gz").unwrap();
+        drop(index);
+
+        let marker = RunMarker::acquire(&config).unwrap();
+        let index = EventIndex::new(&config.index_path()).unwrap();
+        assert!(index.contains(&format!("{:064x}", 1)).unwrap());
+        assert!(!index.contains(&format!("{:064x}", 2)).unwrap());
+        assert!(!temp_dir.path().join("2025_09_27.pb.gz.repair").exists());
+
+        let file = File::open(&pb_file).unwrap();
+        let events: Vec<_> = read_events_delimited(create_decoder(file, None).unwrap())
+            .collect::<proton_beam_core::Result<_>>()
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        marker.release().unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+//! Relay WebSocket connections and NIP-01 wire messages
+
+use crate::health::HealthTracker;
+use crate::metrics::Metrics;
+use crate::pipeline::IngestMessage;
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+/// Subscription ID used for the live REQ sent to every relay
+pub const SUBSCRIPTION_ID: &str = "proton-beam";
+
+/// How long to keep reading after CLOSE while messages are still arriving
+const DRAIN_IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Upper bound on the time spent draining a relay during shutdown
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(3);
+
+type RelayStream = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Messages sent from a relay to a client (NIP-01)
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelayMessage {
+    /// `["EVENT", <subscription_id>, <event JSON>]`
+    Event {
+        subscription_id: String,
+        event: Value,
+    },
+    /// `["EOSE", <subscription_id>]`
+    Eose(String),
+    /// `["CLOSED", <subscription_id>, <message>]`
+    Closed {
+        subscription_id: String,
+        message: String,
+    },
+    /// `["NOTICE", <message>]`
+    Notice(String),
+    /// Any other message type (OK, AUTH, COUNT, ...), which the daemon ignores
+    Other(String),
+}
+
+impl RelayMessage {
+    /// Parse a relay message from its JSON text
+    pub fn from_json(text: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(text).context("Relay message is not JSON")?;
+        let mut parts = match value {
+            Value::Array(parts) => parts.into_iter(),
+            _ => anyhow::bail!("Relay message is not a JSON array"),
+        };
+
+        let kind = match parts.next() {
+            Some(Value::String(kind)) => kind,
+            _ => anyhow::bail!("Relay message has no type"),
+        };
+
+        let message = match kind.as_str() {
+            "EVENT" => {
+                let subscription_id = next_string(&mut parts, &kind, "subscription id")?;
+                let event = parts.next().context("EVENT message is missing the event")?;
+                Self::Event {
+                    subscription_id,
+                    event,
+                }
+            }
+            "EOSE" => Self::Eose(next_string(&mut parts, &kind, "subscription id")?),
+            "CLOSED" => Self::Closed {
+                subscription_id: next_string(&mut parts, &kind, "subscription id")?,
+                message: next_string(&mut parts, &kind, "message").unwrap_or_default(),
+            },
+            "NOTICE" => Self::Notice(next_string(&mut parts, &kind, "message")?),
+            _ => Self::Other(kind),
+        };
+
+        Ok(message)
+    }
+}
+
+fn next_string(parts: &mut impl Iterator<Item = Value>, kind: &str, field: &str) -> Result<String> {
+    match parts.next() {
+        Some(Value::String(s)) => Ok(s),
+        _ => anyhow::bail!("{} message is missing {}", kind, field),
+    }
+}
+
+/// Build a `["REQ", <subscription_id>, <filters>...]` message
+pub fn req_message(subscription_id: &str, filters: &[Value]) -> String {
+    let mut message = vec![Value::from("REQ"), Value::from(subscription_id)];
+    message.extend(filters.iter().cloned());
+    Value::Array(message).to_string()
+}
+
+/// Build a `["CLOSE", <subscription_id>]` message
+pub fn close_message(subscription_id: &str) -> String {
+    serde_json::json!(["CLOSE", subscription_id]).to_string()
+}
+
+/// Why a relay stream stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamEnd {
+    /// The relay closed the connection or the subscription
+    Disconnected,
+    /// The ingest pipeline is gone, so there is nothing left to do
+    ReceiverClosed,
+    /// Shutdown was requested and the connection has been drained
+    Shutdown,
+}
+
+/// What to do after handling one frame
+enum Flow {
+    Continue,
+    Stop(StreamEnd),
+}
+
+/// Wait until `shutdown` flips to `true` (or its sender is dropped)
+pub(crate) async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|&stop| stop).await;
+}
+
+/// Handles shared by every relay connection
+#[derive(Clone)]
+pub struct RelayContext {
+    /// NIP-01 filter sent in the REQ
+    pub filter: Value,
+    /// Channel to the ingest pipeline
+    pub tx: mpsc::Sender<IngestMessage>,
+    pub metrics: Arc<Metrics>,
+    pub health: Arc<HealthTracker>,
+    /// Flips to `true` when the daemon shuts down
+    pub shutdown: watch::Receiver<bool>,
+}
+
+/// Keep a subscription open against one relay, reconnecting when it drops
+///
+/// Every EVENT for the subscription is forwarded to the ingest pipeline.
+/// Reconnects back off exponentially while the relay keeps failing. When
+/// shutdown is requested the subscription is closed, events the relay
+/// already sent are forwarded, and the function returns. It also returns
+/// once the pipeline's receiver has been dropped.
+pub async fn run_relay(url: String, ctx: RelayContext) {
+    let mut shutdown = ctx.shutdown.clone();
+    while !*shutdown.borrow() {
+        match stream_events(&url, &ctx, &mut shutdown).await {
+            Ok(StreamEnd::ReceiverClosed | StreamEnd::Shutdown) => return,
+            Ok(StreamEnd::Disconnected) => info!(relay = %url, "Relay disconnected"),
+            Err(e) => warn!(relay = %url, "Relay connection failed: {:#}", e),
+        }
+
+        let delay = ctx.health.reconnect_delay(&url);
+        debug!(relay = %url, "Reconnecting in {}s", delay.as_secs());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown_requested(&mut shutdown) => return,
+        }
+    }
+}
+
+async fn stream_events(
+    url: &str,
+    ctx: &RelayContext,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<StreamEnd> {
+    let started = Instant::now();
+    let connected = tokio::select! {
+        connected = connect_async(url) => connected,
+        _ = shutdown_requested(shutdown) => return Ok(StreamEnd::Shutdown),
+    };
+    let (mut ws, _) = match connected {
+        Ok(connected) => connected,
+        Err(e) => {
+            ctx.health.record_failure(url);
+            return Err(e).context(format!("Failed to connect to {}", url));
+        }
+    };
+    ctx.health
+        .record_connected(url, started.elapsed(), chrono::Utc::now().timestamp());
+    info!(relay = %url, "Connected");
+
+    ws.send(Message::Text(
+        req_message(SUBSCRIPTION_ID, std::slice::from_ref(&ctx.filter)).into(),
+    ))
+    .await
+    .context("Failed to send REQ")?;
+
+    let mut session = Session {
+        url,
+        ctx,
+        req_sent: Some(Instant::now()),
+    };
+    loop {
+        let frame = tokio::select! {
+            frame = ws.next() => frame,
+            _ = shutdown_requested(shutdown) => return session.drain(&mut ws).await,
+        };
+        let Some(frame) = frame else {
+            return Ok(StreamEnd::Disconnected);
+        };
+        if let Flow::Stop(end) = session
+            .handle_frame(frame.context("WebSocket error")?)
+            .await
+        {
+            return Ok(end);
+        }
+    }
+}
+
+/// State of one open connection
+struct Session<'a> {
+    url: &'a str,
+    ctx: &'a RelayContext,
+    /// When the REQ was sent, until the first EOSE arrives
+    req_sent: Option<Instant>,
+}
+
+impl Session<'_> {
+    /// Close the subscription and forward events that are already in flight
+    ///
+    /// Relays may keep sending for a moment after CLOSE, so frames are read
+    /// until the connection goes quiet or `DRAIN_TIMEOUT` elapses.
+    async fn drain(&mut self, ws: &mut RelayStream) -> Result<StreamEnd> {
+        debug!(relay = %self.url, "Draining connection");
+        let _ = ws
+            .send(Message::Text(close_message(SUBSCRIPTION_ID).into()))
+            .await;
+
+        let deadline = tokio::time::Instant::now() + DRAIN_TIMEOUT;
+        loop {
+            let idle = DRAIN_IDLE_TIMEOUT
+                .min(deadline.saturating_duration_since(tokio::time::Instant::now()));
+            let frame = match tokio::time::timeout(idle, ws.next()).await {
+                Ok(Some(Ok(frame))) => frame,
+                _ => break,
+            };
+            match self.handle_frame(frame).await {
+                Flow::Continue => {}
+                Flow::Stop(StreamEnd::ReceiverClosed) => return Ok(StreamEnd::ReceiverClosed),
+                Flow::Stop(_) => break,
+            }
+        }
+
+        let _ = ws.close(None).await;
+        Ok(StreamEnd::Shutdown)
+    }
+
+    async fn handle_frame(&mut self, frame: Message) -> Flow {
+        let url = self.url;
+        let text = match frame {
+            Message::Text(text) => text,
+            Message::Close(_) => return Flow::Stop(StreamEnd::Disconnected),
+            _ => return Flow::Continue,
+        };
+
+        let message = match RelayMessage::from_json(text.as_str()) {
+            Ok(message) => message,
+            Err(e) => {
+                debug!(relay = %url, "Ignoring malformed relay message: {}", e);
+                return Flow::Continue;
+            }
+        };
+
+        match message {
+            RelayMessage::Event {
+                subscription_id,
+                event,
+            } if subscription_id == SUBSCRIPTION_ID => {
+                self.ctx.metrics.received.fetch_add(1, Ordering::Relaxed);
+                let message = IngestMessage::Event {
+                    relay_url: url.to_string(),
+                    event_json: event.to_string(),
+                };
+                if self.ctx.tx.send(message).await.is_err() {
+                    return Flow::Stop(StreamEnd::ReceiverClosed);
+                }
+            }
+            RelayMessage::Eose(subscription_id) if subscription_id == SUBSCRIPTION_ID => {
+                if let Some(req_sent) = self.req_sent.take() {
+                    self.ctx.health.record_eose(url, req_sent.elapsed());
+                }
+                debug!(relay = %url, "End of stored events");
+            }
+            RelayMessage::Closed {
+                subscription_id,
+                message,
+            } if subscription_id == SUBSCRIPTION_ID => {
+                warn!(relay = %url, "Subscription closed by relay: {}", message);
+                return Flow::Stop(StreamEnd::Disconnected);
+            }
+            RelayMessage::Notice(notice) => info!(relay = %url, "Notice: {}", notice),
+            _ => {}
+        }
+
+        Flow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event_message() {
+        let message = RelayMessage::from_json(r#"["EVENT","sub1",{"id":"abc","kind":1}]"#).unwrap();
+        assert_eq!(
+            message,
+            RelayMessage::Event {
+                subscription_id: "sub1".to_string(),
+                event: serde_json::json!({"id": "abc", "kind": 1}),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_control_messages() {
+        assert_eq!(
+            RelayMessage::from_json(r#"["EOSE","sub1"]"#).unwrap(),
+            RelayMessage::Eose("sub1".to_string())
+        );
+        assert_eq!(
+            RelayMessage::from_json(r#"["NOTICE","slow down"]"#).unwrap(),
+            RelayMessage::Notice("slow down".to_string())
+        );
+        assert_eq!(
+            RelayMessage::from_json(r#"["CLOSED","sub1","error: shutting down"]"#).unwrap(),
+            RelayMessage::Closed {
+                subscription_id: "sub1".to_string(),
+                message: "error: shutting down".to_string(),
+            }
+        );
+        assert_eq!(
+            RelayMessage::from_json(r#"["OK","abc",true,""]"#).unwrap(),
+            RelayMessage::Other("OK".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_messages() {
+        assert!(RelayMessage::from_json("not json").is_err());
+        assert!(RelayMessage::from_json(r#"{"EVENT":1}"#).is_err());
+        assert!(RelayMessage::from_json(r#"["EVENT","sub1"]"#).is_err());
+        assert!(RelayMessage::from_json("[]").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_closed_for_other_subscription_is_ignored() {
+        let (tx, _rx) = mpsc::channel(1);
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let ctx = RelayContext {
+            filter: serde_json::json!({}),
+            tx,
+            metrics: Arc::new(Metrics::default()),
+            health: Arc::new(HealthTracker::default()),
+            shutdown,
+        };
+        let mut session = Session {
+            url: "wss://relay.example",
+            ctx: &ctx,
+            req_sent: None,
+        };
+
+        let other = Message::Text(r#"["CLOSED","other","error: gone"]"#.into());
+        assert!(matches!(session.handle_frame(other).await, Flow::Continue));
+
+        let ours =
+            Message::Text(format!(r#"["CLOSED","{}","error: gone"]"#, SUBSCRIPTION_ID).into());
+        assert!(matches!(
+            session.handle_frame(ours).await,
+            Flow::Stop(StreamEnd::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn test_client_messages() {
+        assert_eq!(
+            req_message("sub1", &[serde_json::json!({"kinds": [1]})]),
+            r#"["REQ","sub1",{"kinds":[1]}]"#
+        );
+        assert_eq!(close_message("sub1"), r#"["CLOSE","sub1"]"#);
+    }
+}
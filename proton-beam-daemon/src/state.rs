@@ -0,0 +1,207 @@
+//! Daemon state that survives restarts, kept in a small SQLite database
+//!
+//! The event index only knows about events; this database holds what the
+//! daemon learns about relays themselves and how far each historical
+//! backfill has progressed.
+
+use crate::backfill::BackfillCheckpoint;
+use crate::health::RelayHealth;
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// SQLite-backed store for daemon state
+pub struct StateStore {
+    conn: Connection,
+}
+
+impl StateStore {
+    /// Open (or create) the state database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .context(format!("Failed to open state database: {}", path.display()))?;
+
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+
+             CREATE TABLE IF NOT EXISTS relay_health (
+                 url TEXT PRIMARY KEY,
+                 connect_attempts INTEGER NOT NULL,
+                 connect_successes INTEGER NOT NULL,
+                 consecutive_failures INTEGER NOT NULL,
+                 connect_latency_ms REAL,
+                 eose_time_ms REAL,
+                 events_received INTEGER NOT NULL,
+                 events_invalid INTEGER NOT NULL,
+                 events_duplicate INTEGER NOT NULL,
+                 last_connected_at INTEGER
+             );
+
+             CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+                 url TEXT PRIMARY KEY,
+                 since INTEGER NOT NULL,
+                 covered_until INTEGER,
+                 pass_upper INTEGER NOT NULL,
+                 cursor INTEGER NOT NULL,
+                 updated_at INTEGER NOT NULL
+             );",
+        )
+        .context("Failed to initialize state database")?;
+
+        Ok(Self { conn })
+    }
+
+    /// Load the health statistics of every relay seen so far
+    pub fn load_health(&self) -> Result<HashMap<String, RelayHealth>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT url, connect_attempts, connect_successes, consecutive_failures,
+                    connect_latency_ms, eose_time_ms, events_received, events_invalid,
+                    events_duplicate, last_connected_at
+             FROM relay_health",
+        )?;
+
+        let entries = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    RelayHealth {
+                        connect_attempts: row.get(1)?,
+                        connect_successes: row.get(2)?,
+                        consecutive_failures: row.get(3)?,
+                        connect_latency_ms: row.get(4)?,
+                        eose_time_ms: row.get(5)?,
+                        events_received: row.get(6)?,
+                        events_invalid: row.get(7)?,
+                        events_duplicate: row.get(8)?,
+                        last_connected_at: row.get(9)?,
+                    },
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()
+            .context("Failed to load relay health")?;
+
+        Ok(entries)
+    }
+
+    /// Replace the stored health statistics for the given relays
+    pub fn save_health(&mut self, entries: &HashMap<String, RelayHealth>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO relay_health
+                 (url, connect_attempts, connect_successes, consecutive_failures,
+                  connect_latency_ms, eose_time_ms, events_received, events_invalid,
+                  events_duplicate, last_connected_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for (url, health) in entries {
+                stmt.execute(params![
+                    url,
+                    health.connect_attempts,
+                    health.connect_successes,
+                    health.consecutive_failures,
+                    health.connect_latency_ms,
+                    health.eose_time_ms,
+                    health.events_received,
+                    health.events_invalid,
+                    health.events_duplicate,
+                    health.last_connected_at,
+                ])?;
+            }
+        }
+        tx.commit().context("Failed to save relay health")?;
+        Ok(())
+    }
+
+    /// Load the backfill checkpoint for a relay, if any
+    pub fn load_checkpoint(&self, url: &str) -> Result<Option<BackfillCheckpoint>> {
+        self.conn
+            .query_row(
+                "SELECT since, covered_until, pass_upper, cursor
+                 FROM backfill_checkpoints WHERE url = ?",
+                params![url],
+                |row| {
+                    Ok(BackfillCheckpoint {
+                        since: row.get(0)?,
+                        covered_until: row.get(1)?,
+                        pass_upper: row.get(2)?,
+                        cursor: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .context("Failed to load backfill checkpoint")
+    }
+
+    /// Store the backfill checkpoint for a relay
+    pub fn save_checkpoint(&self, url: &str, checkpoint: &BackfillCheckpoint) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO backfill_checkpoints
+                 (url, since, covered_until, pass_upper, cursor, updated_at)
+                 VALUES (?, ?, ?, ?, ?, strftime('%s', 'now'))",
+                params![
+                    url,
+                    checkpoint.since,
+                    checkpoint.covered_until,
+                    checkpoint.pass_upper,
+                    checkpoint.cursor,
+                ],
+            )
+            .context("Failed to save backfill checkpoint")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_health_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("daemon_state.db");
+
+        let mut entries = HashMap::new();
+        entries.insert(
+            "wss://relay.example.com".to_string(),
+            RelayHealth {
+                connect_attempts: 4,
+                connect_successes: 3,
+                consecutive_failures: 1,
+                connect_latency_ms: Some(120.5),
+                eose_time_ms: None,
+                events_received: 10,
+                events_invalid: 1,
+                events_duplicate: 2,
+                last_connected_at: Some(1_700_000_000),
+            },
+        );
+
+        StateStore::open(&path)
+            .unwrap()
+            .save_health(&entries)
+            .unwrap();
+        let loaded = StateStore::open(&path).unwrap().load_health().unwrap();
+        assert_eq!(loaded, entries);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = StateStore::open(&temp_dir.path().join("daemon_state.db")).unwrap();
+        assert_eq!(store.load_checkpoint("wss://a").unwrap(), None);
+
+        let checkpoint = BackfillCheckpoint {
+            since: 100,
+            covered_until: None,
+            pass_upper: 500,
+            cursor: 300,
+        };
+        store.save_checkpoint("wss://a", &checkpoint).unwrap();
+        assert_eq!(store.load_checkpoint("wss://a").unwrap(), Some(checkpoint));
+    }
+}
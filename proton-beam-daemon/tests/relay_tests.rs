@@ -0,0 +1,480 @@
+//! End-to-end tests for the daemon against a local relay stand-in
+
+use futures_util::{SinkExt, StreamExt};
+use proton_beam_core::{
+    EventIndex, ProtoEvent, create_gzip_decoder, read_events_delimited, validate_event,
+};
+use proton_beam_daemon::state::StateStore;
+use proton_beam_daemon::{Config, Daemon, Metrics};
+use serde_json::Value;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+fn sample_events(count: usize) -> Vec<String> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("examples")
+        .join("sample_events.jsonl");
+
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .filter(|line| {
+            ProtoEvent::try_from(*line).is_ok_and(|event| validate_event(&event).is_ok())
+        })
+        .take(count)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Accept a single client, answer its REQ with `events` followed by EOSE, and
+/// return every filter the client asked for
+async fn spawn_mock_relay(events: Vec<String>) -> (String, JoinHandle<Vec<Value>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let mut filters = Vec::new();
+
+        while let Some(Ok(frame)) = ws.next().await {
+            let Message::Text(text) = frame else { continue };
+            let request: Vec<Value> = serde_json::from_str(text.as_str()).unwrap();
+            if request[0] != "REQ" {
+                continue;
+            }
+            let subscription_id = request[1].as_str().unwrap().to_string();
+            filters.extend(request[2..].iter().cloned());
+
+            for event in &events {
+                let message = format!(r#"["EVENT","{}",{}]"#, subscription_id, event);
+                ws.send(Message::Text(message.into())).await.unwrap();
+            }
+            let eose = serde_json::json!(["EOSE", subscription_id]).to_string();
+            ws.send(Message::Text(eose.into())).await.unwrap();
+            break;
+        }
+
+        // Keep the connection open until the daemon goes away
+        while let Some(Ok(_)) = ws.next().await {}
+        filters
+    });
+
+    (url, handle)
+}
+
+fn test_config(output_dir: &TempDir, relay_url: &str) -> Config {
+    let mut config = Config::default();
+    config.daemon.output_dir = output_dir.path().to_path_buf();
+    config.daemon.flush_interval_secs = 1;
+    config.relays.urls = vec![relay_url.to_string()];
+    config
+}
+
+async fn wait_for_processed(metrics: Arc<Metrics>, expected: u64) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while metrics.processed() < expected {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("daemon did not process all events in time");
+}
+
+fn read_stored_events(dir: &TempDir) -> Vec<ProtoEvent> {
+    let mut events = Vec::new();
+    for entry in std::fs::read_dir(dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.to_string_lossy().ends_with(".pb.gz") {
+            let gz = create_gzip_decoder(File::open(&path).unwrap());
+            for event in read_events_delimited(gz) {
+                events.push(event.unwrap());
+            }
+        }
+    }
+    events
+}
+
+#[tokio::test]
+async fn test_daemon_archives_relay_events() {
+    let events = sample_events(5);
+    assert_eq!(events.len(), 5);
+
+    let mut messages = events.clone();
+    messages.push(events[0].clone()); // duplicate
+    let mut forged: Value = serde_json::from_str(&events[1]).unwrap();
+    forged["id"] = "0".repeat(64).into();
+    messages.push(forged.to_string());
+    messages.push("{\"not\": \"an event\"}".to_string());
+
+    let (url, relay) = spawn_mock_relay(messages).await;
+    let output_dir = TempDir::new().unwrap();
+    let daemon = Daemon::new(test_config(&output_dir, &url));
+    let metrics = daemon.metrics();
+
+    daemon
+        .run(wait_for_processed(Arc::clone(&metrics), 8))
+        .await
+        .unwrap();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.received, 8);
+    assert_eq!(snapshot.stored, 5);
+    assert_eq!(snapshot.duplicates, 1);
+    assert_eq!(snapshot.invalid, 2);
+
+    let stored = read_stored_events(&output_dir);
+    assert_eq!(stored.len(), 5);
+
+    let index = EventIndex::new(&output_dir.path().join("index.db")).unwrap();
+    assert_eq!(index.stats().unwrap().total_events, 5);
+    for event in &stored {
+        assert!(index.contains(&event.id).unwrap());
+        let relays = index.query_relays(&event.id).unwrap();
+        assert_eq!(relays.len(), 1);
+        assert_eq!(relays[0].relay_url, url);
+    }
+
+    // Relay health is persisted for the next run
+    let health = StateStore::open(&output_dir.path().join("daemon_state.db"))
+        .unwrap()
+        .load_health()
+        .unwrap();
+    let relay_health = &health[&url];
+    assert_eq!(relay_health.connect_successes, 1);
+    assert_eq!(relay_health.events_received, 8);
+    assert_eq!(relay_health.events_invalid, 2);
+    assert_eq!(relay_health.events_duplicate, 1);
+    assert!(relay_health.eose_time_ms.is_some());
+
+    relay.abort();
+}
+
+#[tokio::test]
+async fn test_daemon_stops_when_writer_fails() {
+    let events = sample_events(1);
+    let (url, relay) = spawn_mock_relay(events.clone()).await;
+    let output_dir = TempDir::new().unwrap();
+
+    // A directory where the day file belongs makes the periodic flush fail
+    let event = ProtoEvent::try_from(events[0].as_str()).unwrap();
+    let day = chrono::DateTime::from_timestamp(event.created_at, 0)
+        .unwrap()
+        .format("%Y_%m_%d.pb.gz")
+        .to_string();
+    std::fs::create_dir(output_dir.path().join(day)).unwrap();
+
+    let daemon = Daemon::new(test_config(&output_dir, &url));
+    let result = tokio::time::timeout(Duration::from_secs(15), daemon.run(std::future::pending()))
+        .await
+        .expect("daemon kept running after its writer failed");
+    assert!(result.is_err());
+
+    relay.abort();
+}
+
+#[tokio::test]
+async fn test_daemon_sends_configured_filter() {
+    let (url, relay) = spawn_mock_relay(sample_events(1)).await;
+    let output_dir = TempDir::new().unwrap();
+    let mut config = test_config(&output_dir, &url);
+    config.filters.kinds = vec![1, 7];
+
+    let daemon = Daemon::new(config);
+    let metrics = daemon.metrics();
+    daemon
+        .run(wait_for_processed(Arc::clone(&metrics), 1))
+        .await
+        .unwrap();
+
+    let filters = relay.await.unwrap();
+    assert_eq!(filters.len(), 1);
+    assert_eq!(filters[0]["kinds"], serde_json::json!([1, 7]));
+    assert_eq!(filters[0]["limit"], 0);
+}
+
+/// Accept a single client and signal `req_received` once its REQ arrives, but
+/// only send `events` after the client asks to CLOSE, as if they had still
+/// been in flight when the daemon started shutting down
+async fn spawn_slow_relay(events: Vec<String>, req_received: oneshot::Sender<()>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let mut req_received = Some(req_received);
+
+        while let Some(Ok(frame)) = ws.next().await {
+            let Message::Text(text) = frame else { continue };
+            let request: Vec<Value> = serde_json::from_str(text.as_str()).unwrap();
+            let subscription_id = request[1].as_str().unwrap().to_string();
+            match request[0].as_str() {
+                Some("REQ") => {
+                    if let Some(tx) = req_received.take() {
+                        let _ = tx.send(());
+                    }
+                }
+                Some("CLOSE") => {
+                    for event in &events {
+                        let message = format!(r#"["EVENT","{}",{}]"#, subscription_id, event);
+                        ws.send(Message::Text(message.into())).await.unwrap();
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    url
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_in_flight_events() {
+    let (req_tx, req_rx) = oneshot::channel();
+    let url = spawn_slow_relay(sample_events(3), req_tx).await;
+    let output_dir = TempDir::new().unwrap();
+
+    let daemon = Daemon::new(test_config(&output_dir, &url));
+    let metrics = daemon.metrics();
+    daemon
+        .run(async {
+            let _ = req_rx.await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.snapshot().stored, 3);
+
+    // Every file was finished cleanly and the run marker is gone
+    assert_eq!(read_stored_events(&output_dir).len(), 3);
+    let leftovers: Vec<_> = std::fs::read_dir(output_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with('.'))
+        .collect();
+    assert!(leftovers.is_empty(), "unexpected files: {:?}", leftovers);
+}
+
+/// A freshly signed text note carrying the given tags
+fn signed_event(tags: Vec<Vec<String>>) -> String {
+    use nostr_sdk::{EventBuilder, JsonUtil, Keys, Tag};
+
+    let tags = tags.into_iter().map(|tag| Tag::parse(tag).unwrap());
+    EventBuilder::text_note("relay hints")
+        .tags(tags)
+        .sign_with_keys(&Keys::generate())
+        .unwrap()
+        .as_json()
+}
+
+#[tokio::test]
+async fn test_daemon_connects_to_discovered_relays() {
+    let (discovered_url, discovered_relay) = spawn_mock_relay(sample_events(2)).await;
+    let relay_list = signed_event(vec![vec!["r".to_string(), format!("{}/", discovered_url)]]);
+    let (seed_url, seed_relay) = spawn_mock_relay(vec![relay_list]).await;
+
+    let output_dir = TempDir::new().unwrap();
+    let mut config = test_config(&output_dir, &seed_url);
+    config.relays.auto_discover = true;
+    config.relays.discover_private_hosts = true;
+    // Keep the hints in the sample events from reaching real relays
+    config.relays.max_relays = 2;
+
+    let daemon = Daemon::new(config);
+    let metrics = daemon.metrics();
+    daemon
+        .run(wait_for_processed(Arc::clone(&metrics), 3))
+        .await
+        .unwrap();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.stored, 3);
+    // The sample events carry relay hints of their own
+    assert!(snapshot.discovered >= 1);
+    assert_eq!(discovered_relay.await.unwrap().len(), 1);
+    seed_relay.abort();
+}
+
+#[tokio::test]
+async fn test_discovery_respects_max_relays() {
+    let (discovered_url, discovered_relay) = spawn_mock_relay(sample_events(1)).await;
+    let relay_list = signed_event(vec![vec!["r".to_string(), discovered_url]]);
+    let (seed_url, seed_relay) = spawn_mock_relay(vec![relay_list]).await;
+
+    let output_dir = TempDir::new().unwrap();
+    let mut config = test_config(&output_dir, &seed_url);
+    config.relays.auto_discover = true;
+    config.relays.discover_private_hosts = true;
+    config.relays.max_relays = 1;
+
+    let daemon = Daemon::new(config);
+    let metrics = daemon.metrics();
+    daemon
+        .run(async {
+            wait_for_processed(Arc::clone(&metrics), 1).await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.snapshot().discovered, 1);
+    assert!(!discovered_relay.is_finished());
+    discovered_relay.abort();
+    seed_relay.abort();
+}
+
+/// Serve `events` to any number of clients like a relay with stored history:
+/// each REQ gets the newest events matching its `since`/`until`/`limit`, then
+/// EOSE. Returns every paged filter (`limit` > 0) the clients asked for.
+async fn spawn_history_relay(events: Vec<String>) -> (String, Arc<std::sync::Mutex<Vec<Value>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let pages = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let mut events: Vec<Value> = events
+        .iter()
+        .map(|event| serde_json::from_str(event).unwrap())
+        .collect();
+    events.sort_by_key(|event| std::cmp::Reverse(event["created_at"].as_i64().unwrap()));
+    let events = Arc::new(events);
+
+    let recorded = Arc::clone(&pages);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let events = Arc::clone(&events);
+            let recorded = Arc::clone(&recorded);
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(frame)) = ws.next().await {
+                    let Message::Text(text) = frame else { continue };
+                    let request: Vec<Value> = serde_json::from_str(text.as_str()).unwrap();
+                    if request[0] != "REQ" {
+                        continue;
+                    }
+                    let subscription_id = request[1].as_str().unwrap().to_string();
+                    let filter = &request[2];
+                    let limit = filter["limit"].as_u64().unwrap_or(0) as usize;
+                    if limit > 0 {
+                        recorded.lock().unwrap().push(filter.clone());
+                    }
+
+                    let since = filter["since"].as_i64().unwrap_or(i64::MIN);
+                    let until = filter["until"].as_i64().unwrap_or(i64::MAX);
+                    let page = events
+                        .iter()
+                        .filter(|event| {
+                            let created_at = event["created_at"].as_i64().unwrap();
+                            created_at >= since && created_at <= until
+                        })
+                        .take(limit);
+                    for event in page {
+                        let message = serde_json::json!(["EVENT", subscription_id, event]);
+                        ws.send(Message::Text(message.to_string().into()))
+                            .await
+                            .unwrap();
+                    }
+                    let eose = serde_json::json!(["EOSE", subscription_id]).to_string();
+                    ws.send(Message::Text(eose.into())).await.unwrap();
+                }
+            });
+        }
+    });
+
+    (url, pages)
+}
+
+/// Wait until the backfill of `url` has covered its whole window
+async fn wait_for_backfill(output_dir: &TempDir, url: &str) {
+    let state = StateStore::open(&output_dir.path().join("daemon_state.db")).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while state
+            .load_checkpoint(url)
+            .unwrap()
+            .and_then(|checkpoint| checkpoint.covered_until)
+            .is_none()
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("backfill did not finish in time");
+}
+
+#[tokio::test]
+async fn test_backfill_pages_history_and_resumes() {
+    let events = sample_events(10);
+    let (url, pages) = spawn_history_relay(events.clone()).await;
+    let output_dir = TempDir::new().unwrap();
+
+    let mut config = test_config(&output_dir, &url);
+    config.historical.enabled = true;
+    // Larger than the number of sample events sharing one second
+    config.historical.page_size = 5;
+
+    let daemon = Daemon::new(config.clone());
+    let metrics = daemon.metrics();
+    daemon
+        .run(wait_for_backfill(&output_dir, &url))
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.snapshot().stored, 10);
+    assert_eq!(read_stored_events(&output_dir).len(), 10);
+    let first_run: Vec<Value> = pages.lock().unwrap().drain(..).collect();
+    assert!(
+        first_run.len() >= 3,
+        "expected several pages: {:?}",
+        first_run
+    );
+    assert!(first_run.iter().all(|filter| filter["limit"] == 5));
+
+    // The next run only asks for what happened since the first one finished
+    let covered_until = StateStore::open(&config.state_path())
+        .unwrap()
+        .load_checkpoint(&url)
+        .unwrap()
+        .unwrap()
+        .covered_until
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let daemon = Daemon::new(config);
+    let metrics = daemon.metrics();
+    daemon
+        .run(async {
+            let state = StateStore::open(&output_dir.path().join("daemon_state.db")).unwrap();
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while state
+                    .load_checkpoint(&url)
+                    .unwrap()
+                    .and_then(|checkpoint| checkpoint.covered_until)
+                    == Some(covered_until)
+                {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            })
+            .await
+            .expect("gap backfill did not finish in time");
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(metrics.snapshot().stored, 0);
+    let second_run = pages.lock().unwrap().clone();
+    assert!(!second_run.is_empty());
+    assert!(
+        second_run
+            .iter()
+            .all(|filter| filter["since"].as_i64() == Some(covered_until))
+    );
+}